@@ -0,0 +1,95 @@
+//! How long handling an incoming message and drawing the message list take, with a busy room.
+//! Run with `cargo bench`; `--profile` measures the same things in a real session.
+
+#[path = "../src/tests/mock.rs"]
+#[allow(dead_code)]
+mod mock;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use ilo_toki::bench::App;
+use mock::MockServer;
+use serde_json::{json, value::RawValue, Value};
+use tui::{backend::TestBackend, Terminal};
+
+const ROOM: &str = "!room:example.org";
+const ME: &str = "@me:example.org";
+const ALICE: &str = "@alice:example.org";
+/// How many messages the room starts with.
+const HISTORY: usize = 1000;
+
+fn message(i: usize) -> Value {
+    json!({
+        "type": "m.room.message",
+        "sender": ALICE,
+        "event_id": format!("$message{}", i),
+        "origin_server_ts": 1_000_000 + i as u64 * 1000,
+        "content": { "msgtype": "m.text", "body": format!("message number {}, long enough to wrap onto a second line on a narrow terminal", i) },
+    })
+}
+
+fn state_event(event_type: &str, state_key: &str, content: Value) -> Value {
+    json!({
+        "type": event_type,
+        "state_key": state_key,
+        "sender": ALICE,
+        "content": content,
+        "event_id": format!("${}{}", event_type, state_key),
+        "origin_server_ts": 1,
+    })
+}
+
+/// An app synced with a room of `HISTORY` messages, and the server it's synced with, which has to
+/// outlive it.
+fn app(runtime: &tokio::runtime::Runtime) -> (MockServer, App) {
+    runtime.block_on(async {
+        let server = MockServer::start().await;
+        let events: Vec<_> = (0..HISTORY).map(message).collect();
+        server.on(
+            "GET",
+            "/sync",
+            json!({
+                "next_batch": "s1",
+                "rooms": {
+                    "join": {
+                        ROOM: {
+                            "state": {
+                                "events": [
+                                    state_event("m.room.create", "", json!({ "creator": ALICE })),
+                                    state_event("m.room.name", "", json!({ "name": "Busy room" })),
+                                    state_event("m.room.member", ME, json!({ "membership": "join" })),
+                                    state_event("m.room.member", ALICE, json!({ "membership": "join" })),
+                                ],
+                            },
+                            "timeline": { "events": events, "limited": false, "prev_batch": "p1" },
+                        },
+                    },
+                },
+            }),
+        );
+        let app = App::new(server.url(), ME, ROOM).await;
+        (server, app)
+    })
+}
+
+fn handle_new_message(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let (_server, app) = app(&runtime);
+    let mut i = HISTORY;
+    c.bench_function("handle_new_message", |b| {
+        b.iter(|| {
+            let event = RawValue::from_string(message(i).to_string()).unwrap();
+            app.receive(&event);
+            i += 1;
+        })
+    });
+}
+
+fn draw_timeline(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let (_server, app) = app(&runtime);
+    let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+    c.bench_function("draw timeline", |b| b.iter(|| app.draw(&mut terminal)));
+}
+
+criterion_group!(benches, handle_new_message, draw_timeline);
+criterion_main!(benches);
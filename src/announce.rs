@@ -0,0 +1,41 @@
+//! Plain lines saying what just happened, for screen readers that can't follow the layout.
+
+use std::collections::VecDeque;
+
+/// How many announcements stay on screen.
+const MAX_LINES: usize = 3;
+
+pub struct Announcements {
+    enabled: bool,
+    lines: VecDeque<String>,
+}
+
+impl Announcements {
+    pub fn new(enabled: bool) -> Announcements {
+        Announcements {
+            enabled,
+            lines: VecDeque::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Adds a line below the others, pushing out the oldest. Does nothing unless enabled.
+    pub fn push(&mut self, text: &str) {
+        if !self.enabled {
+            return;
+        }
+
+        // one announcement is one line, so it's read in one go
+        self.lines.push_back(text.split_whitespace().collect::<Vec<_>>().join(" "));
+        while self.lines.len() > MAX_LINES {
+            self.lines.pop_front();
+        }
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().map(|v| v.as_str())
+    }
+}
@@ -0,0 +1,77 @@
+//! Setting presence to unavailable after a while without input, sooner when the terminal isn't
+//! focused, and back to online on the next key.
+
+use std::time::{Duration, Instant};
+
+use matrix_sdk::{
+    ruma::{api::client::presence::set_presence, presence::PresenceState},
+    Client,
+};
+
+use crate::config::AwaySettings;
+
+pub struct Away {
+    idle: Option<Duration>,
+    unfocused: Option<Duration>,
+    message: String,
+    last_input: Instant,
+    /// When the terminal lost focus, if it has.
+    focus_lost: Option<Instant>,
+    away: bool,
+}
+
+impl Away {
+    pub fn new(settings: &AwaySettings) -> Away {
+        let minutes = |v: u64| Some(Duration::from_secs(v * 60)).filter(|_| settings.enabled && v != 0);
+        Away {
+            idle: minutes(settings.idle_minutes),
+            unfocused: minutes(settings.unfocused_minutes),
+            message: settings.message.clone(),
+            last_input: Instant::now(),
+            focus_lost: None,
+            away: false,
+        }
+    }
+
+    /// Records a key press or the terminal getting focus.
+    pub fn active(&mut self) {
+        self.last_input = Instant::now();
+        self.focus_lost = None;
+    }
+
+    pub fn unfocus(&mut self) {
+        self.focus_lost.get_or_insert_with(Instant::now);
+    }
+
+    /// Whether we should now be away, if that changed since the last call.
+    pub fn update(&mut self, now: Instant) -> Option<bool> {
+        let idle = self.idle.map(|v| now - self.last_input >= v).unwrap_or(false);
+        let unfocused = self.unfocused.zip(self.focus_lost).map(|(v, lost)| now - lost >= v).unwrap_or(false);
+        let away = idle || unfocused;
+        if away == self.away {
+            return None;
+        }
+        self.away = away;
+        Some(away)
+    }
+
+    pub fn focused(&self) -> bool {
+        self.focus_lost.is_none()
+    }
+
+    pub fn is_away(&self) -> bool {
+        self.away
+    }
+
+    /// Tells the server whether we're away. Failures are ignored, since presence is best effort.
+    pub fn send(&self, client: &Client, away: bool) {
+        let (client, message) = (client.clone(), self.message.clone());
+        tokio::task::spawn(async move {
+            if let Some(user_id) = client.user_id() {
+                let mut request = set_presence::v3::Request::new(user_id, if away { PresenceState::Unavailable } else { PresenceState::Online });
+                request.status_msg = Some(message.as_str()).filter(|v| away && !v.is_empty());
+                let _ = client.send(request, None).await;
+            }
+        });
+    }
+}
@@ -0,0 +1,54 @@
+//! Calls can't be joined from a terminal, but they can be seen: `m.call.*` events show up in the
+//! timeline, and `J` opens the room's call in a browser.
+
+use matrix_sdk::{room::Joined, ruma::events::MessageLikeEventType};
+
+use crate::{
+    config::CallSettings,
+    widget::{self, Widget},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallEvent {
+    Invite,
+    Answer,
+    Hangup,
+}
+
+impl CallEvent {
+    pub fn of(event_type: &MessageLikeEventType) -> Option<CallEvent> {
+        match event_type {
+            MessageLikeEventType::CallInvite => Some(CallEvent::Invite),
+            MessageLikeEventType::CallAnswer => Some(CallEvent::Answer),
+            MessageLikeEventType::CallHangup => Some(CallEvent::Hangup),
+            _ => None,
+        }
+    }
+
+    /// What the sender did, shown as the event's content.
+    pub fn describe(self) -> &'static str {
+        match self {
+            CallEvent::Invite => "incoming call (J to join)",
+            CallEvent::Answer => "answered the call",
+            CallEvent::Hangup => "ended the call",
+        }
+    }
+}
+
+/// Where to join the room's call: its Jitsi or Element Call widgets if it has any, or the URL from
+/// the config otherwise.
+pub async fn links(room: &Joined, settings: &CallSettings) -> Vec<Widget> {
+    let widgets: Vec<_> = widget::list(room).await.into_iter().filter(|v| v.kind == "jitsi" || v.kind.contains("call")).collect();
+    if !widgets.is_empty() || settings.url.is_empty() {
+        return widgets;
+    }
+
+    // room ids aren't valid Jitsi room names, so they get one made of just their letters and numbers
+    let slug: String = room.room_id().localpart().chars().filter(char::is_ascii_alphanumeric).collect();
+    let vars = [(String::from("matrix_room_id"), room.room_id().to_string()), (String::from("room_slug"), slug)];
+    vec![Widget {
+        name: String::from("Call"),
+        kind: String::from("configured"),
+        url: widget::fill(&settings.url, &vars),
+    }]
+}
@@ -0,0 +1,78 @@
+//! Writing times and dates the way they're read here: on a 12 or 24 hour clock, following the
+//! locale unless it's set, or with any strftime pattern.
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    Local, TimeZone,
+};
+use serde::Deserialize;
+
+use crate::config::TimeSettings;
+
+#[derive(Clone, Copy, Default, Deserialize)]
+pub enum Hours {
+    /// 12 hour in regions that write it that way, like `en_US`, and 24 hour everywhere else.
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "12")]
+    Twelve,
+    #[serde(rename = "24")]
+    TwentyFour,
+}
+
+/// Regions, from the locale's `ll_RR`, that mostly read a 12 hour clock.
+const TWELVE_HOUR_REGIONS: [&str; 12] = ["US", "CA", "AU", "NZ", "PH", "IN", "PK", "BD", "EG", "SA", "MY", "CO"];
+
+pub struct Clock {
+    time: String,
+    /// The time with seconds, for when it's looked at closely.
+    precise: String,
+    date: String,
+}
+
+impl Clock {
+    pub fn new(settings: &TimeSettings) -> Clock {
+        let twelve = match settings.hours {
+            Hours::Auto => locale_is_twelve_hour(),
+            Hours::Twelve => true,
+            Hours::TwentyFour => false,
+        };
+        let (time, precise) = if twelve { ("%-I:%M %p", "%-I:%M:%S %p") } else { ("%H:%M", "%H:%M:%S") };
+        // chrono panics on writing a bad pattern, so those are left for the defaults
+        let pattern = |custom: &Option<String>, default: &str| custom.clone().filter(|v| !StrftimeItems::new(v).any(|v| v == Item::Error)).unwrap_or_else(|| String::from(default));
+        Clock {
+            time: pattern(&settings.time_format, time),
+            precise: pattern(&settings.time_format, precise),
+            date: pattern(&settings.date_format, "%Y-%m-%d"),
+        }
+    }
+
+    /// A timestamp in seconds as a time of day.
+    pub fn time(&self, timestamp: i64) -> String {
+        format(timestamp, &self.time)
+    }
+
+    pub fn date(&self, timestamp: i64) -> String {
+        format(timestamp, &self.date)
+    }
+
+    /// The date and the time to the second.
+    pub fn precise(&self, timestamp: i64) -> String {
+        format!("{} {}", format(timestamp, &self.date), format(timestamp, &self.precise))
+    }
+}
+
+fn format(timestamp: i64, pattern: &str) -> String {
+    match Local.timestamp_opt(timestamp, 0).single() {
+        Some(v) => v.format(pattern).to_string(),
+        None => String::new(),
+    }
+}
+
+fn locale_is_twelve_hour() -> bool {
+    let locale = ["LC_ALL", "LC_TIME", "LANG"].iter().filter_map(|v| std::env::var(v).ok()).find(|v| !v.is_empty()).unwrap_or_default();
+    // like en_US.UTF-8 or en_US@euro
+    let region = locale.split(['.', '@']).next().unwrap_or_default().split_once('_').map(|(_, v)| v).unwrap_or_default();
+    TWELVE_HOUR_REGIONS.contains(&region)
+}
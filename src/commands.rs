@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use matrix_sdk::ruma::{OwnedUserId, UserId};
+
+use crate::composer::Transform;
+
+/// A slash command typed into the input box.
+pub enum Command {
+    Quit,
+    /// Switches the composer into code block mode, with an optional language.
+    Code(Option<String>),
+    /// Uploads a video file.
+    Video(String),
+    /// Uploads an image file.
+    Image(String),
+    /// Uploads any file, or picks one to upload without a path.
+    Upload(String),
+    /// Asks a user to verify each other, or our other sessions to verify this one without a user.
+    Verify(String),
+    /// Lists the current channel's members.
+    Members,
+    /// Saves our room keys to a file, encrypted with a passphrase.
+    ExportKeys(String),
+    /// Loads room keys from a file exported by another client.
+    ImportKeys(String),
+    /// Loads the history in an Element JSON export into the current channel.
+    ImportHistory(String),
+    /// Invites the users listed in a file, one per line, to the current channel.
+    InviteFile(String),
+    /// Jumps the timeline to the messages from a date like 2023-05-01.
+    Date(String),
+    /// Lists the policy rules in the current channel.
+    Policy,
+    /// Adds a rule to the current channel's policy list banning a user, room, or server.
+    PolicyBan(String, String),
+    /// Follows or stops following the current channel's policy list.
+    PolicySubscribe(bool),
+    /// Shows how far this session is trusted.
+    Security,
+    /// Shows what the homeserver supports.
+    Server,
+    /// Shows how active the current channel has been.
+    Stats,
+    /// Leaves the current channel, which stays in the room list as a read-only archive.
+    Leave,
+    /// Shows the current channel's settings.
+    Room,
+    /// Turns on encryption in the current channel, for good.
+    Encrypt,
+    /// Lists or delists the current channel in the server's public room directory.
+    Publish(bool),
+    /// Searches the messages loaded so far, as the search is typed.
+    Search(String),
+    /// Searches the homeserver's user directory.
+    UserSearch(String),
+    /// Shows someone's profile and the rooms we share with them.
+    Whois(OwnedUserId),
+    /// Lists the messages that failed to send or are still waiting to.
+    Outbox,
+    /// Lists the current channel's widgets, like calls and shared documents.
+    Widgets,
+    /// Translates the last message from someone else in the current channel.
+    Translate,
+    /// Starts or stops translating new messages in the current channel.
+    AutoTranslate,
+    /// Turns link previews on or off for the current channel.
+    Previews,
+    /// Holds notifications back for a while like `1h30m`, until `/dnd off`, or toggles them without one.
+    Dnd(String),
+    /// Stops or starts sending read receipts and typing notifications to the current channel.
+    Private,
+    /// Sends the text with transforms like `/shrug` applied, outermost first.
+    Transform(Vec<Transform>, String),
+}
+
+/// Parses the input as a command. Returns `None` for anything that should be sent as a message,
+/// including unknown commands.
+pub fn parse(input: &str) -> Option<Command> {
+    let input = input.strip_prefix('/')?;
+    let (name, args) = match input.split_once(char::is_whitespace) {
+        Some((name, args)) => (name, args.trim()),
+        None => (input, ""),
+    };
+
+    let transform = match name {
+        "color" => {
+            let (color, text) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+            Transform::color(color).map(|v| (v, text.trim()))
+        }
+
+        _ => Transform::named(name).map(|v| (v, args)),
+    };
+    if let Some((transform, args)) = transform {
+        let (mut transforms, text) = match parse(args) {
+            Some(Command::Transform(transforms, text)) => (transforms, text),
+            _ => (vec![], args.to_string()),
+        };
+        transforms.insert(0, transform);
+
+        return if text.is_empty() && !transforms.iter().all(Transform::is_prefix) {
+            None
+        } else {
+            Some(Command::Transform(transforms, text))
+        };
+    }
+
+    match name {
+        "quit" if args.is_empty() => Some(Command::Quit),
+        "code" => Some(Command::Code(Some(args).filter(|v| !v.is_empty()).map(String::from))),
+        "video" if !args.is_empty() => Some(Command::Video(args.to_string())),
+        "image" if !args.is_empty() => Some(Command::Image(args.to_string())),
+        "upload" => Some(Command::Upload(args.to_string())),
+        "verify" => Some(Command::Verify(args.to_string())),
+        "members" if args.is_empty() => Some(Command::Members),
+        "export-keys" if !args.is_empty() => Some(Command::ExportKeys(args.to_string())),
+        "import-keys" if !args.is_empty() => Some(Command::ImportKeys(args.to_string())),
+        "import-history" if !args.is_empty() => Some(Command::ImportHistory(args.to_string())),
+        "invite-file" if !args.is_empty() => Some(Command::InviteFile(args.to_string())),
+        "date" if !args.is_empty() => Some(Command::Date(args.to_string())),
+        "policy" => match args.split_once(char::is_whitespace).unwrap_or((args, "")) {
+            ("", _) => Some(Command::Policy),
+            ("ban", rest) if !rest.trim().is_empty() => {
+                let (entity, reason) = rest.trim().split_once(char::is_whitespace).unwrap_or((rest.trim(), ""));
+                Some(Command::PolicyBan(entity.to_string(), reason.trim().to_string()))
+            }
+            ("subscribe", "") => Some(Command::PolicySubscribe(true)),
+            ("unsubscribe", "") => Some(Command::PolicySubscribe(false)),
+            _ => None,
+        },
+        "security" if args.is_empty() => Some(Command::Security),
+        "server" if args.is_empty() => Some(Command::Server),
+        "stats" if args.is_empty() => Some(Command::Stats),
+        "leave" if args.is_empty() => Some(Command::Leave),
+        "room" if args.is_empty() => Some(Command::Room),
+        "encrypt" if args.is_empty() => Some(Command::Encrypt),
+        "publish" if args.is_empty() => Some(Command::Publish(true)),
+        "unpublish" if args.is_empty() => Some(Command::Publish(false)),
+        "private" if args.is_empty() => Some(Command::Private),
+        "dnd" => Some(Command::Dnd(args.to_string())),
+        "previews" if args.is_empty() => Some(Command::Previews),
+        "outbox" if args.is_empty() => Some(Command::Outbox),
+        "whois" if !args.is_empty() => match UserId::parse(args) {
+            Ok(user_id) => Some(Command::Whois(user_id)),
+            Err(_) => Some(Command::UserSearch(args.to_string())),
+        },
+        "search" => Some(Command::Search(args.to_string())),
+        "usersearch" if !args.is_empty() => Some(Command::UserSearch(args.to_string())),
+        "widgets" if args.is_empty() => Some(Command::Widgets),
+        "translate" => match args {
+            "" => Some(Command::Translate),
+            "auto" => Some(Command::AutoTranslate),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The input with the alias it starts with replaced by what it stands for. `$*` in the alias is
+/// replaced by the arguments, which are otherwise added to the end. `None` if it isn't an alias.
+pub fn expand_alias(input: &str, aliases: &HashMap<String, String>) -> Option<String> {
+    let input = input.strip_prefix('/')?;
+    let (name, args) = match input.split_once(char::is_whitespace) {
+        Some((name, args)) => (name, args.trim()),
+        None => (input, ""),
+    };
+    let alias = aliases.get(name)?;
+    let alias = if alias.starts_with('/') { alias.clone() } else { format!("/{}", alias) };
+    if alias.contains("$*") {
+        Some(alias.replace("$*", args))
+    } else if args.is_empty() {
+        Some(alias)
+    } else {
+        Some(format!("{} {}", alias, args))
+    }
+}
@@ -0,0 +1,389 @@
+use matrix_sdk::ruma::{
+    events::room::message::{Relation, Replacement, RoomMessageEventContent},
+    OwnedEventId,
+};
+use regex::Regex;
+
+use crate::{config::ComposerSettings, html};
+
+/// The largest event servers accept, in bytes.
+const MAX_EVENT_BYTES: usize = 65_536;
+/// Room left for the event's other fields, like its sender and signatures.
+const EVENT_OVERHEAD: usize = 2_048;
+
+const SHORTCODES: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("100", "💯"),
+    ("angry", "😠"),
+    ("blush", "😊"),
+    ("broken_heart", "💔"),
+    ("clap", "👏"),
+    ("confused", "😕"),
+    ("cry", "😢"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("grin", "😁"),
+    ("grinning", "😀"),
+    ("heart", "❤️"),
+    ("heart_eyes", "😍"),
+    ("hugs", "🤗"),
+    ("joy", "😂"),
+    ("kiss", "😘"),
+    ("laughing", "😆"),
+    ("neutral_face", "😐"),
+    ("ok_hand", "👌"),
+    ("party", "🥳"),
+    ("pensive", "😔"),
+    ("pray", "🙏"),
+    ("rage", "😡"),
+    ("relieved", "😌"),
+    ("rocket", "🚀"),
+    ("rofl", "🤣"),
+    ("scream", "😱"),
+    ("shrug", "🤷"),
+    ("skull", "💀"),
+    ("sleeping", "😴"),
+    ("slightly_smiling_face", "🙂"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("smirk", "😏"),
+    ("sob", "😭"),
+    ("sparkles", "✨"),
+    ("star", "⭐"),
+    ("sunglasses", "😎"),
+    ("sweat_smile", "😅"),
+    ("tada", "🎉"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("tired_face", "😫"),
+    ("upside_down_face", "🙃"),
+    ("wave", "👋"),
+    ("wink", "😉"),
+    ("x", "❌"),
+    ("white_check_mark", "✅"),
+    ("yum", "😋"),
+    ("zany_face", "🤪"),
+];
+
+/// Builds the event content for a composed message according to the room's settings.
+pub fn message_content(text: &str, settings: &ComposerSettings) -> RoomMessageEventContent {
+    if settings.plaintext {
+        return RoomMessageEventContent::text_plain(text);
+    }
+
+    let text = if settings.emoji { replace_shortcodes(text) } else { text.to_string() };
+    if settings.markdown {
+        RoomMessageEventContent::text_markdown(text)
+    } else {
+        RoomMessageEventContent::text_plain(text)
+    }
+}
+
+/// The shortcode for an emoji, without the colons.
+pub fn shortcode(emoji: &str) -> Option<&'static str> {
+    // reactions often carry a variation selector the table doesn't
+    let emoji = emoji.trim_end_matches('\u{fe0f}');
+    SHORTCODES.iter().find(|(_, v)| v.trim_end_matches('\u{fe0f}') == emoji).map(|(code, _)| *code)
+}
+
+/// The shortcodes containing `query` with their emoji, the ones starting with it first.
+pub fn search_shortcodes(query: &str) -> Vec<(&'static str, &'static str)> {
+    let query = query.trim_matches(':').to_lowercase();
+    let mut found: Vec<_> = SHORTCODES.iter().filter(|(code, _)| code.contains(query.as_str())).copied().collect();
+    found.sort_by_key(|(code, _)| !code.starts_with(query.as_str()));
+    found
+}
+
+/// Replaces known `:shortcode:`s with their emoji, leaving anything inside backticks alone.
+fn replace_shortcodes(text: &str) -> String {
+    let mut result = String::new();
+    for (i, part) in text.split('`').enumerate() {
+        if i != 0 {
+            result.push('`');
+        }
+
+        if i % 2 == 1 {
+            result.push_str(part);
+            continue;
+        }
+
+        let mut rest = part;
+        while let Some(start) = rest.find(':') {
+            let after = &rest[start + 1..];
+            let end = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '+' || c == '-'));
+            match end.filter(|&end| after[end..].starts_with(':')).and_then(|end| SHORTCODES.iter().find(|(code, _)| *code == &after[..end]).map(|v| (end, v.1))) {
+                Some((end, emoji)) => {
+                    result.push_str(&rest[..start]);
+                    result.push_str(emoji);
+                    rest = &after[end + 1..];
+                }
+
+                None => {
+                    result.push_str(&rest[..=start]);
+                    rest = after;
+                }
+            }
+        }
+        result.push_str(rest);
+    }
+    result
+}
+
+/// Builds a code block message, sent literally regardless of the composer settings.
+pub fn code_block(code: &str, language: Option<&str>) -> RoomMessageEventContent {
+    let body = format!("```{}\n{}\n```", language.unwrap_or(""), code);
+    let html = match language {
+        Some(language) => format!("<pre><code class=\"language-{}\">{}</code></pre>", escape_html(language), escape_html(code)),
+        None => format!("<pre><code>{}</code></pre>", escape_html(code)),
+    };
+    RoomMessageEventContent::text_html(body, html)
+}
+
+/// A text transform, as in Element's `/shrug` and friends.
+#[derive(Clone, Copy)]
+pub enum Transform {
+    Shrug,
+    TableFlip,
+    Unflip,
+    Rainbow,
+    Spoiler,
+    Color(u8, u8, u8),
+}
+
+impl Transform {
+    pub fn named(name: &str) -> Option<Transform> {
+        match name {
+            "shrug" => Some(Transform::Shrug),
+            "tableflip" => Some(Transform::TableFlip),
+            "unflip" => Some(Transform::Unflip),
+            "rainbow" => Some(Transform::Rainbow),
+            "spoiler" => Some(Transform::Spoiler),
+            _ => None,
+        }
+    }
+
+    /// `/color`, which takes the colour as its first argument, like `#ff0000` or `#f00`.
+    pub fn color(color: &str) -> Option<Transform> {
+        html::parse_color(color).map(|(r, g, b)| Transform::Color(r, g, b))
+    }
+
+    /// Whether the transform is just some text in front of the message, which makes sense alone.
+    pub fn is_prefix(&self) -> bool {
+        matches!(self, Transform::Shrug | Transform::TableFlip | Transform::Unflip)
+    }
+}
+
+/// Builds a message with transforms applied innermost first, so `/rainbow /shrug hi` colours the shrug too.
+/// Messages that only gain a prefix are composed as usual.
+pub fn transformed(text: &str, transforms: &[Transform], settings: &ComposerSettings) -> RoomMessageEventContent {
+    // prefixes that end up parsed as markdown need escaping to survive it
+    let markdown = settings.markdown && !settings.plaintext && transforms.iter().all(Transform::is_prefix);
+    let mut body = text.to_string();
+    let mut html: Option<String> = None;
+    for transform in transforms.iter().rev() {
+        let prefix = match transform {
+            Transform::Shrug => "¯\\_(ツ)_/¯",
+            Transform::TableFlip => "(╯°□°）╯︵ ┻━┻",
+            Transform::Unflip => "┬──┬ ノ( ゜-゜ノ)",
+
+            Transform::Rainbow => {
+                html = Some(rainbow(&body));
+                continue;
+            }
+
+            Transform::Spoiler => {
+                html = Some(format!("<span data-mx-spoiler>{}</span>", html.unwrap_or_else(|| escape_html(&body))));
+                continue;
+            }
+
+            Transform::Color(r, g, b) => {
+                html = Some(format!("<span data-mx-color=\"#{:02x}{:02x}{:02x}\">{}</span>", r, g, b, html.unwrap_or_else(|| escape_html(&body))));
+                continue;
+            }
+        };
+
+        let separator = if body.is_empty() { "" } else { " " };
+        let escaped = if markdown { escape_markdown(prefix) } else { prefix.to_string() };
+        body = format!("{}{}{}", escaped, separator, body);
+        html = html.map(|v| format!("{}{}{}", escape_html(prefix), separator, v));
+    }
+
+    match html {
+        Some(html) => RoomMessageEventContent::text_html(body, html),
+        None => message_content(&body, settings),
+    }
+}
+
+/// Colours each character along the hue wheel, like Element's `/rainbow`.
+fn rainbow(text: &str) -> String {
+    let count = text.chars().count().max(1);
+    let mut html = String::new();
+    for (i, c) in text.chars().enumerate() {
+        if c.is_whitespace() {
+            html.push(c);
+            continue;
+        }
+
+        let (r, g, b) = hue_to_rgb(i as f64 / count as f64 * 360.0);
+        html.push_str(&format!("<font color=\"#{:02x}{:02x}{:02x}\">{}</font>", r, g, b, escape_html(&c.to_string())));
+    }
+    html
+}
+
+/// The fully saturated colour at a hue, in degrees.
+fn hue_to_rgb(hue: f64) -> (u8, u8, u8) {
+    let x = 1.0 - ((hue / 60.0) % 2.0 - 1.0).abs();
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    ((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8)
+}
+
+/// Wraps the word under the cursor in backticks, returning the new text and cursor byte position.
+pub fn wrap_inline_code(text: &str, cursor: usize) -> (String, usize) {
+    let start = text[..cursor].rfind(char::is_whitespace).map(|v| v + text[v..].chars().next().unwrap().len_utf8()).unwrap_or(0);
+    let end = text[cursor..].find(char::is_whitespace).map(|v| v + cursor).unwrap_or(text.len());
+    let wrapped = format!("{}`{}`{}", &text[..start], &text[start..end], &text[end..]);
+    (wrapped, cursor + 1)
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if "\\`*_~[]<>#".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Whether a message is too big for the server to accept. Encrypted content grows by a third once
+/// it's base64 encoded.
+pub fn too_large(content: &RoomMessageEventContent, encrypted: bool) -> bool {
+    let mut size = serde_json::to_vec(content).map(|v| v.len()).unwrap_or(0);
+    if encrypted {
+        size = size * 4 / 3;
+    }
+    size + EVENT_OVERHEAD > MAX_EVENT_BYTES
+}
+
+/// Splits text into pieces of at most `max_bytes`, between lines where it can.
+pub fn split(text: &str, max_bytes: usize) -> Vec<String> {
+    let mut pieces = vec![];
+    let mut piece = String::new();
+    for line in text.split_inclusive('\n') {
+        if piece.len() + line.len() > max_bytes && !piece.is_empty() {
+            pieces.push(std::mem::take(&mut piece));
+        }
+
+        for c in line.chars() {
+            if piece.len() + c.len_utf8() > max_bytes {
+                pieces.push(std::mem::take(&mut piece));
+            }
+            piece.push(c);
+        }
+    }
+    if !piece.is_empty() {
+        pieces.push(piece);
+    }
+    // the newlines a piece ends on would only be trimmed by the receiving clients
+    pieces.into_iter().map(|v| v.trim_end_matches('\n').to_string()).filter(|v| !v.is_empty()).collect()
+}
+
+/// An IRC style `s/old/new/` correction of our last message. `g` replaces every match and `i`
+/// ignores case.
+pub struct Sed {
+    pattern: Regex,
+    replacement: String,
+    global: bool,
+}
+
+impl Sed {
+    /// Reads `s/pattern/replacement/flags`, where `\/` is a literal slash and the last slash is
+    /// optional. Patterns that aren't valid regexes are matched literally.
+    pub fn parse(text: &str) -> Option<Sed> {
+        let mut parts = vec![String::new()];
+        let mut chars = text.strip_prefix("s/")?.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('/') => parts.last_mut().unwrap().push('/'),
+                    Some(c) => {
+                        parts.last_mut().unwrap().push('\\');
+                        parts.last_mut().unwrap().push(c);
+                    }
+                    None => parts.last_mut().unwrap().push('\\'),
+                },
+                '/' => parts.push(String::new()),
+                c => parts.last_mut().unwrap().push(c),
+            }
+        }
+
+        let (pattern, replacement, flags) = match parts.as_slice() {
+            [pattern, replacement] => (pattern, replacement, ""),
+            [pattern, replacement, flags] if flags.chars().all(|c| c == 'g' || c == 'i') => (pattern, replacement, flags.as_str()),
+            _ => return None,
+        };
+        if pattern.is_empty() {
+            return None;
+        }
+
+        let case = if flags.contains('i') { "(?i)" } else { "" };
+        let pattern = Regex::new(&format!("{}{}", case, pattern)).or_else(|_| Regex::new(&format!("{}{}", case, regex::escape(pattern)))).ok()?;
+        Some(Sed {
+            pattern,
+            replacement: sed_replacement(replacement),
+            global: flags.contains('g'),
+        })
+    }
+
+    /// The corrected text, or `None` if the pattern isn't found.
+    pub fn apply(&self, text: &str) -> Option<String> {
+        if !self.pattern.is_match(text) {
+            return None;
+        }
+        Some(if self.global { self.pattern.replace_all(text, self.replacement.as_str()) } else { self.pattern.replace(text, self.replacement.as_str()) }.into_owned())
+    }
+}
+
+/// Turns sed's `\1` and `&` into the regex crate's `${1}` and `${0}`, keeping other `$`s literal.
+fn sed_replacement(text: &str) -> String {
+    let mut replacement = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c) if c.is_ascii_digit() => replacement.push_str(&format!("${{{}}}", c)),
+                Some('&') => replacement.push('&'),
+                Some(c) => {
+                    replacement.push('\\');
+                    replacement.push(c);
+                }
+                None => replacement.push('\\'),
+            },
+            '&' => replacement.push_str("${0}"),
+            '$' => replacement.push_str("$$"),
+            c => replacement.push(c),
+        }
+    }
+    replacement
+}
+
+/// An edit replacing a message with new content, with the `* ` fallback older clients show.
+pub fn replacement(event_id: OwnedEventId, content: RoomMessageEventContent) -> RoomMessageEventContent {
+    let mut edit = RoomMessageEventContent::text_plain(format!("* {}", content.body()));
+    edit.relates_to = Some(Relation::Replacement(Replacement::new(event_id, Box::new(content))));
+    edit
+}
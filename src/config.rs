@@ -0,0 +1,511 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::{
+    clock::Hours,
+    cursor::{CursorStyle, Shape},
+    graphics::Protocol,
+    platform,
+    symbols::Profile,
+    theme::ThemeName,
+};
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// How each message row is laid out. See `template.rs` for the available fields.
+    pub message_template: String,
+    /// The one line layout of messages in rooms marked `irc`.
+    pub irc_template: String,
+    pub composer: ComposerSettings,
+    /// Where attachments are downloaded to.
+    pub downloads_dir: String,
+    /// The program used to play videos.
+    pub video_player: String,
+    /// Open attachments once they're downloaded, videos with `video_player`. Off by default, since
+    /// anyone in a room can send a file.
+    pub open_downloads: bool,
+    /// Whether to draw with unicode or plain ASCII.
+    pub symbols: Profile,
+    /// The room opened on startup, by id or alias, in place of the one open when last closed.
+    pub default_room: Option<String>,
+    pub time: TimeSettings,
+    pub uploads: UploadSettings,
+    pub encryption: EncryptionSettings,
+    pub notifications: NotificationSettings,
+    pub cursor: CursorSettings,
+    pub colors: ColorSettings,
+    pub accessibility: AccessibilitySettings,
+    pub sidebar: SidebarSettings,
+    pub moderation: ModerationSettings,
+    pub reactions: ReactionSettings,
+    pub filters: FilterSettings,
+    pub highlights: HighlightSettings,
+    /// Commands that stand for others, like `j = "/join"`, keyed by name without the `/`.
+    pub aliases: HashMap<String, String>,
+    pub away: AwaySettings,
+    pub privacy: PrivacySettings,
+    pub translate: TranslateSettings,
+    pub spellcheck: SpellcheckSettings,
+    pub webhooks: Vec<WebhookConfig>,
+    pub workspaces: Vec<WorkspaceConfig>,
+    pub calls: CallSettings,
+    pub previews: PreviewSettings,
+    pub images: ImageSettings,
+    pub updates: UpdateSettings,
+    /// Per-room overrides, keyed by room id.
+    pub rooms: HashMap<String, RoomConfig>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ComposerSettings {
+    /// Parse outgoing messages as markdown.
+    pub markdown: bool,
+    /// Replace `:shortcode:`s with emoji.
+    pub emoji: bool,
+    /// Send exactly what was typed, ignoring the other settings.
+    pub plaintext: bool,
+    /// Pastes with more lines than this ask whether to send them as a code block or file instead.
+    pub paste_confirm_lines: usize,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct RoomConfig {
+    pub markdown: Option<bool>,
+    pub emoji: Option<bool>,
+    pub plaintext: Option<bool>,
+    /// Words, or `/regexes/`, that highlight messages and count as mentions.
+    pub highlights: Vec<String>,
+    /// Messages starting with any of these are hidden, like commands to bots.
+    pub hide_prefixes: Vec<String>,
+    /// Overrides `privacy.private` for this room.
+    pub private: Option<bool>,
+    /// Overrides `previews.enabled` for this room.
+    pub previews: Option<bool>,
+    /// Overrides `notifications.bell` for this room.
+    pub bell: Option<bool>,
+    /// Translate new messages from others as they arrive.
+    pub auto_translate: bool,
+    /// The language written here, like `de_DE`, shown on the input box and used to spellcheck.
+    pub language: Option<String>,
+    /// A command outgoing messages are piped through, like `["uconv", "-x", "Latin-Cyrillic"]`.
+    pub transliterate: Vec<String>,
+    /// Bridged from IRC: lay messages out with `irc_template` and show mIRC colour codes.
+    pub irc: bool,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct UploadSettings {
+    /// Images over this many bytes are offered to be downscaled before uploading.
+    pub downscale_images_over: u64,
+    /// The largest width or height a downscaled image is given.
+    pub max_image_dimension: u32,
+    /// Size limits in bytes, keyed by mime type (`image/png`) or top level type (`video`).
+    pub limits: HashMap<String, u64>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct EncryptionSettings {
+    pub unverified_devices: DevicePolicy,
+}
+
+/// Whether encrypted messages are sent to devices nobody has verified.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DevicePolicy {
+    /// Send to every device that hasn't been blocked.
+    #[default]
+    Always,
+    /// Accept a user's devices the first time we see them, and ask about any they add later.
+    Tofu,
+    /// Ask about every unverified device before sending.
+    Block,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct NotificationSettings {
+    pub backend: NotificationBackend,
+    /// The program run by the `notify-send` backend.
+    pub command: String,
+    /// When to hold notifications back every day, like `22:00-08:00`, or empty for never.
+    pub quiet_hours: String,
+    /// Ring the terminal bell on mentions while it's unfocused.
+    pub bell: bool,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotificationBackend {
+    /// Desktop notifications through D-Bus, or Notification Center on macOS.
+    #[default]
+    NotifyRust,
+    /// Runs a libnotify style command.
+    NotifySend,
+    /// Asks the terminal to show the notification, which works over SSH.
+    Osc777,
+    None,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        NotificationSettings {
+            backend: NotificationBackend::default(),
+            command: String::from("notify-send"),
+            quiet_hours: String::new(),
+            bell: false,
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct TranslateSettings {
+    /// The program and its arguments, which get the message on stdin and print the translation.
+    pub command: Vec<String>,
+}
+
+/// Where new messages matching a pattern are POSTed.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Room ids, or empty for every room.
+    pub rooms: Vec<String>,
+    /// A regex the body must match, or empty for every message.
+    pub pattern: String,
+}
+
+/// A named group of rooms, which `W` narrows the room list to.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct WorkspaceConfig {
+    pub name: String,
+    /// Room ids.
+    pub rooms: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct SpellcheckSettings {
+    /// A checker that reads text and prints the words it doesn't know, with `{language}` replaced
+    /// by the room's language.
+    pub command: Vec<String>,
+    /// The language of rooms that don't set one.
+    pub language: String,
+}
+
+impl Default for SpellcheckSettings {
+    fn default() -> Self {
+        SpellcheckSettings {
+            command: vec![],
+            language: String::from("en_US"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct CallSettings {
+    /// Where to join a room's call when it has no call widget, with `$matrix_room_id` and
+    /// `$room_slug` filled in, or empty to only use widgets.
+    pub url: String,
+}
+
+impl Default for CallSettings {
+    fn default() -> Self {
+        CallSettings {
+            url: String::from("https://meet.jit.si/ilo-toki-$room_slug"),
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct PreviewSettings {
+    /// Show previews of links, which the homeserver fetches and so learns about.
+    pub enabled: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ImageSettings {
+    /// The graphics protocol pictures are drawn with, or `none` to only name them.
+    pub protocol: Protocol,
+    /// How many lines tall pictures are.
+    pub rows: u16,
+}
+
+impl Default for ImageSettings {
+    fn default() -> Self {
+        ImageSettings {
+            protocol: Protocol::Auto,
+            rows: 8,
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct UpdateSettings {
+    /// Ask GitHub on startup whether there's a newer release. Off unless turned on, since GitHub sees
+    /// the address asking and which version is running.
+    pub check: bool,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct PrivacySettings {
+    /// Don't tell others what we've read or that we're typing.
+    pub private: bool,
+}
+
+/// Going away automatically when ilo-toki isn't being used.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct AwaySettings {
+    pub enabled: bool,
+    /// Minutes without a key press before going away, or 0 for never.
+    pub idle_minutes: u64,
+    /// Minutes with the terminal unfocused before going away, or 0 for never.
+    pub unfocused_minutes: u64,
+    /// The status message set while away.
+    pub message: String,
+}
+
+impl Default for AwaySettings {
+    fn default() -> Self {
+        AwaySettings {
+            enabled: true,
+            idle_minutes: 15,
+            unfocused_minutes: 5,
+            message: String::from("away"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ColorSettings {
+    pub theme: ThemeName,
+    /// Give each user's nick its own colour.
+    pub nick_colors: bool,
+    /// Messages older than this many minutes are drawn dimmer, or 0 to never fade them.
+    pub fade_after_minutes: u64,
+}
+
+impl Default for ColorSettings {
+    fn default() -> Self {
+        ColorSettings {
+            theme: ThemeName::default(),
+            nick_colors: true,
+            fade_after_minutes: 0,
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct AccessibilitySettings {
+    /// Drop the borders, keep the cursor in the input box, and announce what happens as plain lines.
+    pub screen_reader: bool,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct TimeSettings {
+    pub hours: Hours,
+    /// A strftime pattern used in place of the clock's, like `%H.%M`.
+    pub time_format: Option<String>,
+    pub date_format: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct SidebarSettings {
+    /// Show the latest message of each room under its name.
+    pub previews: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ModerationSettings {
+    /// The ids of the policy list rooms to follow.
+    pub policy_rooms: Vec<String>,
+    /// Hide messages from users and servers banned by the lists followed.
+    pub hide_banned: bool,
+}
+
+impl Default for ModerationSettings {
+    fn default() -> Self {
+        ModerationSettings {
+            policy_rooms: vec![],
+            hide_banned: true,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ReactionSettings {
+    /// The emoji offered first when reacting with `+`, picked with 1 through 0.
+    pub favorites: Vec<String>,
+}
+
+impl Default for ReactionSettings {
+    fn default() -> Self {
+        ReactionSettings {
+            favorites: ["👍", "❤️", "😂", "🎉", "👀", "🙏", "😮", "😢", "🔥", "✅"].iter().map(|v| v.to_string()).collect(),
+        }
+    }
+}
+
+/// Messages hidden in every room, until `F` reveals them.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct FilterSettings {
+    /// Regexes matched against the content.
+    pub patterns: Vec<String>,
+    /// User ids.
+    pub senders: Vec<String>,
+}
+
+/// Words, or `/regexes/`, that highlight messages in every room, on top of each room's own.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct HighlightSettings {
+    pub words: Vec<String>,
+}
+
+/// The cursor in each mode.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct CursorSettings {
+    pub insert: CursorStyle,
+    pub normal: CursorStyle,
+    pub select: CursorStyle,
+    pub scroll: CursorStyle,
+}
+
+impl Default for CursorSettings {
+    fn default() -> Self {
+        CursorSettings {
+            insert: CursorStyle::new(Shape::Bar),
+            normal: CursorStyle::new(Shape::Block),
+            select: CursorStyle::new(Shape::Hidden),
+            scroll: CursorStyle::new(Shape::Hidden),
+        }
+    }
+}
+
+impl Default for UploadSettings {
+    fn default() -> Self {
+        UploadSettings {
+            downscale_images_over: 2_000_000,
+            max_image_dimension: 2048,
+            limits: HashMap::new(),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            message_template: String::from("{user}{edited}{imported}{encryption}\n{content}"),
+            irc_template: String::from("{time} <{nick}>{edited}{encryption} {content}"),
+            composer: ComposerSettings::default(),
+            downloads_dir: platform::home_dir().map(|v| format!("{}/Downloads", v)).unwrap_or_else(|| String::from(".")),
+            video_player: String::from(platform::default_opener()),
+            open_downloads: false,
+            symbols: Profile::default(),
+            default_room: None,
+            time: TimeSettings::default(),
+            uploads: UploadSettings::default(),
+            encryption: EncryptionSettings::default(),
+            notifications: NotificationSettings::default(),
+            cursor: CursorSettings::default(),
+            colors: ColorSettings::default(),
+            accessibility: AccessibilitySettings::default(),
+            sidebar: SidebarSettings::default(),
+            moderation: ModerationSettings::default(),
+            reactions: ReactionSettings::default(),
+            filters: FilterSettings::default(),
+            highlights: HighlightSettings::default(),
+            aliases: HashMap::new(),
+            away: AwaySettings::default(),
+            privacy: PrivacySettings::default(),
+            translate: TranslateSettings::default(),
+            spellcheck: SpellcheckSettings::default(),
+            webhooks: vec![],
+            workspaces: vec![],
+            calls: CallSettings::default(),
+            previews: PreviewSettings::default(),
+            images: ImageSettings::default(),
+            updates: UpdateSettings::default(),
+            rooms: HashMap::new(),
+        }
+    }
+}
+
+impl Default for ComposerSettings {
+    fn default() -> Self {
+        ComposerSettings {
+            markdown: true,
+            emoji: true,
+            plaintext: false,
+            paste_confirm_lines: 5,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config, or the defaults when there isn't one. A config that doesn't parse is an
+    /// error rather than ignored, so a typo isn't mistaken for a setting that does nothing.
+    pub fn load(path: &str) -> Result<Config, toml::de::Error> {
+        match std::fs::read_to_string(path) {
+            Ok(v) => toml::from_str(&v),
+            Err(_) => Ok(Config::default()),
+        }
+    }
+
+    /// The composer settings for a room, with its overrides applied.
+    pub fn composer(&self, room_id: &str) -> ComposerSettings {
+        let mut settings = self.composer.clone();
+        if let Some(room) = self.rooms.get(room_id) {
+            settings.markdown = room.markdown.unwrap_or(settings.markdown);
+            settings.emoji = room.emoji.unwrap_or(settings.emoji);
+            settings.plaintext = room.plaintext.unwrap_or(settings.plaintext);
+        }
+        settings
+    }
+
+    /// The language to spellcheck a room in.
+    pub fn language(&self, room_id: &str) -> &str {
+        self.rooms.get(room_id).and_then(|v| v.language.as_deref()).unwrap_or(&self.spellcheck.language)
+    }
+
+    /// Whether read receipts and typing notifications are kept from a room.
+    pub fn private(&self, room_id: &str) -> bool {
+        self.rooms.get(room_id).and_then(|v| v.private).unwrap_or(self.privacy.private)
+    }
+
+    /// Whether mentions in a room ring the terminal bell.
+    pub fn bell(&self, room_id: &str) -> bool {
+        self.rooms.get(room_id).and_then(|v| v.bell).unwrap_or(self.notifications.bell)
+    }
+
+    /// Whether links in a room's messages get previews.
+    pub fn previews(&self, room_id: &str) -> bool {
+        self.rooms.get(room_id).and_then(|v| v.previews).unwrap_or(self.previews.enabled)
+    }
+
+    /// Whether a room is bridged from IRC and shown like it.
+    pub fn irc(&self, room_id: &str) -> bool {
+        self.rooms.get(room_id).map(|v| v.irc).unwrap_or(false)
+    }
+}
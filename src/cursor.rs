@@ -0,0 +1,59 @@
+//! Cursor shapes and colours for each mode, written through the terminal backend.
+
+use std::io::{self, Write};
+
+use crossterm::cursor::{CursorShape, SetCursorShape};
+use serde::Deserialize;
+
+use crate::platform;
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Shape {
+    Block,
+    Bar,
+    Underline,
+    /// No cursor at all.
+    Hidden,
+}
+
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+pub struct CursorStyle {
+    pub shape: Shape,
+    /// Any colour the terminal understands, like `#ff00ff` or `magenta`. Unset leaves the terminal's own.
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+impl CursorStyle {
+    pub fn new(shape: Shape) -> CursorStyle {
+        CursorStyle { shape, color: None }
+    }
+
+    pub fn visible(&self) -> bool {
+        self.shape != Shape::Hidden
+    }
+}
+
+/// Switches the terminal's cursor to a style. Hidden cursors are left to the caller not to place.
+pub fn apply(out: &mut impl Write, style: &CursorStyle) -> io::Result<()> {
+    match style.shape {
+        Shape::Block => crossterm::queue!(out, SetCursorShape(CursorShape::Block))?,
+        Shape::Bar => crossterm::queue!(out, SetCursorShape(CursorShape::Line))?,
+        Shape::Underline => crossterm::queue!(out, SetCursorShape(CursorShape::UnderScore))?,
+        Shape::Hidden => (),
+    }
+
+    // OSC 12 sets the cursor colour and OSC 112 puts the terminal's back
+    match style.color.as_ref() {
+        _ if !platform::supports_osc() => (),
+        Some(color) => write!(out, "\x1b]12;{}\x07", color)?,
+        None => write!(out, "\x1b]112\x07")?,
+    }
+    out.flush()
+}
+
+/// Puts the cursor back how the terminal had it, as near as we can tell.
+pub fn reset(out: &mut impl Write) -> io::Result<()> {
+    apply(out, &CursorStyle::new(Shape::Block))
+}
@@ -0,0 +1,43 @@
+//! The fields of a message's content beyond its body, like an attachment's filename and size or
+//! keys a bridge added, which `x` lists under the selected message.
+
+use serde::Deserialize;
+use serde_json::{value::RawValue, Map, Value};
+
+/// Keys shown as the message itself, so they'd only repeat it.
+const SHOWN: [&str; 3] = ["body", "format", "formatted_body"];
+
+#[derive(Deserialize)]
+struct Event {
+    #[serde(default)]
+    content: Map<String, Value>,
+}
+
+/// Every other field of the event's content by key, with nested objects flattened into `dotted.keys`.
+pub fn fields(event: &RawValue) -> Vec<(String, String)> {
+    let content = match serde_json::from_str::<Event>(event.get()) {
+        Ok(v) => v.content,
+        Err(_) => return vec![],
+    };
+
+    let mut fields = vec![];
+    for (key, value) in content.iter().filter(|(k, _)| !SHOWN.contains(&k.as_str())) {
+        flatten(key.clone(), value, &mut fields);
+    }
+    // the order they were sent in isn't kept, so sort them to always read the same
+    fields.sort();
+    fields
+}
+
+fn flatten(key: String, value: &Value, fields: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (k, v) in map.iter() {
+                flatten(format!("{}.{}", key, k), v, fields);
+            }
+        }
+
+        Value::String(v) => fields.push((key, v.clone())),
+        v => fields.push((key, v.to_string())),
+    }
+}
@@ -0,0 +1,100 @@
+//! Do not disturb: `/dnd` and the configured quiet hours silence notifications, while mentions are
+//! still counted in the room list.
+
+use chrono::{DateTime, Duration, Local, NaiveTime};
+
+use crate::config::NotificationSettings;
+
+enum Until {
+    /// Until `/dnd` is used again.
+    Off,
+    Time(DateTime<Local>),
+}
+
+pub struct DoNotDisturb {
+    until: Option<Until>,
+    /// When the quiet hours start and end, which can be across midnight.
+    quiet_hours: Option<(NaiveTime, NaiveTime)>,
+}
+
+impl DoNotDisturb {
+    /// Quiet hours that aren't two `HH:MM` times are ignored.
+    pub fn new(settings: &NotificationSettings) -> DoNotDisturb {
+        let time = |v: &str| NaiveTime::parse_from_str(v.trim(), "%H:%M").ok();
+        DoNotDisturb {
+            until: None,
+            quiet_hours: settings.quiet_hours.split_once('-').and_then(|(start, end)| Some((time(start)?, time(end)?))),
+        }
+    }
+
+    /// Turns do not disturb on for a while, or until it's turned off.
+    pub fn start(&mut self, duration: Option<Duration>, now: DateTime<Local>) {
+        self.until = Some(match duration {
+            Some(duration) => Until::Time(now + duration),
+            None => Until::Off,
+        });
+    }
+
+    /// Turns do not disturb off, returning whether it was on. Quiet hours still apply.
+    pub fn stop(&mut self, now: DateTime<Local>) -> bool {
+        let on = self.manual(now);
+        self.until = None;
+        on
+    }
+
+    fn manual(&self, now: DateTime<Local>) -> bool {
+        match self.until {
+            Some(Until::Off) => true,
+            Some(Until::Time(until)) => now < until,
+            None => false,
+        }
+    }
+
+    fn quiet(&self, now: DateTime<Local>) -> bool {
+        match self.quiet_hours {
+            Some((start, end)) if start <= end => start <= now.time() && now.time() < end,
+            Some((start, end)) => start <= now.time() || now.time() < end,
+            None => false,
+        }
+    }
+
+    /// Whether notifications should be held back.
+    pub fn active(&self, now: DateTime<Local>) -> bool {
+        self.manual(now) || self.quiet(now)
+    }
+
+    /// What the status bar says while do not disturb is on.
+    pub fn status(&self, now: DateTime<Local>) -> Option<String> {
+        match self.until {
+            Some(Until::Off) => Some(String::from("dnd")),
+            Some(Until::Time(until)) if now < until => Some(format!("dnd until {}", until.format("%H:%M"))),
+            _ if self.quiet(now) => Some(format!("quiet hours until {}", self.quiet_hours.unwrap().1.format("%H:%M"))),
+            _ => None,
+        }
+    }
+}
+
+/// A duration like `90`, `45m`, `2h`, `1h30m`, or `1d`. Numbers without a unit are minutes.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    if let Ok(minutes) = text.parse::<i64>() {
+        return Some(Duration::minutes(minutes)).filter(|_| minutes > 0);
+    }
+
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let n: i64 = std::mem::take(&mut number).parse().ok()?;
+        total += match c {
+            's' => Duration::seconds(n),
+            'm' => Duration::minutes(n),
+            'h' => Duration::hours(n),
+            'd' => Duration::days(n),
+            _ => return None,
+        };
+    }
+    Some(total).filter(|v| number.is_empty() && *v > Duration::zero())
+}
@@ -0,0 +1,25 @@
+//! Reading the JSON files made by Element's "Export chat", so archives of a room can be browsed.
+
+use std::path::Path;
+
+use matrix_sdk::ruma::{events::AnySyncTimelineEvent, serde::Raw, OwnedRoomId};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct Export {
+    pub room_name: String,
+    /// Oldest first, with encrypted events already decrypted.
+    pub messages: Vec<Raw<AnySyncTimelineEvent>>,
+}
+
+impl Export {
+    /// The room the events were exported from, if they say.
+    pub fn room_id(&self) -> Option<OwnedRoomId> {
+        self.messages.iter().find_map(|v| v.get_field("room_id").ok().flatten())
+    }
+}
+
+pub fn read(path: &Path) -> Result<Export, String> {
+    let file = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    serde_json::from_str(&file).map_err(|e| format!("{} isn't an Element JSON export: {}", path.display(), e))
+}
@@ -0,0 +1,40 @@
+//! Local filters, like an IRC killfile: messages matching a pattern, from certain senders, or starting
+//! with a room's bot prefixes are hidden until revealed.
+
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::config::Config;
+
+pub struct Filters {
+    patterns: Vec<Regex>,
+    senders: Vec<String>,
+    /// Keyed by room id.
+    prefixes: HashMap<String, Vec<String>>,
+    /// Whether filtered messages are shown anyway, for a moment.
+    pub revealed: bool,
+}
+
+impl Filters {
+    /// Invalid regexes are skipped.
+    pub fn new(config: &Config) -> Filters {
+        Filters {
+            patterns: config.filters.patterns.iter().filter_map(|v| Regex::new(v).ok()).collect(),
+            senders: config.filters.senders.clone(),
+            prefixes: config.rooms.iter().filter(|(_, v)| !v.hide_prefixes.is_empty()).map(|(id, v)| (id.clone(), v.hide_prefixes.clone())).collect(),
+            revealed: false,
+        }
+    }
+
+    /// Whether a message matches a filter, whether or not filtered messages are being revealed.
+    pub fn matches(&self, room_id: &str, sender: &str, content: &str) -> bool {
+        self.senders.iter().any(|v| v == sender)
+            || self.prefixes.get(room_id).map(|v| v.iter().any(|v| content.starts_with(v.as_str()))).unwrap_or(false)
+            || self.patterns.iter().any(|v| v.is_match(content))
+    }
+
+    pub fn hides(&self, room_id: &str, sender: &str, content: &str) -> bool {
+        !self.revealed && self.matches(room_id, sender, content)
+    }
+}
@@ -0,0 +1,168 @@
+//! Drawing pictures in the terminal with whichever graphics protocol it speaks: kitty's, iTerm2's, or
+//! sixel. Not every terminal answers when asked, and those that don't would leave the question
+//! sitting in the input, so the protocol is worked out from the environment instead.
+
+use std::fmt::Write;
+
+use image::{imageops::FilterType, DynamicImage, ImageOutputFormat, RgbImage};
+use serde::Deserialize;
+
+/// The size of a cell in pixels when the terminal won't say, which is about right for most fonts.
+const CELL: (u32, u32) = (10, 20);
+/// Kitty takes its pictures in pieces of at most this many bytes.
+const KITTY_CHUNK: usize = 4096;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    /// Whatever the terminal is known to support.
+    #[default]
+    Auto,
+    Kitty,
+    Iterm,
+    Sixel,
+    None,
+}
+
+impl Protocol {
+    /// Works out `Auto` from the environment.
+    pub fn detect(self) -> Protocol {
+        match self {
+            Protocol::Auto => from_env(|v| std::env::var(v).ok().filter(|v| !v.is_empty())),
+            v => v,
+        }
+    }
+
+    /// Whether pictures are drawn into the cells under them, so they stay until those cells are
+    /// drawn again. Kitty's sit over the text and are taken away on their own.
+    pub fn in_cells(self) -> bool {
+        matches!(self, Protocol::Iterm | Protocol::Sixel)
+    }
+}
+
+/// The protocol a terminal speaks, from environment variables read by `var`.
+pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Protocol {
+    let term = var("TERM").unwrap_or_default();
+    let program = var("TERM_PROGRAM").unwrap_or_default();
+    // multiplexers don't pass pictures through to the terminal they run in
+    if var("TMUX").is_some() || term.starts_with("screen") || term.starts_with("tmux") {
+        Protocol::None
+    } else if var("KITTY_WINDOW_ID").is_some() || term == "xterm-kitty" || program == "ghostty" {
+        Protocol::Kitty
+    } else if program == "iTerm.app" || program == "WezTerm" || var("LC_TERMINAL").as_deref() == Some("iTerm2") {
+        Protocol::Iterm
+    } else if term.contains("sixel") || ["foot", "mlterm", "contour"].iter().any(|v| term.starts_with(v)) {
+        Protocol::Sixel
+    } else {
+        Protocol::None
+    }
+}
+
+/// How many columns a picture takes at `rows` lines tall, keeping its shape, at most `max_cols`.
+pub fn columns(image: &DynamicImage, rows: u16, max_cols: u16) -> u16 {
+    let (cell_width, cell_height) = crate::platform::cell_size().unwrap_or(CELL);
+    let width = image.width() as u64 * rows as u64 * cell_height as u64 / (image.height().max(1) as u64 * cell_width.max(1) as u64);
+    width.clamp(1, max_cols as u64) as u16
+}
+
+/// The escape sequence drawing a picture at the cursor, `cols` wide and `rows` tall, or `None` for
+/// terminals without a protocol.
+pub fn encode(protocol: Protocol, image: &DynamicImage, cols: u16, rows: u16) -> Option<String> {
+    match protocol {
+        Protocol::Kitty => {
+            let data = base64::encode(png(image)?);
+            let chunks: Vec<_> = data.as_bytes().chunks(KITTY_CHUNK).map(|v| std::str::from_utf8(v).unwrap()).collect();
+            let mut escape = String::new();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let more = (i + 1 < chunks.len()) as u8;
+                // q=2 keeps kitty from answering, and C=1 leaves the cursor where it was
+                if i == 0 {
+                    let _ = write!(escape, "\x1b_Ga=T,f=100,q=2,C=1,c={},r={},m={};{}\x1b\\", cols, rows, more, chunk);
+                } else {
+                    let _ = write!(escape, "\x1b_Gm={};{}\x1b\\", more, chunk);
+                }
+            }
+            Some(escape)
+        }
+
+        Protocol::Iterm => {
+            let data = png(image)?;
+            Some(format!("\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07", data.len(), cols, rows, base64::encode(data)))
+        }
+
+        Protocol::Sixel => {
+            let (cell_width, cell_height) = crate::platform::cell_size().unwrap_or(CELL);
+            let image = image.resize(cols as u32 * cell_width, rows as u32 * cell_height, FilterType::Triangle);
+            Some(sixel(&image.to_rgb8()))
+        }
+
+        Protocol::Auto | Protocol::None => None,
+    }
+}
+
+fn png(image: &DynamicImage) -> Option<Vec<u8>> {
+    let mut data = std::io::Cursor::new(vec![]);
+    image.write_to(&mut data, ImageOutputFormat::Png).ok()?;
+    Some(data.into_inner())
+}
+
+/// Writes a picture as sixels, six rows of pixels at a time with one pass per colour.
+pub fn sixel(image: &RgbImage) -> String {
+    // a 6x6x6 colour cube does for thumbnails, and needs no palette worked out for each picture
+    let level = |v: u8| (v as usize * 5 + 127) / 255;
+    let color = |x: u32, y: u32| {
+        let pixel = image.get_pixel(x, y);
+        level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2])
+    };
+
+    let (width, height) = image.dimensions();
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", width, height);
+    for i in 0..216 {
+        let _ = write!(out, "#{};2;{};{};{}", i, i / 36 * 20, i / 6 % 6 * 20, i % 6 * 20);
+    }
+
+    for top in (0..height).step_by(6) {
+        let rows = top..(top + 6).min(height);
+        let mut colors: Vec<_> = rows.clone().flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| color(x, y)).collect();
+        colors.sort_unstable();
+        colors.dedup();
+
+        for (n, wanted) in colors.into_iter().enumerate() {
+            // $ goes back to the start of the band for the next colour
+            if n > 0 {
+                out.push('$');
+            }
+            let _ = write!(out, "#{}", wanted);
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..width {
+                let bits = rows.clone().filter(|y| color(x, *y) == wanted).fold(0, |bits, y| bits | 1 << (y - top));
+                let c = char::from(63 + bits as u8);
+                run = match run {
+                    Some((last, count)) if last == c => Some((c, count + 1)),
+                    Some(last) => {
+                        push_run(&mut out, last);
+                        Some((c, 1))
+                    }
+                    None => Some((c, 1)),
+                };
+            }
+            if let Some(last) = run {
+                push_run(&mut out, last);
+            }
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+/// Repeats of a sixel are written as a count once that's shorter.
+fn push_run(out: &mut String, (c, count): (char, usize)) {
+    if count > 3 {
+        let _ = write!(out, "!{}{}", count, c);
+    } else {
+        for _ in 0..count {
+            out.push(c);
+        }
+    }
+}
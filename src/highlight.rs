@@ -0,0 +1,35 @@
+//! Words and patterns that highlight messages, on top of the server's push rules.
+
+use std::collections::HashMap;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::config::Config;
+
+pub struct Highlights {
+    everywhere: Vec<Regex>,
+    /// Keyed by room id.
+    rooms: HashMap<String, Vec<Regex>>,
+}
+
+impl Highlights {
+    /// Reads `[highlights]` and each room's `highlights`. Entries written `/like this/` are regexes
+    /// and the rest are whole words, both ignoring case. Invalid regexes are skipped.
+    pub fn new(config: &Config) -> Highlights {
+        let everywhere = config.highlights.words.iter().filter_map(|v| pattern(v)).collect();
+        let rooms = config.rooms.iter().map(|(id, room)| (id.clone(), room.highlights.iter().filter_map(|v| pattern(v)).collect())).collect();
+        Highlights { everywhere, rooms }
+    }
+
+    pub fn matches(&self, room_id: &str, text: &str) -> bool {
+        self.everywhere.iter().any(|v| v.is_match(text)) || self.rooms.get(room_id).map(|v| v.iter().any(|v| v.is_match(text))).unwrap_or(false)
+    }
+}
+
+fn pattern(entry: &str) -> Option<Regex> {
+    let pattern = match entry.strip_prefix('/').and_then(|v| v.strip_suffix('/')) {
+        Some(regex) => regex.to_string(),
+        None => format!(r"\b{}\b", regex::escape(entry)),
+    };
+    RegexBuilder::new(&pattern).case_insensitive(true).build().ok()
+}
@@ -0,0 +1,19 @@
+//! History imported after the fact, like a bridge backfilling old messages with MSC2716 batch
+//! sends. These say so in their content, and carry timestamps from long before their neighbours.
+
+use serde::Deserialize;
+use serde_json::{value::RawValue, Map, Value};
+
+/// The content keys that mark an event as historical, unstable first.
+const KEYS: [&str; 2] = ["org.matrix.msc2716.historical", "m.historical"];
+
+#[derive(Deserialize)]
+struct Event {
+    #[serde(default)]
+    content: Map<String, Value>,
+}
+
+/// Whether an event was imported into the room rather than sent to it.
+pub fn is_imported(event: &RawValue) -> bool {
+    serde_json::from_str::<Event>(event.get()).map(|v| KEYS.iter().any(|k| v.content.get(*k) == Some(&Value::Bool(true)))).unwrap_or(false)
+}
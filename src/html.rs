@@ -0,0 +1,199 @@
+//! Reading the HTML of formatted messages, which the timeline otherwise shows as their plain body.
+
+use matrix_sdk::ruma::events::room::message::{MessageFormat, MessageType};
+
+pub type Rgb = (u8, u8, u8);
+
+/// How a piece of a formatted message is drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Format {
+    pub color: Option<Rgb>,
+    pub bold: bool,
+    pub italic: bool,
+    pub strike: bool,
+    pub code: bool,
+    pub link: bool,
+    pub quote: bool,
+}
+
+impl Format {
+    /// Only coloured, like mIRC colour codes.
+    pub fn color(color: Option<Rgb>) -> Format {
+        Format { color, ..Format::default() }
+    }
+}
+
+/// The text of a message split where its formatting changes, from tags like `<b>`, `<code>`, `<a>`,
+/// and `<blockquote>`, and colours from `data-mx-color` and `<font color>`. `None` if none of it is
+/// formatted, so the plain body can be shown instead.
+pub fn formatted(msgtype: &MessageType) -> Option<Vec<(String, Format)>> {
+    let formatted = match msgtype {
+        MessageType::Text(v) => v.formatted.as_ref(),
+        MessageType::Emote(v) => v.formatted.as_ref(),
+        MessageType::Notice(v) => v.formatted.as_ref(),
+        _ => None,
+    }?;
+    if formatted.format != MessageFormat::Html {
+        return None;
+    }
+
+    let parts = format_spans(&formatted.body);
+    if parts.iter().any(|(_, format)| *format != Format::default()) {
+        Some(parts)
+    } else {
+        None
+    }
+}
+
+fn format_spans(html: &str) -> Vec<(String, Format)> {
+    let mut parts: Vec<(String, Format)> = vec![];
+    // each open tag with the formatting inside it
+    let mut stack: Vec<(String, Format)> = vec![];
+    // the open link's target and text, which is written after it unless it's the same
+    let mut link: Option<(String, String)> = None;
+    // replies quote the message they answer, which the body leaves out too
+    let mut in_reply = 0usize;
+    let mut rest = html;
+    while !rest.is_empty() {
+        let (text, tag) = match rest.find('<') {
+            Some(0) => match rest.find('>') {
+                Some(end) => {
+                    let tag = &rest[1..end];
+                    rest = &rest[end + 1..];
+                    ("", Some(tag))
+                }
+
+                None => (std::mem::take(&mut rest), None),
+            },
+
+            Some(start) => {
+                let text = &rest[..start];
+                rest = &rest[start..];
+                (text, None)
+            }
+
+            None => (std::mem::take(&mut rest), None),
+        };
+
+        let format = stack.last().map(|(_, v)| *v).unwrap_or_default();
+        // line breaks between tags are just formatting, except in code blocks
+        let layout = text.trim().is_empty() && text.contains('\n') && !format.code;
+        if !text.is_empty() && !layout && in_reply == 0 {
+            let text = decode_entities(text);
+            if let Some((_, link_text)) = link.as_mut() {
+                link_text.push_str(&text);
+            }
+            // quoted lines start with `> ` like the plain body's
+            for (i, line) in text.split('\n').enumerate() {
+                if i != 0 {
+                    push_newline(&mut parts);
+                }
+                let line_start = parts.last().map(|(v, _)| v.ends_with('\n')).unwrap_or(true);
+                if format.quote && line_start && !line.is_empty() {
+                    push(&mut parts, "> ", Format { quote: true, ..Format::default() });
+                }
+                if !line.is_empty() {
+                    push(&mut parts, line, format);
+                }
+            }
+        }
+
+        let tag = match tag {
+            Some(v) => v.trim_end_matches('/').trim(),
+            None => continue,
+        };
+        let name = tag.split_whitespace().next().unwrap_or_default().to_ascii_lowercase();
+        match name.strip_prefix('/') {
+            Some(closing) => {
+                if let Some(i) = stack.iter().rposition(|(v, _)| v == closing) {
+                    stack.truncate(i);
+                }
+                if closing == "mx-reply" {
+                    in_reply = in_reply.saturating_sub(1);
+                }
+                // links to users and rooms are pills, named well enough by their text
+                if let Some((href, text)) = link.take().filter(|_| closing == "a") {
+                    if href != text && !href.starts_with("https://matrix.to/") && in_reply == 0 {
+                        push(&mut parts, &format!(" ({})", href), Format::default());
+                    }
+                }
+                // code blocks usually end in a newline of their own
+                let line_start = parts.last().map(|(v, _)| v.ends_with('\n')).unwrap_or(true);
+                if matches!(closing, "p" | "div" | "li" | "blockquote" | "pre" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6") && !line_start {
+                    push_newline(&mut parts);
+                }
+            }
+
+            None if name == "br" => push_newline(&mut parts),
+            None => {
+                let mut format = format;
+                match name.as_str() {
+                    "mx-reply" => in_reply += 1,
+                    "b" | "strong" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => format.bold = true,
+                    "i" | "em" => format.italic = true,
+                    "s" | "del" | "strike" => format.strike = true,
+                    "code" | "pre" => format.code = true,
+                    "blockquote" => format.quote = true,
+                    "a" => {
+                        format.link = true;
+                        link = attribute(tag, "href").map(|v| (v, String::new()));
+                    }
+                    _ => (),
+                }
+                if let Some(color) = attribute(tag, "data-mx-color").or_else(|| attribute(tag, "color")).and_then(|v| parse_color(&v)) {
+                    format.color = Some(color);
+                }
+                stack.push((name, format));
+            }
+        }
+    }
+
+    // block ends leave a newline behind the last line
+    if let Some((last, _)) = parts.last_mut() {
+        while last.ends_with('\n') {
+            last.pop();
+        }
+    }
+    parts.retain(|(v, _)| !v.is_empty());
+    parts
+}
+
+fn push(parts: &mut Vec<(String, Format)>, text: &str, format: Format) {
+    match parts.last_mut() {
+        Some((last, last_format)) if *last_format == format => last.push_str(text),
+        _ => parts.push((text.to_string(), format)),
+    }
+}
+
+fn push_newline(parts: &mut Vec<(String, Format)>) {
+    match parts.last_mut() {
+        Some((last, _)) => last.push('\n'),
+        None => parts.push((String::from("\n"), Format::default())),
+    }
+}
+
+/// The value of an attribute in a tag like `font color="#ff0000"`.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let start = tag.find(&format!(" {}=", name))? + name.len() + 2;
+    let value = &tag[start..];
+    let value = match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next()?,
+        _ => value.split_whitespace().next()?,
+    };
+    Some(decode_entities(value))
+}
+
+/// A colour written as `#rrggbb` or `#rgb`.
+pub fn parse_color(text: &str) -> Option<Rgb> {
+    let hex = text.strip_prefix('#')?;
+    let digits: Vec<u8> = hex.chars().map(|c| c.to_digit(16).map(|v| v as u8)).collect::<Option<_>>()?;
+    match digits[..] {
+        [r, g, b] => Some((r * 17, g * 17, b * 17)),
+        [r1, r2, g1, g2, b1, b2] => Some((r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2)),
+        _ => None,
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&#39;", "'").replace("&nbsp;", " ").replace("&amp;", "&")
+}
@@ -0,0 +1,64 @@
+//! Slowing the UI loop down while nothing is happening, so a client left running all day barely
+//! wakes the CPU. Input and new events bring it back to full speed straight away.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Notify;
+
+/// How long the loop waits between frames while in use.
+const FRAME: Duration = Duration::from_millis(10);
+/// How long without input or events before slowing down.
+const IDLE_AFTER: Duration = Duration::from_secs(30);
+/// How long the loop waits between wake-ups while idle, which only keep timers like away going.
+const IDLE_WAKE: Duration = Duration::from_secs(1);
+/// How often the screen is still redrawn while idle, for clocks and fading.
+const IDLE_REDRAW: Duration = Duration::from_secs(60);
+
+pub struct Idle {
+    wake: Arc<Notify>,
+    last_activity: Instant,
+    last_draw: Option<Instant>,
+    /// Whether something happened since the last frame.
+    changed: bool,
+}
+
+impl Idle {
+    pub fn new(now: Instant) -> Idle {
+        Idle {
+            wake: Arc::new(Notify::new()),
+            last_activity: now,
+            last_draw: None,
+            changed: true,
+        }
+    }
+
+    /// Something happened that should be drawn, waking the loop if it's waiting.
+    pub fn active(&mut self, now: Instant) {
+        self.last_activity = now;
+        self.changed = true;
+        self.wake.notify_one();
+    }
+
+    pub fn is_idle(&self, now: Instant) -> bool {
+        now.duration_since(self.last_activity) >= IDLE_AFTER
+    }
+
+    /// Whether to draw a frame now. Idle loops skip drawing unless something changed.
+    pub fn should_draw(&self, now: Instant) -> bool {
+        self.changed || !self.is_idle(now) || self.last_draw.map(|v| now.duration_since(v) >= IDLE_REDRAW).unwrap_or(true)
+    }
+
+    pub fn drawn(&mut self, now: Instant) {
+        self.changed = false;
+        self.last_draw = Some(now);
+    }
+
+    /// What to wait on before the next frame, and for at most how long. The app state should be
+    /// unlocked while waiting, or nothing could wake it.
+    pub fn wait(&self, now: Instant) -> (Arc<Notify>, Duration) {
+        (self.wake.clone(), if self.is_idle(now) { IDLE_WAKE } else { FRAME })
+    }
+}
@@ -0,0 +1,149 @@
+//! Pictures in the message list: thumbnails of image messages, fetched as they come near the screen
+//! and kept ready to draw, and where they were drawn. The message list leaves blank lines for each
+//! one, and the pictures are written over those once the frame is.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    future::Future,
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use matrix_sdk::{
+    media::{MediaEventContent, MediaThumbnailSize},
+    ruma::{api::client::media::get_content_thumbnail::v3::Method, OwnedEventId, UInt},
+    Client,
+};
+
+use crate::graphics::{self, Protocol};
+
+/// How many pictures are kept, dropping the ones fetched longest ago.
+const CACHE_SIZE: usize = 64;
+/// The widest a picture is drawn, in columns.
+pub const MAX_COLUMNS: u16 = 60;
+/// The size asked of the server, which scales thumbnails down to about this.
+const THUMBNAIL_SIZE: u32 = 320;
+
+/// A thumbnail written out for the terminal's protocol.
+pub struct Picture {
+    pub cols: u16,
+    pub rows: u16,
+    escape: String,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+struct Placement {
+    id: OwnedEventId,
+    x: u16,
+    y: u16,
+}
+
+pub struct Images {
+    protocol: Protocol,
+    /// How many lines tall pictures are.
+    rows: u16,
+    cache: VecDeque<(OwnedEventId, Picture)>,
+    /// The messages whose thumbnails have been asked for, so each is only fetched once.
+    requested: HashSet<OwnedEventId>,
+    /// Where pictures go this frame, filled in while drawing, which only borrows the app state.
+    placed: Mutex<Vec<Placement>>,
+    /// Where they were last written.
+    drawn: Vec<Placement>,
+}
+
+impl Images {
+    pub fn new(protocol: Protocol, rows: u16) -> Images {
+        Images {
+            protocol: protocol.detect(),
+            rows,
+            cache: VecDeque::new(),
+            requested: HashSet::new(),
+            placed: Mutex::new(vec![]),
+            drawn: vec![],
+        }
+    }
+
+    /// Whether pictures are drawn at all, which needs a terminal with a graphics protocol.
+    pub fn enabled(&self) -> bool {
+        self.protocol != Protocol::None
+    }
+
+    /// Whether a message's thumbnail still needs fetching, which it then no longer does.
+    pub fn request(&mut self, event_id: &OwnedEventId) -> bool {
+        self.enabled() && self.requested.insert(event_id.clone())
+    }
+
+    /// Lets a thumbnail be asked for again, like when its fetch was cancelled.
+    pub fn forget(&mut self, event_id: &OwnedEventId) {
+        self.requested.remove(event_id);
+    }
+
+    pub fn insert(&mut self, event_id: OwnedEventId, picture: Picture) {
+        if self.cache.len() >= CACHE_SIZE {
+            // pictures pushed out are fetched again if they're scrolled back to
+            if let Some((id, _)) = self.cache.pop_front() {
+                self.requested.remove(&id);
+            }
+        }
+        self.cache.push_back((event_id, picture));
+    }
+
+    pub fn get(&self, event_id: &OwnedEventId) -> Option<&Picture> {
+        self.cache.iter().find(|(id, _)| id == event_id).map(|(_, v)| v)
+    }
+
+    /// Fetches a thumbnail and writes it out for the terminal, off the app state since it's slow.
+    pub fn fetch(&self, client: Arc<Client>, content: impl MediaEventContent + Send + 'static) -> impl Future<Output = Option<Picture>> + Send + 'static {
+        let (protocol, rows) = (self.protocol, self.rows);
+        async move {
+            let size = MediaThumbnailSize { method: Method::Scale, width: UInt::from(THUMBNAIL_SIZE), height: UInt::from(THUMBNAIL_SIZE) };
+            let data = client.media().get_thumbnail(content, size, true).await.ok()??;
+            tokio::task::spawn_blocking(move || {
+                let image = image::load_from_memory(&data).ok()?;
+                let cols = graphics::columns(&image, rows, MAX_COLUMNS);
+                let escape = graphics::encode(protocol, &image, cols, rows)?;
+                Some(Picture { cols, rows, escape })
+            })
+            .await
+            .ok()?
+        }
+    }
+
+    /// Forgets where pictures were put, for a frame about to be drawn.
+    pub fn start_frame(&self) {
+        self.placed.lock().unwrap().clear();
+    }
+
+    /// Puts a picture at a cell this frame.
+    pub fn place(&self, event_id: &OwnedEventId, x: u16, y: u16) {
+        self.placed.lock().unwrap().push(Placement { id: event_id.clone(), x, y });
+    }
+
+    /// Whether pictures drawn into cells have moved since they were written, so the screen has to
+    /// be drawn from scratch first to be rid of the old ones.
+    pub fn needs_clear(&self) -> bool {
+        self.protocol.in_cells() && *self.placed.lock().unwrap() != self.drawn
+    }
+
+    /// Writes this frame's pictures, unless they're where they were. The cursor is put back after.
+    pub fn flush(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let placed = std::mem::take(&mut *self.placed.lock().unwrap());
+        if placed == self.drawn {
+            return Ok(());
+        }
+
+        write!(out, "\x1b7")?;
+        if self.protocol == Protocol::Kitty {
+            write!(out, "\x1b_Ga=d,q=2\x1b\\")?;
+        }
+        for placement in placed.iter() {
+            if let Some(picture) = self.get(&placement.id) {
+                write!(out, "\x1b[{};{}H{}", placement.y + 1, placement.x + 1, picture.escape)?;
+            }
+        }
+        write!(out, "\x1b8")?;
+        out.flush()?;
+        self.drawn = placed;
+        Ok(())
+    }
+}
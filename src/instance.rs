@@ -0,0 +1,54 @@
+//! Keeping a second ilo-toki out of a profile that's already in use. A profile is the directory
+//! ilo-toki runs in, with its `config.toml` and `.session`, and two clients logged in with the
+//! same session would fight over it.
+
+use std::{fs::OpenOptions, io::Write};
+
+/// Holds the lock file until dropped.
+pub struct Lock {
+    path: String,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Takes the lock file, writing our pid to it. Otherwise returns the pid of the instance that
+/// already has it, if it can be read. Files left by instances that have since died are taken over.
+pub fn lock(path: &str) -> Result<Lock, Option<u32>> {
+    for _ in 0..2 {
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(mut file) => {
+                let _ = write!(file, "{}", std::process::id());
+                return Ok(Lock { path: path.to_string() });
+            }
+
+            Err(_) => {
+                let pid = std::fs::read_to_string(path).ok().and_then(|v| v.trim().parse().ok());
+                match pid {
+                    Some(pid) if running(pid) => return Err(Some(pid)),
+                    _ => {
+                        let _ = std::fs::remove_file(path);
+                    }
+                }
+            }
+        }
+    }
+    // something else keeps making the file, so leave it be
+    Err(None)
+}
+
+#[cfg(unix)]
+fn running(pid: u32) -> bool {
+    // SAFETY: signal 0 only checks whether the process exists
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// There's no cheap way to ask, so assume it is and let the message say how to clear the lock.
+#[cfg(not(unix))]
+fn running(_pid: u32) -> bool {
+    true
+}
@@ -0,0 +1,81 @@
+//! Inviting a list of users read from a file, slowly enough not to trip the server's rate limits.
+
+use std::{path::Path, time::Duration};
+
+use matrix_sdk::{
+    room::Joined,
+    ruma::{
+        api::{
+            client::error::ErrorKind,
+            error::{FromHttpResponseError, ServerError},
+        },
+        OwnedUserId, UserId,
+    },
+    HttpError, RumaApiError,
+};
+
+/// The pause between invites, which stays under the default Synapse limits.
+const PACE: Duration = Duration::from_millis(500);
+/// How long to wait when rate limited and the server doesn't say.
+const DEFAULT_RETRY: Duration = Duration::from_secs(5);
+/// How many times one invite is retried after being rate limited.
+const MAX_RETRIES: usize = 5;
+
+/// The user ids in a file, one per line, along with the lines that aren't user ids. Blank lines and
+/// repeats are skipped.
+pub fn read(path: &Path) -> Result<(Vec<OwnedUserId>, Vec<String>), String> {
+    let file = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut users: Vec<OwnedUserId> = vec![];
+    let mut invalid = vec![];
+    for line in file.lines().map(str::trim).filter(|v| !v.is_empty()) {
+        match UserId::parse(line) {
+            Ok(v) if !users.contains(&v) => users.push(v),
+            Ok(_) => (),
+            Err(_) => invalid.push(line.to_string()),
+        }
+    }
+    Ok((users, invalid))
+}
+
+/// How long the server asked us to wait, if the error is from being rate limited.
+fn rate_limited(error: &matrix_sdk::Error) -> Option<Duration> {
+    match error {
+        matrix_sdk::Error::Http(HttpError::Api(FromHttpResponseError::Server(ServerError::Known(RumaApiError::ClientApi(e))))) => match e.kind {
+            ErrorKind::LimitExceeded { retry_after_ms } => Some(retry_after_ms.unwrap_or(DEFAULT_RETRY)),
+            _ => None,
+        },
+
+        matrix_sdk::Error::Http(HttpError::Server(status)) if status.as_u16() == 429 => Some(DEFAULT_RETRY),
+        _ => None,
+    }
+}
+
+/// Invites each user in turn, returning the ones that failed and why.
+pub async fn invite_all(room: &Joined, users: &[OwnedUserId]) -> Vec<String> {
+    let mut failed = vec![];
+    for (i, user_id) in users.iter().enumerate() {
+        if i != 0 {
+            tokio::time::sleep(PACE).await;
+        }
+
+        let mut retries = 0;
+        loop {
+            match room.invite_user_by_id(user_id).await {
+                Ok(_) => break,
+
+                Err(e) => match rate_limited(&e) {
+                    Some(wait) if retries < MAX_RETRIES => {
+                        retries += 1;
+                        tokio::time::sleep(wait).await;
+                    }
+
+                    _ => {
+                        failed.push(format!("{}: {}", user_id, e));
+                        break;
+                    }
+                },
+            }
+        }
+    }
+    failed
+}
@@ -0,0 +1,114 @@
+//! mIRC formatting codes, which messages bridged from IRC keep in their plain body.
+
+use crate::html::Rgb;
+
+const BOLD: char = '\x02';
+const COLOR: char = '\x03';
+const HEX_COLOR: char = '\x04';
+const RESET: char = '\x0f';
+const MONOSPACE: char = '\x11';
+const REVERSE: char = '\x16';
+const ITALIC: char = '\x1d';
+const STRIKETHROUGH: char = '\x1e';
+const UNDERLINE: char = '\x1f';
+
+/// The colours `\x03` picks by number. 99 and the extended colours above 15 are left as default.
+const COLORS: [Rgb; 16] = [
+    (255, 255, 255),
+    (0, 0, 0),
+    (0, 0, 127),
+    (0, 147, 0),
+    (255, 0, 0),
+    (127, 0, 0),
+    (156, 0, 156),
+    (252, 127, 0),
+    (255, 255, 0),
+    (0, 252, 0),
+    (0, 147, 147),
+    (0, 255, 255),
+    (0, 0, 252),
+    (255, 0, 255),
+    (127, 127, 127),
+    (210, 210, 210),
+];
+
+/// The text with every formatting code taken out.
+pub fn strip(text: &str) -> String {
+    spans(text).into_iter().map(|(text, _)| text).collect()
+}
+
+/// The text split where its foreground colour changes. `None` if it sets no colour, so the plain
+/// body (with [`strip`]) can be shown instead. Bold, italics, and the rest are dropped.
+pub fn colored(text: &str) -> Option<Vec<(String, Option<Rgb>)>> {
+    if !text.contains([COLOR, HEX_COLOR]) {
+        return None;
+    }
+    let parts = spans(text);
+    if parts.iter().any(|(_, color)| color.is_some()) {
+        Some(parts)
+    } else {
+        None
+    }
+}
+
+fn spans(text: &str) -> Vec<(String, Option<Rgb>)> {
+    let mut parts: Vec<(String, Option<Rgb>)> = vec![];
+    let mut color = None;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            COLOR => {
+                // up to two digits, then optionally a comma and the background's two digits
+                let foreground = digits(&mut chars);
+                if !foreground.is_empty() && chars.peek() == Some(&',') {
+                    let mut ahead = chars.clone();
+                    ahead.next();
+                    if ahead.peek().map(char::is_ascii_digit).unwrap_or(false) {
+                        chars.next();
+                        digits(&mut chars);
+                    }
+                }
+                color = foreground.parse::<usize>().ok().and_then(|v| COLORS.get(v).copied());
+            }
+
+            HEX_COLOR => {
+                let hex: String = chars.clone().take_while(char::is_ascii_hexdigit).take(6).collect();
+                if hex.len() == 6 {
+                    chars.nth(5);
+                    color = u32::from_str_radix(&hex, 16).ok().map(|v| ((v >> 16) as u8, (v >> 8) as u8, v as u8));
+                } else {
+                    color = None;
+                }
+                if chars.peek() == Some(&',') {
+                    let background: String = chars.clone().skip(1).take_while(char::is_ascii_hexdigit).take(6).collect();
+                    if background.len() == 6 {
+                        chars.nth(6);
+                    }
+                }
+            }
+
+            RESET => color = None,
+            BOLD | MONOSPACE | REVERSE | ITALIC | STRIKETHROUGH | UNDERLINE => (),
+
+            c => match parts.last_mut() {
+                Some((text, last)) if *last == color => text.push(c),
+                _ => parts.push((c.to_string(), color)),
+            },
+        }
+    }
+    parts
+}
+
+fn digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while digits.len() < 2 {
+        match chars.peek() {
+            Some(c) if c.is_ascii_digit() => {
+                digits.push(*c);
+                chars.next();
+            }
+            _ => break,
+        }
+    }
+    digits
+}
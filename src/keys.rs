@@ -0,0 +1,132 @@
+//! Encryption helpers: why keys are missing, asking our other devices for them, and which devices we send to.
+
+use std::collections::BTreeMap;
+
+use matrix_sdk::{
+    encryption::{identities::Device, LocalTrust},
+    room::Joined,
+    ruma::{
+        api::client::to_device::send_event_to_device,
+        events::{
+            room::encrypted::{EncryptedEventScheme, OriginalSyncRoomEncryptedEvent},
+            room_key_request::{Action, RequestedKeyInfo, ToDeviceRoomKeyRequestEventContent},
+            AnyToDeviceEvent,
+        },
+        serde::Raw,
+        to_device::DeviceIdOrAllDevices,
+        EventEncryptionAlgorithm, RoomId, TransactionId,
+    },
+    Client,
+};
+use serde::Deserialize;
+
+use crate::config::DevicePolicy;
+
+#[derive(Deserialize)]
+struct WithheldContent {
+    session_id: String,
+    code: String,
+    reason: Option<String>,
+}
+
+/// The megolm session an event was encrypted with, along with the key of the device that sent it.
+fn megolm_session(event: &Raw<OriginalSyncRoomEncryptedEvent>) -> Option<(String, String)> {
+    match event.deserialize().ok()?.content.scheme {
+        #[allow(deprecated)]
+        EncryptedEventScheme::MegolmV1AesSha2(v) => Some((v.session_id, v.sender_key)),
+        _ => None,
+    }
+}
+
+pub fn session_id(event: &Raw<OriginalSyncRoomEncryptedEvent>) -> Option<String> {
+    megolm_session(event).map(|(session_id, _)| session_id)
+}
+
+/// Reads an `m.room_key.withheld` to-device event, returning the session it's for and why.
+pub fn withheld(event: &Raw<AnyToDeviceEvent>) -> Option<(String, String)> {
+    if event.get_field::<String>("type").ok()?.as_deref() != Some("m.room_key.withheld") {
+        return None;
+    }
+
+    let content: WithheldContent = event.get_field("content").ok()??;
+    let reason = match content.code.as_str() {
+        "m.blacklisted" => String::from("the sender has blocked this device"),
+        "m.unverified" => String::from("the sender only shares keys with verified devices"),
+        "m.unauthorised" => String::from("this device isn't allowed to read this message"),
+        "m.unavailable" => String::from("none of your devices have the keys"),
+        "m.no_olm" => String::from("the sender couldn't set up an encrypted channel with this device"),
+        _ => content.reason.unwrap_or(content.code),
+    };
+    Some((content.session_id, reason))
+}
+
+/// Whether a to-device event may have brought us new room keys.
+pub fn is_room_key(event: &Raw<AnyToDeviceEvent>) -> bool {
+    matches!(event.get_field::<String>("type").ok().flatten().as_deref(), Some("m.room_key" | "m.forwarded_room_key" | "m.room.encrypted"))
+}
+
+/// Asks our own verified devices to forward the keys for an event, returning how many were asked.
+pub async fn request_keys(client: &Client, room_id: &RoomId, event: &Raw<OriginalSyncRoomEncryptedEvent>) -> Result<usize, String> {
+    let (session_id, sender_key) = megolm_session(event).ok_or_else(|| String::from("message isn't encrypted with megolm"))?;
+    let user_id = client.user_id().ok_or_else(|| String::from("not logged in"))?.to_owned();
+    let device_id = client.device_id().ok_or_else(|| String::from("not logged in"))?.to_owned();
+
+    let devices = client.encryption().get_user_devices(&user_id).await.map_err(|e| e.to_string())?;
+    let devices: Vec<_> = devices.devices().filter(|v| v.device_id() != device_id && v.is_verified()).map(|v| v.device_id().to_owned()).collect();
+    if devices.is_empty() {
+        return Err(String::from("none of your other devices are verified"));
+    }
+
+    let info = RequestedKeyInfo::new(EventEncryptionAlgorithm::MegolmV1AesSha2, room_id.to_owned(), sender_key, session_id);
+    let content = ToDeviceRoomKeyRequestEventContent::new(Action::Request, Some(info), device_id, TransactionId::new());
+    let content = Raw::new(&content).map_err(|e| e.to_string())?.cast();
+
+    let mut messages = BTreeMap::new();
+    messages.insert(user_id, devices.iter().map(|v| (DeviceIdOrAllDevices::DeviceId(v.clone()), content.clone())).collect());
+    let txn_id = TransactionId::new();
+    let request = send_event_to_device::v3::Request::new_raw("m.room_key_request", &txn_id, messages);
+    client.send(request, None).await.map_err(|e| e.to_string())?;
+    Ok(devices.len())
+}
+
+/// The unverified devices in a room that haven't been accepted or blocked yet, going by the policy.
+/// Under TOFU the devices of users we've never accepted a device from are accepted on the spot.
+pub async fn unreviewed_devices(room: &Joined, policy: DevicePolicy) -> Result<Vec<Device>, String> {
+    if policy == DevicePolicy::Always || !room.is_encrypted() {
+        return Ok(vec![]);
+    }
+
+    let client = room.client();
+    let mut unreviewed = vec![];
+    for member in room.active_members().await.map_err(|e| e.to_string())? {
+        let devices = client.encryption().get_user_devices(member.user_id()).await.map_err(|e| e.to_string())?;
+        let mut known = false;
+        let mut new = vec![];
+        for device in devices.devices() {
+            if Some(device.device_id()) == client.device_id() {
+                continue;
+            }
+
+            if device.is_verified() || device.local_trust_state() != LocalTrust::Unset {
+                known = true;
+            } else {
+                new.push(device);
+            }
+        }
+
+        if policy == DevicePolicy::Tofu && !known {
+            set_trust(&new, LocalTrust::Ignored).await?;
+        } else {
+            unreviewed.extend(new);
+        }
+    }
+    Ok(unreviewed)
+}
+
+/// Accepts devices with `LocalTrust::Ignored`, or stops sending keys to them with `LocalTrust::BlackListed`.
+pub async fn set_trust(devices: &[Device], trust: LocalTrust) -> Result<(), String> {
+    for device in devices {
+        device.set_local_trust(trust).await.map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,4219 @@
+//! ilo-toki, a terminal Matrix client. The binary only starts the runtime and calls `run`; the app
+//! is a library so the benchmarks in `benches/` can drive the same code.
+
+mod announce;
+mod away;
+mod call;
+mod clock;
+mod commands;
+mod composer;
+mod config;
+mod details;
+mod cursor;
+mod dnd;
+mod export;
+mod filter;
+mod graphics;
+mod highlight;
+mod html;
+mod idle;
+mod images;
+mod historical;
+mod instance;
+mod invite;
+mod irc;
+mod keys;
+mod login;
+mod macros;
+mod matrix;
+mod media;
+mod migrate;
+mod names;
+mod notify;
+mod outbox;
+mod palette;
+mod permissions;
+mod picker;
+mod pipe;
+mod preview;
+mod platform;
+mod policy;
+mod profile;
+mod quote;
+mod react;
+mod reducer;
+mod reply;
+mod resume;
+mod room;
+mod search;
+mod security;
+mod server;
+mod spell;
+mod startup;
+mod stats;
+mod stream;
+mod symbols;
+mod tasks;
+mod template;
+#[cfg(test)]
+mod tests;
+mod term;
+mod theme;
+mod toast;
+mod trust;
+mod typing;
+mod ui;
+mod update;
+mod users;
+mod verification;
+mod viewport;
+mod webhook;
+mod widget;
+
+use std::{
+    cmp::Reverse,
+    io,
+    path::{Path, PathBuf},
+    time::Instant,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration, collections::{HashMap, HashSet},
+};
+
+use chrono::TimeZone;
+use commands::Command;
+use config::Config;
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use matrix_sdk::{
+    config::SyncSettings,
+    deserialized_responses::{EncryptionInfo, LeftRoom, SyncResponse, TimelineEvent},
+    ruma::{
+        api::client::{push::get_notifications::v3::Notification, relations::get_relating_events},
+        events::{key::verification::{request::ToDeviceKeyVerificationRequestEvent, ready::{OriginalSyncKeyVerificationReadyEvent, ToDeviceKeyVerificationReadyEvent}, start::{OriginalSyncKeyVerificationStartEvent, ToDeviceKeyVerificationStartEvent}, key::{OriginalSyncKeyVerificationKeyEvent, ToDeviceKeyVerificationKeyEvent}, done::{OriginalSyncKeyVerificationDoneEvent, ToDeviceKeyVerificationDoneEvent}, cancel::{OriginalSyncKeyVerificationCancelEvent, ToDeviceKeyVerificationCancelEvent}}, room::{message::{InReplyTo, MessageType, RoomMessageEventContent, SyncRoomMessageEvent, Relation, Thread}, encrypted::OriginalSyncRoomEncryptedEvent}, reaction::{ReactionEventContent, Relation as ReactionRelation}, SyncMessageLikeEvent, AnyTimelineEvent, AnyMessageLikeEvent, MessageLikeEvent, OriginalSyncMessageLikeEvent, AnySyncStateEvent, AnySyncTimelineEvent, AnySyncMessageLikeEvent},
+        events::typing::SyncTypingEvent,
+        events::room::power_levels::SyncRoomPowerLevelsEvent,
+        events::room::redaction::OriginalSyncRoomRedactionEvent,
+        push::{Action, Tweak},
+        serde::Raw,
+        UserId, RoomId, OwnedRoomId, OwnedUserId, UInt, OwnedEventId,
+    },
+    Client, LoopCtrl, event_handler::RawEvent, room::{Room, Joined, Messages}, encryption::{identities::Device, LocalTrust, verification::{SasVerification, VerificationRequest}},
+};
+use tokio::{sync::{Mutex, MutexGuard}, task::JoinHandle};
+use macros::MacroAction;
+use reducer::AppEvent;
+use template::Template;
+use serde_json::value::RawValue;
+use tui::{backend::CrosstermBackend, layout, widgets, Terminal};
+
+/// A `/date` jump under way, shown in the status line.
+struct Jump {
+    room: OwnedRoomId,
+    date: String,
+    /// How many pages have been loaded looking for the date so far.
+    pages: usize,
+}
+
+struct Message {
+    id: OwnedEventId,
+    user: String,
+    /// When the edit currently shown was sent, if any.
+    edited: Option<UInt>,
+    //redacted: bool,
+    content: String,
+    /// The content split where its formatting changes, for messages with formatted HTML.
+    formatted: Option<Vec<(String, html::Format)>>,
+    /// The full content of media messages, which is needed to display and download them.
+    media: Option<MessageType>,
+    timestamp: UInt,
+    reactions: Vec<Reaction>,
+    /// Whether the content matches one of the room's highlight words.
+    highlighted: bool,
+    /// The content translated by `/translate`, shown under it.
+    translation: Option<String>,
+    /// Whether this was imported into the room's history rather than sent to it.
+    imported: bool,
+    /// The preview of the first link in the content, once the server has fetched it.
+    preview: Option<preview::Preview>,
+    /// Which call event this is, for `m.call.*` events, whose content describes it.
+    call: Option<call::CallEvent>,
+    /// Whether it was encrypted and by whom, for warning about messages that can't be trusted.
+    encryption: trust::Encryption,
+    /// The content's other fields, shown under the message when it's expanded.
+    details: Vec<(String, String)>,
+    /// The message this replies to, which is quoted above it.
+    reply_to: Option<OwnedEventId>,
+    /// The root of the thread this is in, which shows it in the thread's view instead of the timeline.
+    thread: Option<OwnedEventId>,
+}
+
+struct Reaction {
+    id: OwnedEventId,
+    key: String,
+    sender: String,
+}
+
+struct Edit {
+    content: String,
+    formatted: Option<Vec<(String, html::Format)>>,
+    timestamp: UInt,
+}
+
+/// An event we couldn't decrypt, kept so decryption can be retried once its keys arrive.
+struct Undecrypted {
+    event: Raw<OriginalSyncRoomEncryptedEvent>,
+    session_id: Option<String>,
+    error: String,
+}
+
+struct Channel {
+    name: String,
+    room: Joined,
+    message_ids: Vec<OwnedEventId>,
+    messages: HashMap<OwnedEventId, Message>,
+    /// Edits whose original hasn't been paged in yet, the newest from each sender, since only the
+    /// original's sender's count and that isn't known until it arrives.
+    message_edits: HashMap<OwnedEventId, HashMap<String, Edit>>,
+    at_top: bool,
+    messages_prev_batch: Option<String>,
+    predecessor: Option<OwnedRoomId>,
+    /// Pagination tokens for history missing right before the given message, left by limited syncs.
+    gaps: HashMap<OwnedEventId, String>,
+    undecrypted: HashMap<OwnedEventId, Undecrypted>,
+    typing: typing::Typing,
+    /// Messages that mentioned us or matched a highlight word since the room was last opened.
+    mentions: HashSet<OwnedEventId>,
+    /// We've left the room, so it only shows the history already loaded and can't be sent to.
+    archived: bool,
+    /// The room's name or avatar changed since it was last opened.
+    changed: bool,
+    /// The replies in each thread, by root, oldest first.
+    threads: HashMap<OwnedEventId, Vec<OwnedEventId>>,
+    /// The latest message our read marker has been sent for.
+    read: Option<OwnedEventId>,
+}
+
+impl Channel {
+    fn new(name: String, room: Joined) -> Channel {
+        Channel {
+            name,
+            predecessor: room.create_content().and_then(|v| v.predecessor).map(|v| v.room_id),
+            room,
+            message_ids: vec![],
+            messages: HashMap::new(),
+            message_edits: HashMap::new(),
+            at_top: false,
+            messages_prev_batch: None,
+            gaps: HashMap::new(),
+            undecrypted: HashMap::new(),
+            typing: typing::Typing::default(),
+            mentions: HashSet::new(),
+            archived: false,
+            changed: false,
+            threads: HashMap::new(),
+            read: None,
+        }
+    }
+}
+
+/// Where an event sits in the room's stream, based on how it reached us.
+enum StreamPosition {
+    /// Live events from sync, which come after everything else.
+    End,
+    /// Events paginated backwards from the top of the timeline.
+    Start,
+    /// Events paginated backwards from a gap, just before the given message.
+    Before(OwnedEventId),
+    /// Imported history that arrived live anyway, which goes where its timestamp puts it.
+    Imported,
+}
+
+enum TimelineItem<'a> {
+    Message(&'a Channel, &'a Message),
+    Gap(&'a Channel, &'a OwnedEventId),
+    Divider(String),
+}
+
+struct Popup {
+    title: String,
+    lines: Vec<String>,
+    /// What to do with the key that dismisses the popup, for popups that ask something.
+    action: Option<PopupAction>,
+}
+
+enum PopupAction {
+    /// Whether to downscale an image before uploading it.
+    DownscaleUpload(media::Source),
+    /// Which file to upload.
+    PickFile(picker::Picker),
+    /// Whether to accept or block new devices before sending a message to them.
+    ReviewDevices(Vec<Device>, RoomMessageEventContent),
+    /// Whether to accept someone's request to verify each other.
+    AcceptVerification(VerificationRequest),
+    /// Whether the emoji shown match the other side's.
+    ConfirmSas(Box<SasVerification>),
+    /// Whether to wait for unsent messages before quitting.
+    Quit,
+    /// Whether to invite the users read from a file.
+    InviteFile(Joined, Vec<OwnedUserId>),
+    /// Whether to turn on encryption in a room, asked twice since it can't be undone. The flag is set
+    /// for the second time.
+    Encrypt(Joined, bool),
+    /// Which problem in `/security` to fix.
+    Security(Box<security::Report>),
+    /// Whether to split a message too large to send, or upload it as a file.
+    Oversized(String),
+    /// Which emoji to react to a message with.
+    React(Box<react::Palette>),
+    /// What to do with a long paste.
+    Paste(String),
+    /// Which action to run from the command palette.
+    Palette(palette::Palette),
+    /// Which widget to open, or to copy the URL of if the flag is set.
+    Widgets(Vec<widget::Widget>, bool),
+    /// What to do with the selected entry in `/outbox`.
+    Outbox(usize),
+    /// Which of the rooms shared with someone to go to.
+    Profile(Vec<OwnedRoomId>),
+    /// Which user from `/usersearch` to message directly, or to invite if the flag is set.
+    Users(Vec<users::User>, bool),
+    /// Which message found by `/search` to go to.
+    Search(search::Search),
+    /// Nothing to ask, but the member list of this room is redrawn when power levels change.
+    Members(OwnedRoomId),
+}
+
+/// The composer is writing a literal code block, where Enter inserts a newline.
+struct CodeBlock {
+    language: Option<String>,
+}
+
+/// The input box is asking for a secret, which is masked on screen and cleared once submitted.
+struct SecretPrompt {
+    title: String,
+    purpose: SecretPurpose,
+}
+
+enum SecretPurpose {
+    /// The passphrase to encrypt exported room keys with.
+    ExportKeys(PathBuf),
+    /// The passphrase of a room key file being imported.
+    ImportKeys(PathBuf),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Insert,
+    Normal,
+    SelectChannel,
+    ScrollMessages,
+}
+
+impl Mode {
+    fn name(&self) -> &'static str {
+        match self {
+            Mode::Insert => "INSERT",
+            Mode::Normal => "NORMAL",
+            Mode::SelectChannel => "SELECT",
+            Mode::ScrollMessages => "SCROLL",
+        }
+    }
+}
+
+struct AppState {
+    channels: HashMap<OwnedRoomId, Channel>,
+    channel_ids: Vec<OwnedRoomId>,
+    /// Which of the configured workspaces the room list shows, or `None` for every room.
+    workspace: Option<usize>,
+    current_channel: Option<OwnedRoomId>,
+    channels_state: widgets::ListState,
+
+    messages_state: viewport::Viewport,
+    /// Where `v` started a range of messages to copy as a quote.
+    quote_mark: Option<OwnedEventId>,
+    /// The message `x` expanded to show its content's other fields.
+    expanded: Option<OwnedEventId>,
+
+    input_text: String,
+    input_char_pos: usize,
+    input_byte_pos: usize,
+    code_block: Option<CodeBlock>,
+    secret: Option<SecretPrompt>,
+    /// The message in the current channel that the next one sent replies to.
+    reply_to: Option<OwnedEventId>,
+    /// Our message in the current channel that the next one sent replaces.
+    editing: Option<OwnedEventId>,
+    /// What was in the input box when an edit took its place, put back once it's sent or cancelled.
+    edit_draft: Option<(String, usize, usize, Option<CodeBlock>)>,
+    /// The root of the thread shown in place of the current channel's timeline, which messages are sent to.
+    thread: Option<OwnedEventId>,
+
+    mode: Mode,
+    popup: Option<Popup>,
+    message_template: Template,
+    /// The layout of messages in IRC rooms.
+    irc_template: Template,
+    config: Config,
+    highlights: highlight::Highlights,
+    filters: filter::Filters,
+    /// The largest upload the homeserver accepts, once we've asked.
+    upload_limit: Option<u64>,
+    /// The files being uploaded, shown in the status line until they're sent.
+    uploading: Vec<String>,
+    /// The attachments being downloaded, likewise.
+    downloading: Vec<String>,
+    /// The `/date` jump paging back through history, if one is.
+    jumping: Option<Jump>,
+    /// Why the sender withheld the keys for a megolm session, keyed by session id.
+    withheld: HashMap<String, String>,
+    server: server::ServerFeatures,
+    /// Shown next to the mode in the status line.
+    status: Option<String>,
+    /// A newer release, if there is one.
+    update: Option<String>,
+    toast: toast::Toast,
+    notifier: Box<dyn notify::Notifier>,
+    macros: macros::Macros,
+    spelling: spell::Spelling,
+    /// New messages to translate, sent off by the UI loop.
+    untranslated: Vec<(OwnedRoomId, OwnedEventId, String)>,
+    previews: preview::Previews,
+    images: images::Images,
+    /// Pagination and previews running in the background, per room.
+    tasks: tasks::Tasks,
+    /// Keys to handle next, as if typed, like the command palette pressing an action's key.
+    replay: Vec<Event>,
+    away: away::Away,
+    dnd: dnd::DoNotDisturb,
+    announcements: announce::Announcements,
+    symbols: symbols::Symbols,
+    clock: clock::Clock,
+    theme: theme::Theme,
+    /// Set to have the UI hand the terminal back and stop, like Ctrl-Z in a shell.
+    suspend: bool,
+    outbox: outbox::Outbox,
+    /// The terminal's new size, until the UI has laid itself out again.
+    resized: Option<(u16, u16)>,
+    /// When the UI loop can slow down and stop drawing.
+    idle: idle::Idle,
+    profiler: profile::Profiler,
+    policies: policy::Policies,
+    /// What our power levels let us do in each room.
+    permissions: permissions::Permissions,
+    /// Room and member names saved from last time.
+    names: names::Names,
+    /// Every message loaded, for `/search`.
+    search: search::Index,
+    /// Rooms opened this session, whose read markers are moved up on quit.
+    visited: HashSet<OwnedRoomId>,
+    /// Rooms whose history has been looked at on their first opening.
+    backfilled: HashSet<OwnedRoomId>,
+    client: Arc<Client>,
+}
+
+impl AppState {
+    /// How the cursor looks in the current mode.
+    fn cursor_style(&self) -> &cursor::CursorStyle {
+        match self.mode {
+            Mode::Insert => &self.config.cursor.insert,
+            Mode::Normal => &self.config.cursor.normal,
+            Mode::SelectChannel => &self.config.cursor.select,
+            Mode::ScrollMessages => &self.config.cursor.scroll,
+        }
+    }
+
+    /// The rooms in the room list, which are those of the current workspace if there is one.
+    fn sidebar_ids(&self) -> Vec<OwnedRoomId> {
+        match self.workspace.and_then(|v| self.config.workspaces.get(v)) {
+            Some(workspace) => self.channel_ids.iter().filter(|v| workspace.rooms.iter().any(|room| room == v.as_str())).cloned().collect(),
+            None => self.channel_ids.clone(),
+        }
+    }
+
+    /// Switches to the next workspace, and to every room after the last one.
+    fn cycle_workspace(&mut self) {
+        self.workspace = match self.workspace {
+            Some(i) if i + 1 < self.config.workspaces.len() => Some(i + 1),
+            Some(_) => None,
+            None if !self.config.workspaces.is_empty() => Some(0),
+            None => None,
+        };
+        self.channels_state.select(self.current_channel.as_ref().and_then(|id| self.sidebar_ids().iter().position(|v| v == id)));
+    }
+}
+
+static RUNNING: AtomicBool = AtomicBool::new(true);
+
+/// Where `/security` offers to export room keys when there's no backup.
+const KEY_EXPORT_FILE: &str = "room-keys.txt";
+
+/// How big each piece of a split message is, leaving room for its HTML version and encryption.
+const SPLIT_BYTES: usize = 16_000;
+
+/// How many lines of a long paste are shown when asking what to do with it.
+const PASTE_PREVIEW_LINES: usize = 5;
+
+/// How many rooms have their names worked out at once on startup.
+const LOAD_ROOMS_AT_ONCE: usize = 16;
+
+/// How many background jobs, like pagination, run at once across every room.
+const JOBS_AT_ONCE: usize = 4;
+
+/// How many pages `/date` loads looking for a date before giving up.
+const MAX_JUMP_PAGES: usize = 100;
+
+/// Why edits are refused on servers that don't support them.
+const NO_EDITS: &str = "This server doesn't support edits, so they would show up as new messages.";
+
+/// Where whatever was left in the input box is kept between runs.
+const DRAFT_FILE: &str = ".draft";
+
+/// Where messages that haven't been sent yet, or failed to, are kept between runs.
+const OUTBOX_FILE: &str = ".outbox";
+
+/// Where room and member names are saved between runs.
+const NAMES_FILE: &str = ".names";
+
+/// Where the messages indexed for `/search` are kept between runs.
+const SEARCH_FILE: &str = ".search-index";
+
+/// Where the policy lists subscribed to or unsubscribed from with `/policy` are kept between runs.
+const POLICY_FILE: &str = ".policy-lists";
+
+/// Where the room open and the message selected are kept between runs.
+const RESUME_FILE: &str = ".last-room";
+
+/// Held while running, so a second instance doesn't use the same profile.
+const LOCK_FILE: &str = ".lock";
+
+/// Runs ilo-toki with the command line it was started with, until it's quit.
+pub async fn run() -> Result<(), io::Error> {
+    // importing doesn't need an account, so it happens before logging in
+    let args: Vec<_> = std::env::args().collect();
+    if let Some(i) = args.iter().position(|v| v == "--import-weechat" || v == "--import-irssi") {
+        let path = Path::new(args.get(i + 1).map(String::as_str).unwrap_or_default());
+        let imported = if args[i] == "--import-weechat" { migrate::weechat(path) } else { migrate::irssi(path) };
+        match imported {
+            Ok(imported) => println!("{}", imported.to_toml(path)),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    let _lock = match instance::lock(LOCK_FILE) {
+        Ok(v) => v,
+        Err(pid) => {
+            let pid = pid.map(|v| format!(" (pid {})", v)).unwrap_or_default();
+            eprintln!("ilo-toki is already running with this profile{}, and two instances would fight over its session.", pid);
+            eprintln!("Switch to that one, or run ilo-toki from another directory with its own config.toml and {} to open a different profile.", login::SESSION_FILE);
+            eprintln!("If it isn't running anymore, delete {}.", LOCK_FILE);
+            std::process::exit(1);
+        }
+    };
+
+    let config = match Config::load("config.toml") {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("config.toml: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let client = match login::client().await {
+        Ok(v) => Arc::new(v),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if std::env::args().any(|v| v == "--stream-json") {
+        return stream::run(client, config).await;
+    }
+
+    let server = server::ServerFeatures::query(&client).await;
+    let draft = std::fs::read_to_string(DRAFT_FILE).unwrap_or_default();
+    let profile = std::env::args().any(|v| v == "--profile");
+    let state = Arc::new(Mutex::new(new_state(client.clone(), config, server, draft, profile)));
+    state.lock().await.names = names::Names::load(NAMES_FILE);
+    state.lock().await.policies.load(POLICY_FILE);
+    state.lock().await.search = search::Index::load(SEARCH_FILE);
+    add_event_handlers(&state).await;
+
+    startup::with_progress(&client, client.sync_once(SyncSettings::default())).await.unwrap();
+    load_rooms(&state).await;
+    let policy_rooms: Vec<_> = {
+        let lock = state.lock().await;
+        lock.policies.subscriptions().iter().filter_map(|v| lock.client.get_joined_room(v)).collect()
+    };
+    for room in policy_rooms {
+        load_policy_rules(&mut state.lock().await, &room).await;
+    }
+    {
+        let lock = state.lock().await;
+        lock.outbox.load(OUTBOX_FILE, &lock.client);
+    }
+    restore_room(&mut state.lock().await, resume::Resume::load(RESUME_FILE));
+    webhook::watch(&client, &state.lock().await.config.webhooks);
+    if state.lock().await.config.updates.check {
+        let state = state.clone();
+        tokio::task::spawn(async move {
+            if let Some(version) = update::check().await {
+                state.lock().await.update = Some(version);
+            }
+        });
+    }
+
+    let state2 = state.clone();
+    let sync = tokio::task::spawn(async move {
+        client.sync_with_callback(SyncSettings::default(), |response| {
+            let state = state2.clone();
+            async move {
+                if !RUNNING.load(Ordering::Acquire) {
+                    return LoopCtrl::Break;
+                }
+
+                let mut lock = profile::lock(&state, "lock wait: sync").await;
+                let start = Instant::now();
+                // long polls that time out with nothing new leave the UI asleep
+                let rooms = &response.rooms;
+                if !rooms.join.is_empty() || !rooms.leave.is_empty() || !rooms.invite.is_empty() || !response.to_device.events.is_empty() {
+                    lock.idle.active(start);
+                }
+                for event in response.to_device.events.iter() {
+                    if let Some((session_id, reason)) = keys::withheld(event) {
+                        lock.withheld.insert(session_id, reason);
+                    }
+                }
+                if response.to_device.events.iter().any(keys::is_room_key) {
+                    tokio::task::spawn(retry_decryption(state.clone()));
+                }
+
+                // mentions ring the bell while the terminal is in the background, even in the room being read
+                let highlight = |v: &Notification| v.actions.iter().any(|v| matches!(v, Action::SetTweak(Tweak::Highlight(true))));
+                let mentioned = response.notifications.iter().any(|(id, v)| lock.config.bell(id.as_str()) && v.iter().any(highlight));
+                if mentioned && !lock.away.focused() && !lock.dnd.active(chrono::Local::now()) {
+                    notify::bell();
+                }
+
+                // the push rules decide what's worth a notification, but not for the room being read
+                for (id, notifications) in response.notifications.iter() {
+                    if lock.current_channel.as_ref() == Some(id) {
+                        continue;
+                    }
+
+                    let title = lock.channels.get(id).map(|v| v.name.clone()).unwrap_or_else(|| id.to_string());
+                    for notification in notifications.iter().filter(|v| highlight(v)) {
+                        if let (Some(channel), Ok(Some(event_id))) = (lock.channels.get_mut(id), notification.event.get_field::<OwnedEventId>("event_id")) {
+                            channel.mentions.insert(event_id);
+                        }
+                    }
+                    if lock.dnd.active(chrono::Local::now()) {
+                        continue;
+                    }
+                    for notification in notifications.iter().filter(|v| v.actions.iter().any(|v| matches!(v, Action::Notify))) {
+                        if let Some(body) = notify::message_text(&notification.event, lock.client.user_id()) {
+                            lock.notifier.notify(&title, &body);
+                        }
+                    }
+                }
+
+                handle_gaps(&response, &mut lock);
+                handle_joined(&response, &mut lock);
+                handle_left(&response, &mut lock);
+                lock.profiler.record("handle sync", start.elapsed());
+                LoopCtrl::Continue
+            }
+        })
+        .await
+        .unwrap();
+    });
+    tokio::task::spawn(ui_events(state.clone()));
+    main_ui(state, sync).await
+}
+
+/// The state the UI starts with. `draft` is put in the input box.
+fn new_state(client: Arc<Client>, config: Config, server: server::ServerFeatures, draft: String, profile: bool) -> AppState {
+    let unsupported = server.unsupported();
+    let status = if unsupported.is_empty() { None } else { Some(format!("server doesn't support {}", unsupported.join(", "))) };
+    let notifier = notify::backend(&config.notifications);
+    let announcements = announce::Announcements::new(config.accessibility.screen_reader);
+    let symbols = symbols::Symbols::new(config.symbols);
+    let theme = theme::Theme::new(config.colors.theme, config.colors.nick_colors);
+    let policies = policy::Policies::new(&config.moderation);
+    let highlights = highlight::Highlights::new(&config);
+    let filters = filter::Filters::new(&config);
+    let away = away::Away::new(&config.away);
+    let dnd = dnd::DoNotDisturb::new(&config.notifications);
+    AppState {
+        channels: HashMap::new(),
+        channel_ids: vec![],
+        workspace: None,
+        current_channel: None,
+        channels_state: widgets::ListState::default(),
+        messages_state: viewport::Viewport::default(),
+        quote_mark: None,
+        expanded: None,
+        input_char_pos: draft.chars().count(),
+        input_byte_pos: draft.len(),
+        input_text: draft,
+        code_block: None,
+        secret: None,
+        reply_to: None,
+        editing: None,
+        edit_draft: None,
+        thread: None,
+        mode: Mode::Normal,
+        popup: None,
+        message_template: Template::parse(&config.message_template),
+        irc_template: Template::parse(&config.irc_template),
+        config,
+        highlights,
+        filters,
+        upload_limit: None,
+        uploading: vec![],
+        downloading: vec![],
+        jumping: None,
+        withheld: HashMap::new(),
+        server,
+        status,
+        update: None,
+        toast: toast::Toast::default(),
+        notifier,
+        macros: macros::Macros::default(),
+        replay: vec![],
+        untranslated: vec![],
+        previews: preview::Previews::default(),
+        images: images::Images::new(config.images.protocol, config.images.rows),
+        tasks: tasks::Tasks::new(JOBS_AT_ONCE),
+        spelling: spell::Spelling::default(),
+        away,
+        dnd,
+        announcements,
+        symbols,
+        clock: clock::Clock::new(&config.time),
+        theme,
+        suspend: false,
+        outbox: outbox::Outbox::new(),
+        resized: None,
+        idle: idle::Idle::new(Instant::now()),
+        profiler: profile::Profiler::new(profile),
+        policies,
+        permissions: permissions::Permissions::default(),
+        names: names::Names::default(),
+        search: search::Index::default(),
+        visited: HashSet::new(),
+        backfilled: HashSet::new(),
+        client,
+    }
+}
+
+/// Keeps the app state up to date with events from sync.
+async fn add_event_handlers(state: &Arc<Mutex<AppState>>) {
+    let lock = state.lock().await;
+
+    let state2 = state.clone();
+    lock.client
+        .add_event_handler(move |event: SyncRoomMessageEvent, room: Room, raw: RawEvent, encryption: Option<EncryptionInfo>| {
+            let state = state2.clone();
+            async move {
+                startup::MESSAGES.fetch_add(1, Ordering::Relaxed);
+                let mut lock = profile::lock(&state, "lock wait: message").await;
+                let start = Instant::now();
+                match event {
+                    SyncMessageLikeEvent::Original(message) => {
+                        if let MessageType::VerificationRequest(request) = &message.content.msgtype {
+                            if Some(request.to.as_ref()) == lock.client.user_id() {
+                                let request = lock.client.encryption().get_verification_request(&message.sender, &message.event_id).await;
+                                if let Some(request) = request.filter(|v| !v.is_done() && !v.is_cancelled() && !v.is_ready()) {
+                                    lock.popup = Some(Popup {
+                                        title: String::from("Verification request"),
+                                        lines: vec![format!("{} wants to verify each other.", message.sender), String::from("y: accept, n: decline")],
+                                        action: Some(PopupAction::AcceptVerification(request)),
+                                    });
+                                }
+                            }
+                        }
+
+                        let id = room.room_id().to_owned();
+                        add_channel(room, &mut lock).await;
+                        handle_new_message(&id, message, &raw, encryption.into(), StreamPosition::End, &mut lock);
+                    }
+
+                    SyncMessageLikeEvent::Redacted(_) => (),
+                }
+                lock.profiler.record("handle message", start.elapsed());
+            }
+        });
+
+    let state2 = state.clone();
+    lock.client
+        .add_event_handler(move |event: Raw<OriginalSyncRoomEncryptedEvent>, room: Room| {
+            let state = state2.clone();
+            async move {
+                startup::MESSAGES.fetch_add(1, Ordering::Relaxed);
+                let mut lock = state.lock().await;
+                if let Room::Joined(room) = room {
+                    if lock.channels.contains_key(room.room_id()) {
+                        handle_encrypted(&room, event, StreamPosition::End, &mut lock).await;
+                    }
+                }
+            }
+        });
+
+    let state2 = state.clone();
+    lock.client
+        .add_event_handler(move |event: OriginalSyncRoomRedactionEvent, room: Room| {
+            let state = state2.clone();
+            async move {
+                reducer::apply(&mut state.lock().await, AppEvent::Redacted { room: room.room_id().to_owned(), event: event.redacts });
+            }
+        });
+
+    let state2 = state.clone();
+    lock.client
+        .add_event_handler(move |event: AnySyncMessageLikeEvent, room: Room| {
+            let state = state2.clone();
+            async move {
+                if call::CallEvent::of(&event.event_type()).is_some() {
+                    let mut lock = state.lock().await;
+                    let id = room.room_id().to_owned();
+                    add_channel(room, &mut lock).await;
+                    handle_call(&id, &event, StreamPosition::End, &mut lock);
+                }
+            }
+        });
+
+    let state2 = state.clone();
+    lock.client
+        .add_event_handler(move |event: SyncTypingEvent, room: Room| {
+            let state = state2.clone();
+            async move {
+                let mut typing = vec![];
+                for user_id in event.content.user_ids {
+                    if *user_id == *room.own_user_id() {
+                        continue;
+                    }
+
+                    let saved = state.lock().await.names.member(room.room_id(), user_id.as_str()).map(String::from);
+                    let name = match saved {
+                        Some(v) => v,
+                        None => match room.get_member_no_sync(&user_id).await {
+                            Ok(Some(member)) => member.name().to_string(),
+                            _ => user_id.to_string(),
+                        },
+                    };
+                    typing.push((user_id, name));
+                }
+
+                if let Some(channel) = state.lock().await.channels.get_mut(room.room_id()) {
+                    channel.typing.update(typing);
+                }
+            }
+        });
+
+    let state2 = state.clone();
+    lock.client
+        .add_event_handler(move |event: SyncRoomPowerLevelsEvent, room: Room| {
+            let state = state2.clone();
+            async move {
+                let mut lock = state.lock().await;
+                if let Some(user_id) = lock.client.user_id().map(|v| v.to_owned()) {
+                    lock.permissions.update(room.room_id(), &event.power_levels(), &user_id);
+                }
+                let shown = matches!(lock.popup.as_ref().and_then(|v| v.action.as_ref()), Some(PopupAction::Members(id)) if id == room.room_id());
+                if let (true, Room::Joined(room)) = (shown, room) {
+                    let lines = member_lines(&lock, &room).await;
+                    lock.popup.as_mut().unwrap().lines = lines;
+                }
+            }
+        });
+
+    let state2 = state.clone();
+    lock.client
+        .add_event_handler(move |event: Raw<AnySyncStateEvent>, room: Room| {
+            let state = state2.clone();
+            async move {
+                let mut lock = state.lock().await;
+                lock.policies.update(room.room_id(), &event);
+                if !lock.names.update(room.room_id(), &event) || !lock.channels.contains_key(room.room_id()) {
+                    return;
+                }
+                drop(lock);
+
+                // the name is worked out again without holding the state, like when the rooms are loaded
+                let avatar = event.get_field::<String>("type").ok().flatten().as_deref() == Some("m.room.avatar");
+                let name = if avatar { None } else { room.display_name().await.map(|v| v.to_string()).ok() };
+                if avatar || name.is_some() {
+                    reducer::apply(&mut *state.lock().await, AppEvent::RoomRenamed { room: room.room_id().to_owned(), name });
+                }
+            }
+        });
+
+    // verification: we start emoji verification once our request is accepted, accept it when they
+    // start it, and ask the user to compare emoji once keys are exchanged. Other users are verified
+    // in a room, and devices, like our other sessions, over to-device messages
+    let state2 = state.clone();
+    lock.client
+        .add_event_handler(move |event: OriginalSyncKeyVerificationReadyEvent| {
+            let state = state2.clone();
+            async move { verification_ready(&state, &event.sender, event.content.relates_to.event_id.as_str()).await }
+        });
+
+    let state2 = state.clone();
+    lock.client
+        .add_event_handler(move |event: ToDeviceKeyVerificationReadyEvent| {
+            let state = state2.clone();
+            async move { verification_ready(&state, &event.sender, event.content.transaction_id.as_str()).await }
+        });
+
+    let state2 = state.clone();
+    lock.client
+        .add_event_handler(move |event: OriginalSyncKeyVerificationStartEvent| {
+            let state = state2.clone();
+            async move { verification_started(&state, &event.sender, event.content.relates_to.event_id.as_str()).await }
+        });
+
+    let state2 = state.clone();
+    lock.client
+        .add_event_handler(move |event: ToDeviceKeyVerificationStartEvent| {
+            let state = state2.clone();
+            async move { verification_started(&state, &event.sender, event.content.transaction_id.as_str()).await }
+        });
+
+    let state2 = state.clone();
+    lock.client
+        .add_event_handler(move |event: OriginalSyncKeyVerificationKeyEvent| {
+            let state = state2.clone();
+            async move { verification_keys(&state, &event.sender, event.content.relates_to.event_id.as_str()).await }
+        });
+
+    let state2 = state.clone();
+    lock.client
+        .add_event_handler(move |event: ToDeviceKeyVerificationKeyEvent| {
+            let state = state2.clone();
+            async move { verification_keys(&state, &event.sender, event.content.transaction_id.as_str()).await }
+        });
+
+    let state2 = state.clone();
+    lock.client
+        .add_event_handler(move |event: OriginalSyncKeyVerificationDoneEvent| {
+            let state = state2.clone();
+            async move { verification_done(&state, &event.sender, event.content.relates_to.event_id.as_str()).await }
+        });
+
+    let state2 = state.clone();
+    lock.client
+        .add_event_handler(move |event: ToDeviceKeyVerificationDoneEvent| {
+            let state = state2.clone();
+            async move { verification_done(&state, &event.sender, event.content.transaction_id.as_str()).await }
+        });
+
+    let state2 = state.clone();
+    lock.client
+        .add_event_handler(move |event: OriginalSyncKeyVerificationCancelEvent| {
+            let state = state2.clone();
+            async move {
+                show_error(&mut state.lock().await, "Verification cancelled", format!("{}: {}", event.sender, event.content.reason));
+            }
+        });
+
+    let state2 = state.clone();
+    lock.client
+        .add_event_handler(move |event: ToDeviceKeyVerificationCancelEvent| {
+            let state = state2.clone();
+            async move {
+                show_error(&mut state.lock().await, "Verification cancelled", format!("{}: {}", event.sender, event.content.reason));
+            }
+        });
+
+    // requests in a room arrive as messages, and those for a device as to-device events
+    let state2 = state.clone();
+    lock.client
+        .add_event_handler(move |event: ToDeviceKeyVerificationRequestEvent| {
+            let state = state2.clone();
+            async move {
+                let mut lock = state.lock().await;
+                let request = lock.client.encryption().get_verification_request(&event.sender, &event.content.transaction_id).await;
+                if let Some(request) = request.filter(|v| !v.is_done() && !v.is_cancelled() && !v.is_ready()) {
+                    let who = if request.is_self_verification() {
+                        format!("Your session {} wants to verify this one.", event.content.from_device)
+                    } else {
+                        format!("{} ({}) wants to verify each other.", event.sender, event.content.from_device)
+                    };
+                    lock.popup = Some(Popup {
+                        title: String::from("Verification request"),
+                        lines: vec![who, String::from("y: accept, n: decline")],
+                        action: Some(PopupAction::AcceptVerification(request)),
+                    });
+                }
+            }
+        });
+}
+
+/// The other side accepted our request, so we start comparing emoji.
+async fn verification_ready(state: &Arc<Mutex<AppState>>, sender: &UserId, flow_id: &str) {
+    let client = state.lock().await.client.clone();
+    // our own other sessions send these too, even to requests they didn't accept
+    if let Some(request) = client.encryption().get_verification_request(sender, flow_id).await {
+        if request.we_started() && (Some(sender) != client.user_id() || request.is_self_verification()) {
+            if let Err(e) = request.start_sas().await {
+                show_error(&mut state.lock().await, "Verification failed", e.to_string());
+            }
+        }
+    }
+}
+
+async fn verification_started(state: &Arc<Mutex<AppState>>, sender: &UserId, flow_id: &str) {
+    let client = state.lock().await.client.clone();
+    if let Some(sas) = verification::sas(&client, sender, flow_id).await {
+        if !sas.we_started() && (Some(sender) != client.user_id() || sas.is_self_verification()) {
+            if let Err(e) = sas.accept().await {
+                show_error(&mut state.lock().await, "Verification failed", e.to_string());
+            }
+        }
+    }
+}
+
+async fn verification_keys(state: &Arc<Mutex<AppState>>, sender: &UserId, flow_id: &str) {
+    let mut lock = state.lock().await;
+    // our own key event comes back to us in the room, unless we're verifying our own sessions
+    let own = Some(sender) == lock.client.user_id();
+    if let Some(sas) = verification::sas(&lock.client, sender, flow_id).await.filter(|v| v.can_be_presented() && (!own || v.is_self_verification())) {
+        lock.popup = Some(Popup {
+            title: String::from("Verify"),
+            lines: verification::sas_lines(&sas),
+            action: Some(PopupAction::ConfirmSas(Box::new(sas))),
+        });
+    }
+}
+
+async fn verification_done(state: &Arc<Mutex<AppState>>, sender: &UserId, flow_id: &str) {
+    let mut lock = state.lock().await;
+    let sas = verification::sas(&lock.client, sender, flow_id).await;
+    let verified = match sas {
+        Some(sas) if sas.is_self_verification() && sas.is_done() => Some(format!("Your session {} is now verified.", sas.other_device().device_id())),
+        _ if Some(sender) != lock.client.user_id() && verification::is_verified(&lock.client, sender).await => Some(format!("{} is now verified.", sender)),
+        _ => None,
+    };
+    if let Some(verified) = verified {
+        lock.popup = Some(Popup {
+            title: String::from("Verified"),
+            lines: vec![verified],
+            action: None,
+        });
+    }
+}
+
+/// Adds a channel for every joined room, after the first sync. Rooms whose state hasn't changed
+/// since last time keep their saved names. The rest are worked out a few rooms at a time without
+/// holding the state, and each channel is added as soon as its name is known.
+async fn load_rooms(state: &Arc<Mutex<AppState>>) {
+    let mut lock = state.lock().await;
+    let (client, known): (_, HashSet<_>) = (lock.client.clone(), lock.channels.keys().cloned().collect());
+    let limit = Arc::new(tokio::sync::Semaphore::new(LOAD_ROOMS_AT_ONCE));
+    let mut names = tokio::task::JoinSet::new();
+    for room in client.joined_rooms().into_iter().filter(|v| !known.contains(v.room_id())) {
+        if let Some(name) = lock.names.name(room.room_id()).map(String::from) {
+            reducer::apply(&mut lock, AppEvent::RoomJoined(Box::new(Channel::new(name, room))));
+            continue;
+        }
+
+        let limit = limit.clone();
+        names.spawn(async move {
+            let _permit = limit.acquire_owned().await;
+            let name = room.display_name().await.map(|v| v.to_string()).ok();
+            (room, name)
+        });
+    }
+    drop(lock);
+
+    while let Some(result) = names.join_next().await {
+        let (room, name) = match result {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        let mut lock = state.lock().await;
+        // only names that were worked out are worth keeping
+        if let Some(name) = name.as_ref() {
+            lock.names.set_name(room.room_id(), name);
+        }
+        let name = name.unwrap_or_else(|| String::from("[unknown room]"));
+        reducer::apply(&mut lock, AppEvent::RoomJoined(Box::new(Channel::new(name, room))));
+    }
+
+    // upgraded rooms share a sidebar entry with their successor
+    let mut lock = state.lock().await;
+    let upgraded: Vec<_> = lock.channels.values().filter_map(|v| v.room.tombstone()).map(|v| v.replacement_room).filter(|v| lock.channels.contains_key(v)).collect();
+    for room in lock.client.joined_rooms() {
+        let successor_joined = room.tombstone().map(|v| upgraded.contains(&v.replacement_room)).unwrap_or(false);
+        if !successor_joined {
+            reducer::apply(&mut lock, AppEvent::RoomListed(room.room_id().to_owned()));
+        }
+    }
+}
+
+/// Returns the channel followed by every predecessor it was upgraded from that we have, newest
+/// first. They share its sidebar entry, and their mentions count towards it.
+fn upgrade_chain<'a>(state: &'a AppState, id: &OwnedRoomId) -> Vec<&'a Channel> {
+    let mut chain = vec![];
+    let mut current = state.channels.get(id);
+    while let Some(channel) = current {
+        chain.push(channel);
+        current = channel.predecessor.as_ref().and_then(|v| state.channels.get(v)).filter(|v| !chain.iter().any(|c| c.room.room_id() == v.room.room_id()));
+    }
+    chain
+}
+
+/// Returns the channel followed by the predecessors it was upgraded from, newest first.
+/// A predecessor is only included once everything after it has been paginated.
+fn channel_chain<'a>(state: &'a AppState, id: &OwnedRoomId) -> Vec<&'a Channel> {
+    let mut chain = upgrade_chain(state, id);
+    let shown = chain.iter().take_while(|v| v.at_top).count() + 1;
+    chain.truncate(shown);
+    chain
+}
+
+/// How many unread mentions a channel's sidebar entry shows, counting the rooms it was upgraded from.
+fn mention_count(state: &AppState, id: &OwnedRoomId) -> usize {
+    upgrade_chain(state, id).iter().map(|v| v.mentions.len()).sum()
+}
+
+/// Clears the mentions of a channel and of the rooms it was upgraded from.
+fn clear_mentions(state: &mut AppState, id: &OwnedRoomId) {
+    let ids: Vec<_> = upgrade_chain(state, id).iter().map(|v| v.room.room_id().to_owned()).collect();
+    for id in ids {
+        if let Some(channel) = state.channels.get_mut(&id) {
+            channel.mentions.clear();
+        }
+    }
+}
+
+/// The messages of a logical channel, oldest first, with dividers between upgraded rooms. An open
+/// thread has its root and replies instead.
+fn timeline<'a>(state: &'a AppState, id: &OwnedRoomId) -> Vec<TimelineItem<'a>> {
+    if let Some(root) = state.thread.as_ref() {
+        return match state.channels.get(id) {
+            Some(channel) => thread_timeline(state, channel, root),
+            None => vec![],
+        };
+    }
+
+    let mut items = vec![];
+    for (i, channel) in channel_chain(state, id).into_iter().rev().enumerate() {
+        if i != 0 {
+            items.push(TimelineItem::Divider(format!("--- room upgraded ({}) ---", channel.name)));
+        }
+
+        for (id, message) in channel.message_ids.iter().filter_map(|v| channel.messages.get(v).map(|m| (v, m))) {
+            if channel.gaps.contains_key(id) {
+                items.push(TimelineItem::Gap(channel, id));
+            }
+            // replies in threads are kept to the thread's view
+            if message.thread.is_none() && !state.policies.hides(&message.user) && !state.filters.hides(channel.room.room_id().as_str(), &message.user, &message.content) {
+                items.push(TimelineItem::Message(channel, message));
+            }
+        }
+    }
+    items
+}
+
+fn thread_timeline<'a>(state: &'a AppState, channel: &'a Channel, root: &OwnedEventId) -> Vec<TimelineItem<'a>> {
+    let replies = channel.threads.get(root).into_iter().flatten();
+    std::iter::once(root)
+        .chain(replies)
+        .filter_map(|v| channel.messages.get(v))
+        .filter(|v| !state.policies.hides(&v.user) && !state.filters.hides(channel.room.room_id().as_str(), &v.user, &v.content))
+        .map(|v| TimelineItem::Message(channel, v))
+        .collect()
+}
+
+fn selected_item(state: &AppState) -> Option<TimelineItem<'_>> {
+    let items = timeline(state, state.current_channel.as_ref()?);
+    let index = items.len().checked_sub(state.messages_state.selected()? + 1)?;
+    items.into_iter().nth(index)
+}
+
+/// The messages from the quote mark to the selected one as a markdown quote, or just the selected
+/// one if nothing's marked in this channel. Also returns how many messages were quoted.
+fn quote_selection(state: &AppState) -> Option<(String, usize)> {
+    let items = timeline(state, state.current_channel.as_ref()?);
+    let selected = items.len().checked_sub(state.messages_state.selected()? + 1)?;
+    let mark = state.quote_mark.as_ref().and_then(|mark| items.iter().position(|v| matches!(v, TimelineItem::Message(_, m) if m.id == *mark))).unwrap_or(selected);
+
+    let messages: Vec<_> = items[mark.min(selected)..=mark.max(selected)].iter().filter_map(|v| match v {
+        TimelineItem::Message(_, message) => Some(message),
+        _ => None,
+    }).collect();
+    if messages.is_empty() {
+        return None;
+    }
+
+    let contents: Vec<_> = messages.iter().map(|v| v.media.as_ref().and_then(media::summary).unwrap_or_else(|| v.content.clone())).collect();
+    let quote = quote::format(messages.iter().zip(contents.iter()).map(|(v, content)| {
+        (v.user.trim_start_matches('@').split(':').next().unwrap_or_default(), u64::from(v.timestamp) as i64, content.as_str())
+    }), &state.clock);
+    Some((quote, messages.len()))
+}
+
+fn selected_message(state: &AppState) -> Option<(&Channel, &Message)> {
+    match selected_item(state)? {
+        TimelineItem::Message(channel, message) => Some((channel, message)),
+        _ => None,
+    }
+}
+
+/// Records the history skipped by limited syncs, so it can be loaded later.
+fn handle_gaps(response: &SyncResponse, lock: &mut MutexGuard<AppState>) {
+    for (id, room) in response.rooms.join.iter() {
+        if room.timeline.limited {
+            if let Some(prev_batch) = room.timeline.prev_batch.clone() {
+                let batch: Vec<_> = room.timeline.events.iter().filter_map(|v| v.event_id()).collect();
+                handle_gap(id, batch, prev_batch, lock);
+            }
+        }
+    }
+}
+
+/// Adds channels for rooms joined since startup, like ones joined from another client, and lists
+/// them. Event handlers run before this, so a room whose first message is in this sync already has
+/// a channel from it and only needs listing. Messages that came before the channel are loaded as
+/// history once it's opened.
+fn handle_joined(response: &SyncResponse, lock: &mut MutexGuard<AppState>) {
+    for id in response.rooms.join.keys().filter(|v| !lock.channel_ids.contains(*v)).cloned().collect::<Vec<_>>() {
+        let room = match lock.client.get_joined_room(&id) {
+            Some(v) => v,
+            None => continue,
+        };
+        // upgraded rooms share a sidebar entry with their successor
+        if room.tombstone().map(|v| lock.channels.contains_key(&v.replacement_room)).unwrap_or(false) {
+            continue;
+        }
+        if !lock.channels.contains_key(&id) {
+            let name = lock.names.name(&id).map(String::from).or_else(|| room.name()).unwrap_or_else(|| id.to_string());
+            reducer::apply(lock, AppEvent::RoomJoined(Box::new(Channel::new(name, room))));
+        }
+        reducer::apply(lock, AppEvent::RoomListed(id));
+    }
+}
+
+/// Archives the channels of rooms we've left, even from another client, and brings them back if
+/// we rejoin.
+fn handle_left(response: &SyncResponse, lock: &mut MutexGuard<AppState>) {
+    let user_id = lock.client.user_id().map(|v| v.to_owned());
+    for (id, room) in response.rooms.leave.iter() {
+        let removed = user_id.as_ref().and_then(|v| removal(room, v));
+        reducer::apply(lock, AppEvent::RoomLeft { room: id.clone(), left: true, removed });
+    }
+    for id in response.rooms.join.keys() {
+        reducer::apply(lock, AppEvent::RoomLeft { room: id.clone(), left: false, removed: None });
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct MemberEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    #[serde(default)]
+    state_key: String,
+    sender: OwnedUserId,
+    #[serde(default)]
+    content: serde_json::Value,
+}
+
+/// Who kicked or banned us from a room we've left and why, or `None` if we left it ourselves.
+fn removal(room: &LeftRoom, user_id: &UserId) -> Option<String> {
+    let state = room.state.events.iter().filter_map(|v| v.deserialize_as::<MemberEvent>().ok());
+    let timeline = room.timeline.events.iter().filter_map(|v| v.event.deserialize_as::<MemberEvent>().ok());
+    let event = state.chain(timeline).filter(|v| v.event_type == "m.room.member" && v.state_key == user_id.as_str()).last()?;
+    let how = match event.content.get("membership").and_then(|v| v.as_str())? {
+        "ban" => "banned",
+        "leave" if event.sender.as_str() != user_id.as_str() => "kicked",
+        _ => return None,
+    };
+    let reason = event.content.get("reason").and_then(|v| v.as_str()).filter(|v| !v.is_empty()).map(|v| format!(": {}", v)).unwrap_or_default();
+    Some(format!("{} by {}{}", how, event.sender, reason))
+}
+
+/// Records a gap before a limited sync batch if the room already had history from before it.
+fn handle_gap(id: &OwnedRoomId, batch: Vec<OwnedEventId>, prev_batch: String, lock: &mut MutexGuard<AppState>) {
+    let channel = match lock.channels.get_mut(id) {
+        Some(v) => v,
+        None => return,
+    };
+
+    if !channel.message_ids.iter().any(|v| !batch.contains(v)) {
+        return;
+    }
+
+    if let Some(first) = batch.into_iter().find(|v| channel.messages.contains_key(v)) {
+        channel.gaps.insert(first, prev_batch);
+    }
+}
+
+fn handle_new_message(id: &OwnedRoomId, message: OriginalSyncMessageLikeEvent<RoomMessageEventContent>, raw: &RawValue, encryption: trust::Encryption, position: StreamPosition, lock: &mut MutexGuard<AppState>) {
+    let event = match message.content.relates_to {
+        Some(Relation::Replacement(edit)) => AppEvent::Edited {
+            room: id.clone(),
+            target: edit.event_id,
+            sender: message.sender.to_string(),
+            edit: Edit {
+                content: edit.new_content.body().to_string(),
+                formatted: html::formatted(&edit.new_content.msgtype),
+                timestamp: message.origin_server_ts.0,
+            },
+        },
+
+        _ => {
+            let imported = historical::is_imported(raw);
+            let (reply_to, thread) = match &message.content.relates_to {
+                Some(Relation::Reply { in_reply_to }) => (Some(in_reply_to.event_id.clone()), None),
+                // the reply in a thread is only a fallback for clients without threads, unless it says otherwise
+                Some(Relation::Thread(thread)) => (Some(thread.in_reply_to.event_id.clone()).filter(|_| !thread.is_falling_back), Some(thread.event_id.clone())),
+                _ => (None, None),
+            };
+            let body = message.content.body();
+            let message = Message {
+                id: message.event_id.clone(),
+                user: message.sender.to_string(),
+                edited: None,
+                content: if reply_to.is_some() { reply::strip_fallback(body) } else { body }.to_string(),
+                formatted: html::formatted(&message.content.msgtype),
+                media: match message.content.msgtype {
+                    MessageType::Video(_) | MessageType::Image(_) | MessageType::Audio(_) | MessageType::File(_) => Some(message.content.msgtype),
+                    _ => None,
+                },
+                timestamp: message.origin_server_ts.as_secs(),
+                reactions: vec![],
+                highlighted: false,
+                translation: None,
+                imported,
+                preview: None,
+                call: None,
+                encryption,
+                details: details::fields(raw),
+                reply_to,
+                thread,
+            };
+
+            let position = match position {
+                StreamPosition::End if imported => StreamPosition::Imported,
+                v => v,
+            };
+            AppEvent::MessageAdded { room: id.clone(), message: Box::new(message), position }
+        }
+    };
+    reducer::apply(lock, event);
+}
+
+/// Starts a channel for a joined room we haven't seen yet, like one whose first message just arrived.
+/// It's listed by `load_rooms` or `handle_joined` once the sync it came in is done.
+async fn add_channel(room: Room, lock: &mut MutexGuard<'_, AppState>) {
+    if let (false, Room::Joined(room)) = (lock.channels.contains_key(room.room_id()), room) {
+        let name = room.display_name().await.map(|v| v.to_string()).unwrap_or_else(|_| String::from("[unknown room]"));
+        reducer::apply(lock, AppEvent::RoomJoined(Box::new(Channel::new(name, room))));
+    }
+}
+
+/// Adds an `m.call.*` event to its channel's timeline.
+fn handle_call(id: &RoomId, event: &AnySyncMessageLikeEvent, position: StreamPosition, lock: &mut MutexGuard<AppState>) {
+    let call = match call::CallEvent::of(&event.event_type()) {
+        Some(v) => v,
+        None => return,
+    };
+    match lock.channels.get(id) {
+        Some(channel) if !channel.messages.contains_key(event.event_id()) => (),
+        _ => return,
+    }
+
+    let message = Message {
+        id: event.event_id().to_owned(),
+        user: event.sender().to_string(),
+        edited: None,
+        content: call.describe().to_string(),
+        formatted: None,
+        media: None,
+        timestamp: event.origin_server_ts().as_secs(),
+        reactions: vec![],
+        highlighted: false,
+        translation: None,
+        imported: false,
+        preview: None,
+        call: Some(call),
+        encryption: trust::Encryption::Unknown,
+        details: vec![],
+        reply_to: None,
+        thread: None,
+    };
+    reducer::apply(lock, AppEvent::MessageAdded { room: id.to_owned(), message: Box::new(message), position });
+}
+
+/// Handles an event that has just been decrypted.
+fn handle_decrypted(id: &OwnedRoomId, event: TimelineEvent, position: StreamPosition, lock: &mut MutexGuard<AppState>) {
+    let TimelineEvent { event, encryption_info } = event;
+    match event.deserialize() {
+        Ok(AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(MessageLikeEvent::Original(v)))) => {
+            handle_new_message(id, v.into(), event.json(), encryption_info.into(), position, lock);
+        }
+
+        Ok(AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::Reaction(MessageLikeEvent::Original(v)))) => {
+            handle_new_reaction(id, v.into(), lock);
+        }
+
+        _ => (),
+    }
+}
+
+/// Tries to decrypt an encrypted event, showing a placeholder with the reason if that fails.
+async fn handle_encrypted(room: &Joined, event: Raw<OriginalSyncRoomEncryptedEvent>, position: StreamPosition, lock: &mut MutexGuard<'_, AppState>) {
+    let id = room.room_id().to_owned();
+    let error = match room.decrypt_event(&event).await {
+        Ok(v) => {
+            handle_decrypted(&id, v, position, lock);
+            return;
+        }
+
+        Err(e) => e.to_string(),
+    };
+
+    let parsed = match event.deserialize() {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+    let channel = lock.channels.get_mut(&id).unwrap();
+    if channel.messages.contains_key(&parsed.event_id) || channel.undecrypted.contains_key(&parsed.event_id) {
+        return;
+    }
+
+    channel.undecrypted.insert(parsed.event_id.clone(), Undecrypted {
+        session_id: keys::session_id(&event),
+        event,
+        error,
+    });
+
+    // edits are applied once they can be read, so they don't get a row of their own
+    if let Some(matrix_sdk::ruma::events::room::encrypted::Relation::Replacement(_)) = parsed.content.relates_to {
+        return;
+    }
+
+    let message = Message {
+        id: parsed.event_id,
+        user: parsed.sender.to_string(),
+        edited: None,
+        content: String::new(),
+        formatted: None,
+        media: None,
+        timestamp: parsed.origin_server_ts.as_secs(),
+        reactions: vec![],
+        highlighted: false,
+        translation: None,
+        imported: false,
+        preview: None,
+        call: None,
+        encryption: trust::Encryption::Unknown,
+        details: vec![],
+        reply_to: None,
+        thread: None,
+    };
+    reducer::apply(lock, AppEvent::MessageAdded { room: id, message: Box::new(message), position });
+}
+
+async fn retry_decryption(state: Arc<Mutex<AppState>>) {
+    decrypt_pending(&mut state.lock().await).await;
+}
+
+/// Tries again to decrypt every event we couldn't, in case their keys have arrived.
+async fn decrypt_pending(lock: &mut MutexGuard<'_, AppState>) {
+    let pending: Vec<_> = lock.channels.iter().flat_map(|(id, channel)| {
+        channel.undecrypted.iter().map(|(event_id, v)| (id.clone(), channel.room.clone(), event_id.clone(), v.event.clone()))
+    }).collect();
+
+    for (id, room, event_id, event) in pending {
+        if let Ok(v) = room.decrypt_event(&event).await {
+            lock.channels.get_mut(&id).unwrap().undecrypted.remove(&event_id);
+            let position = reducer::remove(lock, &id, &event_id);
+            handle_decrypted(&id, v, position, lock);
+        }
+    }
+}
+
+fn handle_new_reaction(id: &OwnedRoomId, reaction: OriginalSyncMessageLikeEvent<ReactionEventContent>, lock: &mut MutexGuard<AppState>) {
+    reducer::apply(lock, AppEvent::Reacted {
+        room: id.clone(),
+        target: reaction.content.relates_to.event_id,
+        reaction: Reaction {
+            id: reaction.event_id,
+            key: reaction.content.relates_to.key,
+            sender: reaction.sender.to_string(),
+        },
+    });
+}
+
+/// Reads a room's policy rules from its stored state. Sync only sends the state that changed since
+/// the last run, so without this the rules set before then would be missing.
+async fn load_policy_rules(state: &mut AppState, room: &Joined) {
+    for event_type in policy::EVENT_TYPES {
+        if let Ok(events) = room.get_state_events(event_type.into()).await {
+            for event in events.iter() {
+                state.policies.update(room.room_id(), event);
+            }
+        }
+    }
+}
+
+/// Loads the page of history before the oldest message in a channel. The page is fetched with the
+/// app state unlocked, so the UI carries on while the server answers.
+async fn load_older(state: Arc<Mutex<AppState>>, id: &OwnedRoomId) {
+    let (room, from) = match older_page(&*state.lock().await, id).await {
+        Some(v) => v,
+        None => return,
+    };
+    if let Ok(page) = matrix::messages_before(&room, from.as_deref(), 50).await {
+        add_older(state.clone(), &mut state.lock().await, id, room, page).await;
+    }
+}
+
+/// The room to ask for the page before a channel's oldest message, and the token it starts at,
+/// unless there's nothing older to load.
+async fn older_page(state: &AppState, id: &OwnedRoomId) -> Option<(Joined, Option<String>)> {
+    let sync_token = state.client.sync_token().await;
+    match state.channels.get(id) {
+        Some(v) if !v.at_top && !v.archived => Some((v.room.clone(), v.messages_prev_batch.clone().or(sync_token))),
+        _ => None,
+    }
+}
+
+/// Puts a page of older history before a channel's oldest message.
+async fn add_older(state2: Arc<Mutex<AppState>>, state: &mut MutexGuard<'_, AppState>, id: &OwnedRoomId, room: Joined, page: Messages) {
+    if !state.channels.contains_key(id) {
+        return;
+    }
+    let mut loaded = vec![];
+    for event in page.chunk.into_iter() {
+        match event.event.deserialize() {
+            Ok(AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(MessageLikeEvent::Original(v)))) => {
+                loaded.push(v.event_id.clone());
+                handle_new_message(id, v.into(), event.event.json(), event.encryption_info.into(), StreamPosition::Start, state);
+            }
+
+            Ok(AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomEncrypted(MessageLikeEvent::Original(v)))) => {
+                loaded.push(v.event_id.clone());
+                handle_encrypted(&room, event.event.cast(), StreamPosition::Start, state).await;
+            }
+
+            Ok(AnyTimelineEvent::MessageLike(v)) if call::CallEvent::of(&v.event_type()).is_some() => {
+                loaded.push(v.event_id().to_owned());
+                handle_call(id, &v.into(), StreamPosition::Start, state);
+            }
+
+            _ => (),
+        }
+    }
+
+    // only moved past once the whole page is in, so a cancelled load starts it over
+    let current = state.channels.get_mut(id).unwrap();
+    current.at_top = page.end.is_none();
+    current.messages_prev_batch = page.end;
+    tokio::task::spawn(backfill_relations(state2, room, loaded));
+}
+
+/// Pages back through a channel until `timestamp` (in milliseconds) is loaded, and selects the first
+/// message from then on. Servers that can look up events by time say which message that is. This
+/// runs as the room's pagination job, so the app state is only locked between pages.
+async fn jump_to_time(state: Arc<Mutex<AppState>>, id: OwnedRoomId, timestamp: u64) {
+    let (room, prefix) = {
+        let lock = state.lock().await;
+        match lock.channels.get(&id) {
+            Some(v) => (v.room.clone(), lock.server.timestamp_to_event()),
+            None => return,
+        }
+    };
+    let (target, timestamp) = match prefix {
+        Some(prefix) => match matrix::event_after(&room, prefix, timestamp).await {
+            Ok((event_id, timestamp)) => (Some(event_id), timestamp),
+            Err(_) => (None, timestamp),
+        },
+
+        None => (None, timestamp),
+    };
+    let seconds = UInt::new_saturating(timestamp / 1000);
+
+    for page in 0..MAX_JUMP_PAGES {
+        let mut lock = state.lock().await;
+        let reached = match lock.channels.get(&id) {
+            Some(channel) => {
+                let oldest = channel.message_ids.first().and_then(|v| channel.messages.get(v)).map(|v| v.timestamp);
+                channel.at_top || oldest.map(|v| v <= seconds).unwrap_or(false)
+            }
+            None => true,
+        };
+        if reached {
+            break;
+        }
+        if let Some(jump) = lock.jumping.as_mut() {
+            jump.pages = page + 1;
+        }
+        drop(lock);
+        load_older(state.clone(), &id).await;
+    }
+
+    let mut lock = state.lock().await;
+    lock.jumping = None;
+    let channel = match lock.channels.get(&id) {
+        Some(v) => v,
+        None => return,
+    };
+    let found = target.filter(|v| channel.messages.contains_key(v))
+        .or_else(|| channel.message_ids.iter().find(|v| channel.messages.get(*v).map(|v| v.timestamp >= seconds).unwrap_or(false)).cloned());
+    // the timeline is selected from the bottom, and nothing from then on means the newest message
+    let items = timeline(&lock, &id);
+    let index = found.and_then(|found| items.iter().position(|v| matches!(v, TimelineItem::Message(_, message) if message.id == found)));
+    let count = items.len();
+    if count != 0 {
+        lock.messages_state.select(Some(index.map(|v| count - v - 1).unwrap_or(0)));
+        lock.mode = Mode::ScrollMessages;
+    }
+}
+
+/// Loads the messages missing at a gap left by a limited sync, just before `before`. Like
+/// `load_older`, only applying the page locks the app state.
+async fn fill_gap(state: Arc<Mutex<AppState>>, room: Joined, before: OwnedEventId, token: String) {
+    if let Ok(v) = matrix::messages_before(&room, Some(&token), 50).await {
+        let mut lock = state.lock().await;
+        let id = room.room_id().to_owned();
+        if !lock.channels.contains_key(&id) {
+            return;
+        }
+        let mut filled = v.end.is_none();
+        let mut loaded: Vec<OwnedEventId> = vec![];
+        for event in v.chunk.into_iter() {
+            if let Ok(Some(event_id)) = event.event.get_field::<OwnedEventId>("event_id") {
+                filled |= lock.channels[&id].messages.contains_key(&event_id);
+            }
+
+            let position = StreamPosition::Before(loaded.last().unwrap_or(&before).clone());
+            match event.event.deserialize() {
+                Ok(AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(MessageLikeEvent::Original(v)))) => {
+                    loaded.push(v.event_id.clone());
+                    handle_new_message(&id, v.into(), event.event.json(), event.encryption_info.into(), position, &mut lock);
+                }
+
+                Ok(AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomEncrypted(MessageLikeEvent::Original(v)))) => {
+                    loaded.push(v.event_id.clone());
+                    handle_encrypted(&room, event.event.cast(), position, &mut lock).await;
+                }
+
+                Ok(AnyTimelineEvent::MessageLike(v)) if call::CallEvent::of(&v.event_type()).is_some() => {
+                    loaded.push(v.event_id().to_owned());
+                    handle_call(&id, &v.into(), position, &mut lock);
+                }
+
+                _ => (),
+            }
+        }
+
+        // the rest of the gap now sits before the oldest message we just loaded
+        let channel = lock.channels.get_mut(&id).unwrap();
+        channel.gaps.remove(&before);
+        if let (false, Some(end)) = (filled, v.end) {
+            channel.gaps.insert(loaded.last().cloned().unwrap_or(before), end);
+        }
+        tokio::task::spawn(backfill_relations(state.clone(), room, loaded));
+    }
+}
+
+async fn backfill_relations(state: Arc<Mutex<AppState>>, room: Joined, message_ids: Vec<OwnedEventId>) {
+    if !state.lock().await.server.relations() {
+        return;
+    }
+
+    let client = room.client();
+    let mut relations = vec![];
+    for message_id in message_ids.iter() {
+        let mut from = None;
+        loop {
+            let mut request = get_relating_events::v1::Request::new(room.room_id(), message_id);
+            request.from = from.as_deref();
+            match client.send(request, None).await {
+                Ok(response) => {
+                    relations.extend(response.chunk);
+                    match response.next_batch {
+                        Some(next) => from = Some(next),
+                        None => break,
+                    }
+                }
+
+                Err(_) => break,
+            }
+        }
+    }
+
+    let id = room.room_id().to_owned();
+    let mut lock = state.lock().await;
+    // chunks are most recent first, so apply them backwards for the newest edit to win
+    for event in relations.into_iter().rev() {
+        match event.deserialize() {
+            Ok(AnyMessageLikeEvent::RoomMessage(MessageLikeEvent::Original(v))) => {
+                if let Some(Relation::Replacement(_)) = v.content.relates_to {
+                    handle_new_message(&id, v.into(), event.json(), trust::Encryption::Unknown, StreamPosition::End, &mut lock);
+                }
+            }
+
+            Ok(AnyMessageLikeEvent::Reaction(MessageLikeEvent::Original(v))) => {
+                handle_new_reaction(&id, v.into(), &mut lock);
+            }
+
+            _ => (),
+        }
+    }
+}
+
+/// Sends the input box's contents to the current channel, or runs them as a command.
+/// Returns false if the client should quit.
+async fn submit_input(state2: Arc<Mutex<AppState>>, state: &mut MutexGuard<'_, AppState>) -> bool {
+    if state.input_text.is_empty() {
+        return true;
+    }
+
+    if let Some(prompt) = state.secret.take() {
+        let secret = std::mem::take(&mut state.input_text);
+        state.input_char_pos = 0;
+        state.input_byte_pos = 0;
+        submit_secret(state, prompt.purpose, secret).await;
+        return true;
+    }
+
+    if state.code_block.is_none() {
+        if let Some(expanded) = commands::expand_alias(&state.input_text, &state.config.aliases) {
+            state.input_text = expanded;
+        }
+    }
+
+    let content = match state.code_block.as_ref() {
+        Some(code) => Some(composer::code_block(&state.input_text, code.language.as_deref())),
+
+        None => match commands::parse(&state.input_text) {
+            Some(Command::Quit) => {
+                let (pending, failed) = (state.outbox.pending(), state.outbox.failed());
+                if pending + failed == 0 {
+                    return false;
+                }
+
+                let mut lines = vec![];
+                if pending > 0 {
+                    lines.push(format!("{} message(s) still sending.", pending));
+                }
+                if failed > 0 {
+                    lines.push(format!("{} message(s) failed to send; w keeps them for next time.", failed));
+                }
+                lines.push(String::from("w: wait for them and quit, d: discard them and quit, Esc: stay"));
+                state.popup = Some(Popup {
+                    title: String::from("Quit"),
+                    lines,
+                    action: Some(PopupAction::Quit),
+                });
+                None
+            }
+
+            Some(Command::Code(language)) => {
+                state.code_block = Some(CodeBlock { language });
+                None
+            }
+
+            Some(Command::Video(path)) => {
+                start_upload(state2.clone(), state, Path::new(&path), Some(mime::VIDEO)).await;
+                None
+            }
+
+            Some(Command::Image(path)) => {
+                start_upload(state2.clone(), state, Path::new(&path), Some(mime::IMAGE)).await;
+                None
+            }
+
+            Some(Command::Upload(path)) if path.is_empty() => {
+                let picker = picker::Picker::new(&std::env::current_dir().unwrap_or_default());
+                state.popup = Some(Popup {
+                    title: String::from("Upload file"),
+                    lines: picker.lines(),
+                    action: Some(PopupAction::PickFile(picker)),
+                });
+                None
+            }
+
+            Some(Command::Upload(path)) => {
+                start_upload(state2.clone(), state, Path::new(&path), None).await;
+                None
+            }
+
+            Some(Command::Verify(user_id)) => {
+                let user_id = if user_id.is_empty() { state.client.user_id().map(|v| v.to_string()).unwrap_or_default() } else { user_id };
+                if let Err(e) = verification::request(&state.client, &user_id).await {
+                    show_error(state, "Verification failed", e);
+                }
+                None
+            }
+
+            Some(Command::ExportKeys(path)) => {
+                ask_secret(state, "export passphrase", SecretPurpose::ExportKeys(PathBuf::from(path)));
+                None
+            }
+
+            Some(Command::ImportKeys(path)) => {
+                ask_secret(state, "import passphrase", SecretPurpose::ImportKeys(PathBuf::from(path)));
+                None
+            }
+
+            Some(Command::ImportHistory(path)) => {
+                match import_history(state, Path::new(&path)) {
+                    Ok(popup) => state.popup = Some(popup),
+                    Err(e) => show_error(state, "Import failed", e),
+                }
+                None
+            }
+
+            Some(Command::InviteFile(path)) => {
+                let room = state.current_channel.as_ref().and_then(|v| state.channels.get(v)).map(|v| (v.room.clone(), v.name.clone()));
+                let allowed = room.as_ref().map(|(v, _)| state.permissions.get(v.room_id()).invite).unwrap_or(true);
+                match (room, invite::read(Path::new(&path))) {
+                    (Some(_), _) if !allowed => show_error(state, "Invite failed", String::from("You don't have permission to invite people here.")),
+                    (Some((room, name)), Ok((users, invalid))) if !users.is_empty() => {
+                        let mut lines = vec![format!("Invite {} user(s) to {}?", users.len(), name)];
+                        if !invalid.is_empty() {
+                            lines.push(format!("Skipping {} line(s) that aren't user ids: {}", invalid.len(), invalid.join(", ")));
+                        }
+                        lines.push(String::from("y: invite them, Esc: cancel"));
+                        state.popup = Some(Popup {
+                            title: String::from("Invite"),
+                            lines,
+                            action: Some(PopupAction::InviteFile(room, users)),
+                        });
+                    }
+
+                    (None, _) => show_error(state, "Invite failed", String::from("no channel selected")),
+                    (_, Ok(_)) => show_error(state, "Invite failed", format!("{} has no user ids", path)),
+                    (_, Err(e)) => show_error(state, "Invite failed", e),
+                }
+                None
+            }
+
+            Some(Command::Date(date)) => {
+                let midnight = chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok().and_then(|v| chrono::Local.from_local_datetime(&v.and_hms_opt(0, 0, 0)?).earliest());
+                match (midnight, state.current_channel.clone()) {
+                    (Some(midnight), Some(id)) => {
+                        let jump = jump_to_time(state2, id.clone(), midnight.timestamp_millis() as u64);
+                        if state.tasks.spawn(&id, tasks::Job::Paginate, jump) {
+                            state.jumping = Some(Jump { room: id, date, pages: 0 });
+                        } else {
+                            show_error(state, "Can't jump yet", String::from("Older messages are still loading here. Try again once they're in."));
+                        }
+                    }
+                    (None, _) => show_error(state, "Bad date", format!("{} isn't a date like 2023-05-01", date)),
+                    (_, None) => show_error(state, "Bad date", String::from("no channel selected")),
+                }
+                None
+            }
+
+            Some(Command::Transform(transforms, text)) => {
+                let room_id = state.current_channel.as_ref().map(|v| v.to_string()).unwrap_or_default();
+                Some(composer::transformed(&text, &transforms, &state.config.composer(&room_id)))
+            }
+
+            Some(Command::Policy) => {
+                if let Some(id) = state.current_channel.clone() {
+                    if let Some(room) = state.channels.get(&id).map(|v| v.room.clone()) {
+                        load_policy_rules(state, &room).await;
+                    }
+                    let mut lines: Vec<_> = state.policies.rules(&id).iter().map(|v| v.summary()).collect();
+                    if lines.is_empty() {
+                        lines.push(String::from("This room has no policy rules."));
+                    }
+                    let subscribed = if state.policies.subscribed(&id) { "subscribed" } else { "not subscribed" };
+                    state.popup = Some(Popup {
+                        title: format!("Policy rules ({})", subscribed),
+                        lines,
+                        action: None,
+                    });
+                }
+                None
+            }
+
+            Some(Command::PolicyBan(entity, reason)) => {
+                if let Some(room) = state.current_channel.as_ref().and_then(|v| state.channels.get(v)).map(|v| v.room.clone()) {
+                    let (event_type, state_key, content) = policy::ban_event(&entity, &reason);
+                    if let Err(e) = room.send_state_event_raw(content, event_type, &state_key).await {
+                        show_error(state, "Couldn't add the rule", e.to_string());
+                    }
+                }
+                None
+            }
+
+            Some(Command::PolicySubscribe(subscribed)) => {
+                if let Some(id) = state.current_channel.clone() {
+                    state.policies.set_subscribed(&id, subscribed);
+                    if let Some(room) = state.channels.get(&id).map(|v| v.room.clone()).filter(|_| subscribed) {
+                        load_policy_rules(state, &room).await;
+                    }
+                }
+                None
+            }
+
+            Some(Command::Security) => {
+                show_security(state).await;
+                None
+            }
+
+            Some(Command::Server) => {
+                state.popup = Some(Popup {
+                    title: String::from("Server"),
+                    lines: state.server.summary(),
+                    action: None,
+                });
+                None
+            }
+
+            Some(Command::Stats) => {
+                if let Some(id) = state.current_channel.clone() {
+                    // upgraded rooms count their predecessors' history too
+                    let messages = channel_chain(state, &id).into_iter().flat_map(|v| v.messages.values()).map(|v| (v.user.as_str(), u64::from(v.timestamp) as i64));
+                    let stats = stats::Stats::new(messages);
+                    state.popup = Some(Popup {
+                        title: String::from("Stats"),
+                        lines: stats.lines(&state.symbols),
+                        action: None,
+                    });
+                }
+                None
+            }
+
+            Some(Command::Whois(user_id)) => {
+                show_profile(state, &user_id).await;
+                None
+            }
+
+            Some(Command::UserSearch(term)) => {
+                match users::search(&state.client, &term).await {
+                    Ok(found) => {
+                        let homeserver = state.client.homeserver().await;
+                        state.popup = Some(Popup {
+                            title: format!("Users matching {}", term),
+                            lines: users::lines(&found, &homeserver, false, can_invite(state)),
+                            action: Some(PopupAction::Users(found, false)),
+                        });
+                    }
+
+                    Err(e) => show_error(state, "Couldn't search users", e),
+                }
+                None
+            }
+
+            Some(Command::Search(query)) => {
+                let search = search::Search::new(&query, &state.search);
+                state.popup = Some(Popup {
+                    title: String::from("Search messages"),
+                    lines: search_lines(state, &search),
+                    action: Some(PopupAction::Search(search)),
+                });
+                None
+            }
+
+            Some(Command::Outbox) => {
+                show_outbox(state, 0);
+                None
+            }
+
+            Some(Command::Widgets) => {
+                if let Some(room) = state.current_channel.as_ref().and_then(|v| state.channels.get(v)).map(|v| v.room.clone()) {
+                    let widgets = widget::list(&room).await;
+                    state.popup = Some(Popup {
+                        title: String::from("Widgets"),
+                        lines: widget::lines(&widgets, false),
+                        action: Some(PopupAction::Widgets(widgets, false)),
+                    });
+                }
+                None
+            }
+
+            Some(Command::Translate) => {
+                let own = state.client.user_id().map(|v| v.to_string()).unwrap_or_default();
+                let last = state.current_channel.as_ref().and_then(|id| {
+                    let channel = state.channels.get(id)?;
+                    let message = channel.message_ids.iter().rev().filter_map(|v| channel.messages.get(v)).find(|v| v.user != own && v.media.is_none() && !v.content.is_empty())?;
+                    Some((id.clone(), message.id.clone(), message.content.clone()))
+                });
+                if let Some((room_id, event_id, text)) = last {
+                    translate_message(state2.clone(), state.config.translate.command.clone(), room_id, event_id, text, true);
+                }
+                None
+            }
+
+            Some(Command::AutoTranslate) => {
+                if let Some(id) = state.current_channel.as_ref().map(|v| v.to_string()) {
+                    let room = state.config.rooms.entry(id).or_default();
+                    room.auto_translate = !room.auto_translate;
+                }
+                None
+            }
+
+            Some(Command::Previews) => {
+                if let Some(id) = state.current_channel.as_ref().map(|v| v.to_string()) {
+                    let previews = !state.config.previews(&id);
+                    state.config.rooms.entry(id).or_default().previews = Some(previews);
+                }
+                None
+            }
+
+            Some(Command::Dnd(args)) => {
+                let now = chrono::Local::now();
+                match args.as_str() {
+                    "" => {
+                        if !state.dnd.stop(now) {
+                            state.dnd.start(None, now);
+                        }
+                    }
+
+                    "off" => {
+                        state.dnd.stop(now);
+                    }
+
+                    _ => match dnd::parse_duration(&args) {
+                        Some(duration) => state.dnd.start(Some(duration), now),
+                        None => show_error(state, "Can't turn on do not disturb", format!("{} isn't a duration like 30m or 1h30m.", args)),
+                    },
+                }
+                None
+            }
+
+            Some(Command::Private) => {
+                if let Some(id) = state.current_channel.as_ref().map(|v| v.to_string()) {
+                    let private = !state.config.private(&id);
+                    state.config.rooms.entry(id).or_default().private = Some(private);
+                }
+                None
+            }
+
+            Some(Command::Leave) => {
+                if let Some(room) = state.current_channel.as_ref().and_then(|v| state.channels.get(v)).filter(|v| !v.archived).map(|v| v.room.clone()) {
+                    match room.leave().await {
+                        Ok(()) => {
+                            if let Some(channel) = state.channels.get_mut(room.room_id()) {
+                                channel.archived = true;
+                            }
+                        }
+
+                        Err(e) => show_error(state, "Couldn't leave", e.to_string()),
+                    }
+                }
+                None
+            }
+
+            Some(Command::Encrypt) => {
+                let room = state.current_channel.as_ref().and_then(|v| state.channels.get(v)).filter(|v| !v.archived).map(|v| (v.room.clone(), v.name.clone()));
+                match room {
+                    Some((room, _)) if room.is_encrypted() => show_error(state, "Couldn't encrypt", String::from("This room is already encrypted.")),
+                    Some((room, _)) if !state.permissions.get(room.room_id()).encrypt => show_error(state, "Couldn't encrypt", String::from("You don't have permission to turn on encryption here.")),
+                    Some((room, name)) => {
+                        state.popup = Some(Popup {
+                            title: String::from("Encrypt room"),
+                            lines: vec![
+                                format!("Turn on end-to-end encryption in {}?", name),
+                                String::from("It can never be turned off again. Bridges, bots, and sessions without the keys won't be able to read new messages."),
+                                String::from("y: continue, Esc: cancel"),
+                            ],
+                            action: Some(PopupAction::Encrypt(room, false)),
+                        });
+                    }
+                    None => show_error(state, "Couldn't encrypt", String::from("no channel selected")),
+                }
+                None
+            }
+
+            Some(Command::Room) => {
+                if let Some(room) = state.current_channel.as_ref().and_then(|v| state.channels.get(v)).map(|v| v.room.clone()) {
+                    state.popup = Some(Popup {
+                        title: String::from("Room"),
+                        lines: room::lines(&state.client, &room, state.names.avatar(room.room_id())).await,
+                        action: None,
+                    });
+                }
+                None
+            }
+
+            Some(Command::Publish(published)) => {
+                if let Some(room) = state.current_channel.as_ref().and_then(|v| state.channels.get(v)).map(|v| v.room.clone()) {
+                    match room::set_published(&state.client, room.room_id(), published).await {
+                        Ok(()) => {
+                            state.popup = Some(Popup {
+                                title: String::from("Room"),
+                                lines: room::lines(&state.client, &room, state.names.avatar(room.room_id())).await,
+                                action: None,
+                            });
+                        }
+
+                        Err(e) => show_error(state, if published { "Couldn't publish" } else { "Couldn't unpublish" }, e),
+                    }
+                }
+                None
+            }
+
+            Some(Command::Members) => {
+                if let Some(room) = state.current_channel.as_ref().and_then(|v| state.channels.get(v)).map(|v| v.room.clone()) {
+                    state.popup = Some(Popup {
+                        title: String::from("Members"),
+                        lines: member_lines(state, &room).await,
+                        action: Some(PopupAction::Members(room.room_id().to_owned())),
+                    });
+                }
+                None
+            }
+
+            None => match composer::Sed::parse(&state.input_text) {
+                Some(sed) => match sed_edit(state, &sed) {
+                    Ok(content) => Some(content),
+                    Err(e) => {
+                        show_error(state, "Couldn't edit", e);
+                        return true;
+                    }
+                },
+
+                None => {
+                    let room_id = state.current_channel.as_ref().map(|v| v.to_string()).unwrap_or_default();
+                    let transliterate = state.config.rooms.get(&room_id).map(|v| v.transliterate.clone()).unwrap_or_default();
+                    let text = if transliterate.is_empty() {
+                        state.input_text.clone()
+                    } else {
+                        match pipe::run(&transliterate, &state.input_text).await {
+                            Ok(v) => v,
+                            Err(e) => {
+                                show_error(state, "Couldn't transliterate", e);
+                                return true;
+                            }
+                        }
+                    };
+                    Some(composer::message_content(&text, &state.config.composer(&room_id)))
+                }
+            },
+        },
+    };
+
+    if let Some(content) = content {
+        if composer::too_large(&content, current_room_encrypted(state)) {
+            let pieces = composer::split(&state.input_text, SPLIT_BYTES).len();
+            state.popup = Some(Popup {
+                title: String::from("Message too large"),
+                lines: vec![
+                    format!("This message is {}, more than the server accepts.", media::format_size(state.input_text.len() as u64)),
+                    format!("s: split it into {} messages, f: upload it as a text file, Esc: keep editing", pieces),
+                ],
+                action: Some(PopupAction::Oversized(state.input_text.clone())),
+            });
+            return true;
+        }
+
+        if !send_content(state, content).await {
+            return true;
+        }
+        state.code_block = None;
+        send_typing(state, false);
+    }
+
+    state.input_text.clear();
+    state.input_char_pos = 0;
+    state.input_byte_pos = 0;
+    // commands leave an edit open, so the draft waits for it to be sent
+    if state.editing.is_none() {
+        restore_draft(state);
+    }
+    true
+}
+
+/// An edit of our last text message in the current channel with a `s/old/new/` correction applied.
+fn sed_edit(state: &AppState, sed: &composer::Sed) -> Result<RoomMessageEventContent, String> {
+    if !state.server.relations() {
+        return Err(String::from(NO_EDITS));
+    }
+    let id = state.current_channel.as_ref().ok_or_else(|| String::from("No channel is open."))?;
+    let channel = state.channels.get(id).ok_or_else(|| String::from("No channel is open."))?;
+    let own = state.client.user_id().map(|v| v.as_str()).unwrap_or_default();
+    let message = channel.message_ids.iter().rev().filter_map(|v| channel.messages.get(v)).find(|v| v.user == own && v.media.is_none()).ok_or_else(|| String::from("You haven't sent a message here yet."))?;
+    let text = sed.apply(&message.content).ok_or_else(|| String::from("Your last message doesn't contain that."))?;
+    Ok(composer::replacement(message.id.clone(), composer::message_content(&text, &state.config.composer(id.as_str()))))
+}
+
+/// Sends a message to the current channel, unless it has new devices to ask about first.
+/// Returns false if the message is held back.
+async fn send_content(state: &mut MutexGuard<'_, AppState>, mut content: RoomMessageEventContent) -> bool {
+    if state.current_channel.as_ref().and_then(|v| state.channels.get(v)).map(|v| v.archived).unwrap_or(false) {
+        show_error(state, "Can't send", String::from("You've left this room, so it's read-only."));
+        return false;
+    }
+    if state.current_channel.as_ref().map(|v| !state.permissions.get(v).send).unwrap_or(false) {
+        show_error(state, "Can't send", String::from("You don't have permission to post in this room."));
+        return false;
+    }
+    // the edit or reply being written goes with the next message, unless that's already an edit
+    if content.relates_to.is_none() {
+        if let Some(event_id) = state.editing.take() {
+            content = composer::replacement(event_id, content);
+        } else if let Some(root) = state.thread.clone() {
+            // clients without threads see it as a reply to the thread's latest message
+            content.relates_to = Some(Relation::Thread(match state.reply_to.take() {
+                Some(event_id) => Thread::reply(root, event_id),
+                None => {
+                    let latest = state.current_channel.as_ref().and_then(|v| state.channels.get(v)).and_then(|v| v.threads.get(&root)).and_then(|v| v.last()).cloned();
+                    Thread::plain(root.clone(), latest.unwrap_or(root))
+                }
+            }));
+        } else if let Some(event_id) = state.reply_to.take() {
+            content.relates_to = Some(Relation::Reply { in_reply_to: InReplyTo::new(event_id) });
+        }
+    }
+
+    if let Some(room) = state.current_channel.as_ref().and_then(|v| state.channels.get(v)).map(|v| v.room.clone()) {
+        match keys::unreviewed_devices(&room, state.config.encryption.unverified_devices).await {
+            Ok(devices) if !devices.is_empty() => {
+                let mut lines = vec![String::from("This message would be encrypted for these unverified devices:")];
+                for device in devices.iter() {
+                    lines.push(format!("  {} {} {}", device.user_id(), device.device_id(), device.display_name().unwrap_or_default()));
+                }
+                lines.push(String::from("a: accept them and send, b: block them and send, Esc: cancel"));
+                state.popup = Some(Popup {
+                    title: String::from("New devices"),
+                    lines,
+                    action: Some(PopupAction::ReviewDevices(devices, content)),
+                });
+                return false;
+            }
+
+            Ok(_) => state.outbox.send(room, content),
+
+            Err(e) => {
+                show_error(state, "Couldn't check devices", e);
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Translates a message in the background to show under it, and in a popup if asked for.
+fn translate_message(state: Arc<Mutex<AppState>>, command: Vec<String>, room_id: OwnedRoomId, event_id: OwnedEventId, text: String, popup: bool) {
+    tokio::task::spawn(async move {
+        let result = if command.is_empty() { Err(String::from("No translation command is set in the config.")) } else { pipe::run(&command, &text).await };
+        let mut lock = state.lock().await;
+        match result {
+            Ok(translation) => {
+                if popup {
+                    let lines = text.lines().chain(std::iter::once("")).chain(translation.lines()).map(String::from).collect();
+                    lock.popup = Some(Popup {
+                        title: String::from("Translation"),
+                        lines,
+                        action: None,
+                    });
+                }
+                if let Some(message) = lock.channels.get_mut(&room_id).and_then(|v| v.messages.get_mut(&event_id)) {
+                    message.translation = Some(translation);
+                }
+            }
+
+            Err(e) if popup => show_error(&mut lock, "Couldn't translate", e),
+            Err(_) => (),
+        }
+    });
+}
+
+/// Loads the latest page of history the first time a channel without messages is opened, since
+/// otherwise it stays empty until someone speaks.
+fn backfill_on_open(state2: Arc<Mutex<AppState>>, state: &mut MutexGuard<'_, AppState>) {
+    let id = match state.current_channel.clone() {
+        Some(v) if !state.backfilled.contains(&v) => v,
+        _ => return,
+    };
+    state.backfilled.insert(id.clone());
+    if state.channels.get(&id).map(|v| v.message_ids.is_empty()).unwrap_or(false) {
+        state.tasks.spawn(&id.clone(), tasks::Job::Paginate, async move {
+            load_older(state2, &id).await;
+        });
+    }
+}
+
+/// Fetches previews for the links in the current channel's messages that are on screen, or about
+/// to be, if the channel wants them.
+fn request_previews(state2: Arc<Mutex<AppState>>, state: &mut MutexGuard<'_, AppState>, height: usize) {
+    let id = match state.current_channel.clone() {
+        Some(v) if state.config.previews(v.as_str()) => v,
+        _ => return,
+    };
+    let channel = match state.channels.get(&id) {
+        Some(v) => v,
+        None => return,
+    };
+
+    // the selection counts from the bottom, and each message takes at least a line
+    let skip = state.messages_state.selected().unwrap_or(0).saturating_sub(height);
+    let links: Vec<_> = channel
+        .message_ids
+        .iter()
+        .rev()
+        .skip(skip)
+        .take(height * 2)
+        .filter_map(|v| channel.messages.get(v))
+        .filter(|v| v.media.is_none() && v.call.is_none())
+        .filter_map(|v| Some((v.id.clone(), preview::first_link(&v.content)?.to_string())))
+        .collect();
+    for (event_id, url) in links {
+        if !state.previews.request(&event_id) {
+            continue;
+        }
+
+        let (state2, client, room) = (state2.clone(), state.client.clone(), id.clone());
+        let job = tasks::Job::Preview(event_id.clone());
+        state.tasks.spawn(&id, job, async move {
+            let preview = preview::fetch(&client, &url).await;
+            if let Some(message) = state2.lock().await.channels.get_mut(&room).and_then(|v| v.messages.get_mut(&event_id)) {
+                message.preview = preview;
+            }
+        });
+    }
+}
+
+/// Marks the current channel read up to its latest message, once the marker sent before it is done.
+fn send_receipt(state2: Arc<Mutex<AppState>>, state: &mut MutexGuard<'_, AppState>) {
+    let id = match state.current_channel.clone() {
+        Some(v) => v,
+        None => return,
+    };
+    let (room, last) = match state.channels.get(&id).filter(|v| !v.archived) {
+        Some(channel) => match channel.message_ids.last().filter(|v| channel.read.as_ref() != Some(*v)) {
+            Some(last) => (channel.room.clone(), last.clone()),
+            None => return,
+        },
+        None => return,
+    };
+
+    // private rooms still get the fully read marker, which only we can see
+    let public = !state.config.private(id.as_str());
+    state.tasks.spawn(&id.clone(), tasks::Job::Receipt, async move {
+        let receipt = Some(last.as_ref()).filter(|_| public);
+        // a failed marker isn't retried until there's a newer message, so it doesn't go out every frame
+        let _ = room.read_marker(&last, receipt).await;
+        if let Some(channel) = state2.lock().await.channels.get_mut(&id) {
+            channel.read = Some(last);
+        }
+    });
+}
+
+/// Fetches the thumbnails of image messages on screen and nearby, when the terminal can draw them.
+fn request_images(state2: Arc<Mutex<AppState>>, state: &mut MutexGuard<'_, AppState>, height: usize) {
+    let id = match state.current_channel.clone() {
+        Some(v) if state.images.enabled() => v,
+        _ => return,
+    };
+    let channel = match state.channels.get(&id) {
+        Some(v) => v,
+        None => return,
+    };
+
+    let skip = state.messages_state.selected().unwrap_or(0).saturating_sub(height);
+    let images: Vec<_> = channel
+        .message_ids
+        .iter()
+        .rev()
+        .skip(skip)
+        .take(height * 2)
+        .filter_map(|v| channel.messages.get(v))
+        .filter_map(|v| match v.media.as_ref() {
+            Some(MessageType::Image(image)) => Some((v.id.clone(), image.clone())),
+            _ => None,
+        })
+        .collect();
+    for (event_id, image) in images {
+        if !state.images.request(&event_id) {
+            continue;
+        }
+
+        let (state2, fetch) = (state2.clone(), state.images.fetch(state.client.clone(), image));
+        state.tasks.spawn(&id, tasks::Job::Image(event_id.clone()), async move {
+            if let Some(picture) = fetch.await {
+                state2.lock().await.images.insert(event_id, picture);
+            }
+        });
+    }
+}
+
+/// Tells the current channel whether we're typing, unless it's private.
+fn send_typing(state: &AppState, typing: bool) {
+    if state.secret.is_some() {
+        return;
+    }
+
+    if let Some(channel) = state.current_channel.as_ref().filter(|v| !state.config.private(v.as_str())).and_then(|v| state.channels.get(v)).filter(|v| !v.archived) {
+        let room = channel.room.clone();
+        // the sdk only sends a notice when the last one is about to run out
+        tokio::task::spawn(async move {
+            let _ = room.typing_notice(typing).await;
+        });
+    }
+}
+
+fn current_room_encrypted(state: &AppState) -> bool {
+    state.current_channel.as_ref().and_then(|v| state.channels.get(v)).map(|v| v.room.is_encrypted()).unwrap_or(false)
+}
+
+/// Sends a message too large for one event as several, in order.
+async fn send_split(state: &mut MutexGuard<'_, AppState>, text: &str) {
+    let room = match state.current_channel.as_ref().and_then(|v| state.channels.get(v)) {
+        Some(v) => v.room.clone(),
+        None => return,
+    };
+
+    // asking about new devices partway through would hold up the rest
+    match keys::unreviewed_devices(&room, state.config.encryption.unverified_devices).await {
+        Ok(devices) if !devices.is_empty() => {
+            show_error(state, "New devices", String::from("Send a short message first to review the new devices in this room."));
+            return;
+        }
+
+        Ok(_) => (),
+
+        Err(e) => {
+            show_error(state, "Couldn't check devices", e);
+            return;
+        }
+    }
+
+    let settings = state.config.composer(room.room_id().as_str());
+    for piece in composer::split(text, SPLIT_BYTES) {
+        let content = match state.code_block.as_ref() {
+            Some(code) => composer::code_block(&piece, code.language.as_deref()),
+            None => composer::message_content(&piece, &settings),
+        };
+        state.outbox.send(room.clone(), content);
+    }
+    clear_input(state);
+}
+
+fn clear_input(state: &mut MutexGuard<'_, AppState>) {
+    state.code_block = None;
+    state.input_text.clear();
+    state.input_char_pos = 0;
+    state.input_byte_pos = 0;
+}
+
+/// Puts back what was in the input box before an edit took its place.
+fn restore_draft(state: &mut AppState) {
+    if let Some(draft) = state.edit_draft.take() {
+        (state.input_text, state.input_char_pos, state.input_byte_pos, state.code_block) = draft;
+    }
+}
+
+/// Inserts text into the input box at the cursor.
+fn insert_text(state: &mut MutexGuard<'_, AppState>, text: &str) {
+    let pos = state.input_byte_pos;
+    state.input_text.insert_str(pos, text);
+    state.input_byte_pos += text.len();
+    state.input_char_pos += text.chars().count();
+}
+
+/// Inserts pasted text, first asking what to do with it if it's long enough to be a mistake.
+fn paste(state: &mut MutexGuard<'_, AppState>, text: String) {
+    // terminals send newlines as carriage returns
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let count = text.lines().count();
+    if state.secret.is_some() || count <= state.config.composer.paste_confirm_lines {
+        insert_text(state, &text);
+        return;
+    }
+
+    let mut lines = vec![format!("Pasting {} lines:", count), String::new()];
+    lines.extend(text.lines().take(PASTE_PREVIEW_LINES).map(String::from));
+    if count > PASTE_PREVIEW_LINES {
+        lines.push(format!("{} and {} more", state.symbols.ellipsis(), count - PASTE_PREVIEW_LINES));
+    }
+    lines.push(String::new());
+    lines.push(String::from("c: send as a code block, f: upload as a file, i: insert into the composer, Esc: cancel"));
+    state.popup = Some(Popup {
+        title: String::from("Paste"),
+        lines,
+        action: Some(PopupAction::Paste(text)),
+    });
+}
+
+/// Adds the messages in an Element export that are older than anything loaded to the current channel.
+fn import_history(state: &mut MutexGuard<'_, AppState>, path: &Path) -> Result<Popup, String> {
+    let id = state.current_channel.clone().ok_or_else(|| String::from("no channel selected"))?;
+    let export = export::read(path)?;
+    if let Some(room_id) = export.room_id().filter(|v| *v != id) {
+        return Err(format!("{} was exported from {}", path.display(), room_id));
+    }
+
+    // newer messages come from the server, which knows where they go
+    let channel = &state.channels[&id];
+    let oldest = channel.message_ids.first().and_then(|v| channel.messages.get(v)).map(|v| v.timestamp);
+    let before = channel.messages.len();
+    let mut reactions = vec![];
+    for event in export.messages.iter().rev() {
+        match event.deserialize() {
+            Ok(AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(SyncMessageLikeEvent::Original(v)))) if oldest.map(|oldest| v.origin_server_ts.as_secs() <= oldest).unwrap_or(true) => {
+                handle_new_message(&id, v, event.json(), trust::Encryption::Unknown, StreamPosition::Start, state);
+            }
+
+            Ok(AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::Reaction(SyncMessageLikeEvent::Original(v)))) => reactions.push(v),
+
+            _ => (),
+        }
+    }
+    for reaction in reactions {
+        handle_new_reaction(&id, reaction, state);
+    }
+
+    Ok(Popup {
+        title: String::from("History imported"),
+        lines: vec![format!("Imported {} messages from {}.", state.channels[&id].messages.len() - before, export.room_name)],
+        action: None,
+    })
+}
+
+/// A room's members, admins and moderators first, with their power level and whether they're verified.
+async fn member_lines(state: &AppState, room: &Joined) -> Vec<String> {
+    let mut members = room.joined_members().await.unwrap_or_default();
+    members.sort_by_cached_key(|v| (Reverse(v.power_level()), v.name().to_lowercase()));
+
+    let mut lines = vec![];
+    for member in members {
+        let badge = match member.power_level() {
+            v if v >= 100 => String::from("admin"),
+            v if v >= 50 => String::from("mod"),
+            0 => String::new(),
+            v => v.to_string(),
+        };
+        let verified = if verification::is_verified(&state.client, member.user_id()).await { state.symbols.verified() } else { " " };
+        lines.push(format!("{:>5} {} {} ({})", badge, verified, member.name(), member.user_id()));
+    }
+    lines
+}
+
+/// Opens the `/security` popup for the current channel.
+async fn show_security(state: &mut MutexGuard<'_, AppState>) {
+    let room = state.current_channel.as_ref().and_then(|v| state.channels.get(v)).map(|v| v.room.clone());
+    let report = security::Report::new(&state.client, room.as_ref()).await;
+    state.popup = Some(Popup {
+        title: String::from("Security"),
+        lines: report.lines(KEY_EXPORT_FILE),
+        action: Some(PopupAction::Security(Box::new(report))),
+    });
+}
+
+/// Switches to a channel, selecting it in the channel list.
+fn open_channel(state: &mut MutexGuard<'_, AppState>, room_id: OwnedRoomId) {
+    // rooms outside the current workspace are only in the list of every room
+    if !state.sidebar_ids().contains(&room_id) {
+        state.workspace = None;
+    }
+    if let Some(i) = state.sidebar_ids().iter().position(|v| *v == room_id) {
+        state.channels_state.select(Some(i));
+    }
+    clear_mentions(state, &room_id);
+    if let Some(channel) = state.channels.get_mut(&room_id) {
+        channel.changed = false;
+    }
+    state.visited.insert(room_id.clone());
+    switch_channel(state, Some(room_id));
+    state.mode = Mode::Normal;
+}
+
+/// Changes the current channel, cancelling the background jobs, reply, edit, and thread of the one being closed.
+fn switch_channel(state: &mut AppState, room_id: Option<OwnedRoomId>) {
+    if let Some(closed) = state.current_channel.take().filter(|v| Some(v) != room_id.as_ref()) {
+        for job in state.tasks.cancel(&closed) {
+            // previews and pictures that never arrived are asked for again next time
+            match job {
+                tasks::Job::Preview(event_id) => state.previews.forget(&event_id),
+                tasks::Job::Image(event_id) => state.images.forget(&event_id),
+                // and read markers that never went out are sent on quitting
+                tasks::Job::Receipt => (),
+                tasks::Job::Paginate => {
+                    if state.jumping.as_ref().map(|v| v.room == closed).unwrap_or(false) {
+                        state.jumping = None;
+                    }
+                }
+            }
+        }
+        state.reply_to = None;
+        if state.editing.take().is_some() {
+            restore_draft(state);
+        }
+        state.thread = None;
+    }
+    state.current_channel = room_id;
+}
+
+/// Opens a direct chat, adding it to the channel list if it's new.
+fn open_direct(state: &mut MutexGuard<'_, AppState>, room: Joined) {
+    let room_id = room.room_id().to_owned();
+    let name = room.name().unwrap_or_else(|| room_id.to_string());
+    reducer::apply(state, AppEvent::RoomJoined(Box::new(Channel::new(name, room))));
+    reducer::apply(state, AppEvent::RoomListed(room_id.clone()));
+    open_channel(state, room_id);
+}
+
+async fn show_profile(state: &mut MutexGuard<'_, AppState>, user_id: &UserId) {
+    let rooms: Vec<_> = users::mutual_rooms(&state.client, state.server.mutual_rooms(), user_id)
+        .await
+        .into_iter()
+        .map(|v| {
+            let name = state.channels.get(&v).map(|c| c.name.clone()).unwrap_or_else(|| v.to_string());
+            (v, name)
+        })
+        .collect();
+    state.popup = Some(Popup {
+        title: user_id.to_string(),
+        lines: users::profile_lines(&state.client, user_id, &rooms).await,
+        action: Some(PopupAction::Profile(rooms.into_iter().map(|(v, _)| v).collect())),
+    });
+}
+
+/// Whether we can invite people to the current channel.
+fn can_invite(state: &AppState) -> bool {
+    state.current_channel.as_ref().and_then(|v| state.channels.get(v)).map(|v| !v.archived && state.permissions.get(v.room.room_id()).invite).unwrap_or(false)
+}
+
+fn show_outbox(state: &mut MutexGuard<'_, AppState>, selected: usize) {
+    let selected = selected.min(state.outbox.ids().len().saturating_sub(1));
+    state.popup = Some(Popup {
+        title: String::from("Outbox"),
+        lines: state.outbox.lines(selected),
+        action: Some(PopupAction::Outbox(selected)),
+    });
+}
+
+fn search_lines(state: &AppState, search: &search::Search) -> Vec<String> {
+    // results from earlier runs may not be loaded, so they're described from the index
+    search.lines(|room, event_id| {
+        let channel = state.channels.get(room)?;
+        let message = state.search.get(event_id)?;
+        let content = message.content.lines().next().unwrap_or_default();
+        Some(format!("{} {}: {}", channel.name, message.sender, content))
+    })
+}
+
+/// Searches again once typing in `/search` pauses.
+fn update_search(state: &mut AppState) {
+    let now = Instant::now();
+    let mut popup = match state.popup.take() {
+        Some(v) => v,
+        None => return,
+    };
+    if let Some(PopupAction::Search(search)) = &mut popup.action {
+        if search.update(&state.search, now) {
+            popup.lines = search_lines(state, search);
+            state.idle.active(now);
+        }
+    }
+    state.popup = Some(popup);
+}
+
+/// Shows a thread in place of the timeline, or the timeline again for `None`, with its newest message selected.
+fn open_thread(state: &mut MutexGuard<'_, AppState>, root: Option<OwnedEventId>) {
+    state.thread = root;
+    state.reply_to = None;
+    state.messages_state.select(Some(0));
+}
+
+/// Opens a message's room with the message selected, unless it's filtered out.
+fn go_to_message(state: &mut MutexGuard<'_, AppState>, room: OwnedRoomId, event_id: &OwnedEventId) {
+    open_channel(state, room.clone());
+    // replies in threads are only shown in their thread
+    state.thread = state.channels.get(&room).and_then(|v| v.messages.get(event_id)).and_then(|v| v.thread.clone());
+    let items = timeline(state, &room);
+    let index = items.iter().position(|v| matches!(v, TimelineItem::Message(_, message) if &message.id == event_id));
+    let count = items.len();
+    if let Some(index) = index {
+        state.messages_state.select(Some(count - index - 1));
+        state.mode = Mode::ScrollMessages;
+    }
+}
+
+/// Opens the room set as `default_room`, or else the one open when ilo-toki was last closed, with
+/// the same message selected if it's loaded.
+fn restore_room(state: &mut MutexGuard<'_, AppState>, resume: resume::Resume) {
+    let wanted = state.config.default_room.clone();
+    let default = wanted.and_then(|wanted| {
+        state.channels.values().find(|v| v.room.room_id().as_str() == wanted || v.room.canonical_alias().map(|v| v.as_str() == wanted).unwrap_or(false)).map(|v| v.room.room_id().to_owned())
+    });
+    if let Some(room) = default {
+        open_channel(state, room);
+        return;
+    }
+
+    // rooms left since aren't loaded
+    if let Some(room) = resume.room.filter(|v| state.channels.contains_key(v)) {
+        match resume.selected {
+            Some(event_id) => go_to_message(state, room, &event_id),
+            None => open_channel(state, room),
+        }
+    }
+}
+
+/// Switches the input box to masked entry for a secret.
+fn ask_secret(state: &mut MutexGuard<'_, AppState>, title: &str, purpose: SecretPurpose) {
+    state.code_block = None;
+    state.secret = Some(SecretPrompt {
+        title: String::from(title),
+        purpose,
+    });
+    state.mode = Mode::Insert;
+}
+
+async fn submit_secret(state: &mut MutexGuard<'_, AppState>, purpose: SecretPurpose, secret: String) {
+    let popup = match purpose {
+        SecretPurpose::ExportKeys(path) => match state.client.encryption().export_room_keys(path.clone(), &secret, |_| true).await {
+            Ok(_) => Popup {
+                title: String::from("Keys exported"),
+                lines: vec![path.display().to_string()],
+                action: None,
+            },
+
+            Err(e) => Popup {
+                title: String::from("Export failed"),
+                lines: vec![e.to_string()],
+                action: None,
+            },
+        },
+
+        SecretPurpose::ImportKeys(path) => match state.client.encryption().import_room_keys(path, &secret).await {
+            Ok(result) => {
+                decrypt_pending(state).await;
+                Popup {
+                    title: String::from("Keys imported"),
+                    lines: vec![format!("Imported {} of {} keys.", result.imported_count, result.total_count)],
+                    action: None,
+                }
+            }
+
+            Err(e) => Popup {
+                title: String::from("Import failed"),
+                lines: vec![e.to_string()],
+                action: None,
+            },
+        },
+    };
+    state.popup = Some(popup);
+}
+
+/// Starts uploading a file, asking whether to downscale it first if it's a large image. Files not
+/// of `kind`, if it's given, are turned down. The file itself is only read once the upload is off
+/// the app state.
+async fn start_upload(state2: Arc<Mutex<AppState>>, state: &mut MutexGuard<'_, AppState>, path: &Path, kind: Option<mime::Name<'_>>) {
+    let source = media::Source::File(path.to_path_buf());
+    let content_type = media::content_type(path);
+    if let Some(kind) = kind.filter(|kind| content_type.type_() != *kind) {
+        show_error(state, "Upload failed", format!("{} is {}, not {}", source.name(), content_type, kind));
+        return;
+    }
+    let size = match std::fs::metadata(path) {
+        Ok(v) => v.len(),
+        Err(e) => {
+            show_error(state, "Upload failed", format!("couldn't read {}: {}", path.display(), e));
+            return;
+        }
+    };
+
+    if state.upload_limit.is_none() {
+        state.upload_limit = media::upload_limit(&state.client).await;
+    }
+
+    if media::should_downscale(&content_type, size, &state.config.uploads) {
+        state.popup = Some(Popup {
+            title: String::from("Large image"),
+            lines: vec![
+                format!("{} is {}.", source.name(), media::format_size(size)),
+                String::from("Downscale it before uploading? (y/n, Esc to cancel)"),
+            ],
+            action: Some(PopupAction::DownscaleUpload(source)),
+        });
+        return;
+    }
+
+    finish_upload(state2, state, source, false);
+}
+
+/// Sends an upload in the background, so the rest of ilo-toki isn't held up while a large file goes.
+/// Reading and downscaling happen there too, since a big photo takes seconds to decode.
+fn finish_upload(state2: Arc<Mutex<AppState>>, state: &mut MutexGuard<'_, AppState>, source: media::Source, downscale: bool) {
+    let room = match state.current_channel.as_ref().and_then(|v| state.channels.get(v)) {
+        Some(channel) => channel.room.clone(),
+        None => {
+            show_error(state, "Upload failed", String::from("no channel selected"));
+            return;
+        }
+    };
+
+    // the sdk doesn't say how much has been sent, so the status line only says what's still going
+    let size = match &source {
+        media::Source::File(path) => std::fs::metadata(path).map(|v| v.len()).unwrap_or_default(),
+        media::Source::Ready(upload) => upload.data.len() as u64,
+    };
+    let label = format!("{} ({})", source.name(), media::format_size(size));
+    state.uploading.push(label.clone());
+    let (limit, settings) = (state.upload_limit, state.config.uploads.clone());
+    tokio::task::spawn(async move {
+        let prepared = tokio::task::spawn_blocking(move || -> Result<media::Upload, String> {
+            let mut upload = source.load()?;
+            if downscale {
+                media::downscale_image(&mut upload, settings.max_image_dimension)?;
+            }
+            media::check_size(&upload, limit, &settings)?;
+            Ok(upload)
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+
+        let result = match prepared {
+            Ok(upload) => media::send_upload(&room, &upload).await.map_err(|e| format!("{}: {}", upload.name, e)),
+            Err(e) => Err(e),
+        };
+        let mut state = state2.lock().await;
+        if let Some(index) = state.uploading.iter().position(|v| *v == label) {
+            state.uploading.remove(index);
+        }
+        if let Err(e) = result {
+            show_error(&mut state, "Upload failed", e);
+        }
+    });
+}
+
+/// Sends a message once its new devices have been accepted or blocked.
+async fn send_reviewed(state: &mut MutexGuard<'_, AppState>, devices: Vec<Device>, trust: LocalTrust, content: RoomMessageEventContent) {
+    if let Err(e) = keys::set_trust(&devices, trust).await {
+        show_error(state, "Couldn't update devices", e);
+        return;
+    }
+
+    if let Some(room) = state.current_channel.as_ref().and_then(|v| state.channels.get(v)).map(|v| v.room.clone()) {
+        state.outbox.send(room, content);
+    }
+    clear_input(state);
+    restore_draft(state);
+}
+
+fn show_error(state: &mut MutexGuard<'_, AppState>, title: &str, error: String) {
+    state.popup = Some(Popup {
+        title: String::from(title),
+        lines: vec![error],
+        action: None,
+    });
+}
+
+async fn main_ui(state: Arc<Mutex<AppState>>, sync: JoinHandle<()>) -> Result<(), io::Error> {
+    term::install_panic_hook();
+    term::enter()?;
+    let _guard = term::Guard;
+    let backend = CrosstermBackend::new(io::stdout());
+    let mut terminal = Terminal::new(backend)?;
+    let mut cursor_style = None;
+    terminal.clear()?;
+
+    while RUNNING.load(Ordering::Acquire) {
+        let state2 = state.clone();
+        let mut state = profile::lock(&state, "lock wait: frame").await;
+        if state.suspend {
+            state.suspend = false;
+            terminal.clear()?;
+            term::restore()?;
+
+            let result = platform::suspend();
+
+            term::enter()?;
+            terminal.clear()?;
+            cursor_style = None;
+            if let Err(e) = result {
+                state.status = Some(e.to_string());
+            }
+        }
+
+        if let Some((width, height)) = state.resized.take() {
+            terminal.resize(layout::Rect::new(0, 0, width, height))?;
+            // lists keep the scroll offset from the old size, so anchor them to the selection again
+            let (channel, message) = (state.channels_state.selected(), state.messages_state.selected());
+            state.channels_state = widgets::ListState::default();
+            state.channels_state.select(channel);
+            state.messages_state = viewport::Viewport::default();
+            state.messages_state.select(message);
+        }
+
+        if let Some(away) = state.away.update(Instant::now()) {
+            state.away.send(&state.client, away);
+        }
+
+        for (room_id, event_id, text) in std::mem::take(&mut state.untranslated) {
+            translate_message(state2.clone(), state.config.translate.command.clone(), room_id, event_id, text, false);
+        }
+
+        backfill_on_open(state2.clone(), &mut state);
+        request_previews(state2.clone(), &mut state, terminal.size()?.height as usize);
+        request_images(state2.clone(), &mut state, terminal.size()?.height as usize);
+        send_receipt(state2.clone(), &mut state);
+        update_search(&mut state);
+
+        if !state.config.spellcheck.command.is_empty() && state.secret.is_none() && state.code_block.is_none() {
+            let room_id = state.current_channel.as_ref().map(|v| v.to_string()).unwrap_or_default();
+            let (text, language) = (state.input_text.clone(), state.config.language(&room_id).to_string());
+            if state.spelling.start(&text, &language) {
+                let (state, command) = (state2.clone(), state.config.spellcheck.command.clone());
+                tokio::task::spawn(async move {
+                    let misspelled = spell::check(&command, &language, &text).await.unwrap_or_default();
+                    state.lock().await.spelling.finish(misspelled);
+                });
+            }
+        }
+
+        let errors = state.outbox.take_errors();
+        if !errors.is_empty() {
+            show_error(&mut state, "Couldn't send", format!("{}\n/outbox retries or discards them.", errors.join("\n")));
+        }
+
+        let style = state.cursor_style();
+        // screen readers follow the cursor, so it's left alone
+        if !state.announcements.enabled() && cursor_style.as_ref() != Some(style) {
+            cursor::apply(terminal.backend_mut(), style)?;
+            cursor_style = Some(style.clone());
+        }
+
+        let start = Instant::now();
+        if state.idle.should_draw(start) {
+            terminal.draw(|f| {
+                ui::draw(f, &state);
+            })?;
+            // pictures drawn into cells stay until the cells are drawn again, which tui only does for changed text
+            if state.images.needs_clear() {
+                terminal.clear()?;
+                terminal.draw(|f| {
+                    ui::draw(f, &state);
+                })?;
+            }
+            state.images.flush(terminal.backend_mut())?;
+            state.idle.drawn(start);
+            state.profiler.record("frame", start.elapsed());
+        }
+
+        let (wake, timeout) = state.idle.wait(Instant::now());
+        drop(state);
+        let _ = tokio::time::timeout(timeout, wake.notified()).await;
+    }
+
+    shutdown(&state, sync).await;
+
+    terminal.clear()?;
+    term::restore()?;
+    terminal.set_cursor(0, 0)?;
+
+    Ok(())
+}
+
+/// Stops syncing, sends whatever is still queued, and saves the draft, unsent messages, search index, room, read markers, and profile.
+async fn shutdown(state: &Arc<Mutex<AppState>>, mut sync: JoinHandle<()>) {
+    // the sync loop stops after its current request, but a long poll isn't worth waiting out
+    if tokio::time::timeout(Duration::from_secs(2), &mut sync).await.is_err() {
+        sync.abort();
+    }
+
+    let mut state = state.lock().await;
+    state.outbox.flush().await;
+
+    // secrets are never written down
+    if state.input_text.is_empty() || state.secret.is_some() {
+        let _ = std::fs::remove_file(DRAFT_FILE);
+    } else {
+        let _ = std::fs::write(DRAFT_FILE, &state.input_text);
+    }
+    state.outbox.save(OUTBOX_FILE);
+    state.names.save(NAMES_FILE);
+    state.policies.save(POLICY_FILE);
+    state.search.save(SEARCH_FILE);
+    let selected = selected_message(&state).map(|(_, v)| v.id.clone());
+    resume::Resume { room: state.current_channel.clone(), selected }.save(RESUME_FILE);
+
+    // the markers for rooms closed before theirs went out
+    for id in state.visited.iter() {
+        if let Some(channel) = state.channels.get(id).filter(|v| !v.archived) {
+            if let Some(last) = channel.message_ids.last().filter(|v| channel.read.as_ref() != Some(*v)) {
+                let receipt = Some(last.as_ref()).filter(|_| !state.config.private(id.as_str()));
+                let _ = channel.room.read_marker(last, receipt).await;
+            }
+        }
+    }
+
+    if state.profiler.enabled() {
+        let _ = std::fs::write(profile::REPORT_FILE, state.profiler.report());
+    }
+}
+
+async fn ui_events(state: Arc<Mutex<AppState>>) {
+    while let Ok(Ok(event)) = tokio::task::spawn_blocking(crossterm::event::read).await {
+        let state2 = state.clone();
+        let mut state = profile::lock(&state, "lock wait: input").await;
+        let start = Instant::now();
+        state.idle.active(start);
+        match event {
+            Event::Key(_) | Event::Paste(_) | Event::FocusGained => state.away.active(),
+            Event::FocusLost => state.away.unfocus(),
+            _ => (),
+        }
+
+        let action = match event {
+            Event::Key(key) if !platform::is_key_press(&key) => MacroAction::Consumed,
+            Event::Key(key) => {
+                // popups take every key, so q and @ mean nothing special there
+                let inserting = matches!(state.mode, Mode::Insert) || state.popup.is_some();
+                state.macros.key(key, inserting)
+            }
+
+            _ => MacroAction::Pass,
+        };
+
+        let mut events = match action {
+            MacroAction::Consumed => vec![],
+            MacroAction::Replay(keys) => keys.into_iter().map(Event::Key).collect(),
+            MacroAction::Pass => vec![event],
+        };
+        while !events.is_empty() {
+            let event = events.remove(0);
+            let mode = state.mode.name();
+            let popup = state.popup.is_some();
+            if !handle_event(state2.clone(), &mut state, event).await {
+                return;
+            }
+
+            if state.mode.name() != mode {
+                let announcement = format!("{} mode", state.mode.name().to_lowercase());
+                state.announcements.push(&announcement);
+            }
+            if !popup {
+                if let Some(popup) = state.popup.as_ref() {
+                    let announcement = format!("{}: {}", popup.title, popup.lines.join(" "));
+                    state.announcements.push(&announcement);
+                }
+            }
+
+            let replay = std::mem::take(&mut state.replay);
+            events.splice(0..0, replay);
+        }
+        state.profiler.record("handle input", start.elapsed());
+    }
+}
+
+/// Handles a terminal event. Returns false if the client should quit.
+async fn handle_event(state2: Arc<Mutex<AppState>>, state: &mut MutexGuard<'_, AppState>, event: Event) -> bool {
+    // the layout is redone on the next frame, whatever mode we're in
+    if let Event::Resize(width, height) = event {
+        state.resized = Some((width, height));
+    }
+
+    if state.popup.is_some() {
+        if let Event::Key(key) = event {
+            let popup = state.popup.take().unwrap();
+            match popup.action {
+                Some(PopupAction::DownscaleUpload(upload)) => match key.code {
+                    KeyCode::Char('y') => finish_upload(state2.clone(), state, upload, true),
+                    KeyCode::Char('n') => finish_upload(state2.clone(), state, upload, false),
+                    KeyCode::Esc => (),
+                    _ => {
+                        state.popup = Some(Popup {
+                            action: Some(PopupAction::DownscaleUpload(upload)),
+                            ..popup
+                        });
+                    }
+                },
+
+                Some(PopupAction::PickFile(mut picker)) => match picker.key(key) {
+                    picker::Pick::Chosen(path) => start_upload(state2.clone(), state, &path, None).await,
+                    picker::Pick::Cancelled => (),
+                    picker::Pick::Open => {
+                        state.popup = Some(Popup {
+                            lines: picker.lines(),
+                            action: Some(PopupAction::PickFile(picker)),
+                            ..popup
+                        });
+                    }
+                },
+
+                Some(PopupAction::ReviewDevices(devices, content)) => match key.code {
+                    KeyCode::Char('a') => send_reviewed(state, devices, LocalTrust::Ignored, content).await,
+                    KeyCode::Char('b') => send_reviewed(state, devices, LocalTrust::BlackListed, content).await,
+                    KeyCode::Esc => (),
+                    _ => {
+                        state.popup = Some(Popup {
+                            action: Some(PopupAction::ReviewDevices(devices, content)),
+                            ..popup
+                        });
+                    }
+                },
+
+                Some(PopupAction::AcceptVerification(request)) => {
+                    let result = match key.code {
+                        KeyCode::Char('y') => request.accept().await,
+                        KeyCode::Char('n') | KeyCode::Esc => request.cancel().await,
+                        _ => {
+                            state.popup = Some(Popup {
+                                action: Some(PopupAction::AcceptVerification(request)),
+                                ..popup
+                            });
+                            Ok(())
+                        }
+                    };
+                    if let Err(e) = result {
+                        show_error(state, "Verification failed", e.to_string());
+                    }
+                }
+
+                Some(PopupAction::ConfirmSas(sas)) => {
+                    let result = match key.code {
+                        KeyCode::Char('y') => sas.confirm().await,
+                        KeyCode::Char('n') => sas.mismatch().await,
+                        KeyCode::Esc => sas.cancel().await,
+                        _ => {
+                            state.popup = Some(Popup {
+                                action: Some(PopupAction::ConfirmSas(sas)),
+                                ..popup
+                            });
+                            Ok(())
+                        }
+                    };
+                    if let Err(e) = result {
+                        show_error(state, "Verification failed", e.to_string());
+                    }
+                }
+
+                Some(PopupAction::InviteFile(room, users)) => match key.code {
+                    KeyCode::Char('y') => {
+                        state.popup = Some(Popup {
+                            title: String::from("Inviting"),
+                            lines: vec![format!("Inviting {} user(s){}", users.len(), state.symbols.ellipsis())],
+                            action: None,
+                        });
+                        tokio::task::spawn(async move {
+                            let failed = invite::invite_all(&room, &users).await;
+                            let mut lines = vec![format!("Invited {} of {} user(s).", users.len() - failed.len(), users.len())];
+                            lines.extend(failed);
+                            state2.lock().await.popup = Some(Popup {
+                                title: String::from("Invite"),
+                                lines,
+                                action: None,
+                            });
+                        });
+                    }
+                    KeyCode::Esc => (),
+                    _ => {
+                        state.popup = Some(Popup {
+                            action: Some(PopupAction::InviteFile(room, users)),
+                            ..popup
+                        });
+                    }
+                },
+
+                Some(PopupAction::Encrypt(room, false)) => match key.code {
+                    KeyCode::Char('y') => {
+                        state.popup = Some(Popup {
+                            title: String::from("Encrypt room"),
+                            lines: vec![String::from("Are you sure? This can't be undone."), String::from("y: encrypt the room, Esc: cancel")],
+                            action: Some(PopupAction::Encrypt(room, true)),
+                        });
+                    }
+                    KeyCode::Esc => (),
+                    _ => {
+                        state.popup = Some(Popup {
+                            action: Some(PopupAction::Encrypt(room, false)),
+                            ..popup
+                        });
+                    }
+                },
+
+                Some(PopupAction::Encrypt(room, true)) => match key.code {
+                    // the sdk waits for the next sync after sending, blocking as it does, so it's kept off the ui
+                    KeyCode::Char('y') => {
+                        tokio::task::spawn(async move {
+                            if let Err(e) = room.enable_encryption().await {
+                                show_error(&mut state2.lock().await, "Couldn't encrypt", e.to_string());
+                            }
+                        });
+                    }
+                    KeyCode::Esc => (),
+                    _ => {
+                        state.popup = Some(Popup {
+                            action: Some(PopupAction::Encrypt(room, true)),
+                            ..popup
+                        });
+                    }
+                },
+
+                Some(PopupAction::Security(report)) => match key.code {
+                    KeyCode::Char('v') if !report.session_verified => {
+                        let user_id = state.client.user_id().map(|v| v.to_string()).unwrap_or_default();
+                        match verification::request(&state.client, &user_id).await {
+                            Ok(_) => {
+                                state.popup = Some(Popup {
+                                    title: String::from("Verification requested"),
+                                    lines: vec![String::from("Accept the request in another of your sessions.")],
+                                    action: None,
+                                });
+                            }
+
+                            Err(e) => show_error(state, "Verification failed", e),
+                        }
+                    }
+
+                    KeyCode::Char('c') if !report.cross_signing_ready() => match state.client.encryption().bootstrap_cross_signing(None).await {
+                        Ok(_) => show_security(state).await,
+                        Err(e) => show_error(state, "Couldn't set up cross-signing", e.to_string()),
+                    },
+
+                    KeyCode::Char('e') if !report.has_backup() => ask_secret(state, "export passphrase", SecretPurpose::ExportKeys(PathBuf::from(KEY_EXPORT_FILE))),
+
+                    KeyCode::Char('a') | KeyCode::Char('b') if !report.unreviewed.is_empty() => {
+                        let trust = if key.code == KeyCode::Char('a') { LocalTrust::Ignored } else { LocalTrust::BlackListed };
+                        match keys::set_trust(&report.unreviewed, trust).await {
+                            Ok(_) => show_security(state).await,
+                            Err(e) => show_error(state, "Couldn't update devices", e),
+                        }
+                    }
+
+                    KeyCode::Esc => (),
+                    _ => {
+                        state.popup = Some(Popup {
+                            action: Some(PopupAction::Security(report)),
+                            ..popup
+                        });
+                    }
+                },
+
+                Some(PopupAction::React(mut palette)) => match palette.key(key, &state.config.reactions.favorites) {
+                    react::Pick::Chosen(emoji) => {
+                        // picking one we've already reacted with takes it back
+                        let own = state.client.user_id().map(|v| v.to_string()).unwrap_or_default();
+                        let ours = state.channels.get(palette.room.room_id()).and_then(|v| v.messages.get(&palette.event_id)).and_then(|v| v.reactions.iter().find(|r| r.sender == own && r.key == emoji)).map(|v| v.id.clone());
+                        match ours {
+                            Some(reaction) => {
+                                if let Err(e) = palette.room.redact(&reaction, None, None).await {
+                                    show_error(state, "Couldn't remove reaction", e.to_string());
+                                }
+                            }
+
+                            None => {
+                                let content = ReactionEventContent::new(ReactionRelation::new(palette.event_id.clone(), emoji));
+                                state.outbox.send(palette.room.clone(), content);
+                            }
+                        }
+                    }
+
+                    react::Pick::Cancelled => (),
+
+                    react::Pick::Open => {
+                        state.popup = Some(Popup {
+                            lines: palette.lines(&state.config.reactions.favorites, &state.symbols),
+                            action: Some(PopupAction::React(palette)),
+                            ..popup
+                        });
+                    }
+                },
+
+                Some(PopupAction::Palette(mut palette)) => match palette.key(key) {
+                    palette::Pick::Chosen(palette::Run::Key(code, modifiers)) => state.replay.push(Event::Key(KeyEvent::new(code, modifiers))),
+
+                    palette::Pick::Chosen(palette::Run::Command(command)) => {
+                        // the draft is put back once the command has run
+                        let draft = (std::mem::replace(&mut state.input_text, String::from(command)), state.input_char_pos, state.input_byte_pos, state.code_block.take());
+                        if !submit_input(state2.clone(), state).await {
+                            RUNNING.store(false, Ordering::Release);
+                            return false;
+                        }
+                        if state.input_text.is_empty() {
+                            (state.input_text, state.input_char_pos, state.input_byte_pos, state.code_block) = draft;
+                        }
+                    }
+
+                    palette::Pick::Chosen(palette::Run::Prompt(command)) => {
+                        clear_input(state);
+                        insert_text(state, command);
+                        state.mode = Mode::Insert;
+                    }
+
+                    palette::Pick::Cancelled => (),
+
+                    palette::Pick::Open => {
+                        state.popup = Some(Popup {
+                            lines: palette.lines(),
+                            action: Some(PopupAction::Palette(palette)),
+                            ..popup
+                        });
+                    }
+                },
+
+                Some(PopupAction::Widgets(widgets, copy)) => match key.code {
+                    KeyCode::Char(c) if widget::KEYS.contains(&c) => {
+                        let index = widget::KEYS.iter().position(|v| *v == c).unwrap();
+                        match widgets.get(index) {
+                            Some(widget) if copy => quote::copy(&widget.url),
+                            Some(widget) => {
+                                if let Err(e) = media::open(platform::default_opener(), &widget.url) {
+                                    show_error(state, "Couldn't open widget", e);
+                                }
+                            }
+                            None => {
+                                state.popup = Some(Popup {
+                                    action: Some(PopupAction::Widgets(widgets, copy)),
+                                    ..popup
+                                });
+                            }
+                        }
+                    }
+
+                    KeyCode::Char('c' | 'o') => {
+                        let copy = key.code == KeyCode::Char('c');
+                        state.popup = Some(Popup {
+                            lines: widget::lines(&widgets, copy),
+                            action: Some(PopupAction::Widgets(widgets, copy)),
+                            ..popup
+                        });
+                    }
+
+                    KeyCode::Esc => (),
+                    _ => {
+                        state.popup = Some(Popup {
+                            action: Some(PopupAction::Widgets(widgets, copy)),
+                            ..popup
+                        });
+                    }
+                },
+
+                Some(PopupAction::Outbox(selected)) => {
+                    let id = state.outbox.ids().get(selected).copied();
+                    match (key.code, id) {
+                        (KeyCode::Char('j') | KeyCode::Down, _) => show_outbox(state, selected + 1),
+                        (KeyCode::Char('k') | KeyCode::Up, _) => show_outbox(state, selected.saturating_sub(1)),
+                        (KeyCode::Char('r'), Some(id)) => {
+                            state.outbox.retry(id);
+                            show_outbox(state, selected);
+                        }
+
+                        (KeyCode::Char('d'), Some(id)) => {
+                            state.outbox.remove(id);
+                            show_outbox(state, selected);
+                        }
+
+                        // only text can be edited, and it's sent again from its room's input box
+                        (KeyCode::Char('e'), Some(id)) if state.outbox.is_text(id) => {
+                            if let Some(entry) = state.outbox.remove(id) {
+                                open_channel(state, entry.room.room_id().to_owned());
+                                clear_input(state);
+                                insert_text(state, entry.text().unwrap());
+                                state.mode = Mode::Insert;
+                            }
+                        }
+
+                        (KeyCode::Char('e'), Some(_)) => show_error(state, "Can't edit", String::from("Only text messages can be edited.")),
+
+                        (KeyCode::Esc, _) => (),
+                        _ => {
+                            state.popup = Some(Popup {
+                                action: Some(PopupAction::Outbox(selected)),
+                                ..popup
+                            });
+                        }
+                    }
+                }
+
+                Some(PopupAction::Profile(rooms)) => match key.code {
+                    KeyCode::Char(c) if users::KEYS.contains(&c) => match rooms.get(users::KEYS.iter().position(|v| *v == c).unwrap()) {
+                        Some(room_id) => open_channel(state, room_id.clone()),
+                        None => {
+                            state.popup = Some(Popup {
+                                action: Some(PopupAction::Profile(rooms)),
+                                ..popup
+                            });
+                        }
+                    },
+
+                    KeyCode::Esc => (),
+                    _ => {
+                        state.popup = Some(Popup {
+                            action: Some(PopupAction::Profile(rooms)),
+                            ..popup
+                        });
+                    }
+                },
+
+                Some(PopupAction::Users(found, invite)) => match key.code {
+                    KeyCode::Char(c) if users::KEYS.contains(&c) && users::KEYS.iter().position(|v| *v == c).unwrap() < found.len() => {
+                        let user_id = found[users::KEYS.iter().position(|v| *v == c).unwrap()].user_id.clone();
+                        let room = state.current_channel.as_ref().and_then(|v| state.channels.get(v)).map(|v| v.room.clone());
+                        match (invite, room) {
+                            (true, Some(room)) => {
+                                if let Err(e) = room.invite_user_by_id(&user_id).await {
+                                    show_error(state, "Invite failed", e.to_string());
+                                }
+                            }
+
+                            (true, None) => show_error(state, "Invite failed", String::from("no channel selected")),
+
+                            (false, _) => match users::existing_direct(&state.client, &user_id) {
+                                Some(room) => open_direct(state, room),
+                                None => match users::start_direct(&state.client, &user_id).await {
+                                    // the room arrives with the next sync
+                                    Ok(room_id) => {
+                                        let client = state.client.clone();
+                                        tokio::task::spawn(async move {
+                                            for _ in 0..50 {
+                                                if let Some(room) = client.get_joined_room(&room_id) {
+                                                    open_direct(&mut state2.lock().await, room);
+                                                    return;
+                                                }
+                                                tokio::time::sleep(Duration::from_millis(200)).await;
+                                            }
+                                        });
+                                    }
+
+                                    Err(e) => show_error(state, "Couldn't start a chat", e),
+                                },
+                            },
+                        }
+                    }
+
+                    KeyCode::Char('d' | 'i') if key.code == KeyCode::Char('d') || can_invite(state) => {
+                        let invite = key.code == KeyCode::Char('i');
+                        let homeserver = state.client.homeserver().await;
+                        state.popup = Some(Popup {
+                            lines: users::lines(&found, &homeserver, invite, can_invite(state)),
+                            action: Some(PopupAction::Users(found, invite)),
+                            ..popup
+                        });
+                    }
+
+                    KeyCode::Esc => (),
+                    _ => {
+                        state.popup = Some(Popup {
+                            action: Some(PopupAction::Users(found, invite)),
+                            ..popup
+                        });
+                    }
+                },
+
+                Some(PopupAction::Oversized(text)) => match key.code {
+                    KeyCode::Char('s') => send_split(state, &text).await,
+                    KeyCode::Char('f') => {
+                        finish_upload(state2.clone(), state, media::Source::Ready(media::text_upload("message.txt", text)), false);
+                        clear_input(state);
+                    }
+                    KeyCode::Esc => (),
+                    _ => {
+                        state.popup = Some(Popup {
+                            action: Some(PopupAction::Oversized(text)),
+                            ..popup
+                        });
+                    }
+                },
+
+                Some(PopupAction::Paste(text)) => match key.code {
+                    KeyCode::Char('c') => {
+                        let content = composer::code_block(&text, None);
+                        if composer::too_large(&content, current_room_encrypted(state)) {
+                            show_error(state, "Paste too large", String::from("It's more than the server accepts in one message, so upload it as a file instead."));
+                        } else {
+                            send_content(state, content).await;
+                        }
+                    }
+                    KeyCode::Char('f') => finish_upload(state2.clone(), state, media::Source::Ready(media::text_upload("paste.txt", text)), false),
+                    KeyCode::Char('i') => insert_text(state, &text),
+                    KeyCode::Esc => (),
+                    _ => {
+                        state.popup = Some(Popup {
+                            action: Some(PopupAction::Paste(text)),
+                            ..popup
+                        });
+                    }
+                },
+
+                Some(PopupAction::Search(mut search)) => match search.key(key, Instant::now()) {
+                    search::Pick::Chosen(room, event_id) => {
+                        let loaded = state.channels.get(&room).map(|v| v.messages.contains_key(&event_id)).unwrap_or(false);
+                        match state.search.get(&event_id).map(|v| v.timestamp) {
+                            // found in an earlier run and not paged in yet, so it's paged back to like /date does
+                            Some(timestamp) if !loaded => {
+                                open_channel(state, room.clone());
+                                let jump = jump_to_time(state2.clone(), room.clone(), u64::from(timestamp) * 1000);
+                                if state.tasks.spawn(&room, tasks::Job::Paginate, jump) {
+                                    let date = state.clock.date(u64::from(timestamp) as i64);
+                                    state.jumping = Some(Jump { room, date, pages: 0 });
+                                } else {
+                                    show_error(state, "Can't jump yet", String::from("Older messages are still loading here. Try again once they're in."));
+                                }
+                            }
+
+                            _ => go_to_message(state, room, &event_id),
+                        }
+                    }
+                    search::Pick::Cancelled => (),
+                    search::Pick::Open => {
+                        state.popup = Some(Popup {
+                            lines: search_lines(state, &search),
+                            action: Some(PopupAction::Search(search)),
+                            ..popup
+                        });
+                    }
+                },
+
+                Some(PopupAction::Members(_)) => (),
+
+                Some(PopupAction::Quit) => match key.code {
+                    KeyCode::Char('w') => RUNNING.store(false, Ordering::Release),
+                    KeyCode::Char('d') => {
+                        state.outbox.discard();
+                        RUNNING.store(false, Ordering::Release);
+                    }
+                    KeyCode::Esc => (),
+                    _ => {
+                        state.popup = Some(Popup {
+                            action: Some(PopupAction::Quit),
+                            ..popup
+                        });
+                    }
+                },
+
+                None => (),
+            }
+        }
+        return true;
+    }
+
+    if let Event::Key(key) = event {
+        if key.code == KeyCode::Char('p') && key.modifiers == KeyModifiers::CONTROL && state.secret.is_none() {
+            let allowed = state.current_channel.as_ref().map(|v| state.permissions.get(v)).unwrap_or(permissions::Allowed::ALL);
+            let palette = palette::Palette::new(state.mode, allowed, state.server.relations());
+            state.popup = Some(Popup {
+                title: String::from("Commands"),
+                lines: palette.lines(),
+                action: Some(PopupAction::Palette(palette)),
+            });
+            return true;
+        }
+    }
+
+    match state.mode {
+        Mode::Insert => {
+            match event {
+                Event::FocusGained => (),
+                Event::FocusLost => (),
+                Event::Resize(_, _) => (),
+
+                Event::Key(key) => match key.code {
+                    KeyCode::Backspace => {
+                        if state.input_byte_pos > 0 {
+                            let mut i = 1;
+                            while !state.input_text.is_char_boundary(state.input_byte_pos - i) {
+                                i += 1;
+                            }
+                            state.input_byte_pos -= i;
+                            state.input_char_pos -= 1;
+                            let pos = state.input_byte_pos;
+                            state.input_text.remove(pos);
+                        }
+                    }
+
+                    KeyCode::Enter => {
+                        if state.code_block.is_some() {
+                            let pos = state.input_byte_pos;
+                            state.input_text.insert(pos, '\n');
+                            state.input_byte_pos += 1;
+                            state.input_char_pos += 1;
+                        } else if !submit_input(state2.clone(), state).await {
+                            RUNNING.store(false, Ordering::Release);
+                            return false;
+                        }
+                    }
+
+                    KeyCode::Up => (),
+                    KeyCode::Down => (),
+                    KeyCode::Home => (),
+                    KeyCode::End => (),
+                    KeyCode::PageUp => (),
+                    KeyCode::PageDown => (),
+                    KeyCode::Tab => (),
+                    KeyCode::BackTab => (),
+                    KeyCode::Delete => (),
+                    KeyCode::Insert => (),
+                    KeyCode::F(_) => (),
+
+                    KeyCode::Left => {
+                        if state.input_byte_pos > 0 {
+                            let mut i = 1;
+                            while !state.input_text.is_char_boundary(state.input_byte_pos - i) {
+                                i += 1;
+                            }
+                            state.input_byte_pos -= i;
+                            state.input_char_pos -= 1;
+                        }
+                    }
+
+                    KeyCode::Right => {
+                        if state.input_byte_pos < state.input_text.bytes().len() {
+                            let mut i = 1;
+                            while !state.input_text.is_char_boundary(state.input_byte_pos + i) {
+                                i += 1;
+                            }
+                            state.input_byte_pos += i;
+                            state.input_char_pos += 1;
+                        }
+                    }
+
+                    KeyCode::Char(c) => {
+                        let pos = state.input_byte_pos;
+                        state.input_text.insert(pos, c);
+                        state.input_byte_pos += c.len_utf8();
+                        state.input_char_pos += 1;
+                        if !state.input_text.starts_with('/') {
+                            send_typing(state, true);
+                        }
+                    }
+
+                    KeyCode::Null => (),
+
+                    KeyCode::Esc => {
+                        // a reply with nothing written yet is dropped, and an edit gives the input box back to the draft
+                        if state.input_text.is_empty() {
+                            state.reply_to = None;
+                        }
+                        if state.editing.take().is_some() {
+                            clear_input(state);
+                            restore_draft(state);
+                        }
+                        state.mode = Mode::Normal;
+                    }
+
+                    KeyCode::CapsLock => (),
+                    KeyCode::ScrollLock => (),
+                    KeyCode::NumLock => (),
+                    KeyCode::PrintScreen => (),
+                    KeyCode::Pause => (),
+                    KeyCode::Menu => (),
+                    KeyCode::KeypadBegin => (),
+                    KeyCode::Media(_) => (),
+                    KeyCode::Modifier(_) => (),
+                }
+
+                Event::Mouse(_) => (),
+                Event::Paste(text) => paste(state, text),
+            }
+        }
+
+        Mode::Normal => {
+            match event {
+                Event::FocusGained => (),
+                Event::FocusLost => (),
+
+                Event::Key(key) => {
+                    match key.code {
+                        KeyCode::Backspace => (),
+                        KeyCode::Enter => {
+                            if !submit_input(state2.clone(), state).await {
+                                RUNNING.store(false, Ordering::Release);
+                                return false;
+                            }
+                        }
+
+                        KeyCode::Up => (),
+                        KeyCode::Down => (),
+                        KeyCode::Home => (),
+                        KeyCode::End => (),
+                        KeyCode::PageUp => (),
+                        KeyCode::PageDown => (),
+                        KeyCode::Tab => (),
+                        KeyCode::BackTab => (),
+                        KeyCode::Delete => (),
+                        KeyCode::Insert => (),
+                        KeyCode::F(_) => (),
+
+                        KeyCode::Char('z') if key.modifiers == KeyModifiers::CONTROL => {
+                            state.suspend = true;
+                        }
+
+                        KeyCode::Char('C') => {
+                            state.mode = Mode::SelectChannel;
+                        }
+
+                        KeyCode::Char('F') => {
+                            state.filters.revealed = !state.filters.revealed;
+                        }
+
+                        KeyCode::Char('W') => {
+                            state.cycle_workspace();
+                        }
+
+                        KeyCode::Char('J') => {
+                            if let Some(room) = state.current_channel.as_ref().and_then(|v| state.channels.get(v)).map(|v| v.room.clone()) {
+                                let links = call::links(&room, &state.config.calls).await;
+                                state.popup = Some(Popup {
+                                    title: String::from("Join call"),
+                                    lines: widget::lines(&links, false),
+                                    action: Some(PopupAction::Widgets(links, false)),
+                                });
+                            }
+                        }
+
+                        KeyCode::Char('S') => {
+                            if state.current_channel.clone().and_then(|v| state.channels.get_mut(&v)).is_some() {
+                                state.messages_state.select(Some(0));
+                                state.mode = Mode::ScrollMessages;
+                            }
+                        }
+
+                        KeyCode::Char('i') => {
+                            state.mode = Mode::Insert;
+                        }
+
+                        KeyCode::Char('`') => {
+                            let (text, pos) = composer::wrap_inline_code(&state.input_text, state.input_byte_pos);
+                            state.input_text = text;
+                            state.input_byte_pos = pos;
+                            state.input_char_pos += 1;
+                        }
+
+                        KeyCode::Char('h') | KeyCode::Left => {
+                            if state.input_byte_pos > 0 {
+                                let mut i = 1;
+                                while !state.input_text.is_char_boundary(state.input_byte_pos - i) {
+                                    i += 1;
+                                }
+                                state.input_byte_pos -= i;
+                                state.input_char_pos -= 1;
+                            }
+                        }
+
+                        KeyCode::Char('l') | KeyCode::Right => {
+                            if state.input_byte_pos < state.input_text.bytes().len() {
+                                let mut i = 1;
+                                while !state.input_text.is_char_boundary(state.input_byte_pos + i) {
+                                    i += 1;
+                                }
+                                state.input_byte_pos += i;
+                                state.input_char_pos += 1;
+                            }
+                        }
+
+                        KeyCode::Char(_) => (),
+
+                        KeyCode::Null => (),
+                        KeyCode::Esc => {
+                            state.code_block = None;
+                            if state.secret.take().is_some() {
+                                state.input_text.clear();
+                                state.input_char_pos = 0;
+                                state.input_byte_pos = 0;
+                            }
+                        }
+
+                        KeyCode::CapsLock => (),
+                        KeyCode::ScrollLock => (),
+                        KeyCode::NumLock => (),
+                        KeyCode::PrintScreen => (),
+                        KeyCode::Pause => (),
+                        KeyCode::Menu => (),
+                        KeyCode::KeypadBegin => (),
+                        KeyCode::Media(_) => (),
+                        KeyCode::Modifier(_) => (),
+                    }
+                }
+
+                Event::Mouse(_) => (),
+                Event::Paste(_) => (),
+                Event::Resize(_, _) => (),
+            }
+        }
+
+        Mode::SelectChannel => {
+            match event {
+                Event::FocusGained => (),
+                Event::FocusLost => (),
+
+                Event::Key(key) => {
+                    match key.code {
+                        KeyCode::Backspace => (),
+
+                        KeyCode::Enter => {
+                            let selected = state.channels_state.selected().and_then(|v| state.sidebar_ids().get(v).cloned());
+                            switch_channel(state, selected);
+                            if let Some(id) = state.current_channel.clone() {
+                                clear_mentions(state, &id);
+                                state.visited.insert(id);
+                            }
+                            state.mode = Mode::Normal;
+                        }
+
+                        KeyCode::Left => (),
+                        KeyCode::Right => (),
+
+                        KeyCode::Up | KeyCode::Char('k') => {
+                            match state.channels_state.selected() {
+                                Some(current) => {
+                                    if current > 0 {
+                                        state.channels_state.select(Some(current - 1));
+                                    } else {
+                                        let select = state.sidebar_ids().len().checked_sub(1);
+                                        state.channels_state.select(select);
+                                    }
+                                }
+
+                                None => {
+                                    let select = state.sidebar_ids().len().checked_sub(1);
+                                    state.channels_state.select(select);
+                                }
+                            }
+                        }
+
+                        KeyCode::Down | KeyCode::Char('j') => {
+                            let count = state.sidebar_ids().len();
+                            match state.channels_state.selected() {
+                                Some(current) => {
+                                    if current + 1 < count {
+                                        state.channels_state.select(Some(current + 1));
+                                    } else {
+                                        state.channels_state.select(Some(0));
+                                    }
+                                }
+
+                                None => {
+                                    state.channels_state.select(count.checked_sub(1));
+                                }
+                            }
+                        }
+
+                        KeyCode::Char('W') => {
+                            state.cycle_workspace();
+                            if state.channels_state.selected().is_none() && !state.sidebar_ids().is_empty() {
+                                state.channels_state.select(Some(0));
+                            }
+                        }
+
+                        KeyCode::Esc => {
+                            state.channels_state.select(None);
+                            switch_channel(state, None);
+                            state.mode = Mode::Normal;
+                        }
+
+                        KeyCode::Home => (),
+                        KeyCode::End => (),
+                        KeyCode::PageUp => (),
+                        KeyCode::PageDown => (),
+                        KeyCode::Tab => (),
+                        KeyCode::BackTab => (),
+                        KeyCode::Delete => (),
+                        KeyCode::Insert => (),
+                        KeyCode::F(_) => (),
+                        KeyCode::Char(_) => (),
+                        KeyCode::Null => (),
+                        KeyCode::CapsLock => (),
+                        KeyCode::ScrollLock => (),
+                        KeyCode::NumLock => (),
+                        KeyCode::PrintScreen => (),
+                        KeyCode::Pause => (),
+                        KeyCode::Menu => (),
+                        KeyCode::KeypadBegin => (),
+                        KeyCode::Media(_) => (),
+                        KeyCode::Modifier(_) => (),
+                    }
+                }
+
+                Event::Mouse(_) => (),
+                Event::Paste(_) => (),
+                Event::Resize(_, _) => (),
+            }
+        }
+
+        Mode::ScrollMessages => {
+            match event {
+                Event::FocusGained => (),
+                Event::FocusLost => (),
+
+                Event::Key(key) => {
+                    match key.code {
+                        KeyCode::Backspace => (),
+
+                        KeyCode::Enter => {
+                            let gap = match selected_item(state) {
+                                Some(TimelineItem::Gap(channel, before)) => Some((channel.room.clone(), before.clone(), channel.gaps[before].clone())),
+                                _ => None,
+                            };
+
+                            if let Some((room, before, token)) = gap {
+                                let id = room.room_id().to_owned();
+                                state.tasks.spawn(&id, tasks::Job::Paginate, fill_gap(state2.clone(), room, before, token));
+                            } else if state.thread.is_none() {
+                                // messages with replies in a thread open it
+                                let root = selected_message(state).filter(|(channel, v)| channel.threads.contains_key(&v.id)).map(|(_, v)| v.id.clone());
+                                if let Some(root) = root {
+                                    open_thread(state, Some(root));
+                                }
+                            }
+                        }
+
+                        KeyCode::Left => (),
+                        KeyCode::Right => (),
+
+                        KeyCode::Up | KeyCode::Char('k') => {
+                            if let Some(channel) = state.current_channel.as_ref().filter(|v| state.channels.contains_key(*v)) {
+                                let count = timeline(state, channel).len();
+                                let oldest = channel_chain(state, channel).last().map(|v| v.room.room_id().to_owned());
+                                match state.messages_state.selected() {
+                                    Some(current) => {
+                                        if current + 1 < count {
+                                            state.messages_state.select(Some(current + 1));
+                                        } else if let Some(oldest) = oldest {
+                                            // in the background, so holding the key down only ever asks for one page at a time
+                                            let (state3, room) = (state2.clone(), oldest.clone());
+                                            state.tasks.spawn(&room, tasks::Job::Paginate, async move {
+                                                load_older(state3, &oldest).await;
+                                            });
+                                        }
+                                    }
+
+                                    None => {
+                                        if count != 0 {
+                                            state.channels_state.select(Some(0));
+                                        }
+                                    }
+                                }
+                            }
+                        }
+
+                        KeyCode::Down | KeyCode::Char('j') => {
+                            match state.messages_state.selected() {
+                                Some(current) => {
+                                    if current > 0 {
+                                        state.messages_state.select(Some(current - 1));
+                                    }
+                                }
+
+                                None => {
+                                    if let Some(channel) = state.current_channel.as_ref().and_then(|v| state.channels.get(v)) {
+                                        if !channel.messages.is_empty() {
+                                            state.channels_state.select(Some(0));
+                                        }
+                                    }
+                                }
+                            }
+                        }
+
+                        KeyCode::Home => (),
+                        KeyCode::End => (),
+                        KeyCode::PageUp => (),
+                        KeyCode::PageDown => (),
+                        KeyCode::Tab => (),
+                        KeyCode::BackTab => (),
+                        KeyCode::Delete => (),
+                        KeyCode::Insert => (),
+                        KeyCode::F(_) => (),
+
+                        KeyCode::Char('d') if key.modifiers == KeyModifiers::CONTROL => {
+                            let own = state.client.user_id().map(|v| v.to_string()).unwrap_or_default();
+                            let selected = selected_message(state).map(|(channel, message)| {
+                                let allowed = state.permissions.get(channel.room.room_id());
+                                let can = if message.user == own { allowed.redact_own } else { allowed.redact_others };
+                                (channel.archived, can, channel.room.clone(), message.id.clone())
+                            });
+                            match selected {
+                                Some((true, _, _, _)) => show_error(state, "Can't delete", String::from("You've left this room, so it's read-only.")),
+                                Some((false, false, _, _)) => show_error(state, "Can't delete", String::from("You don't have permission to delete that message.")),
+                                Some((false, true, room, event_id)) => {
+                                    if let Err(e) = room.redact(&event_id, None, None).await {
+                                        show_error(state, "Couldn't delete", e.to_string());
+                                    }
+                                }
+                                None => (),
+                            }
+                        }
+
+                        KeyCode::Char('o') => {
+                            if let Some(media) = selected_message(state).and_then(|(_, v)| v.media.clone()) {
+                                let client = state.client.clone();
+                                let dir = PathBuf::from(&state.config.downloads_dir);
+                                let opener = match media {
+                                    _ if !state.config.open_downloads => None,
+                                    MessageType::Video(_) => Some(state.config.video_player.clone()),
+                                    _ => Some(String::from(platform::default_opener())),
+                                };
+                                let name = media::name(&media).unwrap_or_default();
+                                state.downloading.push(name.clone());
+                                tokio::task::spawn(async move {
+                                    let result = media::save_attachment(&client, media, &dir).await.and_then(|path| match opener {
+                                        Some(opener) => media::open(&opener, &path).map(|_| path),
+                                        None => Ok(path),
+                                    });
+                                    let mut state = state2.lock().await;
+                                    if let Some(index) = state.downloading.iter().position(|v| *v == name) {
+                                        state.downloading.remove(index);
+                                    }
+                                    match result {
+                                        Ok(path) => {
+                                            let notice = format!("Downloaded {}", path.display());
+                                            state.announcements.push(&notice);
+                                            state.toast.show(notice, Instant::now());
+                                        }
+                                        Err(e) => show_error(&mut state, "Download failed", e),
+                                    }
+                                });
+                            }
+                        }
+
+                        KeyCode::Char('v') => {
+                            let id = selected_message(state).map(|(_, v)| v.id.clone());
+                            state.quote_mark = if state.quote_mark == id { None } else { id };
+                        }
+
+                        KeyCode::Char('r') => {
+                            if let Some(id) = selected_message(state).map(|(_, v)| v.id.clone()) {
+                                state.reply_to = Some(id);
+                                state.mode = Mode::Insert;
+                            }
+                        }
+
+                        // opens the selected message's thread, or closes the one open
+                        KeyCode::Char('t') => {
+                            let root = match state.thread {
+                                Some(_) => None,
+                                None => selected_message(state).map(|(_, v)| v.thread.clone().unwrap_or_else(|| v.id.clone())),
+                            };
+                            open_thread(state, root);
+                        }
+
+                        // the message is edited in the input box, in place of the draft
+                        KeyCode::Char('e') => {
+                            let own = state.client.user_id().map(|v| v.to_string()).unwrap_or_default();
+                            let selected = selected_message(state).map(|(channel, v)| (channel.archived, v.user == own && v.media.is_none() && v.call.is_none(), v.id.clone(), v.content.clone()));
+                            match selected {
+                                Some(_) if !state.server.relations() => show_error(state, "Can't edit", String::from(NO_EDITS)),
+                                Some((true, _, _, _)) => show_error(state, "Can't edit", String::from("You've left this room, so it's read-only.")),
+                                Some((false, false, _, _)) => show_error(state, "Can't edit", String::from("Only your own text messages can be edited.")),
+                                Some((false, true, id, content)) => {
+                                    // an edit still open or held back has already taken the draft's place
+                                    if state.edit_draft.is_none() {
+                                        let draft = (std::mem::take(&mut state.input_text), state.input_char_pos, state.input_byte_pos, state.code_block.take());
+                                        state.edit_draft = Some(draft);
+                                    }
+                                    clear_input(state);
+                                    insert_text(state, &content);
+                                    state.reply_to = None;
+                                    state.editing = Some(id);
+                                    state.mode = Mode::Insert;
+                                }
+                                None => (),
+                            }
+                        }
+
+                        KeyCode::Char('x') => {
+                            let id = selected_message(state).map(|(_, v)| v.id.clone());
+                            state.expanded = if state.expanded == id { None } else { id };
+                        }
+
+                        KeyCode::Char('y') => {
+                            if let Some((quote, count)) = quote_selection(state) {
+                                quote::copy(&quote);
+                                state.quote_mark = None;
+                                state.popup = Some(Popup {
+                                    title: String::from("Copied"),
+                                    lines: vec![format!("Copied {} message(s) as a quote.", count)],
+                                    action: None,
+                                });
+                            }
+                        }
+
+                        KeyCode::Char('+') => {
+                            if let Some((channel, message)) = selected_message(state) {
+                                let palette = react::Palette::new(channel.room.clone(), message.id.clone());
+                                state.popup = Some(Popup {
+                                    title: String::from("React"),
+                                    lines: palette.lines(&state.config.reactions.favorites, &state.symbols),
+                                    action: Some(PopupAction::React(Box::new(palette))),
+                                });
+                            }
+                        }
+
+                        KeyCode::Char('T') => {
+                            let selected = selected_message(state).filter(|(_, v)| v.media.is_none()).map(|(channel, v)| (channel.room.room_id().to_owned(), v.id.clone(), v.content.clone()));
+                            if let Some((room_id, event_id, text)) = selected {
+                                translate_message(state2.clone(), state.config.translate.command.clone(), room_id, event_id, text, true);
+                            }
+                        }
+
+                        KeyCode::Char('K') => {
+                            let request = selected_message(state).and_then(|(channel, message)| channel.undecrypted.get(&message.id).map(|v| (channel.room.room_id().to_owned(), v.event.clone())));
+                            if let Some((room_id, event)) = request {
+                                let client = state.client.clone();
+                                tokio::task::spawn(async move {
+                                    let popup = match keys::request_keys(&client, &room_id, &event).await {
+                                        Ok(count) => Popup {
+                                            title: String::from("Keys requested"),
+                                            lines: vec![format!("Asked {} verified device(s) for the keys.", count), String::from("The message will be decrypted once they arrive.")],
+                                            action: None,
+                                        },
+
+                                        Err(e) => Popup {
+                                            title: String::from("Key request failed"),
+                                            lines: vec![e],
+                                            action: None,
+                                        },
+                                    };
+                                    state2.lock().await.popup = Some(popup);
+                                });
+                            }
+                        }
+
+                        KeyCode::Char('P') => {
+                            if let Some(user_id) = selected_message(state).and_then(|(_, v)| UserId::parse(&v.user).ok()) {
+                                show_profile(state, &user_id).await;
+                            }
+                        }
+
+                        KeyCode::Char('R') => {
+                            let popup = match selected_message(state) {
+                                Some((channel, message)) => {
+                                    let selected = channel.message_ids.iter().position(|v| *v == message.id).unwrap_or(0);
+                                    let mut seen_by = vec![];
+                                    if let Ok(members) = channel.room.joined_members().await {
+                                        for member in members {
+                                            if member.user_id() == channel.room.own_user_id() {
+                                                continue;
+                                            }
+
+                                            if let Ok(Some((event_id, receipt))) = channel.room.user_read_receipt(member.user_id()).await {
+                                                // receipts on events we haven't loaded fall back to comparing timestamps
+                                                let seen = match channel.message_ids.iter().position(|v| *v == event_id) {
+                                                    Some(pos) => pos >= selected,
+                                                    None => receipt.ts.map(|v| v.as_secs() >= message.timestamp).unwrap_or(false),
+                                                };
+
+                                                if seen {
+                                                    seen_by.push(format!("{} ({})", member.name(), member.user_id()));
+                                                }
+                                            }
+                                        }
+                                    }
+
+                                    if seen_by.is_empty() {
+                                        seen_by.push(String::from("nobody yet"));
+                                    }
+
+                                    Some(Popup {
+                                        title: String::from("Seen by"),
+                                        lines: seen_by,
+                                        action: None,
+                                    })
+                                }
+
+                                None => None,
+                            };
+                            state.popup = popup;
+                        }
+
+                        KeyCode::Char('i') => {
+                            let popup = selected_message(state).map(|(channel, message)| {
+                                let sent = state.clock.precise(u64::from(message.timestamp) as i64);
+                                let mut lines = vec![format!("Event: {}", message.id), format!("Sender: {}", message.user), format!("Sent: {}", sent)];
+                                lines.extend(message.encryption.lines(channel.room.is_encrypted()));
+                                Popup {
+                                    title: String::from("Message details"),
+                                    lines,
+                                    action: None,
+                                }
+                            });
+                            state.popup = popup;
+                        }
+
+                        KeyCode::Char(_) => (),
+
+                        KeyCode::Null => (),
+
+                        KeyCode::Esc => {
+                            state.messages_state.select(None);
+                            state.mode = Mode::Normal;
+                        }
+
+                        KeyCode::CapsLock => (),
+                        KeyCode::ScrollLock => (),
+                        KeyCode::NumLock => (),
+                        KeyCode::PrintScreen => (),
+                        KeyCode::Pause => (),
+                        KeyCode::Menu => (),
+                        KeyCode::KeypadBegin => (),
+                        KeyCode::Media(_) => (),
+                        KeyCode::Modifier(_) => (),
+                    }
+                }
+
+                Event::Mouse(_) => (),
+                Event::Paste(_) => (),
+                Event::Resize(_, _) => (),
+            }
+        }
+    }
+    true
+}
+
+/// What the benchmarks drive, so none of the app has to be public for them.
+pub mod bench {
+    use std::sync::Arc;
+
+    use matrix_sdk::{
+        config::SyncSettings,
+        reqwest::Url,
+        ruma::{OwnedRoomId, UserId},
+        Client, Session,
+    };
+    use serde_json::value::RawValue;
+    use tokio::sync::Mutex;
+    use tui::{backend::TestBackend, Terminal};
+
+    use crate::{config::Config, handle_new_message, load_rooms, new_state, server::ServerFeatures, trust, ui, AppState, StreamPosition};
+
+    /// The app logged in to a homeserver and synced once, with a room open.
+    pub struct App {
+        state: Arc<Mutex<AppState>>,
+        room: OwnedRoomId,
+    }
+
+    impl App {
+        /// Logs in to `homeserver` with a made up session, syncs, and opens `room`.
+        pub async fn new(homeserver: &str, user_id: &str, room: &str) -> App {
+            let client = Client::new(Url::parse(homeserver).unwrap()).await.unwrap();
+            client
+                .restore_login(Session {
+                    user_id: UserId::parse(user_id).unwrap(),
+                    access_token: String::from("token"),
+                    device_id: "DEVICE".into(),
+                    refresh_token: None,
+                })
+                .await
+                .unwrap();
+            client.sync_once(SyncSettings::default()).await.unwrap();
+
+            let state = Arc::new(Mutex::new(new_state(Arc::new(client), Config::default(), ServerFeatures::default(), String::new(), false)));
+            load_rooms(&state).await;
+            let room = OwnedRoomId::try_from(room).unwrap();
+            state.lock().await.current_channel = Some(room.clone());
+            App { state, room }
+        }
+
+        /// Handles a message as though it had just come from sync. Not for use inside the runtime.
+        pub fn receive(&self, event: &RawValue) {
+            let message = serde_json::from_str(event.get()).unwrap();
+            handle_new_message(&self.room, message, event, trust::Encryption::Plain, StreamPosition::End, &mut self.state.blocking_lock());
+        }
+
+        /// Draws a frame. Not for use inside the runtime.
+        pub fn draw(&self, terminal: &mut Terminal<TestBackend>) {
+            let state = self.state.blocking_lock();
+            terminal.draw(|f| ui::draw(f, &state)).unwrap();
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Logging in. The first run asks for a homeserver, username, and password, and saves the session
+//! the server gives back, which every run after that restores. A `.credentials` file written by
+//! hand for older versions is still read, and saved as a session in its place.
+//!
+//! The client keeps its state and encryption keys in a sled store next to the session, so
+//! encrypted rooms can be read and sent to, and keep working across runs.
+
+use std::io::{self, Write};
+
+use crossterm::event::{Event, KeyCode, KeyModifiers};
+use matrix_sdk::{reqwest::Url, ruma::UserId, Client, Session};
+use serde::{Deserialize, Serialize};
+
+use crate::platform;
+
+/// Where the session is saved. It holds an access token, so it's as secret as a password.
+pub const SESSION_FILE: &str = ".session";
+
+/// Where the client's state and encryption keys are kept. Losing it means losing the keys to
+/// every encrypted message received so far, unless they were backed up or exported.
+const STORE_DIR: &str = "store";
+
+/// The file older versions read, with the homeserver, user id, access token, and device id on
+/// separate lines.
+const CREDENTIALS_FILE: &str = ".credentials";
+
+#[derive(Serialize, Deserialize)]
+struct Saved {
+    homeserver: String,
+    #[serde(flatten)]
+    session: Session,
+}
+
+/// A client logged in with the saved session, or by asking for an account if there isn't one.
+pub async fn client() -> Result<Client, String> {
+    if let Some(saved) = saved()? {
+        let homeserver = Url::parse(&saved.homeserver).map_err(|e| format!("{} has a bad homeserver: {}", SESSION_FILE, e))?;
+        let client = build(homeserver).await?;
+        client.restore_login(saved.session).await.map_err(|e| e.to_string())?;
+        return Ok(client);
+    }
+
+    println!("Log in to your Matrix account. Your password only goes to the homeserver, and the session it gives back is saved to {}.", SESSION_FILE);
+    loop {
+        let homeserver = read_line("Homeserver (like matrix.org): ")?;
+        let username = read_line("Username: ")?;
+        let password = read_secret("Password: ")?;
+        match login(&homeserver, &username, &password).await {
+            Ok(v) => return Ok(v),
+            Err(e) => println!("Couldn't log in: {}", e),
+        }
+    }
+}
+
+async fn login(homeserver: &str, username: &str, password: &str) -> Result<Client, String> {
+    let homeserver = if homeserver.contains("://") { homeserver.to_string() } else { format!("https://{}", homeserver) };
+    let client = build(Url::parse(&homeserver).map_err(|e| e.to_string())?).await?;
+    let response = client.login_username(username, password).initial_device_display_name("ilo-toki").send().await.map_err(|e| e.to_string())?;
+    save(&Saved { homeserver, session: response.into() })?;
+    Ok(client)
+}
+
+async fn build(homeserver: Url) -> Result<Client, String> {
+    Client::builder()
+        .homeserver_url(homeserver)
+        .sled_store(STORE_DIR, None)
+        .map_err(|e| format!("Couldn't open {}: {}", STORE_DIR, e))?
+        .build()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// The saved session, moving a `.credentials` file over to it if that's all there is.
+fn saved() -> Result<Option<Saved>, String> {
+    if let Ok(v) = std::fs::read_to_string(SESSION_FILE) {
+        return serde_json::from_str(&v).map(Some).map_err(|e| format!("{} can't be read: {}", SESSION_FILE, e));
+    }
+
+    let credentials = match std::fs::read_to_string(CREDENTIALS_FILE) {
+        Ok(v) => v,
+        Err(_) => return Ok(None),
+    };
+    let lines: Vec<_> = credentials.lines().map(str::trim).collect();
+    let (homeserver, user_id, access_token, device_id) = match lines[..] {
+        [homeserver, user_id, access_token, device_id, ..] => (homeserver, user_id, access_token, device_id),
+        _ => return Err(format!("{} should have the homeserver, user id, access token, and device id on separate lines", CREDENTIALS_FILE)),
+    };
+    let saved = Saved {
+        homeserver: homeserver.to_string(),
+        session: Session {
+            access_token: access_token.to_string(),
+            refresh_token: None,
+            user_id: UserId::parse(user_id).map_err(|e| format!("{} has a bad user id: {}", CREDENTIALS_FILE, e))?,
+            device_id: device_id.into(),
+        },
+    };
+    save(&saved)?;
+    let _ = std::fs::remove_file(CREDENTIALS_FILE);
+    Ok(Some(saved))
+}
+
+fn save(saved: &Saved) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(saved).map_err(|e| e.to_string())?;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    // only readable by us, like an ssh key
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(SESSION_FILE).and_then(|mut v| v.write_all(json.as_bytes())).map_err(|e| format!("Couldn't save {}: {}", SESSION_FILE, e))
+}
+
+fn read_line(prompt: &str) -> Result<String, String> {
+    print!("{}", prompt);
+    let _ = io::stdout().flush();
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => Err(String::from("Not logged in.")),
+        Ok(_) => Ok(line.trim().to_string()),
+    }
+}
+
+/// Reads a line without showing what's typed.
+fn read_secret(prompt: &str) -> Result<String, String> {
+    print!("{}", prompt);
+    let _ = io::stdout().flush();
+    crossterm::terminal::enable_raw_mode().map_err(|e| e.to_string())?;
+    let mut secret = String::new();
+    let result = loop {
+        match crossterm::event::read() {
+            Ok(Event::Key(key)) if platform::is_key_press(&key) => match key.code {
+                KeyCode::Enter => break Ok(secret),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break Err(String::from("Not logged in.")),
+                KeyCode::Char(c) => secret.push(c),
+                KeyCode::Backspace => {
+                    secret.pop();
+                }
+
+                _ => (),
+            },
+
+            Ok(_) => (),
+            Err(e) => break Err(e.to_string()),
+        }
+    };
+    let _ = crossterm::terminal::disable_raw_mode();
+    println!();
+    result
+}
@@ -0,0 +1,92 @@
+//! Vim style macros: `q<reg>` starts recording keys into a register, `q` stops, and `@<reg>` replays
+//! them (`@@` replays the last one). These keys are only taken outside of insert mode.
+
+use std::collections::HashMap;
+
+use crossterm::event::{KeyCode, KeyEvent};
+
+enum Pending {
+    Record,
+    Replay,
+}
+
+pub enum MacroAction {
+    /// The key was used by the macro system.
+    Consumed,
+    /// These keys should be handled as if they were typed.
+    Replay(Vec<KeyEvent>),
+    /// The key should be handled as usual.
+    Pass,
+}
+
+#[derive(Default)]
+pub struct Macros {
+    registers: HashMap<char, Vec<KeyEvent>>,
+    recording: Option<(char, Vec<KeyEvent>)>,
+    pending: Option<Pending>,
+    last: Option<char>,
+}
+
+impl Macros {
+    /// The register being recorded into, if any.
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(register, _)| *register)
+    }
+
+    /// Looks at a key before it's handled. `inserting` is whether the input box is taking text.
+    pub fn key(&mut self, key: KeyEvent, inserting: bool) -> MacroAction {
+        if let Some(pending) = self.pending.take() {
+            let register = match key.code {
+                KeyCode::Char(c) if c.is_alphanumeric() => c,
+                KeyCode::Char('@') if matches!(pending, Pending::Replay) => match self.last {
+                    Some(v) => v,
+                    None => return MacroAction::Consumed,
+                },
+                _ => return MacroAction::Consumed,
+            };
+
+            return match pending {
+                Pending::Record => {
+                    self.recording = Some((register, vec![]));
+                    MacroAction::Consumed
+                }
+
+                Pending::Replay => {
+                    self.last = Some(register);
+                    let keys = self.registers.get(&register).cloned().unwrap_or_default();
+                    if let Some((_, recorded)) = self.recording.as_mut() {
+                        recorded.extend(keys.iter().cloned());
+                    }
+                    MacroAction::Replay(keys)
+                }
+            };
+        }
+
+        if !inserting {
+            match key.code {
+                KeyCode::Char('q') => {
+                    match self.recording.take() {
+                        Some((register, keys)) => {
+                            self.registers.insert(register, keys);
+                        }
+
+                        None => self.pending = Some(Pending::Record),
+                    }
+                    return MacroAction::Consumed;
+                }
+
+                KeyCode::Char('@') => {
+                    self.pending = Some(Pending::Replay);
+                    return MacroAction::Consumed;
+                }
+
+                _ => (),
+            }
+        }
+
+        if let Some((_, keys)) = self.recording.as_mut() {
+            keys.push(key);
+        }
+        MacroAction::Pass
+    }
+}
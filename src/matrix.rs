@@ -0,0 +1,44 @@
+//! The requests the timeline makes to the homeserver to page back through a room. The tests run
+//! them against a mock homeserver over HTTP, like the rest of the client.
+
+use matrix_sdk::{
+    room::{Joined, Messages, MessagesOptions},
+    ruma::{OwnedEventId, UInt},
+};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct TimestampToEvent {
+    event_id: OwnedEventId,
+    origin_server_ts: u64,
+}
+
+/// Up to `limit` events from before the `from` token, newest first. Without a token, they come from
+/// before the latest event.
+pub async fn messages_before(room: &Joined, from: Option<&str>, limit: u32) -> matrix_sdk::Result<Messages> {
+    let mut options = MessagesOptions::backward();
+    options.limit = UInt::from(limit);
+    options.from = from;
+    room.messages(options).await
+}
+
+/// The first event at or after `timestamp` in milliseconds, along with when it was sent, asked of the
+/// `timestamp_to_event` endpoint under `prefix`.
+pub async fn event_after(room: &Joined, prefix: &str, timestamp: u64) -> Result<(OwnedEventId, u64), String> {
+    // ruma doesn't have this endpoint yet
+    let client = room.client();
+    let mut url = client.homeserver().await;
+    url.path_segments_mut().unwrap().pop_if_empty().extend(prefix.split('/')).extend(["rooms", room.room_id().as_str(), "timestamp_to_event"]);
+    url.query_pairs_mut().append_pair("ts", &timestamp.to_string()).append_pair("dir", "f");
+
+    let response = matrix_sdk::reqwest::Client::new()
+        .get(url)
+        .bearer_auth(client.access_token().unwrap_or_default())
+        .send()
+        .await
+        .and_then(|v| v.error_for_status())
+        .map_err(|e| e.to_string())?;
+    let body = response.text().await.map_err(|e| e.to_string())?;
+    let event: TimestampToEvent = serde_json::from_str(&body).map_err(|e| e.to_string())?;
+    Ok((event.event_id, event.origin_server_ts))
+}
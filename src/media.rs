@@ -0,0 +1,359 @@
+use std::{
+    ffi::OsStr,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType};
+use matrix_sdk::{
+    attachment::{AttachmentConfig, AttachmentInfo, BaseImageInfo, BaseThumbnailInfo, BaseVideoInfo, Thumbnail},
+    media::MediaEventContent,
+    room::Joined,
+    ruma::{api::client::media::get_media_config, events::room::message::MessageType, UInt},
+    Client,
+};
+use mime::Mime;
+
+use crate::config::UploadSettings;
+
+/// Width of the thumbnails generated for videos.
+const THUMBNAIL_WIDTH: u32 = 320;
+
+/// What ffprobe could tell us about a video.
+#[derive(Default)]
+pub struct VideoProbe {
+    pub duration: Option<Duration>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+/// Reads a video's duration and resolution with ffprobe, if it's installed.
+pub async fn probe_video(path: &Path) -> VideoProbe {
+    let output = tokio::process::Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0", "-show_entries", "stream=width,height:format=duration", "-of", "default=noprint_wrappers=1"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .await;
+
+    let mut probe = VideoProbe::default();
+    if let Ok(output) = output {
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            match line.split_once('=') {
+                Some(("width", v)) => probe.width = v.parse().ok(),
+                Some(("height", v)) => probe.height = v.parse().ok(),
+                Some(("duration", v)) => probe.duration = v.parse().ok().map(Duration::from_secs_f64),
+                _ => (),
+            }
+        }
+    }
+    probe
+}
+
+/// Grabs a jpeg frame from the start of a video with ffmpeg, if it's installed.
+pub async fn video_thumbnail(path: &Path) -> Option<Vec<u8>> {
+    let output = tokio::process::Command::new("ffmpeg")
+        .args(["-v", "error", "-ss", "1", "-i"])
+        .arg(path)
+        .args(["-frames:v", "1", "-vf", &format!("scale={}:-2", THUMBNAIL_WIDTH), "-f", "image2", "-c:v", "mjpeg", "-"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .await
+        .ok()?;
+
+    if output.status.success() && !output.stdout.is_empty() {
+        Some(output.stdout)
+    } else {
+        None
+    }
+}
+
+/// Builds the attachment config for a video, with a thumbnail when one could be generated.
+pub fn video_attachment_config<'a>(probe: &VideoProbe, size: usize, thumbnail: Option<&'a [u8]>) -> AttachmentConfig<'a> {
+    let info = AttachmentInfo::Video(BaseVideoInfo {
+        duration: probe.duration,
+        height: probe.height.map(UInt::from),
+        width: probe.width.map(UInt::from),
+        size: UInt::new(size as u64),
+        blurhash: None,
+    });
+
+    match thumbnail {
+        Some(data) => {
+            let height = match (probe.width, probe.height) {
+                (Some(width), Some(height)) if width != 0 => Some(UInt::from(THUMBNAIL_WIDTH * height / width)),
+                _ => None,
+            };
+
+            AttachmentConfig::with_thumbnail(Thumbnail {
+                data,
+                content_type: &mime::IMAGE_JPEG,
+                info: Some(BaseThumbnailInfo {
+                    height,
+                    width: Some(UInt::from(THUMBNAIL_WIDTH)),
+                    size: UInt::new(data.len() as u64),
+                }),
+            })
+            .info(info)
+        }
+
+        None => AttachmentConfig::new().info(info),
+    }
+}
+
+/// A one line description of a media message, or `None` if it isn't one.
+pub fn summary(media: &MessageType) -> Option<String> {
+    match media {
+        MessageType::Video(video) => {
+            let mut details = vec![];
+            if let Some(info) = video.info.as_ref() {
+                if let Some(duration) = info.duration {
+                    details.push(format_duration(duration));
+                }
+                if let (Some(width), Some(height)) = (info.width, info.height) {
+                    details.push(format!("{}x{}", width, height));
+                }
+                if let Some(size) = info.size {
+                    details.push(format_size(u64::from(size)));
+                }
+            }
+
+            if details.is_empty() {
+                Some(format!("[video: {}]", video.body))
+            } else {
+                Some(format!("[video: {}] {}", video.body, details.join(", ")))
+            }
+        }
+
+        MessageType::Image(image) => Some(format!("[image: {}]", image.body)),
+        MessageType::Audio(audio) => match audio.info.as_ref().and_then(|v| v.duration) {
+            Some(duration) => Some(format!("[audio: {}] {}", audio.body, format_duration(duration))),
+            None => Some(format!("[audio: {}]", audio.body)),
+        },
+        MessageType::File(file) => match file.info.as_ref().and_then(|v| v.size) {
+            Some(size) => Some(format!("[file: {}] {}", name(media).unwrap_or_default(), format_size(u64::from(size)))),
+            None => Some(format!("[file: {}]", name(media).unwrap_or_default())),
+        },
+
+        _ => None,
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// A local file waiting to be uploaded.
+pub struct Upload {
+    pub path: PathBuf,
+    pub name: String,
+    pub content_type: Mime,
+    pub data: Vec<u8>,
+}
+
+/// What an upload is made from: a file, only read once the upload is under way since it can be
+/// large, or something already in memory.
+pub enum Source {
+    File(PathBuf),
+    Ready(Upload),
+}
+
+impl Source {
+    pub fn name(&self) -> String {
+        match self {
+            Source::File(path) => file_name(path),
+            Source::Ready(upload) => upload.name.clone(),
+        }
+    }
+
+    /// Reads the file, if it's one. This blocks, so it's for `spawn_blocking`.
+    pub fn load(self) -> Result<Upload, String> {
+        match self {
+            Source::File(path) => read_upload(&path),
+            Source::Ready(upload) => Ok(upload),
+        }
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|v| v.to_string_lossy().to_string()).unwrap_or_else(|| String::from("file"))
+}
+
+pub fn content_type(path: &Path) -> Mime {
+    mime_guess::from_path(path).first_or_octet_stream()
+}
+
+pub fn read_upload(path: &Path) -> Result<Upload, String> {
+    let data = std::fs::read(path).map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+    Ok(Upload {
+        path: path.to_path_buf(),
+        name: file_name(path),
+        content_type: content_type(path),
+        data,
+    })
+}
+
+/// Text to upload as a file, like a long paste.
+pub fn text_upload(name: &str, text: String) -> Upload {
+    Upload {
+        path: PathBuf::from(name),
+        name: String::from(name),
+        content_type: mime::TEXT_PLAIN_UTF_8,
+        data: text.into_bytes(),
+    }
+}
+
+/// Asks the homeserver for the largest upload it accepts.
+pub async fn upload_limit(client: &Client) -> Option<u64> {
+    let response = client.send(get_media_config::v3::Request::new(), None).await.ok()?;
+    Some(u64::from(response.upload_size))
+}
+
+/// Whether an image is big enough that the user should be offered to downscale it first.
+pub fn should_downscale(content_type: &Mime, size: u64, settings: &UploadSettings) -> bool {
+    content_type.type_() == mime::IMAGE && size > settings.downscale_images_over
+}
+
+/// Checks an upload against the server's limit and the configured limit for its type.
+pub fn check_size(upload: &Upload, server_limit: Option<u64>, settings: &UploadSettings) -> Result<(), String> {
+    let size = upload.data.len() as u64;
+    if let Some(limit) = server_limit.filter(|v| size > *v) {
+        return Err(format!("{} is {}, but the server only accepts up to {}", upload.name, format_size(size), format_size(limit)));
+    }
+
+    let limit = settings.limits.get(upload.content_type.essence_str()).or_else(|| settings.limits.get(upload.content_type.type_().as_str()));
+    if let Some(limit) = limit.filter(|v| size > **v) {
+        return Err(format!("{} is {}, over the configured limit of {} for {}", upload.name, format_size(size), format_size(*limit), upload.content_type.essence_str()));
+    }
+
+    Ok(())
+}
+
+/// Shrinks an image to fit within `max_dimension` and recompresses it as a jpeg.
+pub fn downscale_image(upload: &mut Upload, max_dimension: u32) -> Result<(), String> {
+    let image = image::load_from_memory(&upload.data).map_err(|e| e.to_string())?;
+    let image = if image.width() > max_dimension || image.height() > max_dimension {
+        image.resize(max_dimension, max_dimension, FilterType::Lanczos3)
+    } else {
+        image
+    };
+
+    let mut data = vec![];
+    JpegEncoder::new_with_quality(&mut data, 85).encode_image(&image.to_rgb8()).map_err(|e| e.to_string())?;
+    upload.data = data;
+    upload.content_type = mime::IMAGE_JPEG;
+    upload.name = format!("{}.jpg", Path::new(&upload.name).file_stem().map(|v| v.to_string_lossy()).unwrap_or_default());
+    Ok(())
+}
+
+/// Uploads a file to the room, attaching thumbnails and metadata for images and videos.
+pub async fn send_upload(room: &Joined, upload: &Upload) -> Result<(), String> {
+    let probe;
+    let thumbnail;
+    let config = if upload.content_type.type_() == mime::VIDEO {
+        // ffprobe and ffmpeg are run as child processes, so they don't hold up the runtime's threads
+        (probe, thumbnail) = tokio::join!(probe_video(&upload.path), video_thumbnail(&upload.path));
+        video_attachment_config(&probe, upload.data.len(), thumbnail.as_deref())
+    } else if upload.content_type.type_() == mime::IMAGE {
+        let (width, height) = image::load_from_memory(&upload.data).map(|v| (Some(v.width()), Some(v.height()))).unwrap_or((None, None));
+        AttachmentConfig::new().generate_thumbnail(None).info(AttachmentInfo::Image(BaseImageInfo {
+            height: height.map(UInt::from),
+            width: width.map(UInt::from),
+            size: UInt::new(upload.data.len() as u64),
+            blurhash: None,
+        }))
+    } else {
+        AttachmentConfig::new()
+    };
+
+    room.send_attachment(&upload.name, &upload.content_type, &upload.data, config).await.map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// The name of a media message's file. Files can have a body that describes them and a name apart.
+pub fn name(media: &MessageType) -> Option<String> {
+    match media {
+        MessageType::Audio(v) => Some(v.body.clone()),
+        MessageType::File(v) => Some(v.filename.clone().unwrap_or_else(|| v.body.clone())),
+        MessageType::Image(v) => Some(v.body.clone()),
+        MessageType::Video(v) => Some(v.body.clone()),
+        _ => None,
+    }
+}
+
+/// Downloads a media message's attachment into `dir`, returning where it was saved.
+pub async fn save_attachment(client: &Client, media: MessageType, dir: &Path) -> Result<PathBuf, String> {
+    let name = name(&media).ok_or_else(|| String::from("message has no file"))?;
+    match media {
+        MessageType::Audio(v) => save(client, v, &name, dir).await,
+        MessageType::File(v) => save(client, v, &name, dir).await,
+        MessageType::Image(v) => save(client, v, &name, dir).await,
+        MessageType::Video(v) => save(client, v, &name, dir).await,
+        _ => Err(String::from("message has no file")),
+    }
+}
+
+/// Downloads a media message's file into `dir`, returning where it was saved.
+pub async fn save(client: &Client, content: impl MediaEventContent, name: &str, dir: &Path) -> Result<PathBuf, String> {
+    let data = client.media().get_file(content, true).await.map_err(|e| e.to_string())?.ok_or_else(|| String::from("message has no file"))?;
+    let name = Path::new(name).file_name().map(|v| v.to_os_string()).unwrap_or_else(|| "download".into());
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let (path, mut file) = create_new(dir, &name).map_err(|e| e.to_string())?;
+    file.write_all(&data).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+/// Creates a file named `name` in `dir`, or `name (1)`, `name (2)`, and so on with the extension
+/// kept, rather than overwriting one that's already there.
+pub fn create_new(dir: &Path, name: &OsStr) -> io::Result<(PathBuf, File)> {
+    let name = Path::new(name);
+    let stem = name.file_stem().unwrap_or(name.as_os_str()).to_string_lossy();
+    let extension = name.extension().map(|v| format!(".{}", v.to_string_lossy())).unwrap_or_default();
+    let mut i = 0;
+    loop {
+        let path = if i == 0 { dir.join(name) } else { dir.join(format!("{} ({}){}", stem, i, extension)) };
+        // creating it only if it's new means nothing can take the name between checking and writing
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => i += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Opens a file or URL with an external program, without letting it draw over the terminal.
+pub fn open(program: &str, target: impl AsRef<OsStr>) -> Result<(), String> {
+    Command::new(program)
+        .arg(target)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("couldn't run {}: {}", program, e))
+}
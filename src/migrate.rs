@@ -0,0 +1,282 @@
+//! `--import-weechat <dir>` and `--import-irssi <file>`: the highlight words, aliases, and nick
+//! colours of an IRC client's config, printed as TOML to add to config.toml.
+
+use std::{collections::HashMap, path::Path};
+
+use crate::commands;
+
+#[derive(Default)]
+pub struct Imported {
+    pub highlights: Vec<String>,
+    /// Names and what they stand for, in the order they were read.
+    pub aliases: Vec<(String, String)>,
+    pub nick_colors: Option<bool>,
+    /// Settings that have nothing like them here, which the output lists.
+    pub skipped: Vec<String>,
+}
+
+impl Imported {
+    /// Adds an alias if what it runs is a command here and it only uses `$*` of the arguments.
+    fn alias(&mut self, name: &str, target: &str) {
+        let target = target.trim();
+        let command = target.trim_start_matches('/').split_whitespace().next().unwrap_or_default().to_ascii_lowercase();
+        let known = commands::parse(&format!("/{}", command)).is_some() || commands::parse(&format!("/{} x", command)).is_some();
+        let variables = target.replace("$*", "").contains('$');
+        if !known || variables || target.contains(';') {
+            self.skipped.push(format!("alias {} = {}", name, target));
+            return;
+        }
+
+        let rest = target.trim_start_matches('/').split_once(char::is_whitespace).map(|v| v.1).unwrap_or_default();
+        let target = if rest.is_empty() { format!("/{}", command) } else { format!("/{} {}", command, rest) };
+        self.aliases.push((name.to_ascii_lowercase(), target));
+    }
+
+    pub fn to_toml(&self, source: &Path) -> String {
+        let quote = |v: &str| toml::Value::String(v.to_string()).to_string();
+        let mut lines = vec![format!("# Imported from {}. Add these to config.toml.", source.display())];
+        if !self.highlights.is_empty() {
+            lines.push(String::new());
+            lines.push(String::from("[highlights]"));
+            lines.push(format!("words = [{}]", self.highlights.iter().map(|v| quote(v)).collect::<Vec<_>>().join(", ")));
+        }
+        if !self.aliases.is_empty() {
+            lines.push(String::new());
+            lines.push(String::from("[aliases]"));
+            for (name, target) in self.aliases.iter() {
+                let bare = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+                lines.push(format!("{} = {}", if bare { name.clone() } else { quote(name) }, quote(target)));
+            }
+        }
+        if let Some(nick_colors) = self.nick_colors {
+            lines.push(String::new());
+            lines.push(String::from("[colors]"));
+            lines.push(format!("nick_colors = {}", nick_colors));
+        }
+        if !self.skipped.is_empty() {
+            lines.push(String::new());
+            lines.push(String::from("# Not imported, since ilo-toki has nothing like them:"));
+            lines.extend(self.skipped.iter().map(|v| format!("# {}", v)));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Reads `weechat.conf` and `alias.conf` from a WeeChat config directory.
+pub fn weechat(dir: &Path) -> Result<Imported, String> {
+    let weechat = std::fs::read_to_string(dir.join("weechat.conf")).map_err(|e| format!("Can't read {}: {}", dir.join("weechat.conf").display(), e))?;
+    let weechat = weechat_options(&weechat);
+    let mut imported = Imported::default();
+
+    let words = weechat.get(&(String::from("look"), String::from("highlight"))).map(String::as_str).unwrap_or_default();
+    for word in words.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+        // `*` is WeeChat's wildcard
+        if word.contains('*') {
+            imported.highlights.push(format!("/\\b{}/", word.split('*').map(regex::escape).collect::<Vec<_>>().join("\\S*")));
+        } else {
+            imported.highlights.push(word.to_string());
+        }
+    }
+    if let Some(regex) = weechat.get(&(String::from("look"), String::from("highlight_regex"))).filter(|v| !v.is_empty()) {
+        imported.highlights.push(format!("/{}/", regex));
+    }
+    if let Some(colors) = weechat.get(&(String::from("color"), String::from("chat_nick_colors"))) {
+        imported.nick_colors = Some(!colors.is_empty());
+    }
+
+    // aliases are a plugin, so they may not be there
+    if let Ok(aliases) = std::fs::read_to_string(dir.join("alias.conf")) {
+        let mut aliases: Vec<_> = weechat_options(&aliases).into_iter().filter(|((section, _), _)| section == "cmd").map(|((_, k), v)| (k, v)).collect();
+        aliases.sort();
+        for (name, target) in aliases {
+            imported.alias(&name, &target);
+        }
+    }
+    Ok(imported)
+}
+
+/// The options in a WeeChat config file, keyed by section and name.
+fn weechat_options(text: &str) -> HashMap<(String, String), String> {
+    let mut options = HashMap::new();
+    let mut section = String::new();
+    for line in text.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            section = name.to_string();
+        } else if let Some((key, value)) = line.split_once(" = ").filter(|_| !line.starts_with('#')) {
+            let value = value.trim();
+            let value = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+                Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+                None => value.to_string(),
+            };
+            options.insert((section.clone(), key.trim().to_string()), value);
+        }
+    }
+    options
+}
+
+/// Reads an irssi config file.
+pub fn irssi(path: &Path) -> Result<Imported, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Can't read {}: {}", path.display(), e))?;
+    let config = IrssiParser { tokens: irssi_tokens(&text), pos: 0 }.block();
+    let mut imported = Imported::default();
+
+    if let Some(Value::List(hilights)) = get(&config, "hilights") {
+        for hilight in hilights.iter().filter_map(Value::block) {
+            let text = match get(hilight, "text").and_then(Value::text) {
+                Some(v) => v,
+                None => continue,
+            };
+            if get(hilight, "channels").is_some() {
+                imported.skipped.push(format!("hilight {} (only in some channels)", text));
+            } else if get(hilight, "regexp").and_then(Value::text) == Some("yes") {
+                imported.highlights.push(format!("/{}/", text));
+            } else {
+                imported.highlights.push(text.to_string());
+            }
+        }
+    }
+
+    if let Some(aliases) = get(&config, "aliases").and_then(Value::block) {
+        for (name, target) in aliases.iter() {
+            if let Some(target) = target.text() {
+                imported.alias(name, target);
+            }
+        }
+    }
+
+    let settings = get(&config, "settings").and_then(Value::block);
+    if let Some(theme) = settings.and_then(|v| get(v, "fe-common/core")).and_then(Value::block).and_then(|v| get(v, "theme")).and_then(Value::text) {
+        if theme != "default" {
+            imported.skipped.push(format!("theme {}", theme));
+        }
+    }
+    // nick colours come from the nickcolor script
+    if settings.and_then(|v| get(v, "perl/core/scripts")).and_then(Value::block).and_then(|v| get(v, "nickcolor_colors")).is_some() {
+        imported.nick_colors = Some(true);
+    }
+    Ok(imported)
+}
+
+enum Value {
+    Text(String),
+    Block(Vec<(String, Value)>),
+    List(Vec<Value>),
+}
+
+impl Value {
+    fn text(&self) -> Option<&str> {
+        match self {
+            Value::Text(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn block(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Block(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+fn get<'a>(block: &'a [(String, Value)], key: &str) -> Option<&'a Value> {
+    block.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+}
+
+/// Splits irssi's config into strings and the punctuation `{}()=;,`, leaving out comments.
+fn irssi_tokens(text: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '#' => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+
+            '"' => {
+                let mut token = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => token.extend(chars.next()),
+                        '"' => break,
+                        c => token.push(c),
+                    }
+                }
+                tokens.push(token);
+            }
+
+            '{' | '}' | '(' | ')' | '=' | ';' | ',' => tokens.push(c.to_string()),
+            c if c.is_whitespace() => (),
+            c => {
+                let mut token = c.to_string();
+                while let Some(c) = chars.peek().copied().filter(|c| !c.is_whitespace() && !"{}()=;,#\"".contains(*c)) {
+                    token.push(c);
+                    chars.next();
+                }
+                tokens.push(token);
+            }
+        }
+    }
+    tokens
+}
+
+struct IrssiParser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl IrssiParser {
+    fn next(&mut self) -> Option<&str> {
+        self.pos += 1;
+        self.tokens.get(self.pos - 1).map(String::as_str)
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    /// `key = value;` pairs up to a closing `}` or the end.
+    fn block(&mut self) -> Vec<(String, Value)> {
+        let mut block = vec![];
+        while let Some(key) = self.next().map(String::from) {
+            match key.as_str() {
+                "}" => break,
+                ";" | "," => continue,
+                _ => (),
+            }
+            if self.peek() == Some("=") {
+                self.next();
+                block.push((key, self.value()));
+            }
+        }
+        block
+    }
+
+    fn value(&mut self) -> Value {
+        match self.next().map(String::from) {
+            Some(v) if v == "{" => Value::Block(self.block()),
+            Some(v) if v == "(" => {
+                let mut list = vec![];
+                while let Some(token) = self.peek() {
+                    match token {
+                        ")" => {
+                            self.next();
+                            break;
+                        }
+                        "," => {
+                            self.next();
+                        }
+                        _ => list.push(self.value()),
+                    }
+                }
+                Value::List(list)
+            }
+            Some(v) => Value::Text(v),
+            None => Value::Text(String::new()),
+        }
+    }
+}
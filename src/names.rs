@@ -0,0 +1,117 @@
+//! Room names and avatars and members' display names saved between runs, so rooms are named as
+//! soon as the first sync is done instead of each being worked out again. Every entry remembers
+//! the state events it was built from, and only a different event for the same state changes it.
+
+use std::collections::HashMap;
+
+use matrix_sdk::ruma::{events::AnySyncStateEvent, serde::Raw, OwnedRoomId, RoomId};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Names {
+    rooms: HashMap<OwnedRoomId, RoomNames>,
+    /// Whether there's anything new to save.
+    #[serde(skip)]
+    changed: bool,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct RoomNames {
+    /// The latest event id seen for each event type and state key.
+    events: HashMap<String, HashMap<String, String>>,
+    /// `None` once the state it was worked out from has changed.
+    name: Option<String>,
+    avatar: Option<String>,
+    members: HashMap<String, String>,
+}
+
+impl RoomNames {
+    /// Whether the name comes from the room's own state rather than from who's in it.
+    fn named(&self) -> bool {
+        self.events.contains_key("m.room.name") || self.events.contains_key("m.room.canonical_alias")
+    }
+}
+
+#[derive(Deserialize)]
+struct StateEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    state_key: String,
+    event_id: String,
+    #[serde(default)]
+    content: Value,
+}
+
+impl Names {
+    pub fn load(path: &str) -> Names {
+        std::fs::read_to_string(path).ok().and_then(|v| serde_json::from_str(&v).ok()).unwrap_or_default()
+    }
+
+    pub fn save(&mut self, path: &str) {
+        if self.changed && serde_json::to_string(self).map(|v| std::fs::write(path, v)).is_ok() {
+            self.changed = false;
+        }
+    }
+
+    /// Takes in a state event, forgetting whatever it changes. Events already seen change nothing.
+    /// Returns whether the room's name or avatar may be different now.
+    pub fn update(&mut self, room_id: &RoomId, event: &Raw<AnySyncStateEvent>) -> bool {
+        let event = match event.deserialize_as::<StateEvent>() {
+            Ok(v) => v,
+            Err(_) => return false,
+        };
+        if !matches!(event.event_type.as_str(), "m.room.name" | "m.room.canonical_alias" | "m.room.avatar" | "m.room.member") {
+            return false;
+        }
+
+        let room = self.rooms.entry(room_id.to_owned()).or_default();
+        let seen = room.events.entry(event.event_type.clone()).or_default();
+        if seen.get(&event.state_key) == Some(&event.event_id) {
+            return false;
+        }
+        seen.insert(event.state_key.clone(), event.event_id);
+        self.changed = true;
+
+        let string = |key: &str| event.content.get(key).and_then(Value::as_str).map(String::from);
+        match event.event_type.as_str() {
+            "m.room.avatar" => room.avatar = string("url"),
+
+            "m.room.member" => {
+                match (string("membership").as_deref(), string("displayname")) {
+                    (Some("join"), Some(name)) => room.members.insert(event.state_key, name),
+                    _ => room.members.remove(&event.state_key),
+                };
+                // unnamed rooms are named after their members
+                if room.named() {
+                    return false;
+                }
+                room.name = None;
+            }
+
+            _ => room.name = None,
+        }
+        true
+    }
+
+    /// The room's name from last time, unless it's changed since.
+    pub fn name(&self, room_id: &RoomId) -> Option<&str> {
+        self.rooms.get(room_id)?.name.as_deref()
+    }
+
+    pub fn set_name(&mut self, room_id: &RoomId, name: &str) {
+        let room = self.rooms.entry(room_id.to_owned()).or_default();
+        if room.name.as_deref() != Some(name) {
+            room.name = Some(name.to_string());
+            self.changed = true;
+        }
+    }
+
+    pub fn avatar(&self, room_id: &RoomId) -> Option<&str> {
+        self.rooms.get(room_id)?.avatar.as_deref()
+    }
+
+    pub fn member(&self, room_id: &RoomId, user_id: &str) -> Option<&str> {
+        self.rooms.get(room_id)?.members.get(user_id).map(String::as_str)
+    }
+}
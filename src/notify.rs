@@ -0,0 +1,109 @@
+//! Notifications for new messages, sent through whichever backend the config picks.
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use matrix_sdk::ruma::{
+    events::{AnySyncMessageLikeEvent, AnySyncTimelineEvent, SyncMessageLikeEvent},
+    serde::Raw,
+    UserId,
+};
+
+use crate::{
+    config::{NotificationBackend, NotificationSettings},
+    historical,
+};
+
+pub trait Notifier: Send + Sync {
+    /// Shows a notification. Failures are ignored, since there's nowhere useful to report them.
+    fn notify(&self, title: &str, body: &str);
+}
+
+/// Desktop notifications over D-Bus on Linux, or Notification Center on macOS.
+struct NotifyRust;
+
+impl Notifier for NotifyRust {
+    fn notify(&self, title: &str, body: &str) {
+        let mut notification = notify_rust::Notification::new();
+        notification.appname("ilo-toki").summary(title).body(body);
+        // showing can block on the notification daemon
+        std::thread::spawn(move || {
+            let _ = notification.show();
+        });
+    }
+}
+
+/// Runs a `notify-send` style command with the title and body as arguments.
+struct NotifySend {
+    program: String,
+}
+
+impl Notifier for NotifySend {
+    fn notify(&self, title: &str, body: &str) {
+        let _ = Command::new(&self.program)
+            .args(["--app-name", "ilo-toki", title, body])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+    }
+}
+
+/// The OSC 777 escape sequence, which terminals like foot, kitty, and WezTerm turn into desktop
+/// notifications even when ilo-toki is running over SSH.
+struct Osc777;
+
+impl Notifier for Osc777 {
+    fn notify(&self, title: &str, body: &str) {
+        // the sequence ends at a control character and splits its fields on `;`
+        let clean = |v: &str| v.chars().filter(|c| !c.is_control()).map(|c| if c == ';' { ',' } else { c }).collect::<String>();
+        let mut stdout = std::io::stdout();
+        let _ = write!(stdout, "\x1b]777;notify;{};{}\x07", clean(title), clean(body));
+        let _ = stdout.flush();
+    }
+}
+
+/// Rings the terminal bell. xterm is asked to set the urgency hint with it, and then to stop again,
+/// since the mode stays on after we've quit.
+pub fn bell() {
+    let mut stdout = std::io::stdout();
+    let _ = write!(stdout, "\x1b[?1042h\x07\x1b[?1042l");
+    let _ = stdout.flush();
+}
+
+struct NoNotifier;
+
+impl Notifier for NoNotifier {
+    fn notify(&self, _: &str, _: &str) {}
+}
+
+/// The body of a notification for an event, or `None` for our own events and ones not worth one.
+pub fn message_text(event: &Raw<AnySyncTimelineEvent>, own_user_id: Option<&UserId>) -> Option<String> {
+    // imported history is old news
+    if historical::is_imported(event.json()) {
+        return None;
+    }
+
+    let event = event.deserialize().ok()?;
+    if Some(event.sender()) == own_user_id {
+        return None;
+    }
+
+    match event {
+        AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(SyncMessageLikeEvent::Original(v))) => Some(format!("{}: {}", v.sender, v.content.body())),
+        AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomEncrypted(SyncMessageLikeEvent::Original(v))) => Some(format!("{} sent an encrypted message", v.sender)),
+        AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::CallInvite(SyncMessageLikeEvent::Original(v))) => Some(format!("{} is calling", v.sender)),
+        _ => None,
+    }
+}
+
+pub fn backend(settings: &NotificationSettings) -> Box<dyn Notifier> {
+    match settings.backend {
+        NotificationBackend::NotifyRust => Box::new(NotifyRust),
+        NotificationBackend::NotifySend => Box::new(NotifySend { program: settings.command.clone() }),
+        NotificationBackend::Osc777 => Box::new(Osc777),
+        NotificationBackend::None => Box::new(NoNotifier),
+    }
+}
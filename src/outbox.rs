@@ -0,0 +1,245 @@
+//! Messages and reactions waiting to be sent. They're sent one at a time, in order, so the UI doesn't wait on
+//! the network and a quit can tell whether anything is still going out. Sends that fail are kept until
+//! they're retried or discarded from `/outbox`. Whatever is left on quit is saved and queued again next run.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use matrix_sdk::{
+    room::Joined,
+    ruma::{
+        events::{room::message::MessageType, AnyMessageLikeEventContent, EventContent},
+        serde::Raw,
+        OwnedRoomId,
+    },
+    Client,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::mpsc::{self, UnboundedSender},
+    task::JoinHandle,
+};
+
+pub struct Entry {
+    pub id: u64,
+    pub room: Joined,
+    pub content: AnyMessageLikeEventContent,
+    /// Why the last attempt failed, or `None` while it's still waiting to go out.
+    pub error: Option<String>,
+}
+
+impl Entry {
+    /// The text of a message, which can be edited and sent again.
+    pub fn text(&self) -> Option<&str> {
+        match &self.content {
+            AnyMessageLikeEventContent::RoomMessage(v) if matches!(v.msgtype, MessageType::Text(_) | MessageType::Emote(_) | MessageType::Notice(_)) => Some(v.body()),
+            _ => None,
+        }
+    }
+
+    fn summary(&self) -> String {
+        let room = self.room.name().unwrap_or_else(|| self.room.room_id().to_string());
+        let what = match &self.content {
+            AnyMessageLikeEventContent::RoomMessage(v) => v.body().lines().next().unwrap_or_default().to_string(),
+            AnyMessageLikeEventContent::Reaction(v) => format!("reaction {}", v.relates_to.key),
+            v => v.event_type().to_string(),
+        };
+        format!("{}: {}", room, what)
+    }
+}
+
+/// An entry as it's kept between runs.
+#[derive(Serialize, Deserialize)]
+struct Saved {
+    room: OwnedRoomId,
+    event_type: String,
+    content: Raw<AnyMessageLikeEventContent>,
+    error: Option<String>,
+}
+
+pub struct Outbox {
+    sender: Option<UnboundedSender<u64>>,
+    worker: JoinHandle<()>,
+    entries: Arc<std::sync::Mutex<Vec<Entry>>>,
+    next_id: AtomicU64,
+    errors: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+impl Outbox {
+    pub fn new() -> Outbox {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<u64>();
+        let entries = Arc::new(std::sync::Mutex::new(Vec::<Entry>::new()));
+        let errors = Arc::new(std::sync::Mutex::new(vec![]));
+
+        let (entries2, errors2) = (entries.clone(), errors.clone());
+        let worker = tokio::task::spawn(async move {
+            while let Some(id) = receiver.recv().await {
+                // discarded entries are skipped
+                let next = entries2.lock().unwrap().iter().find(|v| v.id == id).map(|v| (v.room.clone(), v.content.clone()));
+                let (room, content) = match next {
+                    Some(v) => v,
+                    None => continue,
+                };
+
+                let result = room.send(content, None).await;
+                let mut entries = entries2.lock().unwrap();
+                match result {
+                    Ok(_) => entries.retain(|v| v.id != id),
+                    Err(e) => {
+                        if let Some(entry) = entries.iter_mut().find(|v| v.id == id) {
+                            entry.error = Some(e.to_string());
+                        }
+                        errors2.lock().unwrap().push(e.to_string());
+                    }
+                }
+            }
+        });
+
+        Outbox {
+            sender: Some(sender),
+            worker,
+            entries,
+            next_id: AtomicU64::new(0),
+            errors,
+        }
+    }
+
+    pub fn send(&self, room: Joined, content: impl Into<AnyMessageLikeEventContent>) {
+        if let Some(sender) = self.sender.as_ref() {
+            let id = self.next_id.fetch_add(1, Ordering::AcqRel);
+            self.entries.lock().unwrap().push(Entry {
+                id,
+                room,
+                content: content.into(),
+                error: None,
+            });
+            sender.send(id).unwrap();
+        }
+    }
+
+    /// How many messages are still waiting to go out.
+    pub fn pending(&self) -> usize {
+        self.entries.lock().unwrap().iter().filter(|v| v.error.is_none()).count()
+    }
+
+    /// How many messages failed and haven't been retried or discarded.
+    pub fn failed(&self) -> usize {
+        self.entries.lock().unwrap().iter().filter(|v| v.error.is_some()).count()
+    }
+
+    /// Queues the entries saved last run again. Failed ones stay failed until they're retried, and ones
+    /// for rooms that have been left since are dropped.
+    pub fn load(&self, path: &str, client: &Client) {
+        let saved: Vec<Saved> = std::fs::read_to_string(path).ok().and_then(|v| serde_json::from_str(&v).ok()).unwrap_or_default();
+        for v in saved {
+            let room = match client.get_joined_room(&v.room) {
+                Some(v) => v,
+                None => continue,
+            };
+            let content = match AnyMessageLikeEventContent::from_parts(&v.event_type, v.content.json()) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            match v.error {
+                Some(error) => {
+                    let id = self.next_id.fetch_add(1, Ordering::AcqRel);
+                    self.entries.lock().unwrap().push(Entry { id, room, content, error: Some(error) });
+                }
+                None => self.send(room, content),
+            }
+        }
+    }
+
+    /// Writes down the entries that haven't gone out, or removes the file if there are none.
+    pub fn save(&self, path: &str) {
+        let entries = self.entries.lock().unwrap();
+        let saved: Vec<_> = entries
+            .iter()
+            .filter_map(|v| {
+                Some(Saved {
+                    room: v.room.room_id().to_owned(),
+                    event_type: v.content.event_type().to_string(),
+                    content: Raw::new(&v.content).ok()?,
+                    error: v.error.clone(),
+                })
+            })
+            .collect();
+
+        if saved.is_empty() {
+            let _ = std::fs::remove_file(path);
+        } else if let Ok(v) = serde_json::to_string(&saved) {
+            let _ = std::fs::write(path, v);
+        }
+    }
+
+    /// Why sends failed since this was last called.
+    pub fn take_errors(&self) -> Vec<String> {
+        std::mem::take(&mut self.errors.lock().unwrap())
+    }
+
+    /// The ids of the failed and waiting entries, oldest first.
+    pub fn ids(&self) -> Vec<u64> {
+        self.entries.lock().unwrap().iter().map(|v| v.id).collect()
+    }
+
+    /// Whether an entry is a text message, which can be edited.
+    pub fn is_text(&self, id: u64) -> bool {
+        self.entries.lock().unwrap().iter().any(|v| v.id == id && v.text().is_some())
+    }
+
+    /// Queues a failed entry again, at the back.
+    pub fn retry(&self, id: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        if let (Some(sender), Some(i)) = (self.sender.as_ref(), entries.iter().position(|v| v.id == id && v.error.is_some())) {
+            let mut entry = entries.remove(i);
+            entry.error = None;
+            entries.push(entry);
+            sender.send(id).unwrap();
+        }
+    }
+
+    /// Takes an entry out so it won't be sent, for discarding or editing.
+    pub fn remove(&self, id: u64) -> Option<Entry> {
+        let mut entries = self.entries.lock().unwrap();
+        let i = entries.iter().position(|v| v.id == id)?;
+        Some(entries.remove(i))
+    }
+
+    /// What's in the outbox, with the selected entry marked and the keys for it.
+    pub fn lines(&self, selected: usize) -> Vec<String> {
+        let entries = self.entries.lock().unwrap();
+        let mut lines: Vec<_> = entries
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let marker = if i == selected { ">" } else { " " };
+                match v.error.as_ref() {
+                    Some(error) => format!("{} failed  {} ({})", marker, v.summary(), error),
+                    None => format!("{} waiting {}", marker, v.summary()),
+                }
+            })
+            .collect();
+        if entries.is_empty() {
+            lines.push(String::from("Everything has been sent."));
+        }
+        lines.push(String::new());
+        lines.push(String::from("j/k: select, r: retry, e: edit and send again, d: discard, Esc: close"));
+        lines
+    }
+
+    /// Stops taking messages and waits for the queued ones to be sent.
+    pub async fn flush(&mut self) {
+        self.sender = None;
+        let _ = (&mut self.worker).await;
+    }
+
+    /// Stops taking messages and drops the queued and failed ones, so they aren't saved either.
+    pub fn discard(&mut self) {
+        self.sender = None;
+        self.worker.abort();
+        self.entries.lock().unwrap().clear();
+    }
+}
@@ -0,0 +1,218 @@
+//! The command palette on Ctrl-P: every action and command, found by fuzzy search and shown with
+//! the key it's bound to.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::{permissions::Allowed, Mode};
+
+/// How many matches are listed.
+const RESULTS: usize = 12;
+
+#[derive(Clone, Copy)]
+pub enum Run {
+    /// Presses a key, as if it was typed in the mode the palette was opened from.
+    Key(KeyCode, KeyModifiers),
+    /// Runs a command that needs nothing more.
+    Command(&'static str),
+    /// Starts a command in the input box, for its arguments to be typed.
+    Prompt(&'static str),
+}
+
+/// What an entry needs our power levels in the current channel to allow.
+#[derive(Clone, Copy)]
+enum Needs {
+    Nothing,
+    Redact,
+    Invite,
+    Encrypt,
+    /// Edits, which the server has to support.
+    Edit,
+}
+
+struct Entry {
+    name: &'static str,
+    /// How to do this without the palette.
+    binding: &'static str,
+    /// The mode the entry's key works in, or `None` for commands, which work anywhere.
+    mode: Option<Mode>,
+    run: Run,
+    needs: Needs,
+}
+
+impl Entry {
+    const fn needs(self, needs: Needs) -> Entry {
+        Entry { needs, ..self }
+    }
+
+    fn allowed(&self, allowed: &Allowed, edits: bool) -> bool {
+        match self.needs {
+            Needs::Nothing => true,
+            Needs::Redact => allowed.redact_own,
+            Needs::Invite => allowed.invite,
+            Needs::Encrypt => allowed.encrypt,
+            Needs::Edit => edits,
+        }
+    }
+}
+
+const fn key(name: &'static str, binding: &'static str, mode: Mode, code: KeyCode) -> Entry {
+    Entry { name, binding, mode: Some(mode), run: Run::Key(code, KeyModifiers::NONE), needs: Needs::Nothing }
+}
+
+const fn ctrl(name: &'static str, binding: &'static str, mode: Mode, c: char) -> Entry {
+    Entry { name, binding, mode: Some(mode), run: Run::Key(KeyCode::Char(c), KeyModifiers::CONTROL), needs: Needs::Nothing }
+}
+
+const fn command(name: &'static str, binding: &'static str, run: Run) -> Entry {
+    Entry { name, binding, mode: None, run, needs: Needs::Nothing }
+}
+
+const ENTRIES: &[Entry] = &[
+    key("Insert text", "i", Mode::Normal, KeyCode::Char('i')),
+    key("Select a channel", "C", Mode::Normal, KeyCode::Char('C')),
+    key("Scroll messages", "S", Mode::Normal, KeyCode::Char('S')),
+    key("Show or hide filtered messages", "F", Mode::Normal, KeyCode::Char('F')),
+    key("Join call", "J", Mode::Normal, KeyCode::Char('J')),
+    key("Next workspace", "W", Mode::Normal, KeyCode::Char('W')),
+    key("Wrap in inline code", "`", Mode::Normal, KeyCode::Char('`')),
+    ctrl("Suspend", "Ctrl-Z", Mode::Normal, 'z'),
+    key("Load missing messages", "Enter", Mode::ScrollMessages, KeyCode::Enter),
+    key("Reply to message", "r", Mode::ScrollMessages, KeyCode::Char('r')),
+    key("Edit your message", "e", Mode::ScrollMessages, KeyCode::Char('e')).needs(Needs::Edit),
+    key("Open or close thread", "t", Mode::ScrollMessages, KeyCode::Char('t')),
+    key("React to message, or take a reaction back", "+", Mode::ScrollMessages, KeyCode::Char('+')),
+    key("Mark start of quote", "v", Mode::ScrollMessages, KeyCode::Char('v')),
+    key("Expand message details", "x", Mode::ScrollMessages, KeyCode::Char('x')),
+    key("Copy as quote", "y", Mode::ScrollMessages, KeyCode::Char('y')),
+    key("Translate message", "T", Mode::ScrollMessages, KeyCode::Char('T')),
+    key("Download attachment", "o", Mode::ScrollMessages, KeyCode::Char('o')),
+    key("Request message keys", "K", Mode::ScrollMessages, KeyCode::Char('K')),
+    key("Seen by", "R", Mode::ScrollMessages, KeyCode::Char('R')),
+    key("Message details", "i", Mode::ScrollMessages, KeyCode::Char('i')),
+    key("Sender's profile", "P", Mode::ScrollMessages, KeyCode::Char('P')),
+    ctrl("Delete message", "Ctrl-D", Mode::ScrollMessages, 'd').needs(Needs::Redact),
+    command("Translate last message", "/translate", Run::Command("/translate")),
+    command("Toggle auto-translate", "/translate auto", Run::Command("/translate auto")),
+    command("Toggle receipts and typing privacy", "/private", Run::Command("/private")),
+    command("Toggle link previews", "/previews", Run::Command("/previews")),
+    command("Unsent messages", "/outbox", Run::Command("/outbox")),
+    command("Do not disturb", "/dnd", Run::Prompt("/dnd ")),
+    command("Search messages", "/search", Run::Command("/search")),
+    command("Search users", "/usersearch", Run::Prompt("/usersearch ")),
+    command("Profile", "/whois", Run::Prompt("/whois ")),
+    command("Widgets", "/widgets", Run::Command("/widgets")),
+    command("Members", "/members", Run::Command("/members")),
+    command("Room stats", "/stats", Run::Command("/stats")),
+    command("Room settings", "/room", Run::Command("/room")),
+    command("Leave room", "/leave", Run::Prompt("/leave")),
+    command("Encrypt room", "/encrypt", Run::Command("/encrypt")).needs(Needs::Encrypt),
+    command("Publish to room directory", "/publish", Run::Command("/publish")),
+    command("Remove from room directory", "/unpublish", Run::Command("/unpublish")),
+    command("Security", "/security", Run::Command("/security")),
+    command("Server features", "/server", Run::Command("/server")),
+    command("Policy rules", "/policy", Run::Command("/policy")),
+    command("Subscribe to policy list", "/policy subscribe", Run::Command("/policy subscribe")),
+    command("Unsubscribe from policy list", "/policy unsubscribe", Run::Command("/policy unsubscribe")),
+    command("Ban with policy", "/policy ban", Run::Prompt("/policy ban ")),
+    command("Jump to date", "/date", Run::Prompt("/date ")),
+    command("Code block", "/code", Run::Prompt("/code ")),
+    command("Send image", "/image", Run::Prompt("/image ")),
+    command("Send video", "/video", Run::Prompt("/video ")),
+    command("Upload file", "/upload", Run::Command("/upload")),
+    command("Verify user", "/verify", Run::Prompt("/verify ")),
+    command("Verify this session", "/verify", Run::Command("/verify")),
+    command("Export room keys", "/export-keys", Run::Prompt("/export-keys ")),
+    command("Import room keys", "/import-keys", Run::Prompt("/import-keys ")),
+    command("Import history", "/import-history", Run::Prompt("/import-history ")),
+    command("Invite from file", "/invite-file", Run::Prompt("/invite-file ")).needs(Needs::Invite),
+    command("Quit", "/quit", Run::Command("/quit")),
+];
+
+pub enum Pick {
+    Chosen(Run),
+    Cancelled,
+    Open,
+}
+
+pub struct Palette {
+    mode: Mode,
+    /// What the current channel lets us do, which hides the entries it doesn't.
+    allowed: Allowed,
+    /// Whether the server supports edits.
+    edits: bool,
+    search: String,
+    selected: usize,
+}
+
+impl Palette {
+    pub fn new(mode: Mode, allowed: Allowed, edits: bool) -> Palette {
+        Palette {
+            mode,
+            allowed,
+            edits,
+            search: String::new(),
+            selected: 0,
+        }
+    }
+
+    /// The entries usable from this mode that match the search, best first.
+    fn matches(&self) -> Vec<&'static Entry> {
+        let mut matches: Vec<_> = ENTRIES
+            .iter()
+            .filter(|v| v.mode.map(|v| v == self.mode).unwrap_or(true) && v.allowed(&self.allowed, self.edits))
+            .filter_map(|v| Some((fuzzy(&self.search, v.name).or_else(|| fuzzy(&self.search, v.binding))?, v)))
+            .collect();
+        matches.sort_by_key(|(score, _)| *score);
+        matches.into_iter().take(RESULTS).map(|(_, v)| v).collect()
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let matches = self.matches();
+        let width = matches.iter().map(|v| v.name.len()).max().unwrap_or(0);
+        let mut lines: Vec<_> = matches.iter().enumerate().map(|(i, v)| format!("{} {:width$}  {}", if i == self.selected { '>' } else { ' ' }, v.name, v.binding, width = width)).collect();
+        if matches.is_empty() {
+            lines.push(String::from("Nothing found."));
+        }
+        lines.push(String::new());
+        lines.push(format!("Search: {}", self.search));
+        lines.push(String::from("Type to search, Up and Down to choose, Enter to run, Esc to cancel"));
+        lines
+    }
+
+    pub fn key(&mut self, key: KeyEvent) -> Pick {
+        match key.code {
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.selected = (self.selected + 1).min(self.matches().len().saturating_sub(1)),
+            KeyCode::Enter => return self.matches().get(self.selected).map(|v| Pick::Chosen(v.run)).unwrap_or(Pick::Open),
+            KeyCode::Esc => return Pick::Cancelled,
+
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.search.push(c);
+                self.selected = 0;
+            }
+
+            KeyCode::Backspace => {
+                self.search.pop();
+                self.selected = 0;
+            }
+
+            _ => (),
+        }
+        Pick::Open
+    }
+}
+
+/// How well a search matches, lower being better, or `None` if its characters don't all appear in
+/// order. Characters found far apart cost more.
+pub fn fuzzy(search: &str, name: &str) -> Option<usize> {
+    let name: Vec<_> = name.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut pos = 0;
+    for c in search.to_lowercase().chars() {
+        let found = name[pos..].iter().position(|v| *v == c)?;
+        // the first character may start anywhere, though the start of the name is best
+        score += found;
+        pos += found + 1;
+    }
+    Some(score)
+}
@@ -0,0 +1,55 @@
+//! What we're allowed to do in each room, read from its power levels, so the composer and actions
+//! we can't use are turned off up front rather than failing once tried.
+
+use std::collections::HashMap;
+
+use matrix_sdk::ruma::{
+    events::{
+        room::power_levels::{PowerLevelAction, RoomPowerLevels},
+        MessageLikeEventType, StateEventType,
+    },
+    OwnedRoomId, RoomId, UserId,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Allowed {
+    pub send: bool,
+    /// Deleting our own messages only needs to be able to send the redaction.
+    pub redact_own: bool,
+    pub redact_others: bool,
+    pub invite: bool,
+    /// Turning on encryption, which is a state event like the room's name.
+    pub encrypt: bool,
+}
+
+impl Allowed {
+    /// Everything, for rooms whose power levels haven't arrived. The server still has the last word.
+    pub const ALL: Allowed = Allowed { send: true, redact_own: true, redact_others: true, invite: true, encrypt: true };
+
+    pub fn new(levels: &RoomPowerLevels, user_id: &UserId) -> Allowed {
+        let redact = levels.user_can_do(user_id, PowerLevelAction::SendMessage(MessageLikeEventType::RoomRedaction));
+        Allowed {
+            send: levels.user_can_do(user_id, PowerLevelAction::SendMessage(MessageLikeEventType::RoomMessage)),
+            redact_own: redact,
+            redact_others: redact && levels.user_can_do(user_id, PowerLevelAction::Redact),
+            invite: levels.user_can_do(user_id, PowerLevelAction::Invite),
+            encrypt: levels.user_can_do(user_id, PowerLevelAction::SendState(StateEventType::RoomEncryption)),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Permissions {
+    rooms: HashMap<OwnedRoomId, Allowed>,
+}
+
+impl Permissions {
+    /// Takes in a room's new power levels.
+    pub fn update(&mut self, room_id: &RoomId, levels: &RoomPowerLevels, user_id: &UserId) {
+        self.rooms.insert(room_id.to_owned(), Allowed::new(levels, user_id));
+    }
+
+    pub fn get(&self, room_id: &RoomId) -> Allowed {
+        self.rooms.get(room_id).copied().unwrap_or(Allowed::ALL)
+    }
+}
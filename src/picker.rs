@@ -0,0 +1,114 @@
+//! The file picker `/upload` opens without a path: a directory's files and folders, walked with the
+//! arrow keys until a file is chosen.
+
+use std::path::{Path, PathBuf};
+
+use crossterm::event::{KeyCode, KeyEvent};
+
+/// How many entries are listed around the selected one.
+const SHOWN: usize = 12;
+
+pub enum Pick {
+    Chosen(PathBuf),
+    Cancelled,
+    Open,
+}
+
+struct Entry {
+    name: String,
+    dir: bool,
+}
+
+pub struct Picker {
+    dir: PathBuf,
+    entries: Vec<Entry>,
+    selected: usize,
+    /// Why the directory couldn't be listed, if it couldn't.
+    error: Option<String>,
+}
+
+impl Picker {
+    pub fn new(dir: &Path) -> Picker {
+        let mut picker = Picker {
+            dir: dir.to_path_buf(),
+            entries: vec![],
+            selected: 0,
+            error: None,
+        };
+        picker.read();
+        picker
+    }
+
+    /// Lists the directory again, folders first. Hidden files are left out, as `ls` does.
+    fn read(&mut self) {
+        self.selected = 0;
+        self.entries.clear();
+        self.error = None;
+        match std::fs::read_dir(&self.dir) {
+            Ok(entries) => {
+                self.entries = entries
+                    .filter_map(|v| v.ok())
+                    .map(|v| Entry {
+                        name: v.file_name().to_string_lossy().to_string(),
+                        // symlinks to folders are followed like folders
+                        dir: v.path().is_dir(),
+                    })
+                    .filter(|v| !v.name.starts_with('.'))
+                    .collect();
+                self.entries.sort_by(|a, b| b.dir.cmp(&a.dir).then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase())));
+            }
+
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+
+    fn up(&mut self) {
+        if let Some(parent) = self.dir.parent() {
+            let left = self.dir.file_name().map(|v| v.to_string_lossy().to_string());
+            self.dir = parent.to_path_buf();
+            self.read();
+            // the folder just left stays selected, to go back into it or past it
+            if let Some(index) = left.and_then(|left| self.entries.iter().position(|v| v.name == left)) {
+                self.selected = index;
+            }
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![self.dir.display().to_string(), String::new()];
+        let start = self.selected.saturating_sub(SHOWN / 2).min(self.entries.len().saturating_sub(SHOWN));
+        for (i, entry) in self.entries.iter().enumerate().skip(start).take(SHOWN) {
+            lines.push(format!("{} {}{}", if i == self.selected { '>' } else { ' ' }, entry.name, if entry.dir { "/" } else { "" }));
+        }
+        match self.error.as_ref() {
+            Some(e) => lines.push(e.clone()),
+            None if self.entries.is_empty() => lines.push(String::from("Nothing here.")),
+            None => (),
+        }
+        lines.push(String::new());
+        lines.push(String::from("Up and Down to choose, Enter to open or upload, Backspace for the folder above, Esc to cancel"));
+        lines
+    }
+
+    pub fn key(&mut self, key: KeyEvent) -> Pick {
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.selected = (self.selected + 1).min(self.entries.len().saturating_sub(1)),
+            KeyCode::Backspace | KeyCode::Left | KeyCode::Char('h') => self.up(),
+            KeyCode::Esc => return Pick::Cancelled,
+
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => match self.entries.get(self.selected) {
+                Some(entry) if entry.dir => {
+                    self.dir = self.dir.join(&entry.name);
+                    self.read();
+                }
+                // only Enter uploads, so walking right doesn't send a file by going one step too far
+                Some(entry) if key.code == KeyCode::Enter => return Pick::Chosen(self.dir.join(&entry.name)),
+                _ => (),
+            },
+
+            _ => (),
+        }
+        Pick::Open
+    }
+}
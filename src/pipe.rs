@@ -0,0 +1,38 @@
+//! Piping text through external commands, for translating, spellchecking, and transliterating.
+
+use std::{process::Stdio, time::Duration};
+
+use tokio::{io::AsyncWriteExt, process::Command};
+
+/// How long a command may take before it's given up on.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Runs the command with the text on stdin, returning what it prints.
+pub async fn run(command: &[String], text: &str) -> Result<String, String> {
+    let (program, args) = command.split_first().ok_or_else(|| String::from("no command given"))?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("couldn't run {}: {}", program, e))?;
+
+    // written alongside the read, so a command that answers as it goes can't fill its pipe and stall
+    let mut stdin = child.stdin.take().unwrap();
+    let text = text.to_string();
+    tokio::task::spawn(async move {
+        let _ = stdin.write_all(text.as_bytes()).await;
+    });
+
+    let output = tokio::time::timeout(TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| format!("{} took too long", program))?
+        .map_err(|e| format!("couldn't run {}: {}", program, e))?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(if error.is_empty() { format!("{} failed ({})", program, output.status) } else { error });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
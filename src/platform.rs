@@ -0,0 +1,68 @@
+//! The parts of terminal handling that differ between Unix and Windows.
+
+use std::io;
+
+use crossterm::event::{KeyEvent, KeyEventKind};
+
+/// Whether a key event is a key going down. Windows consoles also report keys being released,
+/// which would otherwise type everything twice.
+pub fn is_key_press(key: &KeyEvent) -> bool {
+    key.kind != KeyEventKind::Release
+}
+
+/// Stops the process like Ctrl-Z does in a shell, returning once it's resumed. The terminal should
+/// be restored first.
+#[cfg(unix)]
+pub fn suspend() -> io::Result<()> {
+    // SAFETY: raise only sends a signal to this process
+    match unsafe { libc::raise(libc::SIGTSTP) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Windows has no job control to hand the terminal back to.
+#[cfg(not(unix))]
+pub fn suspend() -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "suspending isn't supported on this platform"))
+}
+
+/// The size of a cell in pixels, from the terminal's size in pixels, if it gives one.
+#[cfg(unix)]
+pub fn cell_size() -> Option<(u32, u32)> {
+    // SAFETY: winsize is plain data, and TIOCGWINSZ only writes to it
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } != 0 || size.ws_col == 0 || size.ws_row == 0 || size.ws_xpixel == 0 {
+        return None;
+    }
+    Some((size.ws_xpixel as u32 / size.ws_col as u32, size.ws_ypixel as u32 / size.ws_row as u32))
+}
+
+/// The Windows console doesn't say how big its cells are.
+#[cfg(not(unix))]
+pub fn cell_size() -> Option<(u32, u32)> {
+    None
+}
+
+/// Whether the terminal understands OSC escape sequences like the one for the cursor colour. The
+/// classic Windows console prints them as text; Windows Terminal sets `WT_SESSION`.
+pub fn supports_osc() -> bool {
+    cfg!(unix) || std::env::var_os("WT_SESSION").is_some()
+}
+
+/// The user's home directory.
+pub fn home_dir() -> Option<String> {
+    let var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    std::env::var(var).ok()
+}
+
+/// The program that opens a file with whatever the desktop prefers.
+pub fn default_opener() -> &'static str {
+    if cfg!(windows) {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    }
+}
@@ -0,0 +1,235 @@
+//! Moderation policy lists (MSC2313): rooms whose `m.policy.rule.*` state recommends banning users,
+//! rooms, or servers. Messages from entities banned by a subscribed list can be hidden locally.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use matrix_sdk::ruma::{events::AnySyncStateEvent, serde::Raw, OwnedRoomId, RoomId};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::config::ModerationSettings;
+
+/// The state events rules are, for reading a room's rules from what's stored of its state.
+pub const EVENT_TYPES: [&str; 3] = ["m.policy.rule.user", "m.policy.rule.room", "m.policy.rule.server"];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EntityKind {
+    User,
+    Room,
+    Server,
+}
+
+impl EntityKind {
+    fn event_type(self) -> &'static str {
+        match self {
+            EntityKind::User => "m.policy.rule.user",
+            EntityKind::Room => "m.policy.rule.room",
+            EntityKind::Server => "m.policy.rule.server",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            EntityKind::User => "user",
+            EntityKind::Room => "room",
+            EntityKind::Server => "server",
+        }
+    }
+}
+
+pub struct Rule {
+    pub kind: EntityKind,
+    /// Who the rule applies to, where `*` and `?` are globs.
+    pub entity: String,
+    pub recommendation: String,
+    pub reason: String,
+}
+
+impl Rule {
+    /// Whether the rule says to ban, including the older name still used by Mjolnir.
+    fn bans(&self) -> bool {
+        self.recommendation == "m.ban" || self.recommendation == "org.matrix.mjolnir.ban"
+    }
+
+    /// Something like "user @spam:*: spam".
+    pub fn summary(&self) -> String {
+        let recommendation = if self.bans() { String::new() } else { format!(" ({})", self.recommendation) };
+        format!("{} {}{}: {}", self.kind.name(), self.entity, recommendation, self.reason)
+    }
+}
+
+#[derive(Deserialize)]
+struct StateEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    state_key: String,
+    content: Value,
+}
+
+#[derive(Deserialize)]
+struct RuleContent {
+    entity: String,
+    recommendation: String,
+    #[serde(default)]
+    reason: String,
+}
+
+/// The lists subscribed to or unsubscribed from with `/policy`, saved between runs. They're kept
+/// apart from the config's lists so changes to either still count.
+#[derive(Default, Serialize, Deserialize)]
+struct Subscriptions {
+    subscribed: HashSet<OwnedRoomId>,
+    unsubscribed: HashSet<OwnedRoomId>,
+}
+
+pub struct Policies {
+    /// The rules in each room, keyed by event type and state key like the room state is.
+    rules: HashMap<OwnedRoomId, BTreeMap<(String, String), Rule>>,
+    subscribed: HashSet<OwnedRoomId>,
+    changes: Subscriptions,
+    /// Whether `changes` has anything new to save.
+    changed: bool,
+    hide_banned: bool,
+}
+
+impl Policies {
+    pub fn new(settings: &ModerationSettings) -> Policies {
+        Policies {
+            rules: HashMap::new(),
+            subscribed: settings.policy_rooms.iter().filter_map(|v| RoomId::parse(v).ok()).collect(),
+            changes: Subscriptions::default(),
+            changed: false,
+            hide_banned: settings.hide_banned,
+        }
+    }
+
+    /// Applies the subscriptions changed in earlier runs.
+    pub fn load(&mut self, path: &str) {
+        if let Some(changes) = std::fs::read_to_string(path).ok().and_then(|v| serde_json::from_str::<Subscriptions>(&v).ok()) {
+            self.subscribed.extend(changes.subscribed.iter().cloned());
+            self.subscribed.retain(|v| !changes.unsubscribed.contains(v));
+            self.changes = changes;
+        }
+    }
+
+    pub fn save(&mut self, path: &str) {
+        if self.changed && serde_json::to_string(&self.changes).map(|v| std::fs::write(path, v)).is_ok() {
+            self.changed = false;
+        }
+    }
+
+    /// Keeps track of a room's rules as its state changes. Rules are removed by emptying them.
+    pub fn update(&mut self, room_id: &RoomId, event: &Raw<AnySyncStateEvent>) {
+        let event: StateEvent = match event.deserialize_as() {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+        let kind = match event.event_type.as_str() {
+            "m.policy.rule.user" => EntityKind::User,
+            "m.policy.rule.room" => EntityKind::Room,
+            "m.policy.rule.server" => EntityKind::Server,
+            _ => return,
+        };
+
+        let rules = self.rules.entry(room_id.to_owned()).or_default();
+        let key = (event.event_type, event.state_key);
+        match serde_json::from_value::<RuleContent>(event.content) {
+            Ok(v) => {
+                rules.insert(key, Rule {
+                    kind,
+                    entity: v.entity,
+                    recommendation: v.recommendation,
+                    reason: v.reason,
+                });
+            }
+
+            Err(_) => {
+                rules.remove(&key);
+            }
+        }
+    }
+
+    pub fn rules(&self, room_id: &RoomId) -> Vec<&Rule> {
+        self.rules.get(room_id).map(|v| v.values().collect()).unwrap_or_default()
+    }
+
+    pub fn subscribed(&self, room_id: &RoomId) -> bool {
+        self.subscribed.contains(room_id)
+    }
+
+    pub fn subscriptions(&self) -> Vec<OwnedRoomId> {
+        self.subscribed.iter().cloned().collect()
+    }
+
+    pub fn set_subscribed(&mut self, room_id: &RoomId, subscribed: bool) {
+        if subscribed {
+            self.subscribed.insert(room_id.to_owned());
+            self.changes.subscribed.insert(room_id.to_owned());
+            self.changes.unsubscribed.remove(room_id);
+        } else {
+            self.subscribed.remove(room_id);
+            self.changes.subscribed.remove(room_id);
+            self.changes.unsubscribed.insert(room_id.to_owned());
+        }
+        self.changed = true;
+    }
+
+    /// Whether messages from a user are hidden because a subscribed list bans them or their server.
+    pub fn hides(&self, user_id: &str) -> bool {
+        if !self.hide_banned || self.subscribed.is_empty() {
+            return false;
+        }
+
+        let server = user_id.split_once(':').map(|(_, v)| v).unwrap_or_default();
+        self.subscribed.iter().filter_map(|v| self.rules.get(v)).flat_map(|v| v.values()).filter(|v| v.bans()).any(|v| match v.kind {
+            EntityKind::User => glob(&v.entity, user_id),
+            EntityKind::Server => glob(&v.entity, server),
+            EntityKind::Room => false,
+        })
+    }
+}
+
+/// The state event that bans an entity, as its type, state key, and content. What kind of entity it
+/// is comes from its sigil, and anything without one is a server.
+pub fn ban_event(entity: &str, reason: &str) -> (&'static str, String, Value) {
+    let kind = match entity.chars().next() {
+        Some('@') => EntityKind::User,
+        Some('!') | Some('#') => EntityKind::Room,
+        _ => EntityKind::Server,
+    };
+    let content = json!({ "entity": entity, "recommendation": "m.ban", "reason": reason });
+    (kind.event_type(), format!("rule:{}", entity), content)
+}
+
+/// Matches `*` against any run of characters and `?` against any one.
+fn glob(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    // where to resume after the last `*` if the rest stops matching
+    let (mut p, mut t) = (0, 0);
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|v| *v == '*')
+}
@@ -0,0 +1,63 @@
+//! Link previews, which the homeserver fetches through `/preview_url` so the sites only ever see the
+//! server. The server still learns every link looked at, so previews are off unless turned on.
+
+use std::collections::HashSet;
+
+use matrix_sdk::{
+    ruma::{api::client::media::get_media_preview, MilliSecondsSinceUnixEpoch, OwnedEventId},
+    Client,
+};
+use serde::Deserialize;
+
+/// Descriptions longer than this are cut short.
+pub const MAX_DESCRIPTION: usize = 200;
+
+pub struct Preview {
+    pub title: String,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpenGraph {
+    #[serde(rename = "og:title")]
+    title: Option<String>,
+    #[serde(rename = "og:description")]
+    description: Option<String>,
+}
+
+/// The messages whose previews have been asked for, so each is only fetched once.
+#[derive(Default)]
+pub struct Previews {
+    requested: HashSet<OwnedEventId>,
+}
+
+impl Previews {
+    /// Whether a message's preview still needs fetching, which it then no longer does.
+    pub fn request(&mut self, event_id: &OwnedEventId) -> bool {
+        self.requested.insert(event_id.clone())
+    }
+
+    /// Lets a preview be asked for again, like when its fetch was cancelled.
+    pub fn forget(&mut self, event_id: &OwnedEventId) {
+        self.requested.remove(event_id);
+    }
+}
+
+/// The first web link in some text, without the punctuation that usually follows one.
+pub fn first_link(text: &str) -> Option<&str> {
+    text.split_whitespace()
+        .map(|v| v.trim_start_matches(['<', '(']))
+        .find(|v| v.starts_with("https://") || v.starts_with("http://"))
+        .map(|v| v.trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '>', '"', '\'']))
+}
+
+/// The page's title and description, or `None` if the server couldn't preview it or it has no title.
+pub async fn fetch(client: &Client, url: &str) -> Option<Preview> {
+    let response = client.send(get_media_preview::v3::Request::new(url, MilliSecondsSinceUnixEpoch::now()), None).await.ok()?;
+    let data: OpenGraph = serde_json::from_str(response.data?.get()).ok()?;
+    Some(Preview {
+        title: data.title.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())?,
+        // descriptions are often whole paragraphs, which are shown on one line
+        description: data.description.map(|v| v.split_whitespace().collect::<Vec<_>>().join(" ")).filter(|v| !v.is_empty()),
+    })
+}
@@ -0,0 +1,120 @@
+//! `--profile`: timings of frames, lock waits, and event handling, written to a report on quit.
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::AppState;
+
+/// Where the report is written.
+pub const REPORT_FILE: &str = "profile.txt";
+
+/// How finely durations are told apart: each doubling is split into this many buckets, so
+/// percentiles are right to within about 6% and a long session doesn't keep every sample.
+const BUCKETS_PER_DOUBLING: u32 = 16;
+
+pub struct Profiler {
+    enabled: bool,
+    samples: BTreeMap<&'static str, Histogram>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    count: u64,
+    total: Duration,
+    max: Duration,
+    /// How many samples fell in each bucket, keyed by `bucket`.
+    buckets: BTreeMap<u32, u64>,
+}
+
+impl Histogram {
+    fn record(&mut self, duration: Duration) {
+        self.count += 1;
+        self.total += duration;
+        self.max = self.max.max(duration);
+        *self.buckets.entry(bucket(duration)).or_default() += 1;
+    }
+
+    /// The duration `p` percent of samples took at most, rounded down to its bucket.
+    fn percentile(&self, p: u64) -> Duration {
+        let wanted = (self.count - 1) * p / 100;
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter() {
+            seen += count;
+            if seen > wanted {
+                return lower_bound(*bucket).min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+/// The bucket a duration goes in: exact below `BUCKETS_PER_DOUBLING` nanoseconds, and after that
+/// the power of two it's in followed by the next four bits.
+fn bucket(duration: Duration) -> u32 {
+    let nanos = duration.as_nanos().min(u64::MAX as u128) as u64;
+    if nanos < BUCKETS_PER_DOUBLING as u64 {
+        return nanos as u32;
+    }
+    let doubling = 63 - nanos.leading_zeros();
+    let fraction = (nanos >> (doubling - 4)) as u32 - BUCKETS_PER_DOUBLING;
+    (doubling - 3) * BUCKETS_PER_DOUBLING + fraction
+}
+
+/// The shortest duration in a bucket.
+fn lower_bound(bucket: u32) -> Duration {
+    if bucket < BUCKETS_PER_DOUBLING {
+        return Duration::from_nanos(bucket as u64);
+    }
+    let doubling = bucket / BUCKETS_PER_DOUBLING + 3;
+    let fraction = bucket % BUCKETS_PER_DOUBLING;
+    Duration::from_nanos(((BUCKETS_PER_DOUBLING + fraction) as u64) << (doubling - 4))
+}
+
+impl Profiler {
+    pub fn new(enabled: bool) -> Profiler {
+        Profiler {
+            enabled,
+            samples: BTreeMap::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn record(&mut self, name: &'static str, duration: Duration) {
+        if self.enabled {
+            self.samples.entry(name).or_default().record(duration);
+        }
+    }
+
+    /// A table of how long each thing took: how often, the mean, percentiles, and the worst.
+    pub fn report(&self) -> String {
+        let mut report = format!("{:<24} {:>8} {:>10} {:>10} {:>10} {:>10} {:>10}\n", "", "count", "mean", "p50", "p95", "p99", "max");
+        for (name, samples) in self.samples.iter() {
+            report.push_str(&format!(
+                "{:<24} {:>8} {:>10.2?} {:>10.2?} {:>10.2?} {:>10.2?} {:>10.2?}\n",
+                name,
+                samples.count,
+                samples.total / samples.count as u32,
+                samples.percentile(50),
+                samples.percentile(95),
+                samples.percentile(99),
+                samples.max,
+            ));
+        }
+        report
+    }
+}
+
+/// Locks the app state, recording how long that took under `name`.
+pub async fn lock<'a>(state: &'a Mutex<AppState>, name: &'static str) -> MutexGuard<'a, AppState> {
+    let start = Instant::now();
+    let mut lock = state.lock().await;
+    lock.profiler.record(name, start.elapsed());
+    lock
+}
@@ -0,0 +1,27 @@
+//! Copying messages as a markdown quote, like `> [10:02] <alice> hi`, for pasting into issues and
+//! documents.
+
+use std::io::Write;
+
+use crate::clock::Clock;
+
+/// Quotes messages given as their nick, timestamp in seconds, and content, oldest first.
+pub fn format<'a>(messages: impl IntoIterator<Item = (&'a str, i64, &'a str)>, clock: &Clock) -> String {
+    let mut quote = vec![];
+    for (nick, timestamp, content) in messages {
+        let time = clock.time(timestamp);
+        let mut lines = content.split('\n');
+        quote.push(format!("> [{}] <{}> {}", time, nick, lines.next().unwrap_or_default()).trim_end().to_string());
+        // later lines stay inside the quote
+        quote.extend(lines.map(|v| format!("> {}", v).trim_end().to_string()));
+    }
+    quote.join("\n")
+}
+
+/// Puts text on the clipboard with the OSC 52 escape sequence, which most terminals support and
+/// which also works over SSH.
+pub fn copy(text: &str) {
+    let mut stdout = std::io::stdout();
+    let _ = write!(stdout, "\x1b]52;c;{}\x07", base64::encode(text));
+    let _ = stdout.flush();
+}
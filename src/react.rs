@@ -0,0 +1,100 @@
+//! The quick-react palette: favourite emoji picked with a single key, or found by their shortcodes.
+
+use crossterm::event::{KeyCode, KeyEvent};
+use matrix_sdk::{room::Joined, ruma::OwnedEventId};
+
+use crate::{composer, symbols::Symbols};
+
+/// The keys the choices are picked with, in order.
+const KEYS: [char; 10] = ['1', '2', '3', '4', '5', '6', '7', '8', '9', '0'];
+const FAVORITES_PER_ROW: usize = 5;
+const RESULTS_PER_ROW: usize = 2;
+
+pub enum Pick {
+    Chosen(String),
+    Cancelled,
+    /// The palette is still open, maybe with a new search.
+    Open,
+}
+
+pub struct Palette {
+    pub room: Joined,
+    pub event_id: OwnedEventId,
+    search: String,
+}
+
+impl Palette {
+    pub fn new(room: Joined, event_id: OwnedEventId) -> Palette {
+        Palette {
+            room,
+            event_id,
+            search: String::new(),
+        }
+    }
+
+    /// The emoji on offer with their shortcodes: the favourites until something's searched for.
+    fn choices(&self, favorites: &[String]) -> Vec<(String, Option<&'static str>)> {
+        if self.search.is_empty() {
+            favorites.iter().take(KEYS.len()).map(|v| (v.clone(), None)).collect()
+        } else {
+            composer::search_shortcodes(&self.search).into_iter().take(KEYS.len()).map(|(code, emoji)| (String::from(emoji), Some(code))).collect()
+        }
+    }
+
+    pub fn lines(&self, favorites: &[String], symbols: &Symbols) -> Vec<String> {
+        let choices = self.choices(favorites);
+        let per_row = if self.search.is_empty() { FAVORITES_PER_ROW } else { RESULTS_PER_ROW };
+        let mut lines: Vec<String> = choices
+            .chunks(per_row)
+            .enumerate()
+            .map(|(row, chunk)| {
+                let cells: Vec<_> = chunk.iter().enumerate().map(|(i, (emoji, code))| {
+                    let key = KEYS[row * per_row + i];
+                    match code {
+                        Some(code) if !symbols.ascii() => format!("{} {} {:<16}", key, emoji, code),
+                        _ => format!("{} {:<4}", key, symbols.emoji(emoji)),
+                    }
+                }).collect();
+                cells.join(" ").trim_end().to_string()
+            })
+            .collect();
+
+        if choices.is_empty() {
+            lines.push(String::from("No emoji found."));
+        }
+        lines.push(String::new());
+        lines.push(format!("Search: {}", self.search));
+        lines.push(String::from("Type to search, a number to react, Enter for the first, Esc to cancel"));
+        lines
+    }
+
+    pub fn key(&mut self, key: KeyEvent, favorites: &[String]) -> Pick {
+        match key.code {
+            KeyCode::Char(c) if KEYS.contains(&c) => {
+                let index = KEYS.iter().position(|v| *v == c).unwrap();
+                match self.choices(favorites).into_iter().nth(index) {
+                    Some((emoji, _)) => Pick::Chosen(emoji),
+                    None => Pick::Open,
+                }
+            }
+
+            KeyCode::Char(c) => {
+                self.search.push(c);
+                Pick::Open
+            }
+
+            KeyCode::Backspace => {
+                self.search.pop();
+                Pick::Open
+            }
+
+            KeyCode::Enter => match self.choices(favorites).into_iter().next() {
+                Some((emoji, _)) => Pick::Chosen(emoji),
+                None => Pick::Open,
+            },
+
+            KeyCode::Esc => Pick::Cancelled,
+            _ => Pick::Open,
+        }
+    }
+}
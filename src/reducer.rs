@@ -0,0 +1,263 @@
+//! Changes to the rooms and their messages, as events applied to the app state in one place. Sync
+//! handlers and pagination only work out what happened from what the server sent, and everything
+//! it touches, like the selection and announcements, is kept right here.
+
+use std::{
+    collections::{hash_map::Entry, HashSet},
+    time::Instant,
+};
+
+use matrix_sdk::ruma::{OwnedEventId, OwnedRoomId};
+
+use crate::{media, AppState, Channel, Edit, Message, Reaction, StreamPosition};
+
+pub enum AppEvent {
+    /// A room to show, unless it's shown already.
+    RoomJoined(Box<Channel>),
+    /// A room to list in the room list, once it has a channel. Rooms already listed stay where they are.
+    RoomListed(OwnedRoomId),
+    /// We left a room, or joined one we'd left again. `removed` says who kicked or banned us and why.
+    RoomLeft { room: OwnedRoomId, left: bool, removed: Option<String> },
+    /// A room's name was worked out again, or `None` if only its avatar changed.
+    RoomRenamed { room: OwnedRoomId, name: Option<String> },
+    MessageAdded { room: OwnedRoomId, message: Box<Message>, position: StreamPosition },
+    /// New content for a message, which may not have arrived yet.
+    Edited { room: OwnedRoomId, target: OwnedEventId, sender: String, edit: Edit },
+    Reacted { room: OwnedRoomId, target: OwnedEventId, reaction: Reaction },
+    /// A message or reaction was redacted.
+    Redacted { room: OwnedRoomId, event: OwnedEventId },
+}
+
+pub fn apply(state: &mut AppState, event: AppEvent) {
+    update(state, event);
+    check_channels(state);
+}
+
+/// Every room in the room list has a channel, and none is listed twice.
+fn check_channels(state: &AppState) {
+    debug_assert!(state.channel_ids.iter().all(|v| state.channels.contains_key(v)), "a room is listed without a channel");
+    debug_assert_eq!(state.channel_ids.iter().collect::<HashSet<_>>().len(), state.channel_ids.len(), "a room is listed twice");
+}
+
+fn update(state: &mut AppState, event: AppEvent) {
+    match event {
+        AppEvent::RoomJoined(channel) => {
+            if let Entry::Vacant(v) = state.channels.entry(channel.room.room_id().to_owned()) {
+                v.insert(*channel);
+            }
+        }
+
+        AppEvent::RoomListed(room) => {
+            if state.channels.contains_key(&room) && !state.channel_ids.contains(&room) {
+                state.channel_ids.push(room);
+            }
+        }
+
+        AppEvent::RoomLeft { room, left, removed } => {
+            let channel = match state.channels.get_mut(&room) {
+                Some(v) if v.archived != left => v,
+                _ => return,
+            };
+            channel.archived = left;
+            if !left {
+                return;
+            }
+
+            // anything still running for the room would only be refused now
+            state.tasks.cancel(&room);
+            let notice = match removed {
+                Some(removed) => format!("Removed from {}, {}", channel.name, removed),
+                None => format!("Left {}", channel.name),
+            };
+            state.announcements.push(&notice);
+            state.toast.show(notice, Instant::now());
+        }
+
+        AppEvent::RoomRenamed { room, name } => {
+            let current = state.current_channel.as_ref() == Some(&room);
+            let channel = match state.channels.get_mut(&room) {
+                Some(v) => v,
+                None => return,
+            };
+            if let Some(name) = name {
+                if channel.name == name {
+                    return;
+                }
+                state.announcements.push(&format!("{} is now called {}", channel.name, name));
+                state.names.set_name(&room, &name);
+                channel.name = name;
+            }
+            // the room being read already shows the change
+            channel.changed |= !current;
+        }
+
+        AppEvent::MessageAdded { room, message, position } => insert(state, &room, *message, position),
+        AppEvent::Edited { room, target, sender, edit } => apply_edit(state, &room, target, &sender, edit),
+
+        AppEvent::Reacted { room, target, reaction } => {
+            let message = match state.channels.get_mut(&room).and_then(|v| v.messages.get_mut(&target)) {
+                Some(v) => v,
+                None => return,
+            };
+            if !message.reactions.iter().any(|v| v.id == reaction.id) {
+                message.reactions.push(reaction);
+            }
+        }
+
+        AppEvent::Redacted { room, event } => {
+            let channel = match state.channels.get_mut(&room) {
+                Some(v) => v,
+                None => return,
+            };
+            for message in channel.messages.values_mut() {
+                message.reactions.retain(|v| v.id != event);
+            }
+            channel.undecrypted.remove(&event);
+            channel.mentions.remove(&event);
+            remove(state, &room, &event);
+        }
+    }
+}
+
+fn apply_edit(state: &mut AppState, room: &OwnedRoomId, target: OwnedEventId, sender: &str, edit: Edit) {
+    let own = state.client.user_id().map(|v| v.as_str() == sender).unwrap_or(false);
+    let highlighted = !own && state.highlights.matches(room.as_str(), &edit.content);
+    let channel = match state.channels.get_mut(room) {
+        Some(v) => v,
+        None => return,
+    };
+
+    match channel.messages.get_mut(&target) {
+        // only the original's sender can edit it, however the event says otherwise
+        Some(original) if original.user != sender => (),
+
+        Some(original) => {
+            // the same edit can arrive from both sync and pagination, so only ever move forwards
+            if original.edited.map(|v| v < edit.timestamp).unwrap_or(true) {
+                original.edited = Some(edit.timestamp);
+                original.content = edit.content;
+                original.formatted = edit.formatted;
+                original.highlighted = highlighted;
+                state.search.add(room, &target, original.timestamp, &original.user, &original.content);
+            }
+        }
+
+        // kept until the original is paged in
+        None => {
+            let edits = channel.message_edits.entry(target).or_default();
+            let newer = edits.get(sender).map(|v| v.timestamp < edit.timestamp).unwrap_or(true);
+            if newer {
+                edits.insert(sender.to_string(), edit);
+            }
+        }
+    }
+}
+
+fn insert(state: &mut AppState, id: &OwnedRoomId, mut message: Message, position: StreamPosition) {
+    let current = state.current_channel.as_ref() == Some(id);
+    let own = state.client.user_id().map(|v| v.as_str() == message.user).unwrap_or(false);
+    let channel = match state.channels.get_mut(id) {
+        Some(v) if !v.messages.contains_key(&message.id) => v,
+        _ => return,
+    };
+    // placeholders for encrypted messages get their edits once they're decrypted
+    if !channel.undecrypted.contains_key(&message.id) {
+        if let Some(edit) = channel.message_edits.remove(&message.id).and_then(|mut v| v.remove(&message.user)) {
+            message.edited = Some(edit.timestamp);
+            message.content = edit.content;
+            message.formatted = edit.formatted;
+        }
+    }
+
+    message.highlighted = !own && message.call.is_none() && state.highlights.matches(id.as_str(), &message.content);
+    let auto_translate = message.call.is_none() && state.config.rooms.get(id.as_str()).map(|v| v.auto_translate).unwrap_or(false);
+    if let (StreamPosition::End, false, true, None) = (&position, own, auto_translate && !message.content.is_empty(), &message.media) {
+        state.untranslated.push((id.clone(), message.id.clone(), message.content.clone()));
+    }
+    let channel = state.channels.get_mut(id).unwrap();
+    if let (StreamPosition::End, false, true) = (&position, current, message.highlighted) {
+        channel.mentions.insert(message.id.clone());
+    }
+    // placeholders for encrypted messages are announced once they're decrypted
+    let announcement = match position {
+        StreamPosition::End if !channel.undecrypted.contains_key(&message.id) => {
+            let content = message.media.as_ref().and_then(media::summary).unwrap_or_else(|| message.content.clone());
+            Some(format!("{} in {}: {}", message.user, channel.name, content))
+        }
+
+        _ => None,
+    };
+    // the stream position decides the order; timestamps are only used when it can't
+    let index = match position {
+        StreamPosition::End => channel.message_ids.len(),
+        StreamPosition::Start => 0,
+        StreamPosition::Before(ref before) if channel.message_ids.contains(before) => channel.message_ids.iter().position(|v| v == before).unwrap(),
+        StreamPosition::Before(_) | StreamPosition::Imported => channel.message_ids.iter().rposition(|v| channel.messages.get(v).map(|v| v.timestamp <= message.timestamp).unwrap_or(false)).map(|v| v + 1).unwrap_or(0),
+    };
+    channel.message_ids.insert(index, message.id.clone());
+    // calls are described rather than said, and placeholders are indexed once they're decrypted
+    if message.call.is_none() && !channel.undecrypted.contains_key(&message.id) {
+        state.search.add(id, &message.id, message.timestamp, &message.user, &message.content);
+    }
+    let (message_id, thread) = (message.id.clone(), message.thread.clone());
+    if let Some(root) = thread.as_ref() {
+        let replies = channel.threads.entry(root.clone()).or_default();
+        if !replies.contains(&message_id) {
+            replies.push(message_id.clone());
+            replies.sort_by_key(|v| channel.message_ids.iter().position(|m| m == v));
+        }
+    }
+    channel.messages.insert(message.id.clone(), message);
+
+    // keep the same message selected when something is inserted below it, in the timeline or thread shown
+    let below = match thread.as_ref().and_then(|v| channel.threads.get(v)) {
+        Some(replies) => replies.len() - replies.iter().position(|v| *v == message_id).unwrap() - 1,
+        None => channel.message_ids.len() - index - 1,
+    };
+    match state.messages_state.selected() {
+        Some(sel) if current && state.thread == thread && sel >= below => {
+            state.messages_state.select(Some(sel + 1));
+        }
+
+        _ => (),
+    }
+
+    if let Some(announcement) = announcement {
+        state.announcements.push(&announcement);
+    }
+}
+
+/// Removes a message, returning where it was so something can take its place.
+pub fn remove(state: &mut AppState, id: &OwnedRoomId, event_id: &OwnedEventId) -> StreamPosition {
+    let current = state.current_channel.as_ref() == Some(id);
+    let channel = match state.channels.get_mut(id) {
+        Some(v) => v,
+        None => return StreamPosition::End,
+    };
+    let index = match channel.message_ids.iter().position(|v| v == event_id) {
+        Some(v) => v,
+        None => return StreamPosition::End,
+    };
+
+    channel.message_ids.remove(index);
+    channel.messages.remove(event_id);
+    channel.threads.retain(|_, replies| {
+        replies.retain(|v| v != event_id);
+        !replies.is_empty()
+    });
+    state.search.remove(event_id);
+    let below = channel.message_ids.len() - index;
+    let position = match channel.message_ids.get(index) {
+        Some(next) => StreamPosition::Before(next.clone()),
+        None => StreamPosition::End,
+    };
+
+    match state.messages_state.selected() {
+        Some(sel) if current && sel > below => {
+            state.messages_state.select(Some(sel - 1));
+        }
+
+        _ => (),
+    }
+    position
+}
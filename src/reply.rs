@@ -0,0 +1,16 @@
+//! Rich replies. Clients quote the message replied to at the start of a reply's body, for clients
+//! that don't know about replies. That quote is dropped here, since the original is drawn above the
+//! reply instead, and our own replies are sent without one.
+
+/// The body without the quoted fallback at its start, if it has one.
+pub fn strip_fallback(body: &str) -> &str {
+    let mut rest = body;
+    while rest.starts_with('>') {
+        rest = rest.split_once('\n').map(|(_, v)| v).unwrap_or_default();
+    }
+    if rest.len() == body.len() {
+        return body;
+    }
+    // the quote is followed by a blank line
+    rest.strip_prefix('\n').unwrap_or(rest)
+}
@@ -0,0 +1,23 @@
+//! Where ilo-toki was left: the room open and the message selected, so the next run starts there.
+
+use matrix_sdk::ruma::{OwnedEventId, OwnedRoomId};
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Resume {
+    pub room: Option<OwnedRoomId>,
+    /// The message selected while scrolling, which is only found again if it's loaded.
+    pub selected: Option<OwnedEventId>,
+}
+
+impl Resume {
+    pub fn load(path: &str) -> Resume {
+        std::fs::read_to_string(path).ok().and_then(|v| serde_json::from_str(&v).ok()).unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &str) {
+        if let Ok(v) = serde_json::to_string(self) {
+            let _ = std::fs::write(path, v);
+        }
+    }
+}
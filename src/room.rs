@@ -0,0 +1,54 @@
+//! `/room`: a channel's settings at a glance, including whether it's in the server's public room
+//! directory, which `/publish` and `/unpublish` change.
+
+use matrix_sdk::{
+    room::Joined,
+    ruma::{
+        api::client::{
+            directory::{get_room_visibility, set_room_visibility},
+            room::Visibility,
+        },
+        events::room::join_rules::JoinRule,
+        RoomId,
+    },
+    Client,
+};
+
+/// `avatar` is the one saved from last time, if any.
+pub async fn lines(client: &Client, room: &Joined, avatar: Option<&str>) -> Vec<String> {
+    let mut lines = vec![
+        format!("Name: {}", room.display_name().await.map(|v| v.to_string()).unwrap_or_default()),
+        format!("ID: {}", room.room_id()),
+        format!("Alias: {}", room.canonical_alias().map(|v| v.to_string()).unwrap_or_else(|| String::from("none"))),
+    ];
+    if let Some(avatar) = avatar.map(String::from).or_else(|| room.avatar_url().map(|v| v.to_string())) {
+        lines.push(format!("Avatar: {}", avatar));
+    }
+    if let Some(topic) = room.topic() {
+        lines.push(format!("Topic: {}", topic));
+    }
+
+    let join_rule = match room.join_rule() {
+        JoinRule::Public => "anyone",
+        JoinRule::Invite => "invite only",
+        JoinRule::Knock => "knock",
+        JoinRule::Restricted(_) => "members of other rooms",
+        _ => "other",
+    };
+    lines.push(format!("Who can join: {}", join_rule));
+    lines.push(format!("Encrypted: {}", if room.is_encrypted() { "yes" } else { "no" }));
+
+    let published = match client.send(get_room_visibility::v3::Request::new(room.room_id()), None).await {
+        Ok(response) if response.visibility == Visibility::Public => "yes (/unpublish to remove it)",
+        Ok(_) => "no (/publish to list it)",
+        Err(_) => "unknown",
+    };
+    lines.push(format!("In the public directory: {}", published));
+    lines
+}
+
+/// Lists or delists a room in the server's public room directory, which needs enough power.
+pub async fn set_published(client: &Client, room_id: &RoomId, published: bool) -> Result<(), String> {
+    let visibility = if published { Visibility::Public } else { Visibility::Private };
+    client.send(set_room_visibility::v3::Request::new(room_id, visibility), None).await.map(|_| ()).map_err(|e| e.to_string())
+}
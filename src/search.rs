@@ -0,0 +1,199 @@
+//! Searching the messages we've seen, in this run and earlier ones, as it's typed. Each message is
+//! split into trigrams as it arrives, so a search only reads the messages that share every trigram
+//! with it rather than the whole history. The messages are saved between runs and the trigrams
+//! worked out again from them, so older results can be found without paging back to them first.
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use matrix_sdk::ruma::{OwnedEventId, OwnedRoomId, UInt};
+use serde::{Deserialize, Serialize};
+
+/// How long typing has to pause before searching, so a fast typist doesn't search on every key.
+pub const DEBOUNCE: Duration = Duration::from_millis(150);
+/// How many results are listed.
+const RESULTS: usize = 12;
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Index {
+    messages: HashMap<OwnedEventId, Indexed>,
+    #[serde(skip)]
+    trigrams: HashMap<[char; 3], HashSet<OwnedEventId>>,
+    /// Whether there's anything new to save.
+    #[serde(skip)]
+    changed: bool,
+}
+
+/// What's kept of a message: enough to list it as a result, and to page back to it if it isn't loaded.
+#[derive(Serialize, Deserialize)]
+pub struct Indexed {
+    pub room: OwnedRoomId,
+    pub timestamp: UInt,
+    pub sender: String,
+    pub content: String,
+}
+
+fn trigrams(text: &str) -> HashSet<[char; 3]> {
+    let chars: Vec<_> = text.chars().collect();
+    chars.windows(3).map(|v| [v[0], v[1], v[2]]).collect()
+}
+
+impl Index {
+    /// Loads the messages saved last run, indexing them again.
+    pub fn load(path: &str) -> Index {
+        let mut index: Index = std::fs::read_to_string(path).ok().and_then(|v| serde_json::from_str(&v).ok()).unwrap_or_default();
+        for (event_id, message) in index.messages.iter() {
+            for trigram in trigrams(&message.content.to_lowercase()) {
+                index.trigrams.entry(trigram).or_default().insert(event_id.clone());
+            }
+        }
+        index
+    }
+
+    pub fn save(&mut self, path: &str) {
+        if self.changed && serde_json::to_string(self).map(|v| std::fs::write(path, v)).is_ok() {
+            self.changed = false;
+        }
+    }
+
+    /// Indexes a message, replacing what it said before if it's been edited.
+    pub fn add(&mut self, room: &OwnedRoomId, event_id: &OwnedEventId, timestamp: UInt, sender: &str, content: &str) {
+        self.remove(event_id);
+        for trigram in trigrams(&content.to_lowercase()) {
+            self.trigrams.entry(trigram).or_default().insert(event_id.clone());
+        }
+        let message = Indexed {
+            room: room.clone(),
+            timestamp,
+            sender: sender.to_string(),
+            content: content.to_string(),
+        };
+        self.messages.insert(event_id.clone(), message);
+        self.changed = true;
+    }
+
+    pub fn remove(&mut self, event_id: &OwnedEventId) {
+        let content = match self.messages.remove(event_id) {
+            Some(v) => v.content.to_lowercase(),
+            None => return,
+        };
+        for trigram in trigrams(&content) {
+            if let Some(ids) = self.trigrams.get_mut(&trigram) {
+                ids.remove(event_id);
+                if ids.is_empty() {
+                    self.trigrams.remove(&trigram);
+                }
+            }
+        }
+        self.changed = true;
+    }
+
+    pub fn get(&self, event_id: &OwnedEventId) -> Option<&Indexed> {
+        self.messages.get(event_id)
+    }
+
+    /// The messages containing the query, ignoring case, newest first.
+    pub fn search(&self, query: &str) -> Vec<(OwnedRoomId, OwnedEventId)> {
+        let query = query.to_lowercase();
+        if query.trim().is_empty() {
+            return vec![];
+        }
+
+        let wanted = trigrams(&query);
+        // queries too short for a trigram read every message, which is still only a scan of memory
+        let candidates: Vec<_> = if wanted.is_empty() {
+            self.messages.keys().collect()
+        } else {
+            let mut sets = match wanted.iter().map(|v| self.trigrams.get(v)).collect::<Option<Vec<_>>>() {
+                Some(v) => v,
+                None => return vec![],
+            };
+            sets.sort_by_key(|v| v.len());
+            let (first, rest) = sets.split_first().unwrap();
+            first.iter().filter(|id| rest.iter().all(|v| v.contains(*id))).collect()
+        };
+
+        // sharing every trigram doesn't mean they're in the same order, so the content still decides
+        let mut found: Vec<_> = candidates.into_iter().map(|id| (id, &self.messages[id])).filter(|(_, v)| v.content.to_lowercase().contains(&query)).collect();
+        found.sort_by_key(|(_, v)| std::cmp::Reverse(v.timestamp));
+        found.into_iter().map(|(id, v)| (v.room.clone(), id.clone())).collect()
+    }
+}
+
+pub enum Pick {
+    Chosen(OwnedRoomId, OwnedEventId),
+    Cancelled,
+    Open,
+}
+
+/// The `/search` popup, which searches again whenever typing pauses.
+pub struct Search {
+    query: String,
+    /// When the query last changed, until it's been searched for.
+    typed: Option<Instant>,
+    results: Vec<(OwnedRoomId, OwnedEventId)>,
+    selected: usize,
+}
+
+impl Search {
+    /// Starts with the results for `query` straight away.
+    pub fn new(query: &str, index: &Index) -> Search {
+        Search {
+            query: query.to_string(),
+            typed: None,
+            results: index.search(query).into_iter().take(RESULTS).collect(),
+            selected: 0,
+        }
+    }
+
+    /// Searches again if the query changed and typing has paused since. Returns whether it did.
+    pub fn update(&mut self, index: &Index, now: Instant) -> bool {
+        match self.typed {
+            Some(typed) if now.duration_since(typed) >= DEBOUNCE => {
+                self.typed = None;
+                self.results = index.search(&self.query).into_iter().take(RESULTS).collect();
+                self.selected = 0;
+                true
+            }
+
+            _ => false,
+        }
+    }
+
+    /// `describe` gives a line for a result, or `None` if it's gone since.
+    pub fn lines(&self, describe: impl Fn(&OwnedRoomId, &OwnedEventId) -> Option<String>) -> Vec<String> {
+        let mut lines: Vec<_> = self.results.iter().enumerate().filter_map(|(i, (room, id))| Some(format!("{} {}", if i == self.selected { '>' } else { ' ' }, describe(room, id)?))).collect();
+        if lines.is_empty() && !self.query.trim().is_empty() && self.typed.is_none() {
+            lines.push(String::from("Nothing found."));
+        }
+        lines.push(String::new());
+        lines.push(format!("Search: {}", self.query));
+        lines.push(String::from("Type to search, Up and Down to choose, Enter to go to the message, Esc to cancel"));
+        lines
+    }
+
+    pub fn key(&mut self, key: KeyEvent, now: Instant) -> Pick {
+        match key.code {
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.selected = (self.selected + 1).min(self.results.len().saturating_sub(1)),
+            KeyCode::Enter => return self.results.get(self.selected).map(|(room, id)| Pick::Chosen(room.clone(), id.clone())).unwrap_or(Pick::Open),
+            KeyCode::Esc => return Pick::Cancelled,
+
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.query.push(c);
+                self.typed = Some(now);
+            }
+
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.typed = Some(now);
+            }
+
+            _ => (),
+        }
+        Pick::Open
+    }
+}
@@ -0,0 +1,98 @@
+//! `/security`: how far this session is trusted, and what can be done about what isn't.
+
+use matrix_sdk::{
+    encryption::identities::Device,
+    room::Joined,
+    ruma::api::client::backup::get_latest_backup_info,
+    Client,
+};
+
+use crate::{config::DevicePolicy, keys, verification};
+
+pub struct Report {
+    /// Whether this session trusts our cross-signing identity.
+    pub session_verified: bool,
+    /// The cross-signing keys this session doesn't have.
+    missing_keys: Vec<&'static str>,
+    /// The version of the key backup on the server, if there is one.
+    backup: Option<String>,
+    /// Unverified devices in the current room that haven't been accepted or blocked.
+    pub unreviewed: Vec<Device>,
+}
+
+impl Report {
+    pub async fn new(client: &Client, room: Option<&Joined>) -> Report {
+        // a session trusts our identity once it's been verified or has the cross-signing keys
+        let session_verified = match client.user_id() {
+            Some(user_id) => verification::is_verified(client, user_id).await,
+            None => false,
+        };
+
+        let mut missing_keys = vec![];
+        match client.encryption().cross_signing_status().await {
+            Some(status) => {
+                let keys = [(status.has_master, "master"), (status.has_self_signing, "self-signing"), (status.has_user_signing, "user-signing")];
+                missing_keys.extend(keys.iter().filter(|(has, _)| !has).map(|(_, name)| *name));
+            }
+
+            None => missing_keys.extend(["master", "self-signing", "user-signing"]),
+        }
+
+        let backup = client.send(get_latest_backup_info::v3::Request::new(), None).await.ok().map(|v| v.version);
+        let unreviewed = match room {
+            Some(room) => keys::unreviewed_devices(room, DevicePolicy::Block).await.unwrap_or_default(),
+            None => vec![],
+        };
+
+        Report {
+            session_verified,
+            missing_keys,
+            backup,
+            unreviewed,
+        }
+    }
+
+    pub fn cross_signing_ready(&self) -> bool {
+        self.missing_keys.is_empty()
+    }
+
+    pub fn has_backup(&self) -> bool {
+        self.backup.is_some()
+    }
+
+    /// The popup, with the key that fixes each problem next to it, or works around it.
+    pub fn lines(&self, export_file: &str) -> Vec<String> {
+        let mut lines = vec![];
+        if self.session_verified {
+            lines.push(String::from("This session: verified"));
+        } else {
+            lines.push(String::from("This session: not verified (v: verify it from another session)"));
+        }
+
+        if self.cross_signing_ready() {
+            lines.push(String::from("Cross-signing: ready"));
+        } else {
+            lines.push(format!("Cross-signing: missing the {} key(s) (c: set it up)", self.missing_keys.join(", ")));
+        }
+
+        match self.backup.as_ref() {
+            Some(version) => lines.push(format!("Key backup: version {} on the server", version)),
+            // the sdk can't make or restore a server-side backup, so all that's offered is a file
+            None => {
+                lines.push(String::from("Key backup: none on the server, and ilo-toki can't set one up"));
+                lines.push(format!("  e: export room keys to {} to keep a copy yourself", export_file));
+            }
+        }
+
+        if self.unreviewed.is_empty() {
+            lines.push(String::from("Unverified devices in this room: none"));
+        } else {
+            lines.push(format!("Unverified devices in this room: {} (a: accept them, b: block them)", self.unreviewed.len()));
+            for device in self.unreviewed.iter() {
+                lines.push(format!("  {} {} {}", device.user_id(), device.device_id(), device.display_name().unwrap_or_default()));
+            }
+        }
+        lines.push(String::from("Esc: close"));
+        lines
+    }
+}
@@ -0,0 +1,99 @@
+//! What the homeserver supports, from `/versions` and `/capabilities`.
+
+use std::collections::BTreeMap;
+
+use matrix_sdk::{
+    ruma::api::{
+        client::discovery::{
+            get_capabilities::{self, Capabilities, RoomVersionStability},
+            get_supported_versions,
+        },
+        MatrixVersion,
+    },
+    Client,
+};
+
+#[derive(Default)]
+pub struct ServerFeatures {
+    /// The spec versions the server advertises, as written.
+    versions: Vec<String>,
+    known_versions: Vec<MatrixVersion>,
+    unstable_features: BTreeMap<String, bool>,
+    /// `None` if the server didn't answer, which older servers don't.
+    capabilities: Option<Capabilities>,
+}
+
+impl ServerFeatures {
+    pub async fn query(client: &Client) -> ServerFeatures {
+        let mut features = ServerFeatures::default();
+        if let Ok(response) = client.send(get_supported_versions::Request::new(), None).await {
+            features.known_versions = response.known_versions().collect();
+            features.versions = response.versions;
+            features.unstable_features = response.unstable_features;
+        }
+
+        if let Ok(response) = client.send(get_capabilities::v3::Request::new(), None).await {
+            features.capabilities = Some(response.capabilities);
+        }
+        features
+    }
+
+    /// Whether the server can list the edits and reactions of a message, added in Matrix 1.3. Edits
+    /// aren't sent without it, since a server that doesn't know them shows them as new messages.
+    pub fn relations(&self) -> bool {
+        self.known_versions.contains(&MatrixVersion::V1_3) || self.unstable_features.get("org.matrix.msc2675").copied().unwrap_or(false)
+    }
+
+    /// Where to ask for the event closest to a time, which is in Matrix 1.6 and MSC3030 before it.
+    pub fn timestamp_to_event(&self) -> Option<&'static str> {
+        let stable = self.versions.iter().any(|v| v.strip_prefix("v1.").and_then(|v| v.parse::<u32>().ok()).map(|v| v >= 6).unwrap_or(false));
+        if stable {
+            Some("_matrix/client/v1")
+        } else if self.unstable_features.get("org.matrix.msc3030").copied().unwrap_or(false) {
+            Some("_matrix/client/unstable/org.matrix.msc3030")
+        } else {
+            None
+        }
+    }
+
+    /// Where to ask which rooms we share with someone, from MSC2666. Without it they're worked out
+    /// from the member lists we have.
+    pub fn mutual_rooms(&self) -> Option<&'static str> {
+        if self.unstable_features.get("uk.half-shot.msc2666.query_mutual_rooms").copied().unwrap_or(false) {
+            Some("_matrix/client/unstable/uk.half-shot.msc2666/user/mutual_rooms")
+        } else {
+            None
+        }
+    }
+
+    /// Features this client uses that the server doesn't support, for the status line.
+    pub fn unsupported(&self) -> Vec<&'static str> {
+        let mut unsupported = vec![];
+        if !self.relations() {
+            unsupported.push("edits and reaction history");
+        }
+        unsupported
+    }
+
+    /// A description of what the server supports, one line per feature.
+    pub fn summary(&self) -> Vec<String> {
+        let yes_no = |v: bool| if v { "yes" } else { "no" };
+        let mut lines = vec![format!("Spec versions: {}", if self.versions.is_empty() { String::from("unknown") } else { self.versions.join(", ") })];
+        lines.push(format!("Edit and reaction history: {}", yes_no(self.relations())));
+        lines.push(format!("Jump to date: {}", if self.timestamp_to_event().is_some() { "yes" } else { "by paging back" }));
+
+        match self.capabilities.as_ref() {
+            Some(capabilities) => {
+                let rooms = &capabilities.room_versions;
+                let stable: Vec<_> = rooms.available.iter().filter(|(_, v)| **v == RoomVersionStability::Stable).map(|(k, _)| k.to_string()).collect();
+                lines.push(format!("Default room version: {} (stable: {})", rooms.default, stable.join(", ")));
+                lines.push(format!("Change password: {}", yes_no(capabilities.change_password.enabled)));
+                lines.push(format!("Set display name: {}", yes_no(capabilities.set_displayname.enabled)));
+                lines.push(format!("Set avatar: {}", yes_no(capabilities.set_avatar_url.enabled)));
+            }
+
+            None => lines.push(String::from("Capabilities: unknown")),
+        }
+        lines
+    }
+}
@@ -0,0 +1,43 @@
+//! Spellchecking the input box with a checker like `hunspell -l`, which reads text and prints the
+//! words it doesn't know, one per line.
+
+use std::collections::HashSet;
+
+use crate::pipe;
+
+#[derive(Default)]
+pub struct Spelling {
+    /// The text and dictionary last sent to the checker.
+    checked: Option<(String, String)>,
+    misspelled: HashSet<String>,
+    running: bool,
+}
+
+impl Spelling {
+    pub fn is_misspelled(&self, word: &str) -> bool {
+        self.misspelled.contains(word)
+    }
+
+    /// Whether the text should be checked now, which it is if it changed and no check is running.
+    /// Only one check runs at a time, so fast typing doesn't start a process per key.
+    pub fn start(&mut self, text: &str, language: &str) -> bool {
+        if self.running || self.checked.as_ref().map(|(t, l)| t == text && l == language).unwrap_or(false) {
+            return false;
+        }
+        self.checked = Some((text.to_string(), language.to_string()));
+        self.running = true;
+        true
+    }
+
+    pub fn finish(&mut self, misspelled: HashSet<String>) {
+        self.misspelled = misspelled;
+        self.running = false;
+    }
+}
+
+/// The words the checker doesn't know. `{language}` in the command is replaced with the dictionary.
+pub async fn check(command: &[String], language: &str, text: &str) -> Result<HashSet<String>, String> {
+    let command: Vec<_> = command.iter().map(|v| v.replace("{language}", language)).collect();
+    let output = pipe::run(&command, text).await?;
+    Ok(output.lines().map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect())
+}
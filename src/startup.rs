@@ -0,0 +1,46 @@
+//! The progress line shown during the first sync, which can take minutes on a big account and
+//! would otherwise look like a hang.
+
+use std::{
+    future::Future,
+    io::Write,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use matrix_sdk::Client;
+
+/// Messages handled so far, counted by the event handlers.
+pub static MESSAGES: AtomicUsize = AtomicUsize::new(0);
+
+/// Runs the first sync, redrawing the rooms found, messages handled, and time taken until it's done.
+pub async fn with_progress<F: Future>(client: &Client, sync: F) -> F::Output {
+    let start = Instant::now();
+    let mut ticks = tokio::time::interval(Duration::from_millis(250));
+    tokio::pin!(sync);
+    let output = loop {
+        tokio::select! {
+            output = &mut sync => break output,
+            _ = ticks.tick() => print(client, start),
+        }
+    };
+    print(client, start);
+    eprintln!();
+    output
+}
+
+fn print(client: &Client, start: Instant) {
+    let rooms = client.rooms().len();
+    let messages = MESSAGES.load(Ordering::Relaxed);
+    let elapsed = start.elapsed().as_secs();
+    // nothing is counted until the server answers, which is most of the wait
+    let line = if rooms == 0 && messages == 0 {
+        format!("Syncing: waiting for the server ({}s)", elapsed)
+    } else {
+        format!("Syncing: {} rooms, {} messages ({}s)", rooms, messages, elapsed)
+    };
+    let mut stderr = std::io::stderr();
+    // padded to cover a longer line from before
+    let _ = write!(stderr, "\r{:<60}", line);
+    let _ = stderr.flush();
+}
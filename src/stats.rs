@@ -0,0 +1,101 @@
+//! `/stats`: how active a room has been, from the messages loaded so far.
+
+use std::collections::HashMap;
+
+use chrono::{Duration, Local, NaiveDate, TimeZone, Timelike};
+
+use crate::symbols::Symbols;
+
+/// How many days the per-day sparkline covers, ending at the newest message.
+const DAYS: i64 = 30;
+/// How many posters are listed.
+const TOP_POSTERS: usize = 5;
+/// How many hours are listed under the hourly sparkline.
+const TOP_HOURS: usize = 3;
+
+pub struct Stats {
+    total: usize,
+    per_day: HashMap<NaiveDate, usize>,
+    first: Option<NaiveDate>,
+    last: Option<NaiveDate>,
+    /// Most messages first.
+    posters: Vec<(String, usize)>,
+    per_hour: [usize; 24],
+}
+
+impl Stats {
+    /// Counts messages given as their sender and timestamp in seconds, in local time.
+    pub fn new<'a>(messages: impl IntoIterator<Item = (&'a str, i64)>) -> Stats {
+        let mut stats = Stats {
+            total: 0,
+            per_day: HashMap::new(),
+            first: None,
+            last: None,
+            posters: vec![],
+            per_hour: [0; 24],
+        };
+
+        let mut posters: HashMap<&str, usize> = HashMap::new();
+        for (user, timestamp) in messages {
+            let time = match Local.timestamp_opt(timestamp, 0).single() {
+                Some(v) => v,
+                None => continue,
+            };
+            let day = time.date_naive();
+
+            stats.total += 1;
+            *stats.per_day.entry(day).or_default() += 1;
+            stats.per_hour[time.hour() as usize] += 1;
+            *posters.entry(user).or_default() += 1;
+            stats.first = Some(stats.first.map(|v| v.min(day)).unwrap_or(day));
+            stats.last = Some(stats.last.map(|v| v.max(day)).unwrap_or(day));
+        }
+
+        stats.posters = posters.into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+        stats.posters.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        stats
+    }
+
+    pub fn lines(&self, symbols: &Symbols) -> Vec<String> {
+        let (first, last) = match (self.first, self.last) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return vec![String::from("No messages loaded yet.")],
+        };
+
+        let mut lines = vec![format!("{} messages from {} to {}", self.total, first, last), String::new()];
+
+        let start = first.max(last - Duration::days(DAYS - 1));
+        let days: Vec<_> = (0..=(last - start).num_days()).map(|v| self.per_day.get(&(start + Duration::days(v))).copied().unwrap_or(0)).collect();
+        lines.push(format!("Messages per day (busiest: {})", days.iter().max().unwrap_or(&0)));
+        lines.push(sparkline(&days, symbols));
+        let (from, to) = (start.format("%m-%d").to_string(), last.format("%m-%d").to_string());
+        lines.push(if days.len() > from.len() + to.len() {
+            format!("{}{:>width$}", from, to, width = days.len() - from.len())
+        } else {
+            from
+        });
+        lines.push(String::new());
+
+        lines.push(String::from("Top posters"));
+        let width = self.posters.iter().take(TOP_POSTERS).map(|(v, _)| v.chars().count()).max().unwrap_or(0);
+        for (user, count) in self.posters.iter().take(TOP_POSTERS) {
+            lines.push(format!("  {:width$}  {}", user, count, width = width));
+        }
+        lines.push(String::new());
+
+        lines.push(String::from("Busiest hours"));
+        lines.push(sparkline(&self.per_hour, symbols));
+        lines.push(String::from("0     6     12    18    "));
+        let mut hours: Vec<_> = self.per_hour.iter().enumerate().filter(|(_, v)| **v > 0).collect();
+        hours.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(&b.0)));
+        lines.push(hours.iter().take(TOP_HOURS).map(|(hour, count)| format!("{:02}:00 ({})", hour, count)).collect::<Vec<_>>().join(", "));
+        lines
+    }
+}
+
+/// One character per count, taller for bigger counts. Zero is always blank.
+fn sparkline(counts: &[usize], symbols: &Symbols) -> String {
+    let bars = symbols.bars();
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    counts.iter().map(|&v| if v == 0 { ' ' } else { bars[(v * bars.len() - 1) / max] }).collect()
+}
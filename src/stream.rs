@@ -0,0 +1,71 @@
+//! `--stream-json`: no TUI, just timeline events as JSON lines on stdout and sends read from stdin.
+//!
+//! Every incoming timeline event is printed as `{"room_id": ..., "event": ...}`. Each line on stdin
+//! is a send, either `{"room_id": ..., "body": "..."}` composed like the input box would, or
+//! `{"room_id": ..., "type": "m.room.message", "content": {...}}` sent as is. Each send is answered
+//! with `{"sent": event_id}` or `{"error": message}`.
+
+use std::{io, sync::Arc};
+
+use matrix_sdk::{
+    config::SyncSettings,
+    room::Room,
+    ruma::{events::AnySyncTimelineEvent, serde::Raw, RoomId},
+    Client, LoopCtrl,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::{composer, config::Config};
+
+#[derive(Deserialize)]
+struct Send {
+    room_id: String,
+    body: Option<String>,
+    #[serde(rename = "type")]
+    event_type: Option<String>,
+    content: Option<Value>,
+}
+
+pub async fn run(client: Arc<Client>, config: Config) -> Result<(), io::Error> {
+    // history from before we started isn't streamed
+    let response = client.sync_once(SyncSettings::default()).await.map_err(io::Error::other)?;
+
+    client.add_event_handler(|event: Raw<AnySyncTimelineEvent>, room: Room| async move {
+        println!("{{\"room_id\":{},\"event\":{}}}", json!(room.room_id()), event.json().get());
+    });
+
+    let sync_client = client.clone();
+    tokio::task::spawn(async move {
+        let settings = SyncSettings::default().token(response.next_batch);
+        sync_client.sync_with_callback(settings, |_| async { LoopCtrl::Continue }).await.unwrap();
+    });
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let reply = match send(&client, &config, &line).await {
+            Ok(event_id) => json!({ "sent": event_id }),
+            Err(e) => json!({ "error": e }),
+        };
+        println!("{}", reply);
+    }
+    Ok(())
+}
+
+async fn send(client: &Client, config: &Config, line: &str) -> Result<String, String> {
+    let send: Send = serde_json::from_str(line).map_err(|e| e.to_string())?;
+    let room_id = RoomId::parse(&send.room_id).map_err(|e| e.to_string())?;
+    let room = client.get_joined_room(&room_id).ok_or_else(|| format!("not joined to {}", room_id))?;
+
+    let response = match (send.content, send.body) {
+        (Some(content), _) => room.send_raw(content, send.event_type.as_deref().unwrap_or("m.room.message"), None).await,
+        (None, Some(body)) => room.send(composer::message_content(&body, &config.composer(room_id.as_str())), None).await,
+        (None, None) => return Err(String::from("a send needs either a body or content")),
+    };
+    response.map(|v| v.event_id.to_string()).map_err(|e| e.to_string())
+}
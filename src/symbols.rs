@@ -0,0 +1,98 @@
+//! The characters the UI draws with, so terminals without unicode fonts can get plain ASCII.
+
+use serde::Deserialize;
+use tui::{buffer::Buffer, layout::Rect, widgets::Widget};
+
+use crate::composer;
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    /// ASCII unless the locale says UTF-8.
+    #[default]
+    Auto,
+    Unicode,
+    Ascii,
+}
+
+pub struct Symbols {
+    ascii: bool,
+}
+
+impl Symbols {
+    pub fn new(profile: Profile) -> Symbols {
+        let ascii = match profile {
+            Profile::Auto => !locale_is_utf8(),
+            Profile::Unicode => false,
+            Profile::Ascii => true,
+        };
+        Symbols { ascii }
+    }
+
+    pub fn ascii(&self) -> bool {
+        self.ascii
+    }
+
+    pub fn ellipsis(&self) -> &'static str {
+        if self.ascii { "..." } else { "…" }
+    }
+
+    pub fn dash(&self) -> &'static str {
+        if self.ascii { "-" } else { "—" }
+    }
+
+    /// Sets a quoted message apart, like the one a reply is to.
+    pub fn quote(&self) -> &'static str {
+        if self.ascii { "|" } else { "│" }
+    }
+
+    /// Marks rooms whose name or avatar changed since they were last opened.
+    pub fn changed(&self) -> &'static str {
+        if self.ascii { "*" } else { "•" }
+    }
+
+    /// Marks verified users.
+    pub fn verified(&self) -> &'static str {
+        if self.ascii { "v" } else { "✓" }
+    }
+
+    /// Bars for sparklines, shortest first.
+    pub fn bars(&self) -> &'static [char] {
+        if self.ascii { &['.', ':', '-', '=', '+', '*', '#', '@'] } else { &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'] }
+    }
+
+    /// An emoji badge like a reaction key, as its `:shortcode:` when drawing ASCII.
+    pub fn emoji(&self, emoji: &str) -> String {
+        match composer::shortcode(emoji) {
+            Some(code) if self.ascii => format!(":{}:", code),
+            _ => emoji.to_string(),
+        }
+    }
+}
+
+/// The first of `LC_ALL`, `LC_CTYPE`, and `LANG` that's set decides the character set, like
+/// everything else that reads the locale.
+fn locale_is_utf8() -> bool {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"].iter().filter_map(|v| std::env::var(v).ok()).find(|v| !v.is_empty()).unwrap_or_default().to_lowercase();
+    locale.contains("utf-8") || locale.contains("utf8")
+}
+
+/// Redraws the box drawing characters tui puts in borders as `|`, `-`, and `+`. Rendered last.
+pub struct AsciiBorders;
+
+impl Widget for AsciiBorders {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                let cell = buf.get_mut(x, y);
+                let replacement = match cell.symbol.chars().next() {
+                    Some('│' | '┃' | '║') => "|",
+                    Some('─' | '━' | '═') => "-",
+                    Some('\u{2500}'..='\u{257f}') => "+",
+                    _ => continue,
+                };
+                cell.set_symbol(replacement);
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Background jobs that belong to a room, like loading its history, fetching link previews, or
+//! sending read markers. A room only runs one job of each kind at a time, so holding a key down
+//! doesn't stack up requests, only a few jobs run at once overall, and a room's jobs are cancelled
+//! when it's closed. Jobs only lock the app state to apply what they fetched, never while waiting
+//! on the server.
+
+use std::{collections::HashMap, future::Future, sync::Arc};
+
+use matrix_sdk::ruma::{OwnedEventId, OwnedRoomId, RoomId};
+use tokio::{sync::Semaphore, task::JoinHandle};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Job {
+    /// Loading older history, or the history missing at a gap.
+    Paginate,
+    /// Fetching the preview of a message's link.
+    Preview(OwnedEventId),
+    /// Fetching the thumbnail of an image message.
+    Image(OwnedEventId),
+    /// Sending the read marker for the room's latest message.
+    Receipt,
+}
+
+pub struct Tasks {
+    limit: Arc<Semaphore>,
+    running: HashMap<(OwnedRoomId, Job), JoinHandle<()>>,
+}
+
+impl Tasks {
+    /// Runs at most `limit` jobs at once, across every room.
+    pub fn new(limit: usize) -> Tasks {
+        Tasks {
+            limit: Arc::new(Semaphore::new(limit)),
+            running: HashMap::new(),
+        }
+    }
+
+    /// Starts a job for a room, unless the same one is still running there. Returns whether it did.
+    pub fn spawn<F>(&mut self, room: &RoomId, job: Job, future: F) -> bool
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.running.retain(|_, v| !v.is_finished());
+        let key = (room.to_owned(), job);
+        if self.running.contains_key(&key) {
+            return false;
+        }
+
+        let limit = self.limit.clone();
+        let handle = tokio::task::spawn(async move {
+            // jobs waiting for their turn are cancelled like running ones
+            let _permit = limit.acquire_owned().await;
+            future.await;
+        });
+        self.running.insert(key, handle);
+        true
+    }
+
+    /// Cancels a room's jobs, returning which ones hadn't finished.
+    pub fn cancel(&mut self, room: &RoomId) -> Vec<Job> {
+        let keys: Vec<_> = self.running.keys().filter(|(id, _)| id == room).cloned().collect();
+        let mut cancelled = vec![];
+        for key in keys {
+            let handle = self.running.remove(&key).unwrap();
+            if !handle.is_finished() {
+                handle.abort();
+                cancelled.push(key.1);
+            }
+        }
+        cancelled
+    }
+}
@@ -0,0 +1,134 @@
+//! Message layout templates such as `{time} {nick:>12} │ {content}`.
+//!
+//! A field is written as `{name}` and can be padded to a width with `{name:<N}`, `{name:>N}`,
+//! or `{name:^N}`. `{{` and `}}` produce literal braces, and newlines split the row into lines.
+//! Fields the renderer doesn't know about are kept as written.
+
+use unicode_width::UnicodeWidthStr;
+
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+enum Segment {
+    Literal(String),
+    Field {
+        name: String,
+        align: Align,
+        width: usize,
+    },
+}
+
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Template {
+        let mut segments = vec![];
+        let mut literal = String::new();
+        let mut chars = source.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+
+                '{' => {
+                    let mut field = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        field.push(c);
+                    }
+
+                    if !closed {
+                        literal.push('{');
+                        literal.push_str(&field);
+                        continue;
+                    }
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(parse_field(&field));
+                }
+
+                _ => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Template { segments }
+    }
+
+    /// Renders the template, looking up each field's value with `field`. The result is in pieces,
+    /// each paired with the name of the field it came from, if any, so fields can be styled.
+    pub fn render(&self, field: impl Fn(&str) -> Option<String>) -> Vec<(Option<&str>, String)> {
+        let mut parts = vec![];
+        for segment in self.segments.iter() {
+            match segment {
+                Segment::Literal(v) => parts.push((None, v.clone())),
+
+                Segment::Field { name, align, width } => {
+                    let value = match field(name) {
+                        Some(v) => v,
+                        None => {
+                            parts.push((None, format!("{{{}}}", name)));
+                            continue;
+                        }
+                    };
+
+                    // padded to the columns it takes, which wide characters take two of
+                    let padding = width.saturating_sub(value.width());
+                    let (left, right) = match align {
+                        Align::Left => (0, padding),
+                        Align::Right => (padding, 0),
+                        Align::Center => (padding / 2, padding - padding / 2),
+                    };
+                    let mut result = String::new();
+                    result.extend(std::iter::repeat_n(' ', left));
+                    result.push_str(&value);
+                    result.extend(std::iter::repeat_n(' ', right));
+                    parts.push((Some(name.as_str()), result));
+                }
+            }
+        }
+        parts
+    }
+}
+
+fn parse_field(field: &str) -> Segment {
+    let (name, spec) = match field.split_once(':') {
+        Some((name, spec)) => (name, spec),
+        None => (field, ""),
+    };
+
+    let (align, width) = match spec.chars().next() {
+        Some('<') => (Align::Left, &spec[1..]),
+        Some('>') => (Align::Right, &spec[1..]),
+        Some('^') => (Align::Center, &spec[1..]),
+        _ => (Align::Left, spec),
+    };
+
+    Segment::Field {
+        name: name.trim().to_string(),
+        align,
+        width: width.parse().unwrap_or(0),
+    }
+}
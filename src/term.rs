@@ -0,0 +1,55 @@
+//! Taking over the terminal and always giving it back, even when something panics.
+
+use std::{
+    io::{self, Write},
+    panic,
+};
+
+use crossterm::{
+    event::{DisableBracketedPaste, DisableFocusChange, EnableBracketedPaste, EnableFocusChange},
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+use crate::cursor;
+
+/// Where panics are written, since the terminal they'd print to is about to be cleared.
+const CRASH_FILE: &str = "crash.log";
+
+pub fn enter() -> io::Result<()> {
+    crossterm::execute!(io::stdout(), EnterAlternateScreen, EnableBracketedPaste, EnableFocusChange)?;
+    crossterm::terminal::enable_raw_mode()
+}
+
+pub fn restore() -> io::Result<()> {
+    let mut stdout = io::stdout();
+    cursor::reset(&mut stdout)?;
+    crossterm::terminal::disable_raw_mode()?;
+    crossterm::execute!(stdout, DisableBracketedPaste, DisableFocusChange, LeaveAlternateScreen)
+}
+
+/// Restores the terminal when dropped, however the UI ends.
+pub struct Guard;
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let _ = restore();
+    }
+}
+
+/// Makes any panic restore the terminal, write a crash report, and end the process. A panic in a
+/// background task would otherwise leave the UI running with nothing updating it.
+pub fn install_panic_hook() {
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = restore();
+
+        let report = format!("{}\n{}\n\n{}\n", chrono::Local::now().to_rfc3339(), info, std::backtrace::Backtrace::force_capture());
+        let written = std::fs::OpenOptions::new().create(true).append(true).open(CRASH_FILE).and_then(|mut v| v.write_all(report.as_bytes()));
+
+        default(info);
+        if written.is_ok() {
+            eprintln!("ilo-toki crashed; the details were written to {}", CRASH_FILE);
+        }
+        std::process::exit(101);
+    }));
+}
@@ -0,0 +1,18 @@
+use chrono::{Local, TimeZone};
+
+use crate::{
+    clock::{Clock, Hours},
+    config::TimeSettings,
+};
+
+#[test]
+fn clocks_follow_the_hours_and_patterns_set() {
+    let afternoon = Local.with_ymd_and_hms(2024, 3, 5, 13, 7, 9).unwrap().timestamp();
+    let clock = |hours, time_format: Option<&str>| Clock::new(&TimeSettings { hours, time_format: time_format.map(String::from), date_format: None });
+    assert_eq!(clock(Hours::TwentyFour, None).time(afternoon), "13:07");
+    assert_eq!(clock(Hours::Twelve, None).time(afternoon), "1:07 PM");
+    assert_eq!(clock(Hours::Twelve, None).precise(afternoon), "2024-03-05 1:07:09 PM");
+    assert_eq!(clock(Hours::Twelve, Some("%H.%M")).time(afternoon), "13.07");
+    // a pattern chrono can't write is left for the clock's own
+    assert_eq!(clock(Hours::TwentyFour, Some("%Q")).time(afternoon), "13:07");
+}
@@ -0,0 +1,68 @@
+use matrix_sdk::ruma::events::room::message::RoomMessageEventContent;
+
+use crate::{
+    commands::{self, Command},
+    composer::{split, too_large, transformed, Sed},
+    config::ComposerSettings,
+    html::{self, Format},
+};
+
+#[test]
+fn split_breaks_between_lines() {
+    assert_eq!(split("aaa\nbbb\nccc\n", 8), ["aaa\nbbb", "ccc"]);
+}
+
+#[test]
+fn split_breaks_long_lines_on_char_boundaries() {
+    assert_eq!(split("ééééé", 4), ["éé", "éé", "é"]);
+}
+
+#[test]
+fn encryption_counts_toward_the_size_limit() {
+    let content = RoomMessageEventContent::text_plain("a".repeat(55_000));
+    assert!(!too_large(&content, false));
+    assert!(too_large(&content, true));
+}
+
+#[test]
+fn sed_replaces_first_match_unless_global() {
+    let sed = Sed::parse("s/teh/the").unwrap();
+    assert_eq!(sed.apply("teh cat and teh dog").unwrap(), "the cat and teh dog");
+    let sed = Sed::parse("s/TEH/the/gi").unwrap();
+    assert_eq!(sed.apply("teh cat and teh dog").unwrap(), "the cat and the dog");
+    assert!(sed.apply("nothing here").is_none());
+}
+
+#[test]
+fn sed_handles_escapes_and_groups() {
+    assert_eq!(Sed::parse(r"s/a\/b/c/").unwrap().apply("a/b").unwrap(), "c");
+    assert_eq!(Sed::parse(r"s/(\w+) (\w+)/\2 \1 $5/").unwrap().apply("hello world").unwrap(), "world hello $5");
+    assert!(Sed::parse("s/only").is_none());
+    assert!(Sed::parse("s//x/").is_none());
+}
+
+#[test]
+fn color_round_trips_through_html() {
+    let (transforms, text) = match commands::parse("/color #f00 red <alert>") {
+        Some(Command::Transform(transforms, text)) => (transforms, text),
+        _ => panic!("/color should parse as a transform"),
+    };
+    let content = transformed(&text, &transforms, &ComposerSettings::default());
+    let colored = html::formatted(&content.msgtype).unwrap();
+    assert_eq!(colored, [(String::from("red <alert>"), Format::color(Some((255, 0, 0))))]);
+
+    let incoming = RoomMessageEventContent::text_html("a b", "<mx-reply>quoted</mx-reply>a <font color=\"#00ff00\">b</font>");
+    assert_eq!(html::formatted(&incoming.msgtype).unwrap(), [(String::from("a "), Format::default()), (String::from("b"), Format::color(Some((0, 255, 0))))]);
+    assert!(html::formatted(&RoomMessageEventContent::text_html("a", "<span>a</span>").msgtype).is_none());
+    assert!(commands::parse("/color red text").is_none());
+}
+
+// the stand-in checker is a unix tool, which the Windows runners don't have
+#[cfg(unix)]
+#[tokio::test]
+async fn spellcheck_reads_unknown_words() {
+    // grep stands in for `hunspell -l`, printing the "misspelled" word it finds
+    let command = [String::from("grep"), String::from("-o"), String::from("{language}")];
+    let misspelled = crate::spell::check(&command, "teh", "teh cat saw teh dog").await.unwrap();
+    assert_eq!(misspelled.into_iter().collect::<Vec<_>>(), ["teh"]);
+}
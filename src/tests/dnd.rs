@@ -0,0 +1,28 @@
+use chrono::{Duration, Local, TimeZone};
+
+use crate::{
+    config::NotificationSettings,
+    dnd::{self, DoNotDisturb},
+};
+
+#[test]
+fn dnd_lasts_its_duration_and_quiet_hours_wrap_midnight() {
+    assert_eq!(dnd::parse_duration("1h30m"), Some(Duration::minutes(90)));
+    assert_eq!(dnd::parse_duration("45"), Some(Duration::minutes(45)));
+    assert!(dnd::parse_duration("soon").is_none());
+    assert!(dnd::parse_duration("2h3").is_none());
+
+    let at = |h, m| Local.with_ymd_and_hms(2024, 3, 1, h, m, 0).unwrap();
+    let mut dnd = DoNotDisturb::new(&NotificationSettings {
+        quiet_hours: String::from("22:00-08:00"),
+        ..NotificationSettings::default()
+    });
+    assert!(dnd.active(at(23, 0)) && dnd.active(at(7, 59)));
+    assert!(!dnd.active(at(12, 0)));
+
+    dnd.start(Some(Duration::hours(1)), at(12, 0));
+    assert_eq!(dnd.status(at(12, 30)).unwrap(), "dnd until 13:00");
+    assert!(!dnd.active(at(13, 0)));
+    assert!(!dnd.stop(at(13, 0)));
+    assert_eq!(dnd.status(at(22, 30)).unwrap(), "quiet hours until 08:00");
+}
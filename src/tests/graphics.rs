@@ -0,0 +1,19 @@
+use image::{Rgb, RgbImage};
+
+use crate::graphics::{self, Protocol};
+
+#[test]
+fn graphics_protocols_come_from_the_terminal() {
+    let protocol = |vars: &[(&str, &str)]| graphics::from_env(|name| vars.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string()));
+    assert_eq!(protocol(&[("TERM", "xterm-kitty")]), Protocol::Kitty);
+    assert_eq!(protocol(&[("TERM_PROGRAM", "WezTerm")]), Protocol::Iterm);
+    assert_eq!(protocol(&[("TERM", "foot")]), Protocol::Sixel);
+    assert_eq!(protocol(&[("TERM", "xterm-256color")]), Protocol::None);
+    // tmux would swallow them
+    assert_eq!(protocol(&[("TERM", "xterm-kitty"), ("TMUX", "/tmp/tmux-1000/default")]), Protocol::None);
+
+    // red over blue: each colour gets its own pass over the band, blue's being the lower sixel
+    let sixel = graphics::sixel(&RgbImage::from_fn(2, 2, |_, y| if y == 0 { Rgb([255, 0, 0]) } else { Rgb([0, 0, 255]) }));
+    assert!(sixel.starts_with("\x1bP0;1;0q\"1;1;2;2#0;2;0;0;0"));
+    assert!(sixel.ends_with("#5AA$#180@@-\x1b\\"));
+}
@@ -0,0 +1,15 @@
+use matrix_sdk::ruma::events::room::message::RoomMessageEventContent;
+
+use crate::html;
+
+#[test]
+fn formatted_bodies_keep_their_styles() {
+    let html = "<p><strong>bold</strong> <em>it</em> <code>x</code> <a href=\"https://example.org\">site</a></p><blockquote><p>one<br>two</p></blockquote><pre><code>fn main() {}\n</code></pre>";
+    let parts = html::formatted(&RoomMessageEventContent::text_html("", html).msgtype).unwrap();
+    let text: String = parts.iter().map(|(v, _)| v.as_str()).collect();
+    assert_eq!(text, "bold it x site (https://example.org)\n> one\n> two\nfn main() {}");
+    let format = |piece: &str| parts.iter().find(|(v, _)| v == piece).map(|(_, v)| *v).unwrap();
+    assert!(format("bold").bold && format("it").italic && format("x").code && format("site").link);
+    assert!(format("> one\n> two\n").quote);
+    assert!(format("fn main() {}").code);
+}
@@ -0,0 +1,19 @@
+use crate::idle::Idle;
+
+#[test]
+fn idle_loop_slows_down_and_stops_drawing() {
+    let start = std::time::Instant::now();
+    let mut idle = Idle::new(start);
+    assert!(idle.should_draw(start));
+    idle.drawn(start);
+    assert!(idle.should_draw(start + std::time::Duration::from_secs(5)));
+
+    let later = start + std::time::Duration::from_secs(40);
+    assert!(!idle.should_draw(later));
+    assert_eq!(idle.wait(later).1, std::time::Duration::from_secs(1));
+    assert!(idle.should_draw(start + std::time::Duration::from_secs(61)));
+
+    idle.active(later);
+    assert!(idle.should_draw(later));
+    assert_eq!(idle.wait(later).1, std::time::Duration::from_millis(10));
+}
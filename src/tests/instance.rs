@@ -0,0 +1,19 @@
+use crate::instance;
+
+#[test]
+fn one_instance_per_profile() {
+    let path = std::env::temp_dir().join(format!("ilo-toki-lock-test-{}", std::process::id()));
+    let path = path.to_str().unwrap();
+    let lock = instance::lock(path).unwrap();
+    assert_eq!(instance::lock(path).err(), Some(Some(std::process::id())));
+    drop(lock);
+    assert!(!std::path::Path::new(path).exists());
+
+    // a lock left by an instance that's gone is taken over, where we can tell it's gone
+    if cfg!(unix) {
+        std::fs::write(path, "999999999").unwrap();
+        let lock = instance::lock(path).unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), std::process::id().to_string());
+        drop(lock);
+    }
+}
@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+
+use crate::{commands, irc, migrate};
+
+#[test]
+fn irc_aliases_and_highlights_import() {
+    let path = std::env::temp_dir().join("ilo-toki-irssi-test");
+    std::fs::write(&path, r##"
+aliases = { W = "whois $*"; J = "join"; TR = "/translate"; };
+# a comment
+hilights = ( { text = "ilo"; nick = "yes"; }, { text = "^toki"; regexp = "yes"; }, { text = "pona"; channels = ( "#a" ); } );
+settings = { "fe-common/core" = { theme = "madcow"; }; };
+"##).unwrap();
+    let imported = migrate::irssi(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(imported.highlights, ["ilo", "/^toki/"]);
+    assert_eq!(imported.aliases, [(String::from("w"), String::from("/whois $*")), (String::from("tr"), String::from("/translate"))]);
+    assert_eq!(imported.skipped, ["hilight pona (only in some channels)", "alias J = join", "theme madcow"]);
+
+    let aliases: HashMap<_, _> = imported.aliases.into_iter().collect();
+    assert_eq!(commands::expand_alias("/w @alice:example.org", &aliases).unwrap(), "/whois @alice:example.org");
+    assert_eq!(commands::expand_alias("/tr", &aliases).unwrap(), "/translate");
+    assert!(commands::expand_alias("/quit", &aliases).is_none());
+}
+
+#[test]
+fn mirc_codes_color_and_strip() {
+    let body = "\x02bold\x02 \x0304,01red\x0f plain \x0312blue\x03 \x041b2b3chex\x0f 1,2";
+    assert_eq!(irc::strip(body), "bold red plain blue hex 1,2");
+    assert_eq!(irc::colored(body).unwrap(), [
+        (String::from("bold "), None),
+        (String::from("red"), Some((255, 0, 0))),
+        (String::from(" plain "), None),
+        (String::from("blue"), Some((0, 0, 252))),
+        (String::from(" "), None),
+        (String::from("hex"), Some((0x1b, 0x2b, 0x3c))),
+        (String::from(" 1,2"), None),
+    ]);
+    assert!(irc::colored("\x02just bold").is_none());
+}
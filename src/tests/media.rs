@@ -0,0 +1,28 @@
+use matrix_sdk::ruma::events::room::message::RoomMessageEventContent;
+use serde_json::json;
+
+use crate::media;
+
+#[test]
+fn attachments_go_by_their_file_name() {
+    let content = json!({ "msgtype": "m.file", "body": "the report", "filename": "report.pdf", "url": "mxc://example.org/abc", "info": { "size": 2500 } });
+    let file = serde_json::from_value::<RoomMessageEventContent>(content).unwrap().msgtype;
+    assert_eq!(media::name(&file).as_deref(), Some("report.pdf"));
+    assert_eq!(media::summary(&file).as_deref(), Some("[file: report.pdf] 2.5 KB"));
+}
+
+#[test]
+fn downloads_never_overwrite() {
+    let dir = std::env::temp_dir().join(format!("ilo-toki-download-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("report.pdf"), "mine").unwrap();
+
+    let (path, _) = media::create_new(&dir, "report.pdf".as_ref()).unwrap();
+    assert_eq!(path, dir.join("report (1).pdf"));
+    let (path, _) = media::create_new(&dir, "report.pdf".as_ref()).unwrap();
+    assert_eq!(path, dir.join("report (2).pdf"));
+    let (path, _) = media::create_new(&dir, "notes".as_ref()).unwrap();
+    assert_eq!(path, dir.join("notes"));
+    assert_eq!(std::fs::read_to_string(dir.join("report.pdf")).unwrap(), "mine");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,115 @@
+//! A homeserver that answers with canned JSON, so the real client can be driven without a network.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// A request the server received.
+pub struct Request {
+    pub method: String,
+    /// The path, with the query string.
+    pub path: String,
+}
+
+#[derive(Default)]
+struct Routes {
+    /// Responses for requests whose path ends with the key. Each is used once, except the last.
+    responses: HashMap<(String, String), Vec<Value>>,
+    requests: Vec<Request>,
+}
+
+pub struct MockServer {
+    url: String,
+    routes: Arc<Mutex<Routes>>,
+}
+
+impl MockServer {
+    pub async fn start() -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let routes = Arc::new(Mutex::new(Routes::default()));
+
+        let server = MockServer { url, routes: routes.clone() };
+        server.on("GET", "/versions", json!({ "versions": ["r0.6.1", "v1.1", "v1.2"] }));
+        server.on("POST", "/keys/upload", json!({ "one_time_key_counts": {} }));
+        server.on("POST", "/keys/query", json!({ "device_keys": {}, "failures": {} }));
+
+        tokio::task::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::task::spawn(serve(stream, routes.clone()));
+            }
+        });
+        server
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Answers requests whose path ends with `path` with `body`, after any answers already queued.
+    pub fn on(&self, method: &str, path: &str, body: Value) {
+        let mut routes = self.routes.lock().unwrap();
+        routes.responses.entry((method.to_string(), path.to_string())).or_default().push(body);
+    }
+
+    /// The requests whose path (without the query) ends with `path`, oldest first.
+    pub fn requests(&self, path: &str) -> Vec<String> {
+        let routes = self.routes.lock().unwrap();
+        routes.requests.iter().filter(|v| v.path.split('?').next().unwrap().ends_with(path)).map(|v| format!("{} {}", v.method, v.path)).collect()
+    }
+}
+
+async fn serve(mut stream: TcpStream, routes: Arc<Mutex<Routes>>) {
+    let mut data = vec![];
+    let mut buffer = [0; 4096];
+    let header_end = loop {
+        match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => data.extend_from_slice(&buffer[..n]),
+        }
+        if let Some(i) = data.windows(4).position(|v| v == b"\r\n\r\n") {
+            break i + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&data[..header_end]).to_string();
+    let length = head.lines().filter_map(|v| v.split_once(':')).find(|(k, _)| k.eq_ignore_ascii_case("content-length")).and_then(|(_, v)| v.trim().parse().ok()).unwrap_or(0);
+    while data.len() < header_end + length {
+        match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => data.extend_from_slice(&buffer[..n]),
+        }
+    }
+
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+
+    let response = {
+        let mut routes = routes.lock().unwrap();
+        let bare = path.split('?').next().unwrap().to_string();
+        let key = routes.responses.keys().filter(|(m, p)| *m == method && bare.ends_with(p.as_str())).max_by_key(|(_, p)| p.len()).cloned();
+        let response = key.map(|key| {
+            let queued = routes.responses.get_mut(&key).unwrap();
+            if queued.len() > 1 { queued.remove(0) } else { queued[0].clone() }
+        });
+        routes.requests.push(Request { method, path });
+        response
+    };
+
+    let (status, body) = match response {
+        Some(v) => ("200 OK", v),
+        None => ("404 Not Found", json!({ "errcode": "M_UNRECOGNIZED", "error": "not mocked" })),
+    };
+    let body = body.to_string();
+    let response = format!("HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
@@ -0,0 +1,139 @@
+//! Tests that drive the client against a mock homeserver, and tests of the modules that work on their own, each in a file named for what it covers.
+
+mod clock;
+mod composer;
+mod dnd;
+mod graphics;
+mod html;
+mod idle;
+mod instance;
+mod irc;
+mod media;
+mod mock;
+mod names;
+mod picker;
+mod render;
+mod search;
+mod tasks;
+mod template;
+mod timeline;
+mod trust;
+mod update;
+
+use std::sync::Arc;
+
+use matrix_sdk::{config::SyncSettings, reqwest::Url, ruma::UserId, Client, Session};
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+
+use crate::{add_event_handlers, config::Config, handle_gaps, load_rooms, new_state, server::ServerFeatures, AppState};
+use mock::MockServer;
+
+pub const ROOM: &str = "!room:example.org";
+pub const ME: &str = "@me:example.org";
+pub const ALICE: &str = "@alice:example.org";
+
+/// A client logged in to the mock server, with the same event handlers as the app.
+pub async fn app(server: &MockServer) -> Arc<Mutex<AppState>> {
+    let client = Client::new(Url::parse(server.url()).unwrap()).await.unwrap();
+    client
+        .restore_login(Session {
+            user_id: UserId::parse(ME).unwrap(),
+            access_token: String::from("token"),
+            device_id: "DEVICE".into(),
+            refresh_token: None,
+        })
+        .await
+        .unwrap();
+
+    let state = Arc::new(Mutex::new(new_state(Arc::new(client), Config::default(), ServerFeatures::default(), String::new(), false)));
+    add_event_handlers(&state).await;
+    state
+}
+
+/// Syncs once, like startup does, recording any gaps like the sync loop does.
+pub async fn sync(state: &Arc<Mutex<AppState>>) {
+    let client = state.lock().await.client.clone();
+    let response = client.sync_once(SyncSettings::default()).await.unwrap();
+    load_rooms(state).await;
+    handle_gaps(&response, &mut state.lock().await);
+}
+
+/// A sync response with the given timeline for the test room.
+pub fn sync_response(next_batch: &str, events: Vec<Value>, limited: bool, prev_batch: &str) -> Value {
+    json!({
+        "next_batch": next_batch,
+        "rooms": {
+            "join": {
+                ROOM: {
+                    "state": {
+                        "events": [
+                            state_event("m.room.create", "", json!({ "creator": ALICE })),
+                            state_event("m.room.name", "", json!({ "name": "Test room" })),
+                            state_event("m.room.member", ME, json!({ "membership": "join" })),
+                            state_event("m.room.member", ALICE, json!({ "membership": "join" })),
+                        ],
+                    },
+                    "timeline": { "events": events, "limited": limited, "prev_batch": prev_batch },
+                },
+            },
+        },
+    })
+}
+
+fn state_event(event_type: &str, state_key: &str, content: Value) -> Value {
+    json!({
+        "type": event_type,
+        "state_key": state_key,
+        "sender": ALICE,
+        "content": content,
+        "event_id": format!("${}{}", event_type, state_key),
+        "origin_server_ts": 1,
+    })
+}
+
+/// A text message from alice. `ts` is in seconds.
+pub fn message(id: &str, body: &str, ts: u64) -> Value {
+    // the room id is only needed by pages from `/messages`, and sync doesn't mind it
+    json!({
+        "type": "m.room.message",
+        "room_id": ROOM,
+        "sender": ALICE,
+        "event_id": id,
+        "origin_server_ts": ts * 1000,
+        "content": { "msgtype": "m.text", "body": body },
+    })
+}
+
+/// An edit from alice replacing the body of `original`.
+pub fn edit(id: &str, original: &str, body: &str, ts: u64) -> Value {
+    json!({
+        "type": "m.room.message",
+        "room_id": ROOM,
+        "sender": ALICE,
+        "event_id": id,
+        "origin_server_ts": ts * 1000,
+        "content": {
+            "msgtype": "m.text",
+            "body": format!("* {}", body),
+            "m.new_content": { "msgtype": "m.text", "body": body },
+            "m.relates_to": { "rel_type": "m.replace", "event_id": original },
+        },
+    })
+}
+
+/// A `/messages` page, newest first. No `end` means the start of the room was reached.
+pub fn messages_response(start: &str, end: Option<&str>, chunk: Vec<Value>) -> Value {
+    let mut response = json!({ "start": start, "chunk": chunk });
+    if let Some(end) = end {
+        response["end"] = json!(end);
+    }
+    response
+}
+
+/// The bodies of the test room's messages, oldest first.
+pub async fn bodies(state: &Arc<Mutex<AppState>>) -> Vec<String> {
+    let state = state.lock().await;
+    let channel = &state.channels[&matrix_sdk::ruma::OwnedRoomId::try_from(ROOM).unwrap()];
+    channel.message_ids.iter().map(|v| channel.messages[v].content.clone()).collect()
+}
@@ -0,0 +1,28 @@
+use matrix_sdk::ruma::{serde::Raw, OwnedRoomId};
+use serde_json::json;
+
+use crate::names::Names;
+
+#[test]
+fn saved_names_last_until_their_state_changes() {
+    let room = OwnedRoomId::try_from("!room:example.org").unwrap();
+    let state = |event_type: &str, state_key: &str, id: &str, content: serde_json::Value| {
+        let event = json!({ "type": event_type, "state_key": state_key, "event_id": id, "sender": "@alice:example.org", "origin_server_ts": 0, "content": content });
+        Raw::from_json(serde_json::value::to_raw_value(&event).unwrap())
+    };
+    let mut names = Names::default();
+    names.update(&room, &state("m.room.name", "", "$n1", json!({ "name": "pona" })));
+    assert_eq!(names.name(&room), None);
+    names.set_name(&room, "pona");
+
+    // the same events again, like every first sync sends, keep it, and so do members of named rooms
+    assert!(!names.update(&room, &state("m.room.name", "", "$n1", json!({ "name": "pona" }))));
+    assert!(!names.update(&room, &state("m.room.member", "@alice:example.org", "$m1", json!({ "membership": "join", "displayname": "Alice" }))));
+    assert_eq!(names.name(&room), Some("pona"));
+    assert_eq!(names.member(&room, "@alice:example.org"), Some("Alice"));
+
+    assert!(names.update(&room, &state("m.room.avatar", "", "$a1", json!({ "url": "mxc://example.org/a" }))));
+    assert_eq!(names.avatar(&room), Some("mxc://example.org/a"));
+    assert!(names.update(&room, &state("m.room.name", "", "$n2", json!({ "name": "pali" }))));
+    assert_eq!(names.name(&room), None);
+}
@@ -0,0 +1,24 @@
+use crossterm::event::{KeyCode, KeyEvent};
+
+use crate::picker::{self, Picker};
+
+#[test]
+fn file_picker_walks_folders_and_picks_files() {
+    let dir = std::env::temp_dir().join(format!("ilo-toki-picker-test-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("photos")).unwrap();
+    std::fs::write(dir.join("photos").join("cat.png"), "").unwrap();
+    std::fs::write(dir.join("notes.txt"), "").unwrap();
+    std::fs::write(dir.join(".hidden"), "").unwrap();
+
+    let mut picker = Picker::new(&dir);
+    // folders come first and hidden files are left out
+    assert_eq!(picker.lines()[2..4], ["> photos/", "  notes.txt"]);
+    assert!(matches!(picker.key(KeyEvent::from(KeyCode::Enter)), picker::Pick::Open));
+    assert!(matches!(picker.key(KeyEvent::from(KeyCode::Enter)), picker::Pick::Chosen(v) if v == dir.join("photos").join("cat.png")));
+    picker.key(KeyEvent::from(KeyCode::Backspace));
+    assert_eq!(picker.lines()[2], "> photos/");
+    picker.key(KeyEvent::from(KeyCode::Down));
+    assert!(matches!(picker.key(KeyEvent::from(KeyCode::Right)), picker::Pick::Open));
+    assert!(matches!(picker.key(KeyEvent::from(KeyCode::Esc)), picker::Pick::Cancelled));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,250 @@
+//! Snapshots of whole frames. Set `UPDATE_SNAPSHOTS=1` to write them for a new test, or rewrite them
+//! after an intended change.
+
+use std::{path::PathBuf, sync::Arc};
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use matrix_sdk::ruma::{OwnedEventId, OwnedRoomId};
+use serde_json::json;
+use tokio::sync::Mutex;
+use tui::{backend::TestBackend, buffer::Buffer, layout::Rect, text::Spans, widgets::Widget, Terminal};
+use unicode_width::UnicodeWidthStr;
+
+use super::{edit, message, mock::MockServer, sync, sync_response, ALICE, ROOM};
+use crate::{
+    config::WorkspaceConfig,
+    symbols::{Profile, Symbols},
+    viewport::View,
+    handle_event, ui, AppState, CodeBlock, Mode, Reaction, SecretPrompt, SecretPurpose,
+};
+
+/// Draws a frame and compares it with `snapshots/<name>.txt`.
+fn assert_snapshot(name: &str, state: &AppState, width: u16, height: u16) {
+    let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+    terminal.draw(|f| ui::draw(f, state)).unwrap();
+
+    let buffer = terminal.backend().buffer();
+    let mut frame = String::new();
+    for y in 0..height {
+        let mut line = String::new();
+        let mut x = 0;
+        while x < width {
+            // a wide character covers the cells after it, which hold padding
+            let symbol = &buffer.get(x, y).symbol;
+            line.push_str(symbol);
+            x += symbol.width().max(1) as u16;
+        }
+        frame.push_str(line.trim_end());
+        frame.push('\n');
+    }
+    let (x, y) = terminal.get_cursor().unwrap();
+    frame.push_str(&format!("cursor: {}, {}\n", x, y));
+
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/snapshots").join(format!("{}.txt", name));
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, &frame).unwrap();
+        return;
+    }
+    // a missing snapshot fails rather than being written, so CI can't pass by creating it
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| panic!("{} has no snapshot; run with UPDATE_SNAPSHOTS=1 to write it:\n{}", name, frame));
+    assert!(expected == frame, "{} changed:\n--- expected\n{}--- actual\n{}", name, expected, frame);
+}
+
+fn room_id() -> OwnedRoomId {
+    OwnedRoomId::try_from(ROOM).unwrap()
+}
+
+/// An app synced with a few messages: one edited, one a reply, and one with reactions.
+async fn app(server: &MockServer) -> Arc<Mutex<AppState>> {
+    let reply = json!({
+        "type": "m.room.message",
+        "room_id": ROOM,
+        "sender": ALICE,
+        "event_id": "$c",
+        "origin_server_ts": 30000,
+        "content": {
+            "msgtype": "m.text",
+            "body": "> <@alice:example.org> hello\n\nreplying",
+            "m.relates_to": { "m.in_reply_to": { "event_id": "$a" } },
+        },
+    });
+    let events = vec![message("$a", "hello", 10), message("$b", "tpyo", 20), edit("$e", "$b", "typo", 21), reply];
+    server.on("GET", "/sync", sync_response("s1", events, false, "p1"));
+
+    let state = super::app(server).await;
+    sync(&state).await;
+
+    let mut lock = state.lock().await;
+    lock.symbols = Symbols::new(Profile::Unicode);
+    let channel = lock.channels.get_mut(&room_id()).unwrap();
+    let message = channel.messages.get_mut(&OwnedEventId::try_from("$a").unwrap()).unwrap();
+    for (id, key) in [("$r1", "👍"), ("$r2", "👍"), ("$r3", "🎉")] {
+        message.reactions.push(Reaction {
+            id: OwnedEventId::try_from(id).unwrap(),
+            key: String::from(key),
+            sender: String::from(ALICE),
+        });
+    }
+    drop(lock);
+    state
+}
+
+#[tokio::test]
+async fn channel_list() {
+    let server = MockServer::start().await;
+    let state = app(&server).await;
+    let mut state = state.lock().await;
+    state.mode = Mode::SelectChannel;
+    state.channels_state.select(Some(0));
+
+    assert_snapshot("channel_list", &state, 60, 12);
+}
+
+#[tokio::test]
+async fn channel_list_previews() {
+    let server = MockServer::start().await;
+    let state = app(&server).await;
+    let mut state = state.lock().await;
+    state.config.sidebar.previews = true;
+    state.mode = Mode::SelectChannel;
+    state.channels_state.select(Some(0));
+
+    assert_snapshot("channel_list_previews", &state, 60, 12);
+}
+
+#[tokio::test]
+async fn channel_list_workspaces() {
+    let server = MockServer::start().await;
+    let state = app(&server).await;
+    let mut lock = state.lock().await;
+    lock.config.workspaces = vec![
+        WorkspaceConfig { name: String::from("pona"), rooms: vec![String::from(ROOM)] },
+        WorkspaceConfig { name: String::from("work"), rooms: vec![String::from("!elsewhere:example.org")] },
+    ];
+    lock.mode = Mode::SelectChannel;
+    let w = Event::Key(KeyEvent::new(KeyCode::Char('W'), KeyModifiers::NONE));
+    handle_event(state.clone(), &mut lock, w.clone()).await;
+    assert_snapshot("channel_list_workspaces", &lock, 60, 12);
+
+    handle_event(state.clone(), &mut lock, w.clone()).await;
+    assert!(lock.sidebar_ids().is_empty());
+    handle_event(state.clone(), &mut lock, w).await;
+    assert_eq!(lock.sidebar_ids(), vec![room_id()]);
+}
+
+#[tokio::test]
+async fn message_list() {
+    let server = MockServer::start().await;
+    let state = app(&server).await;
+    let mut state = state.lock().await;
+    state.current_channel = Some(room_id());
+    state.mode = Mode::ScrollMessages;
+    state.messages_state.select(Some(1));
+
+    assert_snapshot("message_list", &state, 60, 16);
+}
+
+/// Scrolling moves by lines, and items arriving below the selection don't move what's on screen.
+#[tokio::test]
+async fn viewport_keeps_its_place() {
+    let server = MockServer::start().await;
+    let events = (1..=5).map(|i| message(&format!("${}", i), &i.to_string(), i * 10)).collect();
+    server.on("GET", "/sync", sync_response("s1", events, false, "p1"));
+    server.on("GET", "/sync", sync_response("s2", vec![message("$6", "6", 60)], false, "p2"));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    let draw = |state: &AppState| {
+        let channel = &state.channels[&room_id()];
+        let count = channel.message_ids.len();
+        // messages are counted from the newest, and keyed by id so they're followed as newer ones arrive
+        let id = |i: usize| &channel.message_ids[count - i - 1];
+        let lines = |i: usize| {
+            let body = &channel.messages[id(i)].content;
+            vec![Spans::from(format!("{}a", body)), Spans::from(format!("{}b", body))]
+        };
+        let mut buffer = Buffer::empty(Rect::new(0, 0, 6, 3));
+        View::new(&state.messages_state, count, |i| id(i).to_string(), lines).highlight_symbol("> ").render(buffer.area, &mut buffer);
+        (0..3).map(|y| (0..6).map(|x| buffer.get(x, y).symbol.as_str()).collect::<String>().trim_end().to_string()).collect::<Vec<_>>()
+    };
+
+    let mut lock = state.lock().await;
+    lock.current_channel = Some(room_id());
+    assert_eq!(draw(&lock), ["4b", "5a", "5b"]);
+    lock.messages_state.select(Some(3));
+    assert_eq!(draw(&lock), ["> 2a", "  2b", "  3a"]);
+    drop(lock);
+
+    // a message arriving below the selection moves it up to stay on the same one
+    sync(&state).await;
+    let mut lock = state.lock().await;
+    assert_eq!(lock.messages_state.selected(), Some(4));
+    assert_eq!(draw(&lock), ["> 2a", "  2b", "  3a"]);
+
+    lock.messages_state.select(Some(5));
+    assert_eq!(draw(&lock), ["> 1a", "  1b", "  2a"]);
+    lock.messages_state.select(Some(0));
+    assert_eq!(draw(&lock), ["  5b", "> 6a", "  6b"]);
+    lock.messages_state.select(None);
+    assert_eq!(draw(&lock), ["5b", "6a", "6b"]);
+}
+
+#[tokio::test]
+async fn composer_wraps_wide_text() {
+    let server = MockServer::start().await;
+    let state = app(&server).await;
+    let mut state = state.lock().await;
+    state.current_channel = Some(room_id());
+    state.mode = Mode::Insert;
+    state.input_text = String::from("a long message with wide 🎉🎉 characters that wraps");
+    state.input_char_pos = 10;
+    state.input_byte_pos = 10;
+
+    assert_snapshot("composer_wraps_wide_text", &state, 50, 14);
+}
+
+#[tokio::test]
+async fn composer_code_block() {
+    let server = MockServer::start().await;
+    let state = app(&server).await;
+    let mut state = state.lock().await;
+    state.mode = Mode::Insert;
+    state.code_block = Some(CodeBlock { language: Some(String::from("rust")) });
+    state.input_text = String::from("fn main() {\n}");
+    state.input_char_pos = state.input_text.chars().count();
+    state.input_byte_pos = state.input_text.len();
+
+    assert_snapshot("composer_code_block", &state, 50, 12);
+}
+
+#[tokio::test]
+async fn composer_secret_is_masked() {
+    let server = MockServer::start().await;
+    let state = app(&server).await;
+    let mut state = state.lock().await;
+    state.mode = Mode::Insert;
+    state.secret = Some(SecretPrompt {
+        title: String::from("export passphrase"),
+        purpose: SecretPurpose::ExportKeys(PathBuf::from("keys.txt")),
+    });
+    state.input_text = String::from("hunter2");
+    state.input_char_pos = 7;
+    state.input_byte_pos = 7;
+
+    assert_snapshot("composer_secret_is_masked", &state, 50, 12);
+}
+
+#[tokio::test]
+async fn command_palette_search() {
+    let server = MockServer::start().await;
+    let state = app(&server).await;
+    let mut lock = state.lock().await;
+    lock.mode = Mode::ScrollMessages;
+    let keys = std::iter::once(KeyEvent::new(KeyCode::Char('p'), KeyModifiers::CONTROL)).chain("quote".chars().map(|c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)));
+    for key in keys {
+        handle_event(state.clone(), &mut lock, Event::Key(key)).await;
+    }
+
+    assert_snapshot("command_palette_search", &lock, 60, 12);
+}
@@ -0,0 +1,43 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use matrix_sdk::ruma::{OwnedEventId, OwnedRoomId, UInt};
+
+use crate::search::{self, Index, Search};
+
+#[test]
+fn search_finds_messages_as_typing_pauses() {
+    let room = OwnedRoomId::try_from("!room:example.org").unwrap();
+    let id = |v: &str| OwnedEventId::try_from(v).unwrap();
+    let mut index = Index::default();
+    index.add(&room, &id("$a"), UInt::from(1u32), "@alice:example.org", "Toki pona li pona");
+    index.add(&room, &id("$b"), UInt::from(2u32), "@alice:example.org", "mi moku e kili");
+    index.add(&room, &id("$c"), UInt::from(3u32), "@alice:example.org", "pona pa pona");
+
+    let found = |index: &Index, query: &str| index.search(query).into_iter().map(|(_, v)| v.to_string()).collect::<Vec<_>>();
+    assert_eq!(found(&index, "PONA"), ["$c", "$a"]);
+    // every trigram of "pona pona" is in $c, but not in that order
+    assert!(found(&index, "pona pona").is_empty());
+    assert_eq!(found(&index, "e"), ["$b"]);
+
+    index.add(&room, &id("$a"), UInt::from(1u32), "@alice:example.org", "toki!");
+    index.remove(&id("$c"));
+    assert!(found(&index, "pona").is_empty());
+
+    // what's saved is indexed again next run
+    let path = std::env::temp_dir().join(format!("ilo-toki-search-test-{}", std::process::id()));
+    let path = path.to_str().unwrap();
+    index.save(path);
+    let index = Index::load(path);
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(found(&index, "KILI"), ["$b"]);
+    assert_eq!(index.get(&id("$a")).unwrap().content, "toki!");
+
+    let start = std::time::Instant::now();
+    let mut search = Search::new("kil", &index);
+    assert!(search.lines(|_, v| Some(v.to_string())).contains(&String::from("> $b")));
+    search.key(KeyEvent::from(KeyCode::Backspace), start);
+    search.key(KeyEvent::from(KeyCode::Backspace), start);
+    assert!(!search.update(&index, start + search::DEBOUNCE / 2));
+    assert!(search.update(&index, start + search::DEBOUNCE));
+    assert!(search.lines(|_, v| Some(v.to_string())).contains(&String::from("  $a")));
+    assert!(!search.update(&index, start + search::DEBOUNCE * 2));
+}
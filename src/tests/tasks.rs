@@ -0,0 +1,32 @@
+use matrix_sdk::ruma::{OwnedEventId, OwnedRoomId};
+
+use crate::tasks::{Job, Tasks};
+
+#[tokio::test]
+async fn room_jobs_run_one_at_a_time_and_stop_when_closed() {
+    let room = OwnedRoomId::try_from("!room:example.org").unwrap();
+    let other = OwnedRoomId::try_from("!other:example.org").unwrap();
+    let preview = Job::Preview(OwnedEventId::try_from("$a").unwrap());
+    let (finished, mut done) = tokio::sync::mpsc::unbounded_channel();
+    let mut tasks = Tasks::new(1);
+
+    assert!(tasks.spawn(&room, Job::Paginate, std::future::pending()));
+    assert!(!tasks.spawn(&room, Job::Paginate, async {}));
+    // only one job runs at once, so this waits behind the first
+    let sender = finished.clone();
+    assert!(tasks.spawn(&room, preview.clone(), async move {
+        let _ = sender.send(());
+    }));
+    assert!(tasks.spawn(&other, Job::Paginate, async move {
+        let _ = finished.send(());
+    }));
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert!(done.try_recv().is_err());
+
+    let mut cancelled = tasks.cancel(&room);
+    cancelled.sort_by_key(|v| v != &Job::Paginate);
+    assert_eq!(cancelled, [Job::Paginate, preview]);
+    assert!(tasks.spawn(&room, Job::Paginate, async {}));
+    // the other room's job gets the freed slot
+    assert!(tokio::time::timeout(std::time::Duration::from_secs(1), done.recv()).await.unwrap().is_some());
+}
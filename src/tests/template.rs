@@ -0,0 +1,28 @@
+use crate::template::Template;
+
+fn render(source: &str) -> String {
+    let field = |name: &str| match name {
+        "nick" => Some(String::from("alice")),
+        "wide" => Some(String::from("ミク")),
+        _ => None,
+    };
+    Template::parse(source).render(field).into_iter().map(|(_, v)| v).collect()
+}
+
+#[test]
+fn fields_are_padded_to_their_width() {
+    assert_eq!(render("[{nick:>7}]"), "[  alice]");
+    assert_eq!(render("[{nick:<7}]"), "[alice  ]");
+    assert_eq!(render("[{nick:^8}]"), "[ alice  ]");
+    assert_eq!(render("[{nick:3}]"), "[alice]");
+    // wide characters take two columns each
+    assert_eq!(render("[{wide:>6}]"), "[  ミク]");
+}
+
+#[test]
+fn braces_escape_and_unknown_fields_stay() {
+    assert_eq!(render("{{nick}} {nick}"), "{nick} alice");
+    assert_eq!(render("{missing} {nick"), "{missing} {nick");
+    let parts = Template::parse("{nick}: {missing}").render(|name| (name == "nick").then(|| String::from("alice")));
+    assert_eq!(parts, [(Some("nick"), String::from("alice")), (None, String::from(": ")), (None, String::from("{missing}"))]);
+}
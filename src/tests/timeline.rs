@@ -0,0 +1,867 @@
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use matrix_sdk::{
+    config::SyncSettings,
+    ruma::{
+        events::{
+            room::message::{Relation, RoomMessageEventContent},
+            AnyMessageLikeEventContent,
+        },
+        OwnedEventId, OwnedRoomId,
+    },
+};
+use serde_json::json;
+
+use super::{bodies, edit, message, messages_response, mock::MockServer, state_event, sync, sync_response, ALICE, ME, ROOM};
+use crate::{
+    backfill_on_open, call,
+    config::{CallSettings, Config, FilterSettings, ModerationSettings, RoomConfig, WebhookConfig},
+    fill_gap, filter::Filters, handle_event, handle_joined, handle_left, highlight::Highlights, import_history, jump_to_time, load_older, load_policy_rules, mention_count, open_channel,
+    policy::Policies, quote_selection,
+    reducer::{self, AppEvent},
+    resume::Resume,
+    server::ServerFeatures,
+    request_previews, restore_room, send_receipt, submit_input, timeline, users, webhook, widget, AppState, Mode, Reaction, TimelineItem,
+};
+
+fn room_id() -> OwnedRoomId {
+    OwnedRoomId::try_from(ROOM).unwrap()
+}
+
+fn event_id(id: &str) -> OwnedEventId {
+    OwnedEventId::try_from(id).unwrap()
+}
+
+#[tokio::test]
+async fn sync_keeps_stream_order() {
+    let server = MockServer::start().await;
+    // timestamps disagree with the stream order, which should win
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "first", 30), message("$b", "second", 10), message("$c", "third", 20)], false, "p1"));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    assert_eq!(bodies(&state).await, ["first", "second", "third"]);
+}
+
+#[tokio::test]
+async fn edit_replaces_content() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "typo", 10), edit("$e", "$a", "fixed", 11)], false, "p1"));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    assert_eq!(bodies(&state).await, ["fixed"]);
+    let state = state.lock().await;
+    assert_eq!(state.channels[&room_id()].messages[&event_id("$a")].edited.map(u64::from), Some(11000));
+}
+
+#[tokio::test]
+async fn newest_edit_wins() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "one", 10), edit("$e2", "$a", "three", 12), edit("$e1", "$a", "two", 11)], false, "p1"));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    assert_eq!(bodies(&state).await, ["three"]);
+}
+
+#[tokio::test]
+async fn edits_from_anyone_else_are_ignored() {
+    let server = MockServer::start().await;
+    let forged = |id: &str, original: &str, ts| {
+        let mut forged = edit(id, original, "forged", ts);
+        forged["sender"] = json!("@mallory:example.org");
+        forged
+    };
+    let events = vec![message("$a", "one", 10), forged("$f1", "$a", 11), forged("$f2", "$b", 30), edit("$e", "$b", "fixed", 20)];
+    server.on("GET", "/sync", sync_response("s1", events, false, "p1"));
+    server.on("GET", "/messages", messages_response("p1", None, vec![message("$b", "typo", 5)]));
+    let state = super::app(&server).await;
+    sync(&state).await;
+    assert_eq!(bodies(&state).await, ["one"]);
+
+    // the forged edit of $b is newer, but it's the one from $b's sender that's kept for it
+    load_older(state.clone(), &room_id()).await;
+    assert_eq!(bodies(&state).await, ["fixed", "one"]);
+}
+
+#[tokio::test]
+async fn edit_applies_once_original_is_paged_in() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![edit("$e", "$a", "fixed", 20), message("$b", "later", 21)], false, "p1"));
+    server.on("GET", "/messages", messages_response("p1", None, vec![message("$a", "typo", 10)]));
+    let state = super::app(&server).await;
+    sync(&state).await;
+    assert_eq!(bodies(&state).await, ["later"]);
+
+    load_older(state.clone(), &room_id()).await;
+    assert_eq!(bodies(&state).await, ["fixed", "later"]);
+}
+
+#[tokio::test]
+async fn pagination_prepends_until_the_top() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![message("$c", "c", 30)], false, "p1"));
+    server.on("GET", "/messages", messages_response("s1", Some("t1"), vec![message("$b", "b", 20)]));
+    server.on("GET", "/messages", messages_response("t1", None, vec![message("$a", "a", 10)]));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    for _ in 0..3 {
+        load_older(state.clone(), &room_id()).await;
+    }
+
+    assert_eq!(bodies(&state).await, ["a", "b", "c"]);
+    assert!(state.lock().await.channels[&room_id()].at_top);
+    // the first page starts at the sync token, the next where it ended, and nothing is asked once at the top
+    let requests = server.requests("/messages");
+    assert_eq!(requests.len(), 2);
+    assert!(requests[0].contains("from=s1"));
+    assert!(requests[1].contains("from=t1"));
+}
+
+#[tokio::test]
+async fn gap_is_filled_in_order() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "a", 10)], false, "p1"));
+    server.on("GET", "/sync", sync_response("s2", vec![message("$d", "d", 40)], true, "p2"));
+    server.on("GET", "/messages", messages_response("p2", Some("p3"), vec![message("$c", "c", 30), message("$b", "b", 20)]));
+    server.on("GET", "/messages", messages_response("p3", Some("p4"), vec![message("$b", "b", 20), message("$a", "a", 10)]));
+    let state = super::app(&server).await;
+    sync(&state).await;
+    sync(&state).await;
+
+    let gap = state.lock().await.channels[&room_id()].gaps.get(&event_id("$d")).cloned();
+    assert_eq!(gap.as_deref(), Some("p2"));
+
+    let room = state.lock().await.channels[&room_id()].room.clone();
+    fill_gap(state.clone(), room.clone(), event_id("$d"), String::from("p2")).await;
+    // the rest of the gap moves up to the oldest message loaded
+    assert_eq!(state.lock().await.channels[&room_id()].gaps.get(&event_id("$b")).map(|v| v.as_str()), Some("p3"));
+
+    fill_gap(state.clone(), room, event_id("$b"), String::from("p3")).await;
+    // reaching a message we already have closes the gap
+    assert!(state.lock().await.channels[&room_id()].gaps.is_empty());
+
+    assert_eq!(bodies(&state).await, ["a", "b", "c", "d"]);
+}
+
+#[tokio::test]
+async fn import_prepends_older_history() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![message("$c", "c", 30)], false, "p1"));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    let export = json!({
+        "room_name": "Test room",
+        "messages": [message("$a", "a", 10), message("$b", "b", 20), message("$c", "c", 30), message("$d", "d", 40)],
+    });
+    let path = std::env::temp_dir().join("ilo-toki-import-test.json");
+    std::fs::write(&path, export.to_string()).unwrap();
+
+    let mut lock = state.lock().await;
+    lock.current_channel = Some(room_id());
+    let popup = import_history(&mut lock, &path).unwrap();
+    drop(lock);
+    std::fs::remove_file(&path).unwrap();
+
+    // messages after the oldest one loaded are left to sync and pagination
+    assert_eq!(popup.lines, ["Imported 2 messages from Test room."]);
+    assert_eq!(bodies(&state).await, ["a", "b", "c"]);
+}
+
+#[tokio::test]
+async fn date_pages_back_and_selects_the_first_message_from_then() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![message("$d", "d", 40)], false, "p1"));
+    server.on("GET", "/messages", messages_response("s1", Some("t1"), vec![message("$c", "c", 30)]));
+    server.on("GET", "/messages", messages_response("t1", Some("t2"), vec![message("$b", "b", 20)]));
+    server.on("GET", "/messages", messages_response("t2", None, vec![message("$a", "a", 10)]));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    jump_to_time(state.clone(), room_id(), 15000).await;
+    let lock = state.lock().await;
+    // paging stops once the date is loaded, and the selection counts from the newest message
+    assert_eq!(server.requests("/messages").len(), 3);
+    assert!(matches!(lock.mode, Mode::ScrollMessages));
+    assert_eq!(lock.messages_state.selected(), Some(2));
+}
+
+#[tokio::test]
+async fn subscribed_policy_list_hides_banned_users() {
+    let server = MockServer::start().await;
+    let rule = json!({
+        "type": "m.policy.rule.user",
+        "state_key": "rule:@alice:*",
+        "sender": ALICE,
+        "event_id": "$rule",
+        "origin_server_ts": 5000,
+        "content": { "entity": "@alice:*", "recommendation": "m.ban", "reason": "spam" },
+    });
+    server.on("GET", "/sync", sync_response("s1", vec![rule, message("$a", "a", 10)], false, "p1"));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    let mut lock = state.lock().await;
+    assert_eq!(timeline(&lock, &room_id()).len(), 1);
+    lock.policies.set_subscribed(&room_id(), true);
+    assert_eq!(timeline(&lock, &room_id()).len(), 0);
+
+    // later runs only sync the state that changed, so the rules are read from what's stored
+    lock.policies = Policies::new(&ModerationSettings::default());
+    lock.policies.set_subscribed(&room_id(), true);
+    assert_eq!(timeline(&lock, &room_id()).len(), 1);
+    let room = lock.channels[&room_id()].room.clone();
+    load_policy_rules(&mut lock, &room).await;
+    assert_eq!(timeline(&lock, &room_id()).len(), 0);
+}
+
+#[tokio::test]
+async fn highlight_words_count_as_mentions() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "the ilo-toki release", 10), message("$b", "unrelated", 20)], false, "p1"));
+    let state = super::app(&server).await;
+    let mut config = Config::default();
+    config.rooms.insert(String::from(ROOM), RoomConfig {
+        highlights: vec![String::from("ILO-TOKI")],
+        ..RoomConfig::default()
+    });
+    state.lock().await.highlights = Highlights::new(&config);
+    sync(&state).await;
+
+    let lock = state.lock().await;
+    let channel = &lock.channels[&room_id()];
+    assert!(channel.messages[&event_id("$a")].highlighted);
+    assert!(!channel.messages[&event_id("$b")].highlighted);
+    assert_eq!(channel.mentions.len(), 1);
+}
+
+#[tokio::test]
+async fn filters_hide_until_revealed() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "!roll d20", 10), message("$b", "buy now", 20), message("$c", "hello", 30)], false, "p1"));
+    let state = super::app(&server).await;
+    let mut config = Config {
+        filters: FilterSettings { patterns: vec![String::from("(?i)BUY")], senders: vec![] },
+        ..Config::default()
+    };
+    config.rooms.insert(String::from(ROOM), RoomConfig {
+        hide_prefixes: vec![String::from("!")],
+        ..RoomConfig::default()
+    });
+    state.lock().await.filters = Filters::new(&config);
+    sync(&state).await;
+
+    let mut lock = state.lock().await;
+    assert_eq!(timeline(&lock, &room_id()).len(), 1);
+    lock.filters.revealed = true;
+    assert_eq!(timeline(&lock, &room_id()).len(), 3);
+}
+
+#[tokio::test]
+async fn quote_covers_marked_range() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "one", 10), message("$b", "two\nlines", 20), message("$c", "three", 30)], false, "p1"));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    let mut lock = state.lock().await;
+    lock.current_channel = Some(room_id());
+    // selections count up from the newest message
+    lock.messages_state.select(Some(0));
+    lock.quote_mark = Some(event_id("$b"));
+    let (quote, count) = quote_selection(&lock).unwrap();
+    assert_eq!(count, 2);
+    let lines: Vec<_> = quote.lines().map(|v| v.split_once("] ").map(|(_, v)| v).unwrap_or(v)).collect();
+    assert_eq!(lines, ["<alice> two", "> lines", "<alice> three"]);
+}
+
+// `tr` stands in for the translator, and the Windows runners don't have it
+#[cfg(unix)]
+#[tokio::test]
+async fn auto_translate_fills_in_translations() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "hello", 10)], false, "p1"));
+    let state = super::app(&server).await;
+    state.lock().await.config.rooms.insert(String::from(ROOM), RoomConfig {
+        auto_translate: true,
+        ..RoomConfig::default()
+    });
+    sync(&state).await;
+
+    let untranslated = std::mem::take(&mut state.lock().await.untranslated);
+    assert_eq!(untranslated.len(), 1);
+    for (room_id, event_id, text) in untranslated {
+        crate::translate_message(state.clone(), vec![String::from("tr"), String::from("a-z"), String::from("A-Z")], room_id, event_id, text, false);
+    }
+
+    for _ in 0..100 {
+        if let Some(translation) = state.lock().await.channels[&room_id()].messages[&event_id("$a")].translation.clone() {
+            assert_eq!(translation, "HELLO");
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("the message was never translated");
+}
+
+#[tokio::test]
+async fn webhooks_post_matching_new_messages() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "!deploy old", 10)], false, "p1"));
+    server.on("GET", "/sync", sync_response("s2", vec![message("$b", "!deploy now", 20), message("$c", "chat", 30)], false, "p2"));
+    server.on("POST", "/hook", json!({}));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    let client = state.lock().await.client.clone();
+    let hook = WebhookConfig {
+        url: format!("{}/hook", server.url()),
+        rooms: vec![String::from(ROOM)],
+        pattern: String::from("^!deploy"),
+    };
+    webhook::watch(&client, &[hook]);
+    sync(&state).await;
+
+    assert_eq!(server.requests("/hook"), ["POST /hook"]);
+}
+
+#[tokio::test]
+async fn widgets_fill_in_url_templates() {
+    let server = MockServer::start().await;
+    let widget = |key: &str, content| json!({
+        "type": "im.vector.modular.widgets",
+        "state_key": key,
+        "sender": ALICE,
+        "event_id": format!("${}", key),
+        "origin_server_ts": 5000,
+        "content": content,
+    });
+    let jitsi = json!({ "type": "jitsi", "name": "Call", "url": "https://jitsi.example.org/$conferenceId?room=$matrix_room_id", "data": { "conferenceId": "abc" } });
+    server.on("GET", "/sync", sync_response("s1", vec![widget("call", jitsi), widget("removed", json!({}))], false, "p1"));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    let room = state.lock().await.channels[&room_id()].room.clone();
+    let widgets = widget::list(&room).await;
+    assert_eq!(widgets.len(), 1);
+    assert_eq!(widgets[0].name, "Call");
+    assert_eq!(widgets[0].url, "https://jitsi.example.org/abc?room=%21room%3Aexample.org");
+}
+
+#[tokio::test]
+async fn call_events_show_in_the_timeline() {
+    let server = MockServer::start().await;
+    let call = |id: &str, event_type: &str, content| json!({ "type": event_type, "sender": ALICE, "event_id": id, "origin_server_ts": 5000, "content": content });
+    let events = vec![
+        call("$invite", "m.call.invite", json!({ "call_id": "c1", "version": 0, "lifetime": 60000, "offer": { "type": "offer", "sdp": "" } })),
+        message("$a", "hello", 6),
+        call("$hangup", "m.call.hangup", json!({ "call_id": "c1", "version": 0 })),
+    ];
+    server.on("GET", "/sync", sync_response("s1", events, false, "p1"));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    assert_eq!(bodies(&state).await, ["incoming call (J to join)", "hello", "ended the call"]);
+    let room = state.lock().await.channels[&room_id()].room.clone();
+    let links = call::links(&room, &CallSettings { url: String::from("https://meet.example.org/$room_slug") }).await;
+    assert_eq!(links[0].url, "https://meet.example.org/room");
+}
+
+#[tokio::test]
+async fn imported_history_goes_by_timestamp() {
+    let server = MockServer::start().await;
+    let mut imported = message("$b", "imported", 15);
+    imported["content"]["org.matrix.msc2716.historical"] = json!(true);
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "a", 10), message("$c", "c", 20)], false, "p1"));
+    server.on("GET", "/sync", sync_response("s2", vec![imported, message("$d", "d", 30)], false, "p2"));
+    let state = super::app(&server).await;
+    sync(&state).await;
+    sync(&state).await;
+
+    assert_eq!(bodies(&state).await, ["a", "imported", "c", "d"]);
+    let lock = state.lock().await;
+    assert!(lock.channels[&room_id()].messages[&event_id("$b")].imported);
+    assert!(!lock.channels[&room_id()].messages[&event_id("$d")].imported);
+}
+
+#[tokio::test]
+async fn outbox_keeps_failed_sends_until_retried() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![], false, "p1"));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    let room = state.lock().await.channels[&room_id()].room.clone();
+    // nothing answers sends yet, so this one fails
+    state.lock().await.outbox.send(room, RoomMessageEventContent::text_plain("hello"));
+    for _ in 0..100 {
+        if state.lock().await.outbox.pending() == 0 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert!(state.lock().await.outbox.lines(0)[0].starts_with("> failed  Test room: hello"));
+
+    // and is still there next run
+    let path = std::env::temp_dir().join(format!("ilo-toki-outbox-test-{}", std::process::id()));
+    let path = path.to_str().unwrap();
+    state.lock().await.outbox.save(path);
+    let reloaded = crate::outbox::Outbox::new();
+    reloaded.load(path, &state.lock().await.client);
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(reloaded.failed(), 1);
+    assert!(reloaded.lines(0)[0].starts_with("> failed  Test room: hello"));
+
+    server.on("PUT", "", json!({ "event_id": "$sent" }));
+    let id = state.lock().await.outbox.ids()[0];
+    state.lock().await.outbox.retry(id);
+    for _ in 0..100 {
+        if state.lock().await.outbox.ids().is_empty() {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("the retry was never sent");
+}
+
+#[tokio::test]
+async fn user_search_lists_names_and_avatars() {
+    let server = MockServer::start().await;
+    let results = json!([
+        { "user_id": ALICE, "display_name": "Alice", "avatar_url": "mxc://example.org/abc" },
+        { "user_id": "@bob:example.org" },
+    ]);
+    server.on("POST", "/user_directory/search", json!({ "results": results, "limited": false }));
+    let state = super::app(&server).await;
+    let client = state.lock().await.client.clone();
+
+    let found = users::search(&client, "a").await.unwrap();
+    let lines = users::lines(&found, &client.homeserver().await, false, true);
+    assert_eq!(lines[0], format!("1 Alice ({})", ALICE));
+    assert_eq!(lines[1], format!("  avatar: {}/_matrix/media/v3/download/example.org/abc", server.url()));
+    assert_eq!(lines[2], "2 @bob:example.org");
+}
+
+#[tokio::test]
+async fn mutual_rooms_ask_the_server_when_it_can() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![], false, "p1"));
+    server.on("GET", "/mutual_rooms", json!({ "joined": ["!other:example.org"] }));
+    let state = super::app(&server).await;
+    sync(&state).await;
+    let client = state.lock().await.client.clone();
+    let alice = matrix_sdk::ruma::UserId::parse(ALICE).unwrap();
+
+    // without the server's help, it's whoever is in our member lists
+    assert_eq!(users::mutual_rooms(&client, None, &alice).await, [room_id()]);
+    let prefix = "_matrix/client/unstable/uk.half-shot.msc2666/user/mutual_rooms";
+    assert_eq!(users::mutual_rooms(&client, Some(prefix), &alice).await.iter().map(|v| v.as_str()).collect::<Vec<_>>(), ["!other:example.org"]);
+}
+
+#[tokio::test]
+async fn previews_are_fetched_for_links_once_turned_on() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "see https://example.org/post.", 10), message("$b", "no link", 20)], false, "p1"));
+    server.on("GET", "/preview_url", json!({ "og:title": "A post", "og:description": "All about\n  things" }));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    let mut lock = state.lock().await;
+    lock.current_channel = Some(room_id());
+    request_previews(state.clone(), &mut lock, 20);
+    assert!(server.requests("/preview_url").is_empty());
+
+    lock.config.previews.enabled = true;
+    request_previews(state.clone(), &mut lock, 20);
+    drop(lock);
+    for _ in 0..100 {
+        if let Some(preview) = state.lock().await.channels[&room_id()].messages[&event_id("$a")].preview.as_ref() {
+            assert_eq!(preview.title, "A post");
+            assert_eq!(preview.description.as_deref(), Some("All about things"));
+            assert_eq!(server.requests("/preview_url").len(), 1);
+            assert!(server.requests("/preview_url")[0].contains("url=https%3A%2F%2Fexample.org%2Fpost&"));
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("the preview was never fetched");
+}
+
+#[tokio::test]
+async fn empty_channels_load_history_when_first_opened() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![], false, "p1"));
+    server.on("GET", "/messages", messages_response("s1", Some("p2"), vec![message("$b", "b", 20), message("$a", "a", 10)]));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    let mut lock = state.lock().await;
+    backfill_on_open(state.clone(), &mut lock);
+    assert!(server.requests("/messages").is_empty());
+
+    lock.current_channel = Some(room_id());
+    backfill_on_open(state.clone(), &mut lock);
+    backfill_on_open(state.clone(), &mut lock);
+    drop(lock);
+    // the page loads in the background, without the app state locked while it's asked for
+    for _ in 0..50 {
+        if bodies(&state).await == ["a", "b"] {
+            assert_eq!(server.requests("/messages").len(), 1);
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("the history was never loaded");
+}
+
+#[tokio::test]
+async fn left_channels_keep_their_history_read_only() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "a", 10)], false, "p1"));
+    server.on("POST", "/leave", json!({}));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    let mut lock = state.lock().await;
+    lock.current_channel = Some(room_id());
+    lock.input_text = String::from("/leave");
+    submit_input(state.clone(), &mut lock).await;
+    assert!(lock.channels[&room_id()].archived);
+    assert_eq!(server.requests("/leave").len(), 1);
+
+    drop(lock);
+    load_older(state.clone(), &room_id()).await;
+    assert!(server.requests("/messages").is_empty());
+    let mut lock = state.lock().await;
+
+    lock.input_text = String::from("hello");
+    submit_input(state.clone(), &mut lock).await;
+    assert_eq!(lock.popup.as_ref().unwrap().title, "Can't send");
+    assert_eq!(lock.input_text, "hello");
+    drop(lock);
+    assert_eq!(bodies(&state).await, ["a"]);
+}
+
+#[tokio::test]
+async fn being_kicked_archives_the_channel_and_says_why() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "a", 10)], false, "p1"));
+    let kick = json!({
+        "type": "m.room.member",
+        "state_key": ME,
+        "sender": ALICE,
+        "event_id": "$kick",
+        "origin_server_ts": 20000,
+        "content": { "membership": "leave", "reason": "spam" },
+    });
+    server.on("GET", "/sync", json!({ "next_batch": "s2", "rooms": { "leave": { ROOM: { "timeline": { "events": [kick] } } } } }));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    let client = state.lock().await.client.clone();
+    let response = client.sync_once(SyncSettings::default()).await.unwrap();
+    let mut lock = state.lock().await;
+    handle_left(&response, &mut lock);
+    assert!(lock.channels[&room_id()].archived);
+    assert_eq!(lock.toast.current(std::time::Instant::now()), Some("Removed from Test room, kicked by @alice:example.org: spam"));
+    drop(lock);
+    assert_eq!(bodies(&state).await, ["a"]);
+}
+
+#[tokio::test]
+async fn renamed_rooms_update_the_sidebar_until_opened() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![], false, "p1"));
+    let mut response = sync_response("s2", vec![], false, "p2");
+    let mut rename = state_event("m.room.name", "", json!({ "name": "New name" }));
+    rename["event_id"] = json!("$rename");
+    response["rooms"]["join"][ROOM]["state"]["events"].as_array_mut().unwrap().push(rename);
+    server.on("GET", "/sync", response);
+    let state = super::app(&server).await;
+    sync(&state).await;
+    assert!(!state.lock().await.channels[&room_id()].changed);
+
+    sync(&state).await;
+    let mut lock = state.lock().await;
+    let channel = &lock.channels[&room_id()];
+    assert_eq!(channel.name, "New name");
+    assert!(channel.changed);
+    assert_eq!(lock.names.name(&room_id()), Some("New name"));
+    open_channel(&mut lock, room_id());
+    assert!(!lock.channels[&room_id()].changed);
+}
+
+#[tokio::test]
+async fn rooms_are_listed_once_however_they_arrive() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "a", 10)], false, "p1"));
+    let mut response = sync_response("s2", vec![message("$b", "b", 20)], false, "p2");
+    // joined from another client while running
+    response["rooms"]["join"]["!new:example.org"] = response["rooms"]["join"][ROOM].clone();
+    server.on("GET", "/sync", response);
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    let client = state.lock().await.client.clone();
+    let response = client.sync_once(SyncSettings::default()).await.unwrap();
+    handle_joined(&response, &mut state.lock().await);
+    let new = OwnedRoomId::try_from("!new:example.org").unwrap();
+    assert_eq!(state.lock().await.channel_ids, [room_id(), new.clone()]);
+
+    // loading the rooms again finds both already listed
+    sync(&state).await;
+    assert_eq!(state.lock().await.channel_ids, [room_id(), new]);
+}
+
+#[tokio::test]
+async fn startup_returns_to_the_last_room_and_message() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "a", 10), message("$b", "b", 20)], false, "p1"));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    let mut lock = state.lock().await;
+    restore_room(&mut lock, Resume { room: Some(room_id()), selected: Some(event_id("$a")) });
+    assert_eq!(lock.current_channel, Some(room_id()));
+    assert!(matches!(lock.mode, Mode::ScrollMessages));
+    assert_eq!(lock.messages_state.selected(), Some(1));
+
+    // a default room wins over the last one
+    lock.config.default_room = Some(String::from(ROOM));
+    restore_room(&mut lock, Resume { room: Some(OwnedRoomId::try_from("!gone:example.org").unwrap()), selected: None });
+    assert_eq!(lock.current_channel, Some(room_id()));
+    assert!(matches!(lock.mode, Mode::Normal));
+}
+
+#[tokio::test]
+async fn announcement_rooms_keep_the_composer_to_themselves() {
+    let server = MockServer::start().await;
+    let mut response = sync_response("s1", vec![message("$a", "a", 10)], false, "p1");
+    let levels = state_event("m.room.power_levels", "", json!({ "users": { ALICE: 100 }, "events_default": 50, "invite": 50 }));
+    response["rooms"]["join"][ROOM]["state"]["events"].as_array_mut().unwrap().push(levels);
+    server.on("GET", "/sync", response);
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    let mut lock = state.lock().await;
+    let allowed = lock.permissions.get(&room_id());
+    assert!(!allowed.send && !allowed.invite && !allowed.redact_others);
+    lock.current_channel = Some(room_id());
+    lock.input_text = String::from("hello");
+    submit_input(state.clone(), &mut lock).await;
+    assert_eq!(lock.popup.as_ref().unwrap().lines, ["You don't have permission to post in this room."]);
+    assert_eq!(lock.input_text, "hello");
+
+    // turning on encryption is a state event, which takes state_default
+    assert!(!allowed.encrypt);
+    lock.input_text = String::from("/encrypt");
+    submit_input(state.clone(), &mut lock).await;
+    assert_eq!(lock.popup.as_ref().unwrap().lines, ["You don't have permission to turn on encryption here."]);
+    drop(lock);
+    assert_eq!(bodies(&state).await, ["a"]);
+}
+
+#[tokio::test]
+async fn replies_drop_their_quoted_fallback_and_send_the_relation() {
+    let server = MockServer::start().await;
+    let reply = json!({
+        "type": "m.room.message",
+        "sender": ALICE,
+        "event_id": "$b",
+        "origin_server_ts": 11000,
+        "content": {
+            "msgtype": "m.text",
+            "body": "> <@me:example.org> first\n> line\n\nsecond",
+            "m.relates_to": { "m.in_reply_to": { "event_id": "$a" } },
+        },
+    });
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "first", 10), reply], false, "p1"));
+    let state = super::app(&server).await;
+    sync(&state).await;
+    assert_eq!(bodies(&state).await, ["first", "second"]);
+
+    let mut lock = state.lock().await;
+    assert_eq!(lock.channels[&room_id()].messages[&event_id("$b")].reply_to, Some(event_id("$a")));
+    lock.current_channel = Some(room_id());
+    lock.reply_to = Some(event_id("$b"));
+    lock.input_text = String::from("third");
+    submit_input(state.clone(), &mut lock).await;
+    // only the next message is a reply
+    assert!(lock.reply_to.is_none());
+    // nothing answers sends, so it stays in the outbox either way
+    assert_eq!(lock.outbox.ids().len(), 1);
+}
+
+#[tokio::test]
+async fn thread_replies_are_kept_to_their_thread() {
+    let server = MockServer::start().await;
+    let mut reply = message("$b", "in the thread", 11);
+    reply["content"]["m.relates_to"] = json!({ "rel_type": "m.thread", "event_id": "$a", "is_falling_back": true, "m.in_reply_to": { "event_id": "$a" } });
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "root", 10), reply, message("$c", "after", 12)], false, "p1"));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    let mut lock = state.lock().await;
+    assert_eq!(lock.channels[&room_id()].threads[&event_id("$a")], [event_id("$b")]);
+    // the fallback reply isn't quoted
+    assert_eq!(lock.channels[&room_id()].messages[&event_id("$b")].reply_to, None);
+    let shown = |lock: &AppState| -> Vec<String> {
+        timeline(lock, &room_id()).into_iter().filter_map(|v| match v {
+            TimelineItem::Message(_, m) => Some(m.content.clone()),
+            _ => None,
+        }).collect()
+    };
+    assert_eq!(shown(&lock), ["root", "after"]);
+
+    lock.current_channel = Some(room_id());
+    lock.thread = Some(event_id("$a"));
+    assert_eq!(shown(&lock), ["root", "in the thread"]);
+    lock.input_text = String::from("me too");
+    submit_input(state.clone(), &mut lock).await;
+    assert_eq!(lock.outbox.ids().len(), 1);
+    // the thread stays open for the next reply
+    assert_eq!(lock.thread, Some(event_id("$a")));
+}
+
+#[tokio::test]
+async fn messages_keep_their_other_content_fields() {
+    let server = MockServer::start().await;
+    let mut file = message("$a", "report.pdf", 10);
+    file["content"] = json!({
+        "msgtype": "m.file",
+        "body": "report.pdf",
+        "url": "mxc://example.org/abc",
+        "info": { "mimetype": "application/pdf", "size": 1234 },
+        "org.example.bridge": { "id": 7 },
+    });
+    server.on("GET", "/sync", sync_response("s1", vec![file], false, "p1"));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    let lock = state.lock().await;
+    let details: Vec<_> = lock.channels[&room_id()].messages[&event_id("$a")].details.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    assert_eq!(details, ["info.mimetype=application/pdf", "info.size=1234", "msgtype=m.file", "org.example.bridge.id=7", "url=mxc://example.org/abc"]);
+}
+
+#[tokio::test]
+async fn redactions_remove_messages_and_reactions() {
+    let server = MockServer::start().await;
+    let redaction = json!({
+        "type": "m.room.redaction",
+        "room_id": ROOM,
+        "sender": ALICE,
+        "event_id": "$x",
+        "origin_server_ts": 12000,
+        "redacts": "$b",
+        "content": {},
+    });
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "a", 10), message("$b", "b", 11), redaction], false, "p1"));
+    let state = super::app(&server).await;
+    sync(&state).await;
+    assert_eq!(bodies(&state).await, ["a"]);
+
+    // events apply the same without a server
+    let mut lock = state.lock().await;
+    let reaction = Reaction { id: event_id("$r"), key: String::from("👍"), sender: String::from(ALICE) };
+    reducer::apply(&mut lock, AppEvent::Reacted { room: room_id(), target: event_id("$a"), reaction });
+    assert_eq!(lock.channels[&room_id()].messages[&event_id("$a")].reactions.len(), 1);
+    reducer::apply(&mut lock, AppEvent::Redacted { room: room_id(), event: event_id("$r") });
+    assert!(lock.channels[&room_id()].messages[&event_id("$a")].reactions.is_empty());
+    drop(lock);
+    assert_eq!(bodies(&state).await, ["a"]);
+}
+
+#[tokio::test]
+async fn editing_keeps_the_draft() {
+    let server = MockServer::start().await;
+    let mut own = message("$a", "typo", 10);
+    own["sender"] = json!(ME);
+    server.on("GET", "/sync", sync_response("s1", vec![own], false, "p1"));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    // edits need a server with relations, and the client asked for the first answer when it synced
+    server.on("GET", "/versions", json!({ "versions": ["v1.3"] }));
+    let mut lock = state.lock().await;
+    lock.server = ServerFeatures::query(&lock.client).await;
+    lock.current_channel = Some(room_id());
+    lock.input_text = String::from("draft");
+    lock.input_char_pos = 5;
+    lock.input_byte_pos = 5;
+    lock.mode = Mode::ScrollMessages;
+    lock.messages_state.select(Some(0));
+
+    let e = Event::Key(KeyEvent::new(KeyCode::Char('e'), KeyModifiers::NONE));
+    handle_event(state.clone(), &mut lock, e.clone()).await;
+    assert_eq!(lock.input_text, "typo");
+    assert!(matches!(lock.mode, Mode::Insert));
+    handle_event(state.clone(), &mut lock, Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE))).await;
+    assert_eq!(lock.input_text, "draft");
+    assert_eq!(lock.input_byte_pos, 5);
+
+    lock.mode = Mode::ScrollMessages;
+    handle_event(state.clone(), &mut lock, e).await;
+    handle_event(state.clone(), &mut lock, Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))).await;
+    assert_eq!(lock.input_text, "draft");
+    assert!(lock.editing.is_none());
+    let id = lock.outbox.ids()[0];
+    match lock.outbox.remove(id).map(|v| v.content) {
+        Some(AnyMessageLikeEventContent::RoomMessage(RoomMessageEventContent { relates_to: Some(Relation::Replacement(replacement)), .. })) => assert_eq!(replacement.event_id, event_id("$a")),
+        _ => panic!("the edit wasn't sent as a replacement"),
+    }
+}
+
+#[tokio::test]
+async fn read_markers_go_out_once_per_message() {
+    let server = MockServer::start().await;
+    server.on("GET", "/sync", sync_response("s1", vec![message("$a", "one", 10)], false, "p1"));
+    server.on("POST", "/read_markers", json!({}));
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    let mut lock = state.lock().await;
+    lock.current_channel = Some(room_id());
+    send_receipt(state.clone(), &mut lock);
+    drop(lock);
+    for _ in 0..100 {
+        if state.lock().await.channels[&room_id()].read.is_some() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(state.lock().await.channels[&room_id()].read, Some(event_id("$a")));
+
+    // nothing new has come in since
+    send_receipt(state.clone(), &mut state.lock().await);
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert_eq!(server.requests("/read_markers").len(), 1);
+}
+
+#[tokio::test]
+async fn upgraded_rooms_keep_their_mentions() {
+    let server = MockServer::start().await;
+    let mut response = sync_response("s1", vec![message("$a", "a", 10)], false, "p1");
+    let new = OwnedRoomId::try_from("!new:example.org").unwrap();
+    let mut successor = response["rooms"]["join"][ROOM].clone();
+    successor["state"]["events"][0] = state_event("m.room.create", "", json!({ "creator": ALICE, "predecessor": { "room_id": ROOM, "event_id": "$tombstone" } }));
+    successor["timeline"]["events"] = json!([message("$b", "b", 20)]);
+    response["rooms"]["join"][new.as_str()] = successor;
+    let tombstone = state_event("m.room.tombstone", "", json!({ "body": "upgraded", "replacement_room": new }));
+    response["rooms"]["join"][ROOM]["state"]["events"].as_array_mut().unwrap().push(tombstone);
+    server.on("GET", "/sync", response);
+    let state = super::app(&server).await;
+    sync(&state).await;
+
+    let mut lock = state.lock().await;
+    assert_eq!(lock.channel_ids, [new.clone()]);
+    // a mention left unread in the old room shows on the new one's entry
+    lock.channels.get_mut(&room_id()).unwrap().mentions.insert(event_id("$a"));
+    lock.channels.get_mut(&new).unwrap().mentions.insert(event_id("$b"));
+    assert_eq!(mention_count(&lock, &new), 2);
+
+    open_channel(&mut lock, new.clone());
+    assert_eq!(mention_count(&lock, &new), 0);
+    assert!(lock.channels[&room_id()].mentions.is_empty());
+}
@@ -0,0 +1,30 @@
+use std::collections::BTreeMap;
+
+use matrix_sdk::{
+    deserialized_responses::{AlgorithmInfo, EncryptionInfo, VerificationState},
+    ruma::DeviceKeyAlgorithm,
+};
+
+use crate::trust::Encryption;
+
+#[test]
+fn encryption_warnings() {
+    let info = |state, signed: bool| {
+        let keys = if signed { BTreeMap::from([(DeviceKeyAlgorithm::Ed25519, String::from("ed"))]) } else { BTreeMap::new() };
+        Encryption::from(Some(EncryptionInfo {
+            sender: "@alice:example.org".try_into().unwrap(),
+            sender_device: "DEVICE".into(),
+            algorithm_info: AlgorithmInfo::MegolmV1AesSha2 { curve25519_key: String::from("curve"), sender_claimed_keys: keys },
+            verification_state: state,
+        }))
+    };
+
+    assert_eq!(Encryption::from(None).warning(true), Some("unencrypted"));
+    assert_eq!(Encryption::from(None).warning(false), None);
+    assert_eq!(Encryption::Unknown.warning(true), None);
+    assert_eq!(info(VerificationState::Trusted, true).warning(true), None);
+    assert_eq!(info(VerificationState::Untrusted, true).warning(true), Some("unverified device"));
+    assert_eq!(info(VerificationState::UnknownDevice, true).warning(true), Some("untrusted session"));
+    assert_eq!(info(VerificationState::Trusted, false).warning(true), Some("untrusted session"));
+    assert_eq!(info(VerificationState::Untrusted, true).lines(true).last().unwrap(), "Warning: unverified device");
+}
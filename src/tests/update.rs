@@ -0,0 +1,12 @@
+use crate::update;
+
+#[test]
+fn releases_are_newer_by_each_number_in_turn() {
+    assert!(update::newer("0.1.0", "0.2.0"));
+    assert!(update::newer("0.9.0", "0.10.0"));
+    assert!(!update::newer("0.2.0", "0.2.0"));
+    assert!(!update::newer("1.0.0", "0.9.9"));
+    // pre-releases count as the release they lead up to
+    assert!(!update::newer("0.2.0", "0.2.0-rc1"));
+    assert!(!update::newer("0.1.0", "nightly"));
+}
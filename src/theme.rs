@@ -0,0 +1,136 @@
+//! The colours the UI uses, including a colour per user for nicks.
+
+use serde::Deserialize;
+use tui::style::{Color, Modifier, Style};
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeName {
+    #[default]
+    Default,
+    /// Colours that stay apart with red-green colour blindness, from the Okabe-Ito palette.
+    Deuteranopia,
+    /// No colour at all, just bold, underline, and reverse.
+    Monochrome,
+}
+
+const DEFAULT_NICKS: &[Color] = &[
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::LightCyan,
+];
+
+const DEUTERANOPIA_NICKS: &[Color] = &[
+    Color::Rgb(230, 159, 0),
+    Color::Rgb(86, 180, 233),
+    Color::Rgb(0, 158, 115),
+    Color::Rgb(240, 228, 66),
+    Color::Rgb(0, 114, 178),
+    Color::Rgb(213, 94, 0),
+    Color::Rgb(204, 121, 167),
+];
+
+pub struct Theme {
+    name: ThemeName,
+    nick_colors: bool,
+}
+
+impl Theme {
+    /// `NO_COLOR` (https://no-color.org) turns any theme monochrome.
+    pub fn new(name: ThemeName, nick_colors: bool) -> Theme {
+        let no_color = std::env::var("NO_COLOR").map(|v| !v.is_empty()).unwrap_or(false);
+        let name = if no_color { ThemeName::Monochrome } else { name };
+        Theme { name, nick_colors }
+    }
+
+    /// The selected room or message.
+    pub fn selected(&self) -> Style {
+        match self.name {
+            ThemeName::Default => Style::default().bg(Color::Magenta),
+            ThemeName::Deuteranopia => Style::default().bg(Color::Rgb(0, 114, 178)),
+            ThemeName::Monochrome => Style::default().add_modifier(Modifier::REVERSED),
+        }
+    }
+
+    /// Things that need attention, like gaps in history and the status message.
+    pub fn warning(&self) -> Style {
+        match self.name {
+            ThemeName::Default => Style::default().fg(Color::Yellow),
+            ThemeName::Deuteranopia => Style::default().fg(Color::Rgb(230, 159, 0)),
+            ThemeName::Monochrome => Style::default().add_modifier(Modifier::BOLD),
+        }
+    }
+
+    /// Messages that mention us or match a highlight word, and the count of them on a room.
+    pub fn highlight(&self) -> Style {
+        match self.name {
+            ThemeName::Default => Style::default().fg(Color::LightRed).add_modifier(Modifier::BOLD),
+            ThemeName::Deuteranopia => Style::default().fg(Color::Rgb(213, 94, 0)).add_modifier(Modifier::BOLD),
+            ThemeName::Monochrome => Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+        }
+    }
+
+    /// Things in the background, like dividers and who's typing.
+    pub fn muted(&self) -> Style {
+        match self.name {
+            ThemeName::Default | ThemeName::Deuteranopia => Style::default().fg(Color::DarkGray),
+            ThemeName::Monochrome => Style::default().add_modifier(Modifier::UNDERLINED),
+        }
+    }
+
+    /// Words in the input box the spellchecker doesn't know.
+    pub fn misspelled(&self) -> Style {
+        match self.name {
+            ThemeName::Default => Style::default().fg(Color::LightRed).add_modifier(Modifier::UNDERLINED),
+            ThemeName::Deuteranopia => Style::default().fg(Color::Rgb(213, 94, 0)).add_modifier(Modifier::UNDERLINED),
+            ThemeName::Monochrome => Style::default().add_modifier(Modifier::UNDERLINED),
+        }
+    }
+
+    /// Code in formatted messages.
+    pub fn code(&self) -> Style {
+        match self.name {
+            ThemeName::Default => Style::default().fg(Color::Cyan),
+            ThemeName::Deuteranopia => Style::default().fg(Color::Rgb(86, 180, 233)),
+            ThemeName::Monochrome => Style::default().add_modifier(Modifier::DIM),
+        }
+    }
+
+    /// A style dimmed for old messages.
+    pub fn faded(&self, style: Style) -> Style {
+        match self.name {
+            ThemeName::Default | ThemeName::Deuteranopia => style.fg(Color::DarkGray),
+            ThemeName::Monochrome => style.add_modifier(Modifier::DIM),
+        }
+    }
+
+    /// A user's nick, coloured the same every time from their user id.
+    /// Text a message colours itself, which monochrome leaves plain.
+    pub fn text_color(&self, (r, g, b): (u8, u8, u8)) -> Style {
+        match self.name {
+            ThemeName::Default | ThemeName::Deuteranopia => Style::default().fg(Color::Rgb(r, g, b)),
+            ThemeName::Monochrome => Style::default(),
+        }
+    }
+
+    pub fn nick(&self, user_id: &str) -> Style {
+        let palette = match self.name {
+            _ if !self.nick_colors => return Style::default(),
+            ThemeName::Default => DEFAULT_NICKS,
+            ThemeName::Deuteranopia => DEUTERANOPIA_NICKS,
+            ThemeName::Monochrome => return Style::default().add_modifier(Modifier::BOLD),
+        };
+
+        // djb2, so the colour doesn't change between runs or builds
+        let hash = user_id.bytes().fold(5381u32, |hash, c| hash.wrapping_mul(33) ^ c as u32);
+        Style::default().fg(palette[hash as usize % palette.len()])
+    }
+}
@@ -0,0 +1,23 @@
+//! Notices shown in the status line for a few seconds, for things that happen without being asked
+//! for, like being kicked from a room.
+
+use std::time::{Duration, Instant};
+
+/// How long a notice stays up.
+const SHOWN_FOR: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+pub struct Toast {
+    shown: Option<(String, Instant)>,
+}
+
+impl Toast {
+    /// Shows a notice in place of the last one.
+    pub fn show(&mut self, text: String, now: Instant) {
+        self.shown = Some((text, now));
+    }
+
+    pub fn current(&self, now: Instant) -> Option<&str> {
+        self.shown.as_ref().filter(|(_, shown)| now.duration_since(*shown) < SHOWN_FOR).map(|(text, _)| text.as_str())
+    }
+}
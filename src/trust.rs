@@ -0,0 +1,71 @@
+//! How far messages in encrypted rooms can be trusted: whether they were encrypted at all, whether
+//! the device that sent them is verified, and whether their megolm session is tied to it.
+
+use matrix_sdk::{
+    deserialized_responses::{AlgorithmInfo, EncryptionInfo, VerificationState},
+    ruma::DeviceKeyAlgorithm,
+};
+
+/// How a message reached us.
+pub enum Encryption {
+    /// Nothing says, like for messages read from an export.
+    Unknown,
+    Plain,
+    Encrypted(Box<EncryptionInfo>),
+}
+
+impl From<Option<EncryptionInfo>> for Encryption {
+    fn from(info: Option<EncryptionInfo>) -> Encryption {
+        match info {
+            Some(v) => Encryption::Encrypted(Box::new(v)),
+            None => Encryption::Plain,
+        }
+    }
+}
+
+impl Encryption {
+    /// What's wrong with a message in a room that is or isn't encrypted, if anything.
+    pub fn warning(&self, room_encrypted: bool) -> Option<&'static str> {
+        match self {
+            Encryption::Plain if room_encrypted => Some("unencrypted"),
+            Encryption::Encrypted(info) if !session_trusted(info) => Some("untrusted session"),
+            Encryption::Encrypted(info) => match info.verification_state {
+                VerificationState::Untrusted => Some("unverified device"),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The details shown with a message's other details.
+    pub fn lines(&self, room_encrypted: bool) -> Vec<String> {
+        let mut lines = match self {
+            Encryption::Unknown => vec![String::from("Encryption: unknown")],
+            Encryption::Plain => vec![String::from("Encryption: none")],
+            Encryption::Encrypted(info) => {
+                let AlgorithmInfo::MegolmV1AesSha2 { curve25519_key, sender_claimed_keys } = &info.algorithm_info;
+                let device = match info.verification_state {
+                    VerificationState::Trusted => "verified",
+                    VerificationState::Untrusted => "unverified",
+                    VerificationState::UnknownDevice => "unknown",
+                };
+                vec![
+                    String::from("Encryption: megolm"),
+                    format!("Device: {} ({})", info.sender_device, device),
+                    format!("Session sender key: {}", curve25519_key),
+                    format!("Session signing key: {}", sender_claimed_keys.get(&DeviceKeyAlgorithm::Ed25519).map(String::as_str).unwrap_or("none")),
+                ]
+            }
+        };
+        if let Some(warning) = self.warning(room_encrypted) {
+            lines.push(format!("Warning: {}", warning));
+        }
+        lines
+    }
+}
+
+/// Whether the session's keys belong to a device we know of, rather than only being claimed.
+fn session_trusted(info: &EncryptionInfo) -> bool {
+    let AlgorithmInfo::MegolmV1AesSha2 { sender_claimed_keys, .. } = &info.algorithm_info;
+    !matches!(info.verification_state, VerificationState::UnknownDevice) && sender_claimed_keys.contains_key(&DeviceKeyAlgorithm::Ed25519)
+}
@@ -0,0 +1,79 @@
+//! Who's typing in a room, summarised for the status line.
+
+use std::time::{Duration, Instant};
+
+use matrix_sdk::ruma::OwnedUserId;
+
+use crate::symbols::Symbols;
+
+/// How long someone is still shown after they stop typing, so quick pauses don't flicker.
+const GRACE: Duration = Duration::from_secs(2);
+/// How many names are listed before the rest are counted.
+const MAX_NAMES: usize = 2;
+/// Names longer than this are cut short.
+const MAX_NAME_WIDTH: usize = 16;
+
+struct Typer {
+    user_id: OwnedUserId,
+    name: String,
+    /// When they stopped typing, or `None` if they still are.
+    stopped: Option<Instant>,
+}
+
+#[derive(Default)]
+pub struct Typing {
+    typers: Vec<Typer>,
+}
+
+impl Typing {
+    /// Replaces who's typing with the users from a typing event, paired with their display names.
+    pub fn update(&mut self, typing: Vec<(OwnedUserId, String)>) {
+        let now = Instant::now();
+        for typer in self.typers.iter_mut() {
+            if typer.stopped.is_none() && !typing.iter().any(|(v, _)| *v == typer.user_id) {
+                typer.stopped = Some(now);
+            }
+        }
+        self.typers.retain(|v| v.stopped.map(|v| now - v < GRACE).unwrap_or(true));
+
+        for (user_id, name) in typing {
+            match self.typers.iter_mut().find(|v| v.user_id == user_id) {
+                Some(typer) => {
+                    typer.stopped = None;
+                    typer.name = name;
+                }
+
+                None => self.typers.push(Typer { user_id, name, stopped: None }),
+            }
+        }
+    }
+
+    /// Something like "alice, bob and 3 others are typing…", no wider than `width` characters.
+    pub fn summary(&self, width: usize, symbols: &Symbols) -> Option<String> {
+        if width == 0 {
+            return None;
+        }
+
+        let now = Instant::now();
+        let names: Vec<_> = self.typers.iter().filter(|v| v.stopped.map(|v| now - v < GRACE).unwrap_or(true)).map(|v| truncate(&v.name, MAX_NAME_WIDTH, symbols.ellipsis())).collect();
+
+        let ellipsis = symbols.ellipsis();
+        let summary = match names.len() {
+            0 => return None,
+            1 => format!("{} is typing{}", names[0], ellipsis),
+            n if n <= MAX_NAMES + 1 => format!("{} and {} are typing{}", names[..n - 1].join(", "), names[n - 1], ellipsis),
+            n => format!("{} and {} others are typing{}", names[..MAX_NAMES].join(", "), n - MAX_NAMES, ellipsis),
+        };
+        Some(truncate(&summary, width, ellipsis))
+    }
+}
+
+pub fn truncate(text: &str, width: usize, ellipsis: &str) -> String {
+    if text.chars().count() <= width {
+        text.to_string()
+    } else {
+        let mut text: String = text.chars().take(width.saturating_sub(ellipsis.chars().count())).collect();
+        text.push_str(ellipsis);
+        text
+    }
+}
@@ -0,0 +1,457 @@
+//! Drawing the app state to the terminal.
+
+use tui::{
+    backend::Backend,
+    layout,
+    style::{Modifier, Style},
+    text::{Span, Spans, Text},
+    widgets, Frame,
+};
+use unicode_width::UnicodeWidthChar;
+
+use crate::{call, html, irc, media, mention_count, preview, symbols, timeline, typing, viewport, AppState, CodeBlock, TimelineItem};
+
+/// Draws one frame.
+pub fn draw<B: Backend>(f: &mut Frame<B>, state: &AppState) {
+    let screen_reader = state.announcements.enabled();
+    let borders = if screen_reader { widgets::Borders::NONE } else { widgets::Borders::ALL };
+    let style = state.cursor_style();
+    state.images.start_frame();
+
+    let horizontal = layout::Layout::default()
+        .direction(layout::Direction::Horizontal)
+        .constraints([
+            layout::Constraint::Length(20),
+            layout::Constraint::Min(3),
+        ])
+        .split(f.size());
+    // secrets are drawn as one * per character, so the cursor's byte position is its character position
+    let (input_text, input_pos) = match state.secret {
+        Some(_) => ("*".repeat(state.input_text.chars().count()), state.input_char_pos),
+        None => (state.input_text.clone(), state.input_byte_pos),
+    };
+    // without borders the only thing around the input is its title line
+    let (border_width, border_height) = if screen_reader { (0, 1) } else { (2, 2) };
+    let (input_lines, (cursor_y, cursor_x)) = wrap_input(&input_text, input_pos, horizontal[1].width.saturating_sub(border_width).max(1) as usize);
+    let input_height = input_lines.len().min(8) as u16;
+    let input_scroll = (cursor_y as u16).saturating_sub(input_height - 1);
+    let announcements: Vec<_> = state.announcements.lines().map(|v| Spans::from(vec![Span::raw(v)])).collect();
+    let content = layout::Layout::default()
+        .direction(layout::Direction::Vertical)
+        .constraints([
+            layout::Constraint::Min(3),
+            layout::Constraint::Length(input_height + border_height),
+            layout::Constraint::Length(1),
+            layout::Constraint::Length(announcements.len() as u16),
+        ])
+        .split(horizontal[1]);
+
+    let channels = widgets::Block::default().borders(borders);
+    let workspace = state.workspace.and_then(|v| state.config.workspaces.get(v)).map(|v| v.name.as_str());
+    let channels = match (screen_reader, workspace) {
+        (true, Some(name)) => channels.title(format!("Rooms: {}", name)),
+        (true, None) => channels.title("Rooms"),
+        (false, Some(name)) => channels.title(name),
+        (false, None) => channels,
+    };
+    let channels_list: Vec<_> = state.sidebar_ids().iter().filter_map(|id| {
+        state.channels.get(id).map(|v| {
+            // rooms we've left stay listed, set apart, with what we'd already loaded
+            let mut name = if v.archived { vec![Span::styled(format!("{} (left)", v.name), state.theme.muted())] } else { vec![Span::raw(v.name.as_str())] };
+            if v.changed {
+                name.push(Span::styled(format!(" {}", state.symbols.changed()), state.theme.muted()));
+            }
+            let mentions = mention_count(state, id);
+            if mentions > 0 {
+                name.push(Span::styled(format!(" ({})", mentions), state.theme.highlight()));
+            }
+            let mut lines = vec![Spans::from(name)];
+            if state.config.sidebar.previews {
+                // filtered messages stay out of the preview as they do the timeline
+                let latest = v.message_ids.iter().rev().filter_map(|id| v.messages.get(id)).find(|m| !state.filters.matches(v.room.room_id().as_str(), &m.user, &m.content));
+                if let Some(latest) = latest {
+                    let nick = latest.user.trim_start_matches('@').split(':').next().unwrap_or_default();
+                    let content = if v.undecrypted.contains_key(&latest.id) {
+                        String::from("[unable to decrypt]")
+                    } else {
+                        latest.media.as_ref().and_then(media::summary).unwrap_or_else(|| if state.config.irc(v.room.room_id().as_str()) { irc::strip(&latest.content) } else { latest.content.clone() })
+                    };
+                    // replies start with a quote of what they answer, which says less than the reply
+                    let line = content.lines().find(|v| !v.is_empty() && !v.starts_with("> ")).unwrap_or_default();
+                    let preview = format!("{}: {}", nick, line);
+                    let width = horizontal[0].width.saturating_sub(border_width) as usize;
+                    lines.push(Spans::from(vec![Span::styled(typing::truncate(&preview, width, state.symbols.ellipsis()), state.theme.muted())]));
+                }
+            }
+            lines
+        })
+    })
+    .map(|v| widgets::ListItem::new(Text::from(v))).collect();
+    let channels = widgets::List::new(channels_list)
+        .highlight_style(state.theme.selected())
+        .highlight_symbol(if screen_reader { "> " } else { "" })
+        .block(channels);
+    f.render_stateful_widget(channels, horizontal[0], &mut state.channels_state.clone());
+
+    let messages = widgets::Block::default().borders(borders);
+    let messages = match state.thread.as_ref() {
+        Some(_) => messages.title("Thread (t to close)"),
+        None if screen_reader => messages.title("Messages"),
+        None => messages,
+    };
+    match state.current_channel.as_ref().filter(|v| state.channels.contains_key(*v)) {
+        Some(current) => {
+            let fade_before = Some(state.config.colors.fade_after_minutes).filter(|v| *v != 0).map(|v| chrono::Utc::now().timestamp() - v as i64 * 60);
+            let items: Vec<_> = timeline(state, current).into_iter().rev().collect();
+            let key = |i: usize| match &items[i] {
+                TimelineItem::Message(_, v) => v.id.to_string(),
+                TimelineItem::Gap(_, before) => format!("gap {}", before),
+                TimelineItem::Divider(text) => text.clone(),
+            };
+            // pictures go over the lines left for them, once it's known where those are
+            let mut pictures = vec![];
+            // only called for the messages on screen and below them
+            let render = |i: usize| {
+                let (channel, v) = match &items[i] {
+                    TimelineItem::Message(channel, v) => (*channel, *v),
+                    TimelineItem::Gap(_, _) => return vec![Spans::from(vec![Span::styled(format!("missing messages {} press Enter to load", state.symbols.dash()), state.theme.warning())])],
+                    TimelineItem::Divider(text) => return vec![Spans::from(vec![Span::styled(text.clone(), state.theme.muted())])],
+                };
+                // IRC rooms get their own one line layout, and their bodies can have mIRC colour codes
+                let irc = state.config.irc(channel.room.room_id().as_str());
+                let template = if irc { &state.irc_template } else { &state.message_template };
+                let parts = template.render(|field| match field {
+                    "time" => Some(state.clock.time(u64::from(v.timestamp) as i64)),
+                    "date" => Some(state.clock.date(u64::from(v.timestamp) as i64)),
+                    "user" => Some(v.user.clone()),
+                    "nick" => Some(v.user.trim_start_matches('@').split(':').next().unwrap_or_default().to_string()),
+                    "content" => Some(match channel.undecrypted.get(&v.id) {
+                        Some(undecrypted) => format!("[unable to decrypt: {}]", undecrypted.session_id.as_ref().and_then(|v| state.withheld.get(v)).unwrap_or(&undecrypted.error)),
+                        None => v.media.as_ref().and_then(media::summary).unwrap_or_else(|| if irc { irc::strip(&v.content) } else { v.content.clone() }),
+                    }),
+                    "edited" => Some(String::from(if v.edited.is_some() { " [EDITED]" } else { "" })),
+                    "imported" => Some(String::from(if v.imported { " [imported]" } else { "" })),
+                    "encryption" => Some(v.encryption.warning(channel.room.is_encrypted()).map(|v| format!(" [{}]", v)).unwrap_or_default()),
+                    "id" => Some(v.id.to_string()),
+                    _ => None,
+                });
+                let nick = state.theme.nick(v.user.as_str());
+                // filtered messages only show while revealed, and are set apart
+                let filtered = state.filters.matches(channel.room.room_id().as_str(), &v.user, &v.content);
+                let faded = v.imported || fade_before.map(|before| (u64::from(v.timestamp) as i64) < before).unwrap_or(false);
+                let irc_colored = if irc && v.formatted.is_none() { irc::colored(&v.content).map(|v| v.into_iter().map(|(text, color)| (text, html::Format::color(color))).collect::<Vec<_>>()) } else { None };
+                let mut lines = vec![];
+                if let Some(reply_to) = v.reply_to.as_ref() {
+                    let quoted = match channel.messages.get(reply_to) {
+                        Some(original) => format!("{} {}: {}", state.symbols.quote(), original.user, original.content.lines().next().unwrap_or_default()),
+                        None => format!("{} a message that isn't loaded", state.symbols.quote()),
+                    };
+                    lines.push(Spans::from(vec![Span::styled(quoted, state.theme.muted())]));
+                }
+                lines.push(Spans::default());
+                for (field, part) in parts {
+                    let style = match field {
+                        Some("content") if filtered => state.theme.muted(),
+                        Some("content") if v.highlighted => state.theme.highlight(),
+                        Some("content") if v.call == Some(call::CallEvent::Invite) => state.theme.warning(),
+                        Some("content") if v.call.is_some() => state.theme.muted(),
+                        Some("encryption") => state.theme.warning(),
+                        Some("user" | "nick") if faded => state.theme.faded(nick),
+                        Some("user" | "nick") => nick,
+                        _ if faded => state.theme.faded(Style::default()),
+                        _ => Style::default(),
+                    };
+                    // formatted text keeps its formatting unless something else sets the content apart
+                    let formatted = match (field, v.formatted.as_ref().or(irc_colored.as_ref())) {
+                        (Some("content"), Some(formatted)) if style == Style::default() && v.media.is_none() && !channel.undecrypted.contains_key(&v.id) => formatted.iter().map(|(text, format)| (text.as_str(), format_style(state, format))).collect(),
+                        _ => vec![(part.as_str(), style)],
+                    };
+                    for (text, style) in formatted {
+                        for (i, piece) in text.split('\n').enumerate() {
+                            if i != 0 {
+                                lines.push(Spans::default());
+                            }
+                            lines.last_mut().unwrap().0.push(Span::styled(piece.to_string(), style));
+                        }
+                    }
+                }
+                if let Some(picture) = state.images.get(&v.id) {
+                    pictures.push((i, lines.len(), &v.id, picture));
+                    lines.extend((0..picture.rows).map(|_| Spans::default()));
+                }
+                if let Some(preview) = v.preview.as_ref() {
+                    lines.push(Spans::from(vec![Span::styled(format!("  {}", preview.title), state.theme.muted().add_modifier(Modifier::BOLD))]));
+                    if let Some(description) = preview.description.as_ref() {
+                        let description = match description.char_indices().nth(preview::MAX_DESCRIPTION) {
+                            Some((i, _)) => format!("{}{}", &description[..i], state.symbols.ellipsis()),
+                            None => description.clone(),
+                        };
+                        lines.push(Spans::from(vec![Span::styled(format!("  {}", description), state.theme.muted())]));
+                    }
+                }
+                if state.expanded.as_ref() == Some(&v.id) {
+                    if v.details.is_empty() {
+                        lines.push(Spans::from(vec![Span::styled("  no other fields", state.theme.muted())]));
+                    }
+                    for (key, value) in v.details.iter() {
+                        lines.push(Spans::from(vec![Span::styled(format!("  {}: ", key), state.theme.muted()), Span::raw(value.replace('\n', " "))]));
+                    }
+                }
+                if let Some(translation) = v.translation.as_ref() {
+                    for line in translation.lines() {
+                        lines.push(Spans::from(vec![Span::styled(format!("  translated: {}", line), state.theme.muted())]));
+                    }
+                }
+                if !v.reactions.is_empty() {
+                    let own = state.client.user_id().map(|v| v.as_str()).unwrap_or_default();
+                    let mut counts: Vec<(&str, usize, bool)> = vec![];
+                    for reaction in v.reactions.iter() {
+                        let ours = reaction.sender == own;
+                        match counts.iter_mut().find(|(key, _, _)| *key == reaction.key) {
+                            Some((_, count, mine)) => {
+                                *count += 1;
+                                *mine |= ours;
+                            }
+                            None => counts.push((&reaction.key, 1, ours)),
+                        }
+                    }
+                    // the ones we've reacted with stand out, since picking them again takes them back
+                    let mut spans = vec![];
+                    for (i, (key, count, mine)) in counts.into_iter().enumerate() {
+                        if i > 0 {
+                            spans.push(Span::raw("  "));
+                        }
+                        let style = if mine { Style::default().add_modifier(Modifier::BOLD) } else { Style::default() };
+                        spans.push(Span::styled(format!("{} {}", state.symbols.emoji(key), count), style));
+                    }
+                    lines.push(Spans::from(spans));
+                }
+                if let Some(replies) = channel.threads.get(&v.id).filter(|_| state.thread.is_none()) {
+                    let replies = if replies.len() == 1 { String::from("[1 reply]") } else { format!("[{} replies]", replies.len()) };
+                    lines.push(Spans::from(vec![Span::styled(replies, state.theme.muted())]));
+                }
+                lines
+            };
+            let view = viewport::View::new(&state.messages_state, items.len(), key, render)
+                .highlight_style(state.theme.selected())
+                .highlight_symbol(if screen_reader { "> " } else { "" });
+            let inner = messages.inner(content[0]);
+            f.render_widget(messages, content[0]);
+            f.render_widget(view, inner);
+
+            // only whole pictures are drawn, and none under a popup, since they'd be drawn over it
+            for (index, top) in state.messages_state.tops().into_iter().filter(|_| state.popup.is_none()) {
+                for (_, line, id, picture) in pictures.iter().filter(|v| v.0 == index) {
+                    let y = top + *line as i32;
+                    if y >= 0 && y + picture.rows as i32 <= inner.height as i32 && picture.cols + 2 <= inner.width {
+                        state.images.place(id, inner.x + 2, inner.y + y as u16);
+                    }
+                }
+            }
+        }
+
+        None => {
+            f.render_widget(messages, content[0]);
+        }
+    }
+
+    let archived = state.current_channel.as_ref().and_then(|v| state.channels.get(v)).map(|v| v.archived).unwrap_or(false);
+    let can_send = state.current_channel.as_ref().map(|v| state.permissions.get(v).send).unwrap_or(true);
+    let input = widgets::Block::default().borders(borders);
+    let input = match (state.code_block.as_ref(), state.secret.as_ref()) {
+        (_, Some(prompt)) => input.title(prompt.title.as_str()),
+        (Some(CodeBlock { language: Some(language) }), _) => input.title(format!("code: {}", language)),
+        (Some(CodeBlock { language: None }), _) => input.title("code"),
+        // typing still works for commands, but nothing can be sent
+        (None, None) if archived => input.title(Span::styled("read-only", state.theme.muted())),
+        (None, None) if !can_send => input.title(Span::styled("you don't have permission to post", state.theme.muted())),
+        (None, None) if state.editing.is_some() => input.title("editing, Esc to cancel"),
+        (None, None) if state.thread.is_some() && state.reply_to.is_none() => input.title("reply in thread"),
+        (None, None) if state.reply_to.is_some() => {
+            let user = state.current_channel.as_ref().and_then(|v| state.channels.get(v)).zip(state.reply_to.as_ref()).and_then(|(channel, id)| channel.messages.get(id)).map(|v| v.user.as_str());
+            input.title(format!("reply to {}", user.unwrap_or("a message")))
+        }
+        (None, None) => {
+            let language = state.current_channel.as_ref().and_then(|v| state.config.rooms.get(v.as_str())).and_then(|v| v.language.as_deref());
+            match language {
+                Some(language) if screen_reader => input.title(format!("Message [{}]", language)),
+                Some(language) => input.title(format!("[{}]", language)),
+                None if screen_reader => input.title("Message"),
+                None => input,
+            }
+        }
+    };
+    let input_lines: Vec<_> = input_lines.into_iter().map(|v| spelling_spans(v, state)).collect();
+    let input = widgets::Paragraph::new(Text::from(input_lines)).block(input).scroll((input_scroll, 0));
+    f.render_widget(input, content[1]);
+
+    let mut status = vec![Span::raw(state.mode.name())];
+    if state.current_channel.as_ref().map(|v| state.config.private(v.as_str())).unwrap_or(false) {
+        status.push(Span::raw("  private"));
+    }
+    if archived {
+        status.push(Span::raw("  archived (read-only)"));
+    }
+    if state.away.is_away() {
+        status.push(Span::raw("  away"));
+    }
+    if let Some(dnd) = state.dnd.status(chrono::Local::now()) {
+        status.push(Span::raw(format!("  {}", dnd)));
+    }
+    if state.quote_mark.is_some() {
+        status.push(Span::raw("  quote marked (y to copy)"));
+    }
+    if state.filters.revealed {
+        status.push(Span::raw("  showing filtered"));
+    }
+    if let Some(register) = state.macros.recording() {
+        status.push(Span::raw(format!("  recording @{}", register)));
+    }
+    if let Some(version) = state.update.as_ref() {
+        status.push(Span::styled(format!("  ilo-toki {} is out", version), state.theme.muted()));
+    }
+    if !state.uploading.is_empty() {
+        status.push(Span::raw(format!("  uploading {}", state.uploading.join(", "))));
+    }
+    if let Some(jump) = state.jumping.as_ref() {
+        status.push(Span::raw(format!("  jumping to {} ({} pages back)", jump.date, jump.pages)));
+    }
+    if !state.downloading.is_empty() {
+        status.push(Span::raw(format!("  downloading {}", state.downloading.join(", "))));
+    }
+    if let Some(toast) = state.toast.current(std::time::Instant::now()) {
+        status.push(Span::raw("  "));
+        status.push(Span::styled(toast, state.theme.warning()));
+    }
+    if let Some(message) = state.status.as_ref() {
+        status.push(Span::raw("  "));
+        status.push(Span::styled(message.as_str(), state.theme.warning()));
+    }
+
+    let used: usize = status.iter().map(|v| v.content.chars().count()).sum();
+    let typing = state.current_channel.as_ref().and_then(|v| state.channels.get(v)).and_then(|v| v.typing.summary((content[2].width as usize).saturating_sub(used + 2), &state.symbols));
+    if let Some(typing) = typing {
+        status.push(Span::raw("  "));
+        status.push(Span::styled(typing, state.theme.muted()));
+    }
+    let status = Spans::from(status);
+    let status = widgets::Paragraph::new(status);
+    f.render_widget(status, content[2]);
+    f.render_widget(widgets::Paragraph::new(Text::from(announcements)), content[3]);
+
+    // leaving the cursor unset hides it, but screen readers are kept in the input box
+    if style.visible() || screen_reader {
+        f.set_cursor(content[1].x + cursor_x as u16 + border_width / 2, content[1].y + cursor_y as u16 - input_scroll + 1);
+    }
+
+    if let Some(popup) = state.popup.as_ref() {
+        let width = popup.lines.iter().map(|v| v.chars().count()).chain(std::iter::once(popup.title.chars().count())).max().unwrap_or(0) as u16 + 4;
+        let height = popup.lines.len() as u16 + 2;
+        let area = content[0];
+        let area = layout::Rect {
+            x: area.x + area.width.saturating_sub(width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 2,
+            width: width.min(area.width),
+            height: height.min(area.height),
+        };
+        let lines: Vec<_> = popup.lines.iter().map(|v| Spans::from(vec![Span::raw(v)])).collect();
+        let block = widgets::Block::default().borders(borders).title(popup.title.as_str());
+        let paragraph = widgets::Paragraph::new(Text::from(lines)).block(block);
+        f.render_widget(widgets::Clear, area);
+        f.render_widget(paragraph, area);
+    }
+
+    if state.symbols.ascii() {
+        f.render_widget(symbols::AsciiBorders, f.size());
+    }
+}
+
+fn format_style(state: &AppState, format: &html::Format) -> Style {
+    let mut style = format.color.map(|v| state.theme.text_color(v)).unwrap_or_default();
+    if format.code {
+        style = style.patch(state.theme.code());
+    }
+    if format.quote {
+        style = style.patch(state.theme.muted());
+    }
+    for (on, modifier) in [(format.bold, Modifier::BOLD), (format.italic, Modifier::ITALIC), (format.strike, Modifier::CROSSED_OUT), (format.link, Modifier::UNDERLINED)] {
+        if on {
+            style = style.add_modifier(modifier);
+        }
+    }
+    style
+}
+
+/// A line of the input box with the words the spellchecker doesn't know underlined. Words split
+/// across lines aren't recognised.
+fn spelling_spans(line: String, state: &AppState) -> Spans<'static> {
+    if state.secret.is_some() || state.code_block.is_some() {
+        return Spans::from(vec![Span::raw(line)]);
+    }
+
+    let mut spans = vec![];
+    let mut word = String::new();
+    let mut other = String::new();
+    let push_word = |spans: &mut Vec<Span<'static>>, word: String| {
+        let style = if state.spelling.is_misspelled(&word) { state.theme.misspelled() } else { Style::default() };
+        spans.push(Span::styled(word, style));
+    };
+    for c in line.chars() {
+        if c.is_alphabetic() || c == '\'' {
+            if !other.is_empty() {
+                spans.push(Span::raw(std::mem::take(&mut other)));
+            }
+            word.push(c);
+        } else {
+            if !word.is_empty() {
+                push_word(&mut spans, std::mem::take(&mut word));
+            }
+            other.push(c);
+        }
+    }
+    if !word.is_empty() {
+        push_word(&mut spans, word);
+    }
+    if !other.is_empty() {
+        spans.push(Span::raw(other));
+    }
+    Spans::from(spans)
+}
+
+/// Splits the input into the lines shown in the input box, returning them along with the
+/// line and column the cursor is on. Columns are terminal cells, so wide characters like CJK and
+/// emoji take two and are moved to the next line whole rather than split.
+fn wrap_input(text: &str, cursor: usize, width: usize) -> (Vec<String>, (usize, usize)) {
+    let mut lines = vec![String::new()];
+    let mut column = 0;
+    let mut position = None;
+    for (i, c) in text.char_indices() {
+        let char_width = c.width().unwrap_or(0);
+        if column > 0 && column + char_width > width && c != '\n' {
+            lines.push(String::new());
+            column = 0;
+        }
+
+        if i == cursor {
+            position = Some((lines.len() - 1, column));
+        }
+
+        if c == '\n' {
+            lines.push(String::new());
+            column = 0;
+        } else {
+            lines.last_mut().unwrap().push(c);
+            column += char_width;
+        }
+    }
+
+    let position = match position {
+        Some(v) => v,
+        None if column >= width => {
+            lines.push(String::new());
+            (lines.len() - 1, 0)
+        }
+        None => (lines.len() - 1, column),
+    };
+    (lines, position)
+}
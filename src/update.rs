@@ -0,0 +1,43 @@
+//! Checking for a newer release on startup, once `updates.check` is turned on. GitHub is asked for
+//! the latest release, and sees the IP address asking and a User-Agent with the exact version
+//! running, which it requires. Left off, no request is made.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+const LATEST_RELEASE: &str = "https://api.github.com/repos/melody-notpond/ilo-toki/releases/latest";
+/// A slow answer isn't worth holding a connection open for.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+}
+
+/// The latest release's version, if it's newer than this one.
+pub async fn check() -> Option<String> {
+    let response = matrix_sdk::reqwest::Client::new()
+        .get(LATEST_RELEASE)
+        // GitHub turns away requests without one
+        .header("User-Agent", concat!("ilo-toki/", env!("CARGO_PKG_VERSION")))
+        .header("Accept", "application/vnd.github+json")
+        .timeout(TIMEOUT)
+        .send()
+        .await
+        .and_then(|v| v.error_for_status())
+        .ok()?;
+    let release: Release = serde_json::from_str(&response.text().await.ok()?).ok()?;
+    let latest = release.tag_name.trim_start_matches('v').to_string();
+    Some(latest).filter(|v| newer(env!("CARGO_PKG_VERSION"), v))
+}
+
+/// Whether `latest` is a later version than `current`, comparing each number in turn. Anything after
+/// a `-`, like `-rc1`, is left out.
+pub fn newer(current: &str, latest: &str) -> bool {
+    let numbers = |version: &str| -> Option<Vec<u64>> { version.split('-').next()?.split('.').map(|v| v.parse().ok()).collect() };
+    match (numbers(current), numbers(latest)) {
+        (Some(current), Some(latest)) => latest > current,
+        _ => false,
+    }
+}
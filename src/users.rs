@@ -0,0 +1,157 @@
+//! `/usersearch`: people in the homeserver's user directory, who can be messaged directly or invited
+//! to the current channel. `/whois` and `P` show one person's profile and the rooms we share.
+
+use matrix_sdk::{
+    room::Joined,
+    ruma::{
+        api::client::{
+            profile::get_profile,
+            room::create_room::{self, v3::RoomPreset},
+            user_directory::search_users,
+        },
+        events::{direct::DirectEventContent, room::member::MembershipState},
+        MxcUri, OwnedMxcUri, OwnedRoomId, OwnedUserId, UInt, UserId,
+    },
+    reqwest::Url,
+    Client,
+};
+use serde::Deserialize;
+
+/// The keys the users are picked with, in order.
+pub const KEYS: [char; 9] = ['1', '2', '3', '4', '5', '6', '7', '8', '9'];
+
+pub struct User {
+    pub user_id: OwnedUserId,
+    pub name: Option<String>,
+    pub avatar: Option<OwnedMxcUri>,
+}
+
+/// The users whose id or display name matches the term. Servers only search people who share a
+/// room with us or are in a public one, unless they're set up to search everyone.
+pub async fn search(client: &Client, term: &str) -> Result<Vec<User>, String> {
+    let mut request = search_users::v3::Request::new(term);
+    request.limit = UInt::from(KEYS.len() as u32);
+    let response = client.send(request, None).await.map_err(|e| e.to_string())?;
+    Ok(response
+        .results
+        .into_iter()
+        .map(|v| User {
+            user_id: v.user_id,
+            name: v.display_name.filter(|v| !v.is_empty()),
+            avatar: v.avatar_url,
+        })
+        .collect())
+}
+
+/// `can_invite` is whether inviting to the current channel is offered at all.
+pub fn lines(users: &[User], homeserver: &Url, invite: bool, can_invite: bool) -> Vec<String> {
+    let mut lines = vec![];
+    for (user, key) in users.iter().zip(KEYS) {
+        match user.name.as_ref() {
+            Some(name) => lines.push(format!("{} {} ({})", key, name, user.user_id)),
+            None => lines.push(format!("{} {}", key, user.user_id)),
+        }
+        if let Some(url) = user.avatar.as_deref().and_then(|v| avatar_url(homeserver, v)) {
+            lines.push(format!("  avatar: {}", url));
+        }
+    }
+    if users.is_empty() {
+        lines.push(String::from("Nobody matches."));
+    }
+    lines.push(String::new());
+    lines.push(String::from(match (invite, can_invite) {
+        (true, _) => "A number to invite them here, d to message directly instead, Esc to close",
+        (false, true) => "A number to message them directly, i to invite here instead, Esc to close",
+        (false, false) => "A number to message them directly, Esc to close",
+    }));
+    lines
+}
+
+/// Where to download an avatar, which a terminal can't show but a browser can.
+fn avatar_url(homeserver: &Url, avatar: &MxcUri) -> Option<String> {
+    let (server, id) = avatar.parts().ok()?;
+    homeserver.join(&format!("_matrix/media/v3/download/{}/{}", server, id)).ok().map(String::from)
+}
+
+/// The direct chat we already have with someone, if any.
+pub fn existing_direct(client: &Client, user_id: &UserId) -> Option<Joined> {
+    client.joined_rooms().into_iter().find(|v| {
+        let targets = v.direct_targets();
+        targets.len() == 1 && targets.contains(user_id)
+    })
+}
+
+/// Starts a direct chat with someone, and records it in `m.direct` so our other sessions know it's one.
+pub async fn start_direct(client: &Client, user_id: &UserId) -> Result<OwnedRoomId, String> {
+    let invite = [user_id.to_owned()];
+    let mut request = create_room::v3::Request::new();
+    request.invite = &invite;
+    request.is_direct = true;
+    request.preset = Some(RoomPreset::TrustedPrivateChat);
+    let room_id = client.create_room(request).await.map_err(|e| e.to_string())?.room_id;
+
+    let account = client.account();
+    let mut direct = account.account_data::<DirectEventContent>().await.ok().flatten().and_then(|v| v.deserialize().ok()).unwrap_or_default();
+    direct.entry(user_id.to_owned()).or_default().push(room_id.clone());
+    account.set_account_data(direct).await.map_err(|e| e.to_string())?;
+    Ok(room_id)
+}
+
+#[derive(Deserialize)]
+struct MutualRooms {
+    joined: Vec<OwnedRoomId>,
+}
+
+/// The rooms we share with someone. The server is asked under `prefix` when it can answer, since
+/// our member lists may be missing people who haven't spoken.
+pub async fn mutual_rooms(client: &Client, prefix: Option<&str>, user_id: &UserId) -> Vec<OwnedRoomId> {
+    if let Some(prefix) = prefix {
+        let mut url = client.homeserver().await;
+        url.path_segments_mut().unwrap().pop_if_empty().extend(prefix.split('/'));
+        url.query_pairs_mut().append_pair("user_id", user_id.as_str());
+        let response = matrix_sdk::reqwest::Client::new().get(url).bearer_auth(client.access_token().unwrap_or_default()).send().await.and_then(|v| v.error_for_status());
+        if let Ok(body) = response {
+            if let Some(rooms) = body.text().await.ok().and_then(|v| serde_json::from_str::<MutualRooms>(&v).ok()) {
+                return rooms.joined;
+            }
+        }
+    }
+
+    let mut rooms = vec![];
+    for room in client.joined_rooms() {
+        if let Ok(Some(member)) = room.get_member_no_sync(user_id).await {
+            if *member.membership() == MembershipState::Join {
+                rooms.push(room.room_id().to_owned());
+            }
+        }
+    }
+    rooms
+}
+
+/// Someone's profile, with the rooms we share numbered so they can be jumped to.
+pub async fn profile_lines(client: &Client, user_id: &UserId, rooms: &[(OwnedRoomId, String)]) -> Vec<String> {
+    let mut lines = vec![];
+    if let Ok(profile) = client.send(get_profile::v3::Request::new(user_id), None).await {
+        if let Some(name) = profile.displayname.filter(|v| !v.is_empty()) {
+            lines.push(format!("Name: {}", name));
+        }
+        let homeserver = client.homeserver().await;
+        if let Some(url) = profile.avatar_url.as_deref().and_then(|v| avatar_url(&homeserver, v)) {
+            lines.push(format!("Avatar: {}", url));
+        }
+    }
+    lines.push(String::new());
+
+    if rooms.is_empty() {
+        lines.push(String::from("No rooms in common."));
+    } else {
+        lines.push(String::from("Rooms in common:"));
+        lines.extend(rooms.iter().zip(KEYS).map(|((_, name), key)| format!("{} {}", key, name)));
+        if rooms.len() > KEYS.len() {
+            lines.push(format!("and {} more", rooms.len() - KEYS.len()));
+        }
+        lines.push(String::new());
+        lines.push(String::from("A number to go there, Esc to close"));
+    }
+    lines
+}
@@ -0,0 +1,53 @@
+//! Interactive verification, comparing emoji with other users over a shared room, or with a
+//! device, like another of our own sessions, over to-device messages.
+
+use matrix_sdk::{
+    encryption::verification::{SasVerification, Verification, VerificationRequest},
+    ruma::UserId,
+    Client,
+};
+
+/// Sends a verification request to a user through our direct message room with them, or to our
+/// other sessions over to-device messages if it's our own user id.
+pub async fn request(client: &Client, user_id: &str) -> Result<VerificationRequest, String> {
+    let user_id = UserId::parse(user_id).map_err(|e| format!("{} isn't a user id: {}", user_id, e))?;
+    let identity = client.encryption().get_user_identity(&user_id).await.map_err(|e| e.to_string())?;
+    let identity = identity.ok_or_else(|| format!("{} hasn't set up cross-signing", user_id))?;
+    identity.request_verification().await.map_err(|e| e.to_string())
+}
+
+/// The emoji verification started by an event in the given flow, if there is one.
+pub async fn sas(client: &Client, user_id: &UserId, flow_id: &str) -> Option<SasVerification> {
+    match client.encryption().get_verification(user_id, flow_id).await? {
+        Verification::SasV1(v) => Some(v),
+        #[allow(unreachable_patterns)]
+        _ => None,
+    }
+}
+
+/// The lines of a popup asking whether both sides see the same emoji.
+pub fn sas_lines(sas: &SasVerification) -> Vec<String> {
+    let device = sas.other_device();
+    let name = device.display_name().map(|v| format!("{}, {}", device.device_id(), v)).unwrap_or_else(|| device.device_id().to_string());
+    let mut lines = vec![format!("Compare with {} ({}):", sas.other_user_id(), name), String::new()];
+    match (sas.emoji(), sas.decimals()) {
+        (Some(emoji), _) => {
+            lines.push(emoji.iter().map(|v| format!("{:^9}", v.symbol)).collect());
+            lines.push(emoji.iter().map(|v| format!("{:^9}", v.description)).collect());
+        }
+
+        (None, Some((a, b, c))) => lines.push(format!("{} {} {}", a, b, c)),
+        (None, None) => lines.push(String::from("(nothing to compare yet)")),
+    }
+    lines.push(String::new());
+    lines.push(String::from("y: they match, n: they don't"));
+    lines
+}
+
+/// Whether we've verified a user's cross-signing identity.
+pub async fn is_verified(client: &Client, user_id: &UserId) -> bool {
+    match client.encryption().get_user_identity(user_id).await {
+        Ok(Some(identity)) => identity.is_verified(),
+        _ => false,
+    }
+}
@@ -0,0 +1,220 @@
+//! The message list, scrolled by lines rather than by messages. Each frame only renders the
+//! messages on screen and those between them and the newest, so long histories cost nothing extra.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
+
+use tui::{buffer::Buffer, layout::Rect, style::Style, text::Spans, widgets::Widget};
+use unicode_width::UnicodeWidthStr;
+
+/// Which item is selected and how far up the list is scrolled, kept between frames. Drawing only
+/// borrows the app state, and the state is shared between tasks, hence the atomic and mutex.
+#[derive(Default)]
+pub struct Viewport {
+    /// Counted from the newest item.
+    selected: Option<usize>,
+    /// Lines between the bottom of the newest item and the bottom of the screen.
+    offset: AtomicUsize,
+    /// The key of the newest item last frame, to tell how much has arrived below the view since.
+    newest: Mutex<Option<String>>,
+    /// The items on screen last frame, with the row each starts at counted down from the top of the
+    /// list, which is negative for one cut off at the top.
+    tops: Mutex<Vec<(usize, i32)>>,
+}
+
+impl Viewport {
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    pub fn tops(&self) -> Vec<(usize, i32)> {
+        self.tops.lock().unwrap().clone()
+    }
+
+    /// Selects an item, or goes back to following the newest with `None`.
+    pub fn select(&mut self, index: Option<usize>) {
+        self.selected = index;
+        if index.is_none() {
+            self.offset.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Draws items newest first from the bottom up, like a chat.
+pub struct View<'v, K, L> {
+    viewport: &'v Viewport,
+    count: usize,
+    key: K,
+    lines: L,
+    highlight_style: Style,
+    highlight_symbol: &'v str,
+}
+
+impl<'v, 'a, K, L> View<'v, K, L>
+where
+    K: Fn(usize) -> String,
+    L: FnMut(usize) -> Vec<Spans<'a>>,
+{
+    /// `key` names an item so the view can follow it as others arrive, and `lines` renders it.
+    /// Both are given the item's index counted from the newest.
+    pub fn new(viewport: &'v Viewport, count: usize, key: K, lines: L) -> View<'v, K, L> {
+        View {
+            viewport,
+            count,
+            key,
+            lines,
+            highlight_style: Style::default(),
+            highlight_symbol: "",
+        }
+    }
+
+    pub fn highlight_style(mut self, style: Style) -> View<'v, K, L> {
+        self.highlight_style = style;
+        self
+    }
+
+    pub fn highlight_symbol(mut self, symbol: &'v str) -> View<'v, K, L> {
+        self.highlight_symbol = symbol;
+        self
+    }
+}
+
+impl<'v, 'a, K, L> Widget for View<'v, K, L>
+where
+    K: Fn(usize) -> String,
+    L: FnMut(usize) -> Vec<Spans<'a>>,
+{
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let height = area.height as usize;
+        self.viewport.tops.lock().unwrap().clear();
+        if area.width < 1 || height < 1 || self.count == 0 {
+            return;
+        }
+
+        let mut rendered = Rendered {
+            lines: self.lines,
+            count: self.count,
+            items: vec![],
+            bottoms: vec![],
+        };
+        let mut offset = self.viewport.offset.load(Ordering::Relaxed);
+
+        // while something is selected, items that arrived below push the view up by their height, so
+        // what's on screen stays put. The selection was already moved up when they were inserted
+        let newest = (self.key)(0);
+        let previous = self.viewport.newest.lock().unwrap().replace(newest.clone());
+        if let Some(previous) = previous.filter(|v| *v != newest && self.viewport.selected.is_some()) {
+            if let Some(index) = (1..self.count).find(|i| (self.key)(*i) == previous) {
+                offset += rendered.bottom(index);
+            }
+        }
+
+        // the view only moves as far as it has to for the selection to be on screen
+        let selected = self.viewport.selected.map(|v| v.min(self.count - 1));
+        match selected {
+            Some(selected) => {
+                let (bottom, top) = (rendered.bottom(selected), rendered.top(selected));
+                if bottom < offset {
+                    offset = bottom;
+                } else if top > offset + height {
+                    offset = top - height;
+                }
+            }
+
+            None => offset = 0,
+        }
+
+        // and never past the oldest item
+        if rendered.cover(offset + height) {
+            offset = offset.min(rendered.height().saturating_sub(height));
+        }
+        self.viewport.offset.store(offset, Ordering::Relaxed);
+
+        let blank = " ".repeat(self.highlight_symbol.width());
+        let mut tops = vec![];
+        for (i, lines) in rendered.items.iter().enumerate() {
+            let top = rendered.bottoms[i] + lines.len();
+            if top <= offset {
+                continue;
+            }
+            if rendered.bottoms[i] >= offset + height {
+                break;
+            }
+            tops.push((i, (height + offset) as i32 - top as i32));
+
+            for (j, line) in lines.iter().enumerate() {
+                let up = top - 1 - j;
+                if up < offset || up >= offset + height {
+                    continue;
+                }
+
+                let y = area.bottom() - 1 - (up - offset) as u16;
+                // with something selected every line is indented by the symbol marking it
+                let x = match selected {
+                    Some(selected) => {
+                        let symbol = if selected == i && j == 0 { self.highlight_symbol } else { &blank };
+                        buf.set_stringn(area.left(), y, symbol, area.width as usize, Style::default()).0
+                    }
+
+                    None => area.left(),
+                };
+                buf.set_spans(x, y, line, area.width - (x - area.left()));
+                if selected == Some(i) {
+                    buf.set_style(Rect::new(area.left(), y, area.width, 1), self.highlight_style);
+                }
+            }
+        }
+        *self.viewport.tops.lock().unwrap() = tops;
+    }
+}
+
+/// The items rendered so far, newest first, with where each one's bottom is in lines up from the
+/// bottom of the newest.
+struct Rendered<'a, L> {
+    lines: L,
+    count: usize,
+    items: Vec<Vec<Spans<'a>>>,
+    bottoms: Vec<usize>,
+}
+
+impl<'a, L: FnMut(usize) -> Vec<Spans<'a>>> Rendered<'a, L> {
+    /// The lines rendered so far.
+    fn height(&self) -> usize {
+        self.bottoms.last().zip(self.items.last()).map(|(bottom, lines)| bottom + lines.len()).unwrap_or(0)
+    }
+
+    /// Renders the next item, unless there are no more.
+    fn render_next(&mut self) -> bool {
+        let index = self.items.len();
+        if index >= self.count {
+            return false;
+        }
+
+        let bottom = self.height();
+        self.items.push((self.lines)(index));
+        self.bottoms.push(bottom);
+        true
+    }
+
+    /// Where an item's bottom is, rendering up to it first.
+    fn bottom(&mut self, index: usize) -> usize {
+        while self.items.len() <= index && self.render_next() {}
+        self.bottoms[index]
+    }
+
+    fn top(&mut self, index: usize) -> usize {
+        self.bottom(index) + self.items[index].len()
+    }
+
+    /// Renders until `lines` lines are covered, returning whether that took every item.
+    fn cover(&mut self, lines: usize) -> bool {
+        while self.height() < lines {
+            if !self.render_next() {
+                return true;
+            }
+        }
+        self.items.len() == self.count
+    }
+}
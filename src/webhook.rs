@@ -0,0 +1,74 @@
+//! Outgoing webhooks: new messages in chosen rooms that match a pattern are POSTed as JSON to a
+//! URL, for simple automations without a bot account.
+//!
+//! The body is `{"room_id": ..., "event": ...}`, like `--stream-json` prints.
+
+use std::sync::Arc;
+
+use matrix_sdk::{
+    room::Room,
+    ruma::{events::AnySyncTimelineEvent, serde::Raw},
+    Client,
+};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::config::WebhookConfig;
+
+struct Webhook {
+    url: String,
+    /// Room ids, or empty for every room.
+    rooms: Vec<String>,
+    pattern: Option<Regex>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    #[serde(rename = "type")]
+    event_type: String,
+    content: Content,
+}
+
+#[derive(Deserialize)]
+struct Content {
+    body: Option<String>,
+}
+
+impl Webhook {
+    fn matches(&self, room_id: &str, body: &str) -> bool {
+        (self.rooms.is_empty() || self.rooms.iter().any(|v| v == room_id)) && self.pattern.as_ref().map(|v| v.is_match(body)).unwrap_or(true)
+    }
+}
+
+/// Starts posting messages to the configured webhooks. This should be called after the first sync,
+/// so the history it loads isn't posted. Webhooks with invalid patterns are skipped.
+pub fn watch(client: &Client, config: &[WebhookConfig]) {
+    let hooks: Vec<_> = config
+        .iter()
+        .filter_map(|v| {
+            let pattern = if v.pattern.is_empty() { None } else { Some(Regex::new(&v.pattern).ok()?) };
+            Some(Webhook { url: v.url.clone(), rooms: v.rooms.clone(), pattern })
+        })
+        .collect();
+    if hooks.is_empty() {
+        return;
+    }
+
+    let hooks = Arc::new(hooks);
+    client.add_event_handler(move |event: Raw<AnySyncTimelineEvent>, room: Room| {
+        let hooks = hooks.clone();
+        async move {
+            let body = match event.deserialize_as::<Message>() {
+                Ok(message) if message.event_type == "m.room.message" => message.content.body.unwrap_or_default(),
+                _ => return,
+            };
+
+            let payload = json!({ "room_id": room.room_id(), "event": event }).to_string();
+            for hook in hooks.iter().filter(|v| v.matches(room.room_id().as_str(), &body)) {
+                // failures are ignored, since there's nowhere useful to report them
+                let _ = matrix_sdk::reqwest::Client::new().post(&hook.url).header("Content-Type", "application/json").body(payload.clone()).send().await;
+            }
+        }
+    });
+}
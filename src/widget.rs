@@ -0,0 +1,86 @@
+//! `/widgets`: the room's widgets from `im.vector.modular.widgets` state, like Jitsi calls and
+//! Etherpads, which a terminal can't show but can open in a browser.
+
+use matrix_sdk::{
+    room::Joined,
+    ruma::events::StateEventType,
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+const EVENT_TYPE: &str = "im.vector.modular.widgets";
+/// The keys the widgets are picked with, in order.
+pub const KEYS: [char; 9] = ['1', '2', '3', '4', '5', '6', '7', '8', '9'];
+
+pub struct Widget {
+    pub name: String,
+    pub kind: String,
+    pub url: String,
+}
+
+#[derive(Deserialize)]
+struct StateEvent {
+    state_key: String,
+    content: Content,
+}
+
+#[derive(Deserialize)]
+struct Content {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    url: Option<String>,
+    name: Option<String>,
+    #[serde(default)]
+    data: Map<String, Value>,
+}
+
+/// The room's widgets, skipping removed ones, which have empty content.
+pub async fn list(room: &Joined) -> Vec<Widget> {
+    let events = room.get_state_events(StateEventType::from(EVENT_TYPE)).await.unwrap_or_default();
+    let mut widgets: Vec<_> = events
+        .into_iter()
+        .filter_map(|v| v.deserialize_as::<StateEvent>().ok())
+        .filter_map(|v| {
+            let url = v.content.url?;
+            let kind = v.content.kind.unwrap_or_default();
+            let mut vars: Vec<_> = v.content.data.iter().map(|(k, v)| (k.clone(), v.as_str().map(String::from).unwrap_or_else(|| v.to_string()))).collect();
+            vars.push((String::from("matrix_room_id"), room.room_id().to_string()));
+            vars.push((String::from("matrix_user_id"), room.own_user_id().to_string()));
+            vars.push((String::from("matrix_widget_id"), v.state_key.clone()));
+            Some(Widget {
+                name: v.content.name.filter(|v| !v.is_empty()).unwrap_or_else(|| v.state_key.clone()),
+                kind: kind.trim_start_matches("m.").to_string(),
+                url: fill(&url, &vars),
+            })
+        })
+        .collect();
+    widgets.sort_by(|a, b| a.name.cmp(&b.name));
+    widgets
+}
+
+/// Replaces `$name` in a widget URL with its value, URL encoded. Longer names go first so `$a`
+/// doesn't eat `$ab`.
+pub fn fill(url: &str, vars: &[(String, String)]) -> String {
+    let mut vars: Vec<_> = vars.iter().collect();
+    vars.sort_by_key(|(k, _)| std::cmp::Reverse(k.len()));
+    vars.into_iter().fold(url.to_string(), |url, (k, v)| url.replace(&format!("${}", k), &encode(v)))
+}
+
+fn encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+pub fn lines(widgets: &[Widget], copy: bool) -> Vec<String> {
+    let mut lines: Vec<_> = widgets.iter().zip(KEYS).map(|(v, key)| format!("{} {} ({}): {}", key, v.name, v.kind, v.url)).collect();
+    if widgets.is_empty() {
+        lines.push(String::from("This room has no widgets."));
+    }
+    lines.push(String::new());
+    lines.push(String::from(if copy { "A number to copy its URL, o to open instead, Esc to close" } else { "A number to open it, c to copy instead, Esc to close" }));
+    lines
+}